@@ -0,0 +1,118 @@
+// what a pass spends on turning the new items of a large feed into what is
+// mailed: the diff against the last snapshot and the conversion for
+// rendering, the way they worked before they borrowed the items ("cloned")
+// and now ("borrowed"). Run with 'cargo bench'; it prints the allocations
+// and the wall time per pass of both.
+
+extern crate bbsmon;
+extern crate rss;
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use rss::Item;
+
+use bbsmon::feed::RssContext;
+use bbsmon::queue::pending_items;
+use bbsmon::render::convert_to_ser_items;
+use bbsmon::render::restrict_fields;
+use bbsmon::render::SerItem;
+
+const ITEMS: usize = 5000;
+const PASSES: u32 = 20;
+
+// counts the allocations of the whole process, the benchmark being all of it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// a feed of 'items' posts, every one with a guid, a link, an author, a date
+// and a description of some length, like those of a busy board.
+fn feed(items: usize) -> RssContext {
+    let mut xml = "<rss version=\"2.0\"><channel><title>bbs</title><link>http://bbs/</link>\
+                   <description>d</description>".to_string();
+    for tid in 0..items {
+        xml.push_str(&format!("<item><guid>http://bbs/thread-{0}</guid><title>thread {0}</title>\
+                               <link>http://bbs/thread-{0}</link><author>user{1}</author>\
+                               <pubDate>Fri, 04 Nov 2016 23:30:00 +0800</pubDate>\
+                               <description>{2}</description></item>",
+                              tid, tid % 97, "the post goes on and on. ".repeat(20)));
+    }
+    xml.push_str("</channel></rss>");
+    RssContext::from_reader(xml.as_bytes()).unwrap()
+}
+
+// the diff and conversion as they were: the new items and those notified
+// copied out of the document, the items kept for the queue copied once more,
+// every field converted before 'fields' dropped those it doesn't keep, and
+// the text of the items copied into what is rendered.
+fn cloned(new: &RssContext, old: &RssContext, fields: &Option<Vec<String>>) -> (usize, usize) {
+    let diff: Vec<Item> = RssContext::diff(new, old).into_iter().cloned().collect();
+    let ser_items: Vec<SerItem> = convert_to_ser_items(&diff, &None, "use_guid_as_link").into_iter()
+        .map(|item| restrict_fields(item, fields).into_owned())
+        .collect();
+    let notified: Vec<Item> = diff.to_vec();
+    let kept: Vec<Item> = notified.to_vec();
+    (ser_items.len(), pending_items(&kept, "use_guid_as_link").len())
+}
+
+fn borrowed(new: &RssContext, old: &RssContext, fields: &Option<Vec<String>>) -> (usize, usize) {
+    let diff = RssContext::diff(new, old);
+    let ser_items = convert_to_ser_items(diff.iter().cloned(), fields, "use_guid_as_link");
+    (ser_items.len(), pending_items(diff.iter().cloned(), "use_guid_as_link").len())
+}
+
+// 'pass' tells how many items it converted and queued.
+fn measure<F>(name: &str, pass: F) where F: Fn() -> (usize, usize) {
+    // once to warm up, and to check the pass does what it should.
+    let (converted, pending) = pass();
+    assert_eq!(converted, ITEMS);
+    assert_eq!(pending, ITEMS);
+
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+    let start = Instant::now();
+    for _ in 0..PASSES {
+        pass();
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst) - allocations;
+
+    println!("{:<32} {:>10} allocations {:>10.2} ms per pass", name, allocations / PASSES as usize,
+             millis(elapsed) / PASSES as f64);
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1000000.0
+}
+
+fn main() {
+    let new = feed(ITEMS);
+    let old = feed(0);
+    let all = None;
+    let titles = Some(vec!["title".to_string(), "link".to_string()]);
+
+    println!("{} new items, {} passes", ITEMS, PASSES);
+    measure("cloned, all fields", || cloned(&new, &old, &all));
+    measure("borrowed, all fields", || borrowed(&new, &old, &all));
+    measure("cloned, title and link", || cloned(&new, &old, &titles));
+    measure("borrowed, title and link", || borrowed(&new, &old, &titles));
+}
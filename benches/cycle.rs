@@ -0,0 +1,137 @@
+// how long a pass over 20 feeds and 4 notifiers takes against a local
+// server that answers every feed slowly, with 'max_concurrent_fetches' and
+// 'max_concurrent_deliveries' at 1, 4, 8 and 20. Run with 'cargo bench'; it
+// prints the wall time per pass of each.
+
+extern crate bbsmon;
+extern crate tokio;
+
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use bbsmon::Config;
+use bbsmon::Notifier;
+use bbsmon::Pipeline;
+use bbsmon::render::SerItem;
+use bbsmon::runtime::BoxFuture;
+
+const FEEDS: usize = 20;
+const NOTIFIERS: usize = 4;
+const PASSES: usize = 3;
+// how long the server takes for a feed, and a notifier for a post.
+const FEED_MILLIS: u64 = 200;
+const DELIVERY_MILLIS: u64 = 200;
+
+// a sink that takes its time and does nothing.
+struct Slow {
+    name: String,
+}
+
+impl Notifier for Slow {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify<'a>(&'a self, _feed: &'a str, _items: &'a [SerItem]) -> BoxFuture<'a, ()> {
+        Box::pin(async {
+            tokio::time::sleep(Duration::from_millis(DELIVERY_MILLIS)).await;
+            Ok(())
+        })
+    }
+}
+
+// answer every request after FEED_MILLIS with a feed of 'pass' + 1 items, so
+// every pass after the first has a new item in every feed.
+fn serve(pass: Arc<AtomicUsize>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let pass = pass.load(Ordering::SeqCst);
+            thread::spawn(move || {
+                let mut head = Vec::new();
+                let mut byte = [0; 1];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    head.push(byte[0]);
+                }
+                thread::sleep(Duration::from_millis(FEED_MILLIS));
+
+                let items: String = (0..pass + 1)
+                    .map(|i| format!("<item><guid>{0}</guid><title>post {0}</title></item>", i))
+                    .collect();
+                let body = format!("<rss version=\"2.0\"><channel><title>bbs</title><link>http://bbs</link>\
+                                    <description>board</description>{}</channel></rss>", items);
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                       body.len(), body);
+                let _ = stream.write_all(response.as_bytes());
+            });
+        }
+    });
+
+    url
+}
+
+// a pipeline over FEEDS feeds of 'url' and NOTIFIERS slow notifiers. the
+// only subscription matches no title, so no mail is sent.
+fn pipeline(name: &str, url: &str, concurrency: usize) -> Pipeline {
+    let dir = ::std::env::temp_dir().join(format!("bbsmon-bench-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let feeds: Vec<String> = (0..FEEDS)
+        .map(|i| format!("{{\"name\": \"bbs{0}\", \"remote_rss\": \"{1}/feed-{0}.xml\"}}", i, url))
+        .collect();
+    let config_file = dir.join("bbsmon.json");
+    fs::File::create(&config_file).unwrap().write_all(format!(
+        "{{\"state_dir\": \"{}\", \"feeds\": [{}],
+           \"subject\": \"s\", \"from\": \"f@bbs\", \"to\": \"t@bbs\", \"password\": \"p\",
+           \"server\": \"localhost\", \"subscriptions\": [{{\"to\": \"t@bbs\", \"include\": [\"no such title\"]}}],
+           \"max_concurrent_fetches\": {2}, \"max_concurrent_deliveries\": {2}}}",
+        dir.join("state").display(), feeds.join(", "), concurrency).as_bytes()).unwrap();
+
+    let mut pipeline = Pipeline::new(Config::load(config_file.to_str().unwrap()).unwrap());
+    for i in 0..NOTIFIERS {
+        pipeline.add_notifier(Box::new(Slow { name: format!("slow{}", i) }));
+    }
+    pipeline
+}
+
+fn measure(name: &str, url: &str, pass: &Arc<AtomicUsize>, concurrency: usize) {
+    let pipeline = pipeline(name, url, concurrency);
+
+    // the first pass seeds the snapshots and notifies nothing.
+    pass.store(0, Ordering::SeqCst);
+    pipeline.run_once().unwrap();
+
+    let start = Instant::now();
+    for i in 0..PASSES {
+        pass.store(i + 1, Ordering::SeqCst);
+        pipeline.run_once().unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    let millis = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1000000;
+    println!("{:<28} {:>8} ms per pass", name, millis / PASSES as u64);
+}
+
+fn main() {
+    let pass = Arc::new(AtomicUsize::new(0));
+    let url = serve(pass.clone());
+
+    println!("{} feeds answered in {} ms, {} notifiers taking {} ms, {} passes",
+             FEEDS, FEED_MILLIS, NOTIFIERS, DELIVERY_MILLIS, PASSES);
+    measure("one at a time", &url, &pass, 1);
+    measure("4 at a time", &url, &pass, 4);
+    measure("8 at a time", &url, &pass, 8);
+    measure("20 at a time", &url, &pass, 20);
+}
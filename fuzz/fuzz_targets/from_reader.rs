@@ -0,0 +1,11 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate bbsmon;
+
+use bbsmon::feed::RssContext;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(a) = RssContext::from_reader(data) {
+        assert!(RssContext::diff(&a, &a).is_empty());
+    }
+});
@@ -0,0 +1,188 @@
+//! Snapshots of notified items on the Wayback Machine, for feeds with
+//! 'archive_links'. The link of a snapshot is noted in the item itself
+//! until it is converted, see 'archive_link'.
+
+use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_LOCATION;
+use rss::Item;
+
+use crate::error::*;
+use crate::feed::item_note;
+use crate::feed::set_item_note;
+use crate::log;
+use crate::log::Record;
+
+/// Where a link is sent to be archived, the link appended as it is.
+pub const SAVE_ENDPOINT: &str = "https://web.archive.org/save/";
+
+/// Least time between two saves, across all feeds; the endpoint turns
+/// away clients that save faster.
+pub const SAVE_INTERVAL_MILLIS: usize = 5000;
+
+/// Longest a run of one feed waits for its saves before notifying
+/// without the ones still missing.
+pub const ARCHIVE_BUDGET_SECS: u64 = 15;
+
+const ARCHIVE_HOST: &str = "https://web.archive.org";
+
+// the note 'add_archive_links' keeps the snapshot link in.
+const NOTE: &str = "archive";
+
+// when the next save may start, in milliseconds since the epoch.
+static NEXT_SAVE: AtomicUsize = AtomicUsize::new(0);
+
+fn now_millis() -> usize {
+    let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    since.as_secs() as usize * 1000 + since.subsec_nanos() as usize / 1000000
+}
+
+// how long to wait before the save the caller is about to make.
+fn take_slot() -> Duration {
+    loop {
+        let next = NEXT_SAVE.load(Ordering::SeqCst);
+        let now = now_millis();
+        let start = cmp::max(next, now);
+        if NEXT_SAVE.compare_exchange(next, start + SAVE_INTERVAL_MILLIS, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            return Duration::from_millis((start - now) as u64);
+        }
+    }
+}
+
+/// The snapshot link in an answer of the save endpoint: its
+/// Content-Location path, else the URL it redirected to.
+pub fn snapshot_url(content_location: Option<&str>, final_url: &str) -> Option<String> {
+    if let Some(path) = content_location {
+        if path.starts_with("/web/") {
+            return Some(format!("{}{}", ARCHIVE_HOST, path));
+        }
+    }
+
+    if final_url.starts_with(&format!("{}/web/", ARCHIVE_HOST)) {
+        return Some(final_url.to_string());
+    }
+    None
+}
+
+/// Have the Wayback Machine archive 'link', and the link of the snapshot.
+pub fn save(client: &Client, link: &str) -> Result<String> {
+    let url = format!("{}{}", SAVE_ENDPOINT, link);
+    let resp = client.get(&url).send().chain_err(|| format!("can't archive {}", link))?;
+    if !resp.status().is_success() {
+        bail!(ErrorKind::HttpStatus(url, resp.status().as_u16()));
+    }
+
+    let location = resp.headers().get(CONTENT_LOCATION).map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned());
+    match snapshot_url(location.as_deref(), resp.url().as_str()) {
+        Some(snapshot) => Ok(snapshot),
+        None => bail!("no snapshot of {} in the answer", link),
+    }
+}
+
+/// The snapshot link 'add_archive_links' put in 'item'.
+pub fn archive_link(item: &Item) -> Option<String> {
+    item_note(item, NOTE)
+}
+
+fn set_archive_link(item: &mut Item, snapshot: String) {
+    set_item_note(item, NOTE, snapshot);
+}
+
+/// Archive the link of each of 'items' of 'feed', and keep the snapshot
+/// links in the items. The saves are made one after another in the
+/// background, 'SAVE_INTERVAL_MILLIS' apart; those not done within
+/// 'budget' are given up and their items go without. Failures are only
+/// logged.
+pub fn add_archive_links(feed: &str, items: &mut [Item], budget: Duration) {
+    let links: Vec<(usize, String)> = items.iter().enumerate()
+        .filter_map(|(i, item)| item.link.as_ref().map(|l| (i, l.trim().to_string())))
+        .filter(|(_, link)| link.starts_with("http://") || link.starts_with("https://"))
+        .collect();
+    if links.is_empty() {
+        return;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let given_up = Arc::new(AtomicBool::new(false));
+    let stop = given_up.clone();
+    let name = feed.to_string();
+    let job = log::job();
+    thread::spawn(move || {
+        log::set_job(job);
+        let client = match Client::builder().build() {
+            Ok(client) => client,
+            Err(e) => {
+                Record::warn("can't archive items").field("feed", &name).field("error", e.to_string())
+                    .log(format!("{}: warning: can't archive items: {}", name, e));
+                return;
+            }
+        };
+
+        for (i, link) in links {
+            let wait = take_slot();
+            thread::sleep(wait);
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+
+            match save(&client, &link) {
+                Ok(snapshot) => {
+                    if tx.send((i, snapshot)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    Record::warn("can't archive item").field("feed", &name).field("link", &link)
+                        .field("error", error_chain_string(&e))
+                        .log(format!("{}: warning: {}", name, error_chain_string(&e)));
+                }
+            }
+        }
+    });
+
+    let deadline = Instant::now() + budget;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match rx.recv_timeout(deadline - now) {
+            Ok((i, snapshot)) => set_archive_link(&mut items[i], snapshot),
+            Err(_) => break,
+        }
+    }
+    given_up.store(true, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_come_from_the_location_or_the_redirect() {
+        assert_eq!(snapshot_url(Some("/web/20161016120000/http://bbs.example/t/1"), SAVE_ENDPOINT),
+                   Some("https://web.archive.org/web/20161016120000/http://bbs.example/t/1".to_string()));
+        assert_eq!(snapshot_url(None, "https://web.archive.org/web/20161016120000/http://bbs.example/t/1"),
+                   Some("https://web.archive.org/web/20161016120000/http://bbs.example/t/1".to_string()));
+        assert_eq!(snapshot_url(Some("/save/error"), "https://web.archive.org/save/http://bbs.example/t/1"), None);
+    }
+
+    #[test]
+    fn archive_links_ride_in_the_item() {
+        let mut item = Item::default();
+        assert_eq!(archive_link(&item), None);
+        set_archive_link(&mut item, "https://web.archive.org/web/1/http://bbs.example/".to_string());
+        assert_eq!(archive_link(&item), Some("https://web.archive.org/web/1/http://bbs.example/".to_string()));
+    }
+}
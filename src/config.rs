@@ -0,0 +1,1906 @@
+//! The `bbsmon.json` configuration.
+
+use std::io::Read;
+use std::fs;
+use std::fs::File;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Local;
+use chrono::NaiveDateTime;
+use chrono::NaiveTime;
+use chrono::TimeZone;
+use chrono::Datelike;
+
+use std::env;
+
+use serde_json;
+use serde_json::Value;
+
+use regex::Regex;
+
+use crate::error::*;
+use crate::render::SER_ITEM_FIELDS;
+use crate::render::PUB_DATE_FORMAT;
+use crate::render::LOCALES;
+use crate::render::locale_language;
+use crate::retry::RetryPolicy;
+use crate::feed::MAX_FEED_BYTES;
+use crate::log::LOG_FORMATS;
+use crate::log::Record;
+use crate::scrape;
+use crate::source::SOURCE_TYPES;
+use crate::source::Timeouts;
+use crate::source::source_type;
+use crate::state::StatePaths;
+
+/// A watched feed. 'local_rss' and 'validators' override the default
+/// locations under 'state_dir'.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FeedConfig {
+    /// Free text name, used in mails, logs and state file names.
+    pub name: String,
+    /// Where the feed is fetched from: a URL, a file, "-" for stdin, or a
+    /// shell command, read as 'source_type' says.
+    pub remote_rss: String,
+    /// "http", "file", "command" or "scrape", see 'SOURCE_TYPES'. Inferred
+    /// from 'remote_rss' when absent, never "scrape".
+    #[serde(rename = "type")]
+    pub source_type: Option<String>,
+    /// Override of the snapshot location.
+    pub local_rss: Option<String>,
+    /// Override of the HTTP validators location.
+    pub validators: Option<String>,
+    /// Ask with a HEAD request whether the feed changed before fetching it,
+    /// for servers that ignore conditional GETs.
+    #[serde(default)]
+    pub precheck_head: bool,
+    /// Also read the pages an http feed links to with an atom:link
+    /// rel="next", for boards that page their feed; see 'max_pages'.
+    #[serde(default)]
+    pub follow_next: bool,
+    /// Most pages read with 'follow_next', the first one included.
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    /// Form to log in with before fetching an http feed that needs a
+    /// session cookie.
+    pub login: Option<LoginConfig>,
+    /// How to pick items out of the page of a "scrape" feed.
+    pub scrape: Option<ScrapeConfig>,
+
+    /// Folder the feed is listed under in OPML exports.
+    pub group: Option<String>,
+    /// Site the feed is a board of. Once any feed has one, mails spanning
+    /// several feeds are sectioned by site and board, see 'Section'.
+    pub site: Option<String>,
+    /// Link of 'site' in section headers, the scheme and host of
+    /// 'remote_rss' when absent.
+    pub site_url: Option<String>,
+    /// Show the items of one thread, like the replies of a board posting
+    /// one item per reply, as a single entry in notifications.
+    pub thread_key: Option<ThreadKey>,
+    /// A regular expression whose capture group is the reply count of an
+    /// item, tried on its title, then its description, when the item has
+    /// no slash:comments; see 'hot_threshold'.
+    pub reply_count_regex: Option<String>,
+    /// 'reply_count_regex' compiled when the config is read.
+    #[serde(skip_deserializing)]
+    pub reply_count_compiled: Option<Regex>,
+
+    /// Override of the global 'batch_threshold' for this feed.
+    pub batch_threshold: Option<usize>,
+    /// Override of the global 'max_hold_secs' for this feed.
+    pub max_hold_secs: Option<i64>,
+
+    /// Only send a scheduled digest of this feed instead of every change.
+    pub digest: Option<DigestSchedule>,
+    /// Release changes one item at a time, at most one every this many
+    /// seconds, oldest first.
+    pub drip_interval_secs: Option<i64>,
+
+    /// Override of the global 'max_notifications_per_hour' for this feed.
+    pub max_notifications_per_hour: Option<u32>,
+    /// Send at most one mail of this feed every this many seconds, however
+    /// often it is polled. Changes found sooner wait in its pending queue
+    /// and go out with the held back items once the time is over. Batched,
+    /// digest and drip feeds keep their own pace.
+    pub send_cooldown_secs: Option<i64>,
+
+    /// Forget items of this feed in the seen store once they were last
+    /// seen this many days ago, for boards that reuse links. The global
+    /// 'state_retention_days' when absent.
+    pub seen_retention_days: Option<i64>,
+    /// Only look at this many of the newest items of the feed; the older
+    /// ones are neither diffed, notified nor marked seen. All of them when
+    /// absent.
+    pub max_feed_items: Option<usize>,
+
+    /// Shell command each new or updated item is piped through before it
+    /// is notified, e.g. a summarizer; see 'transform::transform_items'.
+    pub transform_command: Option<String>,
+
+    /// Have the Wayback Machine archive the link of each new item and
+    /// show the snapshot next to it, see 'archive::add_archive_links'.
+    #[serde(default)]
+    pub archive_links: bool,
+}
+
+impl FeedConfig {
+    /// 'site', or 'OTHER_SITE' for feeds without one.
+    pub fn site(&self) -> &str {
+        self.site.as_ref().map_or(OTHER_SITE, |s| s.as_str())
+    }
+
+    /// 'site_url', else "scheme://host" of an http 'remote_rss', else empty.
+    pub fn site_url(&self) -> String {
+        if let Some(ref url) = self.site_url {
+            return url.clone();
+        }
+        if source_type(self) != "http" {
+            return String::new();
+        }
+
+        let remote = &self.remote_rss;
+        let host_at = remote.find("://").map_or(0, |i| i + 3);
+        match remote[host_at..].find('/') {
+            Some(i) => remote[..host_at + i].to_string(),
+            None => remote.clone(),
+        }
+    }
+}
+
+/// How to log in to a board whose feed needs a session, see 'login::Login'.
+/// Passwords in 'form' are best taken from the environment, "${BBS_PASSWORD}".
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoginConfig {
+    /// Where the login form is sent.
+    pub url: String,
+    /// "POST" to send 'form' as the body, "GET" to send it in the query.
+    #[serde(default = "default_login_method")]
+    pub method: String,
+    /// Fields of the login form.
+    #[serde(default)]
+    pub form: BTreeMap<String, String>,
+    /// Names of the cookies sent along with the feed request, every cookie
+    /// the login set when empty.
+    #[serde(default)]
+    pub cookies: Vec<String>,
+    /// Text in a response to the login telling it failed, even though it
+    /// answered with a 2xx status.
+    pub failure_marker: Option<String>,
+    /// Text in a feed response telling it is the login page. Without it,
+    /// anything but an RSS 2.0 document counts as the login page.
+    pub login_page_marker: Option<String>,
+    /// Keep the session in the state directory and reuse it for this many
+    /// seconds. Without it, every run logs in once.
+    pub ttl_secs: Option<i64>,
+}
+
+fn default_login_method() -> String {
+    "POST".to_string()
+}
+
+impl LoginConfig {
+    /// Fails on an unknown method or TTL below one second.
+    pub fn validate(&self) -> Result<()> {
+        if self.method != "POST" && self.method != "GET" {
+            bail!("bad login method '{}', expected POST or GET", self.method);
+        }
+
+        if self.ttl_secs.is_some_and(|ttl| ttl <= 0) {
+            bail!("login ttl_secs must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// CSS selectors picking the items out of a board page without a feed,
+/// see 'scrape::ScrapeSource'. The sub-selectors apply within an item.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScrapeConfig {
+    /// Elements that are an item each.
+    pub item: String,
+    /// Element holding the title.
+    pub title: String,
+    /// Element whose href, or text without one, is the link; resolved
+    /// against the page URL.
+    #[serde(default = "default_scrape_link")]
+    pub link: String,
+    /// Element holding the date.
+    pub date: Option<String>,
+    /// chrono format of the date, e.g. "%Y-%m-%d %H:%M", in local time.
+    /// Dates are kept as they are without it or when they don't match.
+    pub date_format: Option<String>,
+    /// Element holding the author.
+    pub author: Option<String>,
+    /// Element holding the identity of an item, its link when absent.
+    pub guid: Option<String>,
+}
+
+fn default_scrape_link() -> String {
+    "a".to_string()
+}
+
+impl ScrapeConfig {
+    /// Fails on a selector that doesn't parse.
+    pub fn validate(&self) -> Result<()> {
+        let mut selectors = vec![&self.item, &self.title, &self.link];
+        selectors.extend(self.date.iter().chain(self.author.iter()).chain(self.guid.iter()));
+
+        for selector in selectors {
+            scrape::selector(selector)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// When a digest is due: every day at 'at' ("HH:MM", local time), or every
+/// week on 'day' ("Mon", "Tue", ...) at 'at'.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DigestSchedule {
+    /// Time of day, "HH:MM".
+    pub at: String,
+    /// "daily" or "weekly".
+    #[serde(default = "default_digest_period")]
+    pub period: String,
+    /// Day of a weekly digest, one of 'WEEKDAYS'.
+    #[serde(default = "default_digest_day")]
+    pub day: String,
+}
+
+fn default_digest_period() -> String {
+    "daily".to_string()
+}
+
+fn default_digest_day() -> String {
+    "Mon".to_string()
+}
+
+impl DigestSchedule {
+    /// Fails on a malformed time, period or day.
+    pub fn validate(&self) -> Result<()> {
+        NaiveTime::parse_from_str(&self.at, "%H:%M")
+            .chain_err(|| format!("bad digest time '{}', expected HH:MM", self.at))?;
+
+        if self.period != "daily" && self.period != "weekly" {
+            bail!("bad digest period '{}', expected daily or weekly", self.period);
+        }
+
+        if !WEEKDAYS.contains(&self.day.as_str()) {
+            bail!("bad digest day '{}', expected one of {:?}", self.day, WEEKDAYS);
+        }
+
+        Ok(())
+    }
+
+    /// The latest scheduled time not after 'now'. On a day the clocks skip
+    /// 'at' it is the end of the skipped hour.
+    pub fn last_due<Tz: TimeZone>(&self, now: DateTime<Tz>) -> DateTime<Tz> {
+        // checked by 'DigestSchedule::validate' when the config was loaded.
+        let at = NaiveTime::parse_from_str(&self.at, "%H:%M").unwrap();
+
+        let mut date = now.date_naive();
+        if now.time() < at {
+            date = date.pred_opt().unwrap();
+        }
+
+        if self.period == "weekly" {
+            while WEEKDAYS[date.weekday().num_days_from_monday() as usize] != self.day {
+                date = date.pred_opt().unwrap();
+            }
+        }
+
+        first_instant(&now.timezone(), date.and_time(at))
+    }
+}
+
+// the first instant at or after the wall clock time 'local' in 'tz': the
+// earlier of the two when the clocks went back over it, the end of the gap
+// when they sprang forward over it.
+fn first_instant<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> DateTime<Tz> {
+    let mut local = local;
+    loop {
+        if let Some(instant) = tz.from_local_datetime(&local).earliest() {
+            return instant;
+        }
+        local += chrono::Duration::minutes(1);
+    }
+}
+
+/// Everything read from `bbsmon.json`, see `Config::load`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// Single feed form, kept working as a feed named "default".
+    pub remote_rss: Option<String>,
+
+    /// The watched feeds.
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+
+    /// How many feeds are fetched at once.
+    #[serde(default = "default_max_concurrent_fetches")]
+    pub max_concurrent_fetches: usize,
+
+    /// How fetches, mails and webhook posts are retried, not at all by default.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Longest feed document read, larger ones fail the feed. At most
+    /// 'MAX_FEED_BYTES'.
+    #[serde(default = "default_max_feed_bytes")]
+    pub max_feed_bytes: u64,
+    /// Seconds a fetch may spend connecting, and reading the response, when
+    /// 'connect_timeout_secs' or 'read_timeout_secs' don't say otherwise.
+    pub timeout_secs: Option<u64>,
+    /// Seconds a fetch may spend connecting to the server.
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds a fetch may wait for the answer, then for each read of the
+    /// response body; a command feed may take this long to print its feed.
+    pub read_timeout_secs: Option<u64>,
+
+    /// How many notifiers are told about a run at once.
+    #[serde(default = "default_max_concurrent_deliveries")]
+    pub max_concurrent_deliveries: usize,
+    /// How many 'transform_command's run at once.
+    #[serde(default = "default_max_concurrent_transforms")]
+    pub max_concurrent_transforms: usize,
+    /// Seconds a 'transform_command' has to answer for one item.
+    #[serde(default = "default_transform_timeout_secs")]
+    pub transform_timeout_secs: u64,
+
+    /// Directory every state artifact lives in by default.
+    #[serde(default = "default_state_dir")]
+    pub state_dir: String,
+
+    /// Snapshot override of the "default" feed, relative to the working directory.
+    pub local_rss: Option<String>,
+    /// Validators override of the "default" feed.
+    pub validators: Option<String>,
+    /// Override of the seen store location.
+    pub seen_db: Option<String>,
+    /// Forget items of the seen store last seen this many days ago and no
+    /// longer in their feed, for feeds without a 'seen_retention_days'.
+    /// Kept forever when absent.
+    pub state_retention_days: Option<i64>,
+    /// Override of the spool directory.
+    pub spool_dir: Option<String>,
+    /// Override of the status file.
+    pub status_file: Option<String>,
+    /// Override of the lock file, held while a run, the daemon,
+    /// 'retry-failed', 'prune-seen' or 'migrate-state' works on the state;
+    /// see 'StatePaths::lock'.
+    pub lock_file: Option<String>,
+
+    /// Number of rotated snapshots to keep next to the current one.
+    #[serde(default)]
+    pub snapshot_keep: usize,
+    /// Gzip rotated snapshots.
+    #[serde(default)]
+    pub snapshot_compress: bool,
+    /// Gzip the current snapshots too, stored as '<name>.xml.gz'.
+    #[serde(default)]
+    pub compress_state: bool,
+    /// Gzip everything that grows: current and rotated snapshots, as with
+    /// 'compress_state' and 'snapshot_compress', and the 'history' archive,
+    /// which keeps its configured name.
+    #[serde(default)]
+    pub compress_snapshots: bool,
+
+    /// HMAC key for the '.sig' sidecar of snapshots, plain SHA-256 without it.
+    pub state_key: Option<String>,
+
+    /// Subject of notification mails.
+    pub subject: String,
+    /// Add how many new items each feed has to the subject of mails that
+    /// gather several feeds, see 'counts_subject'.
+    #[serde(default)]
+    pub subject_counts: bool,
+    /// Order of the feeds in mails that gather several, one of
+    /// 'DIGEST_ORDERS': as configured, by name, or most new items first.
+    #[serde(default = "default_digest_order")]
+    pub digest_order: String,
+    /// HTML put between the feeds of such mails, e.g. "<hr>".
+    pub digest_separator: Option<String>,
+    /// Sender address, also the SMTP login.
+    pub from: String,
+    /// Recipient address.
+    pub to: String,
+    /// How mails name 'to', the "recipient" message of 'locale' when absent.
+    pub to_name: Option<String>,
+    /// Recipients of only the items whose title matches their keywords.
+    /// Once there is one, mails of items go to them instead of 'to'; mails
+    /// about channels and error reports still go to 'to'.
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
+    /// SMTP password.
+    pub password: String,
+    /// SMTP server.
+    pub server: String,
+    /// Port of 'server'.
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    /// How the SMTP connection is secured, one of 'SMTP_MODES': "plain"
+    /// never encrypts, "starttls" insists on STARTTLS, "smtps" speaks TLS
+    /// from the start, as on port 465. When absent STARTTLS is used if the
+    /// server offers it.
+    pub smtp_mode: Option<String>,
+    /// Name sent with HELO/EHLO, for servers that want a proper FQDN;
+    /// lettre's default when absent.
+    pub helo_name: Option<String>,
+    /// Look up the MX records of the domain of each recipient before mailing
+    /// it: "warn" only prints when it has none, "abort" doesn't send. Off
+    /// when absent.
+    pub verify_recipients: Option<String>,
+
+    /// SerItem fields that leave the machine, all of them when absent.
+    pub include_fields: Option<Vec<String>>,
+
+    /// JSON Lines archive of every notified item.
+    pub history: Option<String>,
+    /// When to mail a report of the activity the 'history' recorded over
+    /// the day or week before, see 'report::send_report'.
+    pub report_schedule: Option<DigestSchedule>,
+    /// Don't mail a report of a period without any notified item.
+    #[serde(default)]
+    pub skip_empty_report: bool,
+    /// When to mail a short note that bbsmon is still running, with how
+    /// each feed last fared; see 'report::send_heartbeat'.
+    pub heartbeat: Option<DigestSchedule>,
+    /// Send the heartbeat even when items were notified since the last
+    /// one was due, which it is skipped for otherwise.
+    #[serde(default)]
+    pub heartbeat_even_if_notified: bool,
+
+    /// Show a thread again under "Heating up" once its reply count grew by
+    /// this many since it was last shown, or first seen. Off when absent.
+    pub hot_threshold: Option<u64>,
+
+    /// RSS 2.0 feed of the latest items of all feeds, rewritten every run.
+    pub merged_feed: Option<MergedFeed>,
+
+    /// Where to report failed feeds, at most once per 'error_email_interval_secs'.
+    pub error_email: Option<String>,
+    /// Minimum pause between two error reports.
+    #[serde(default = "default_error_email_interval_secs")]
+    pub error_email_interval_secs: i64,
+    /// Alert once a feed, a notifier or another step failed this many
+    /// runs in a row, and again when it works; see 'notify::send_alerts'.
+    /// Off when absent.
+    pub alert_after_failures: Option<u32>,
+    /// Where alerts are mailed, 'error_email' or else 'to' when absent.
+    pub alert_to: Option<String>,
+
+    /// Hold changes back until this many items piled up, or the oldest of
+    /// them waited 'max_hold_secs'.
+    #[serde(default)]
+    pub batch_threshold: usize,
+    /// See 'batch_threshold'.
+    pub max_hold_secs: Option<i64>,
+
+    /// Send a "monitoring started" mail when a feed is seeded.
+    #[serde(default)]
+    pub notify_first_run: bool,
+
+    /// Keep the stored snapshot when a feed suddenly comes back without items.
+    #[serde(default)]
+    pub skip_if_empty: bool,
+
+    /// Mail right away when a feed changes its title, link or description.
+    #[serde(default)]
+    pub watch_channel_meta: bool,
+
+    /// How runs log, one of 'LOG_FORMATS': "text" lines on stdout, or one
+    /// "json" object per line on stderr.
+    #[serde(default = "default_log_format")]
+    pub log_format: String,
+
+    /// Directory the mail templates are read from, see 'templates'.
+    #[serde(default = "default_templates_dir")]
+    pub templates_dir: String,
+
+    /// Longest a run, or a cycle of 'bbsmon daemon', may take. Feeds not
+    /// handled by then are skipped and keep their state for the next run.
+    pub max_run_seconds: Option<u64>,
+
+    /// Pause between runs of 'bbsmon daemon'.
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// Each feed is fetched up to this many seconds after its run starts,
+    /// by an offset picked at random per feed when bbsmon starts, so that
+    /// copies of one config don't all hit a board on the same second.
+    /// Applies to the runs of 'bbsmon daemon' and to runs started from
+    /// cron alike. 0, the default, fetches right away.
+    #[serde(default)]
+    pub jitter_seconds: u64,
+    /// Whether 'bbsmon daemon' follows what a feed says about polling it:
+    /// a <ttl> longer than 'interval_secs' stretches the interval of that
+    /// feed, and no fetch happens in its <skipHours> and <skipDays>, see
+    /// 'hints'. Single runs ignore them. Defaults to true.
+    #[serde(default = "default_respect_ttl")]
+    pub respect_ttl: bool,
+    /// Address 'bbsmon daemon' serves its triggers and health check on,
+    /// like "127.0.0.1:8787"; see 'server'. Nothing is served when absent.
+    pub listen: Option<String>,
+    /// What requests to 'listen' must carry in their X-Bbsmon-Token
+    /// header. Required with 'listen'.
+    pub trigger_token: Option<String>,
+
+    /// Notifications are held back during this window and sent as one
+    /// digest after it; items matching 'priority_keywords' may still pass.
+    pub quiet_hours: Option<QuietHours>,
+    /// Send priority items during the quiet hours.
+    #[serde(default)]
+    pub quiet_hours_override_priority: bool,
+    /// Days notifications go out on ("Mon", "Tue", ...), every day when
+    /// empty. Changes found on other days are held like during the quiet
+    /// hours, priority items included, until the first active day outside
+    /// the quiet hours.
+    #[serde(default)]
+    pub active_days: Vec<String>,
+    /// Titles containing one of these, ignoring case, are priority items.
+    #[serde(default)]
+    pub priority_keywords: Vec<String>,
+    /// Names the user posts under. Changed items whose author or
+    /// dc:creator is one of them, ignoring case, are not notified.
+    #[serde(default)]
+    pub self_authors: Vec<String>,
+
+    /// How notifications show publication dates, a strftime pattern.
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    /// Language of the month and weekday names in 'date_format' and of the
+    /// built-in strings of mails, one of 'LOCALES', optionally with a
+    /// region ("de_DE"). Built-in strings are only translated to "zh".
+    #[serde(default = "default_locale")]
+    pub locale: String,
+
+    /// Build notification mails from this line per item instead of the
+    /// templates, see 'format_item'.
+    pub simple_format: Option<String>,
+    /// What goes between the lines of 'simple_format'.
+    #[serde(default = "default_simple_format_joiner")]
+    pub simple_format_joiner: String,
+
+    /// Leave out the items a mail can't be rendered with instead of failing
+    /// the whole mail, see 'render_changes'.
+    #[serde(default)]
+    pub skip_unrenderable_items: bool,
+
+    /// Drop items repeating the guid, or link, of an earlier item of the same
+    /// document.
+    #[serde(default = "default_dedupe_items")]
+    pub dedupe_items: bool,
+
+    /// How items are told apart across fetches, one of 'DIFF_STRATEGIES';
+    /// see 'diff::diff_key' for what each is good for.
+    #[serde(default = "default_diff_strategy")]
+    pub diff_strategy: String,
+
+    /// What to do with items without a link: "drop", "keep" or "use_guid_as_link".
+    #[serde(default = "default_missing_link")]
+    pub missing_link: String,
+
+    /// Incoming webhook of a Rocket.Chat channel, posted to besides the mail.
+    pub rocketchat_webhook: Option<String>,
+    /// Only post how many items a feed has to webhooks, none of the items.
+    #[serde(default)]
+    pub webhook_summary_only: bool,
+    /// Cut descriptions posted to webhooks to this many characters, with
+    /// an ellipsis; mails keep them whole.
+    pub push_description_chars: Option<usize>,
+
+    /// Once a feed sent this many mails within an hour, hold its further
+    /// changes until the hour is over and send them together.
+    pub max_notifications_per_hour: Option<u32>,
+    /// Send priority items even when a feed is throttled.
+    #[serde(default)]
+    pub throttle_override_priority: bool,
+}
+
+impl Config {
+    /// Read and check the config in 'filename'. Environment variables in
+    /// its 'EXPANDED_SETTINGS' are expanded first, see 'expand_env', and the mail
+    /// settings it leaves out are taken from 'ENV_FALLBACKS'. The top level
+    /// 'remote_rss' becomes the first feed, named 'DEFAULT_FEED_NAME'. Every
+    /// failure is an 'ErrorKind::Config'.
+    pub fn load(filename: &str) -> Result<Config> {
+        read_value(filename).and_then(Config::from_value)
+            .chain_err(|| ErrorKind::Config(filename.to_string()))
+    }
+
+    /// The jobs of the config in 'filename', each read and checked like
+    /// 'load' does. A file with a top level 'jobs' array holds a complete
+    /// config per job, with a unique 'name' that is a plain directory name.
+    /// A job without a 'state_dir' keeps its state in the directory of its
+    /// name under the default one, and no two jobs may share one. Besides
+    /// 'jobs' only 'log_format' may be set at the top, for every job, as
+    /// they all log through the same process. Any other file is a single
+    /// job named "".
+    pub fn load_jobs(filename: &str) -> Result<Vec<Job>> {
+        read_value(filename).and_then(jobs_from_value)
+            .chain_err(|| ErrorKind::Config(filename.to_string()))
+    }
+
+    /// The jobs of the '.json' files in the directory 'dir', one for each,
+    /// named after the file. Every file is a complete config without
+    /// 'jobs', and the jobs are checked like those of 'load_jobs'.
+    pub fn load_job_dir(dir: &str) -> Result<Vec<Job>> {
+        job_files(dir).and_then(|files| {
+            let mut named = Vec::new();
+            for file in files {
+                let name = file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                let filename = file.display().to_string();
+                let value = read_value(&filename).chain_err(|| format!("in {}", filename))?;
+                if value.get("jobs").is_some() {
+                    bail!("{} has 'jobs', a file of a config directory is a single job", filename);
+                }
+                named.push((name, value));
+            }
+            named_jobs(named)
+        }).chain_err(|| ErrorKind::Config(dir.to_string()))
+    }
+
+    fn from_value(mut value: Value) -> Result<Config> {
+        expand_env_values(&mut value, &|name| env::var(name).ok())?;
+        apply_env_fallbacks(&mut value, |name| env::var(name).ok());
+        let mut config: Config = serde_json::from_value(value).chain_err(|| "can't parse it")?;
+
+        if let Some(ref remote) = config.remote_rss {
+            config.feeds.insert(0, FeedConfig {
+                name: DEFAULT_FEED_NAME.to_string(),
+                remote_rss: remote.clone(),
+                source_type: None,
+                local_rss: config.local_rss.clone(),
+                validators: config.validators.clone(),
+                precheck_head: false,
+                follow_next: false,
+                max_pages: default_max_pages(),
+                login: None,
+                scrape: None,
+                group: None,
+                site: None,
+                site_url: None,
+                thread_key: None,
+                reply_count_regex: None,
+                reply_count_compiled: None,
+                batch_threshold: None,
+                max_hold_secs: None,
+                digest: None,
+                drip_interval_secs: None,
+                max_notifications_per_hour: None,
+                send_cooldown_secs: None,
+                seen_retention_days: None,
+                max_feed_items: None,
+                transform_command: None,
+                archive_links: false,
+            });
+        }
+
+        if config.feeds.is_empty() {
+            bail!("no feed configured, set 'remote_rss' or 'feeds'");
+        }
+
+        if config.max_concurrent_fetches == 0 {
+            bail!("max_concurrent_fetches must be at least 1");
+        }
+
+        if config.max_concurrent_deliveries == 0 {
+            bail!("max_concurrent_deliveries must be at least 1");
+        }
+
+        if config.max_concurrent_transforms == 0 {
+            bail!("max_concurrent_transforms must be at least 1");
+        }
+
+        if config.transform_timeout_secs == 0 {
+            bail!("transform_timeout_secs must be at least 1");
+        }
+
+        config.retry.validate()?;
+
+        if config.max_feed_bytes == 0 || config.max_feed_bytes > MAX_FEED_BYTES {
+            bail!("max_feed_bytes must be between 1 and {}", MAX_FEED_BYTES);
+        }
+
+        if let Some(ref listen) = config.listen {
+            if listen.parse::<SocketAddr>().is_err() {
+                bail!("listen must be an address like 127.0.0.1:8787, not '{}'", listen);
+            }
+            if config.trigger_token.as_ref().is_none_or(|t| t.is_empty()) {
+                bail!("'listen' needs a trigger_token");
+            }
+        }
+
+        for &(name, secs) in &[("timeout_secs", config.timeout_secs),
+                               ("connect_timeout_secs", config.connect_timeout_secs),
+                               ("read_timeout_secs", config.read_timeout_secs)] {
+            if secs == Some(0) {
+                bail!("{} must be at least 1", name);
+            }
+        }
+
+        if config.jitter_seconds > 0 && config.jitter_seconds >= config.interval_secs {
+            bail!("jitter_seconds must be less than interval_secs");
+        }
+        if config.max_run_seconds.is_some_and(|max| config.jitter_seconds >= max) {
+            bail!("jitter_seconds must be less than max_run_seconds");
+        }
+
+        if config.state_retention_days.is_some_and(|days| days <= 0) {
+            bail!("state_retention_days must be at least 1");
+        }
+
+        if config.alert_after_failures == Some(0) {
+            bail!("alert_after_failures must be at least 1");
+        }
+
+        if config.push_description_chars == Some(0) {
+            bail!("push_description_chars must be at least 1");
+        }
+
+        if cfg!(not(feature = "sink-webhook")) && config.rocketchat_webhook.is_some() {
+            bail!("'rocketchat_webhook' is set but this bbsmon was built without webhooks, \
+                   recompile it with the 'sink-webhook' feature");
+        }
+
+        if config.compress_snapshots {
+            config.compress_state = true;
+            config.snapshot_compress = true;
+        }
+
+        if let Some(ref quiet) = config.quiet_hours {
+            quiet.validate()?;
+        }
+
+        for day in &config.active_days {
+            if !WEEKDAYS.contains(&day.as_str()) {
+                bail!("bad active_days day '{}', expected one of {:?}", day, WEEKDAYS);
+            }
+        }
+
+        if let Some(ref merged) = config.merged_feed {
+            merged.validate()?;
+        }
+
+        if let Some(ref schedule) = config.heartbeat {
+            schedule.validate().chain_err(|| "in heartbeat")?;
+        }
+
+        if let Some(ref schedule) = config.report_schedule {
+            schedule.validate().chain_err(|| "in report_schedule")?;
+            if config.history.is_none() {
+                bail!("report_schedule needs a 'history' file to report from");
+            }
+        }
+
+        // the regexes of the feeds are compiled once, here.
+        for feed in &mut config.feeds {
+            let name = feed.name.clone();
+            if let Some(ref mut thread_key) = feed.thread_key {
+                thread_key.validate().chain_err(|| format!("in feed '{}'", name))?;
+            }
+
+            feed.reply_count_compiled = match feed.reply_count_regex {
+                Some(ref re) => match Regex::new(re) {
+                    Ok(ref compiled) if compiled.captures_len() > 1 => Some(compiled.clone()),
+                    Ok(_) => bail!("reply_count_regex '{}' of feed '{}' has no capture group", re, name),
+                    Err(e) => bail!("bad reply_count_regex '{}' of feed '{}': {}", re, name, e),
+                },
+                None => None,
+            };
+        }
+
+        for feed in &config.feeds {
+            if !SOURCE_TYPES.contains(&source_type(feed)) {
+                bail!("unknown type '{}' of feed '{}', expected one of {:?}",
+                      source_type(feed), feed.name, SOURCE_TYPES);
+            }
+
+            if let Some(ref digest) = feed.digest {
+                digest.validate().chain_err(|| format!("in feed '{}'", feed.name))?;
+            }
+
+            match (source_type(feed), feed.scrape.as_ref()) {
+                ("scrape", Some(scrape)) => scrape.validate().chain_err(|| format!("in feed '{}'", feed.name))?,
+                ("scrape", None) => bail!("feed '{}' is scraped but has no 'scrape' selectors", feed.name),
+                (_, Some(_)) => bail!("feed '{}' has 'scrape' selectors but its type isn't \"scrape\"", feed.name),
+                _ => {}
+            }
+
+            if let Some(ref login) = feed.login {
+                if source_type(feed) != "http" && source_type(feed) != "scrape" {
+                    bail!("feed '{}' has a login but isn't fetched over http", feed.name);
+                }
+                login.validate().chain_err(|| format!("in feed '{}'", feed.name))?;
+                // a scraped page never looks like a feed, so only a marker tells the login page.
+                if source_type(feed) == "scrape" && login.login_page_marker.is_none() {
+                    bail!("feed '{}' is scraped, its login needs a login_page_marker", feed.name);
+                }
+            }
+
+            if feed.follow_next && source_type(feed) != "http" {
+                bail!("feed '{}' follows next links but isn't fetched over http", feed.name);
+            }
+            if feed.max_pages == 0 {
+                bail!("max_pages of feed '{}' must be at least 1", feed.name);
+            }
+
+            if let Some(interval) = feed.drip_interval_secs {
+                if interval <= 0 {
+                    bail!("drip_interval_secs of feed '{}' must be at least 1", feed.name);
+                }
+                if feed.digest.is_some() {
+                    bail!("feed '{}' can't have both a digest and drip_interval_secs", feed.name);
+                }
+            }
+
+            if feed.send_cooldown_secs.is_some_and(|secs| secs <= 0) {
+                bail!("send_cooldown_secs of feed '{}' must be at least 1", feed.name);
+            }
+
+            if feed.seen_retention_days.is_some_and(|days| days <= 0) {
+                bail!("seen_retention_days of feed '{}' must be at least 1", feed.name);
+            }
+
+            if config.throttle_limit(feed) == Some(0) {
+                bail!("max_notifications_per_hour of feed '{}' must be at least 1", feed.name);
+            }
+        }
+
+        check_feed_collisions(&config)?;
+
+        if config.hot_threshold == Some(0) {
+            bail!("hot_threshold must be at least 1");
+        }
+
+        if !LOCALES.contains(&locale_language(&config.locale)) {
+            bail!("unsupported locale '{}', expected one of {:?}", config.locale, LOCALES);
+        }
+
+        if !DIGEST_ORDERS.contains(&config.digest_order.as_str()) {
+            bail!("unknown digest_order '{}', expected one of {:?}", config.digest_order, DIGEST_ORDERS);
+        }
+
+        if let Some(ref verify) = config.verify_recipients {
+            if !VERIFY_RECIPIENTS_POLICIES.contains(&verify.as_str()) {
+                bail!("unknown verify_recipients policy '{}', expected one of {:?}",
+                      verify, VERIFY_RECIPIENTS_POLICIES);
+            }
+        }
+
+        for subscription in &mut config.subscriptions {
+            subscription.validate()?;
+        }
+        let mut groups = HashSet::new();
+        for subscription in &config.subscriptions {
+            if let Some(ref group) = subscription.group {
+                if !groups.insert(group.as_str()) {
+                    bail!("more than one subscription is the group '{}'", group);
+                }
+            }
+        }
+
+        if let Some(ref mode) = config.smtp_mode {
+            if !SMTP_MODES.contains(&mode.as_str()) {
+                bail!("unknown smtp_mode '{}', expected one of {:?}", mode, SMTP_MODES);
+            }
+            if let Some(warning) = smtp_mode_mismatch(mode, config.smtp_port) {
+                Record::warn("smtp_mode and smtp_port don't match").field("smtp_mode", mode)
+                    .field("smtp_port", config.smtp_port).log(format!("warning: {}", warning));
+            }
+        }
+
+        if let Some(ref helo_name) = config.helo_name {
+            if helo_name.is_empty() || helo_name.contains(char::is_whitespace) {
+                bail!("helo_name '{}' must be a host name, without spaces", helo_name);
+            }
+        }
+
+        if !LOG_FORMATS.contains(&config.log_format.as_str()) {
+            bail!("unknown log_format '{}', expected one of {:?}", config.log_format, LOG_FORMATS);
+        }
+
+        if !DIFF_STRATEGIES.contains(&config.diff_strategy.as_str()) {
+            bail!("unknown diff_strategy '{}', expected one of {:?}", config.diff_strategy, DIFF_STRATEGIES);
+        }
+
+        if !MISSING_LINK_POLICIES.contains(&config.missing_link.as_str()) {
+            bail!("unknown missing_link policy '{}', expected one of {:?}",
+                  config.missing_link, MISSING_LINK_POLICIES);
+        }
+
+        if let Some(ref fields) = config.include_fields {
+            for field in fields {
+                if !SER_ITEM_FIELDS.contains(&field.as_str()) {
+                    bail!("unknown field '{}' in include_fields, expected one of {:?}",
+                          field, SER_ITEM_FIELDS);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Drop every feed not named (by name or url) in 'wanted'.
+    pub fn select_feeds(&mut self, wanted: Vec<&str>) -> Result<()> {
+        for w in &wanted {
+            if !self.feeds.iter().any(|f| f.name == *w || f.remote_rss == *w) {
+                bail!("no feed named '{}' in the config", w);
+            }
+        }
+
+        self.feeds.retain(|f| wanted.iter().any(|w| f.name == *w || f.remote_rss == *w));
+
+        Ok(())
+    }
+
+    /// Read the only feed left from stdin instead of its 'remote_rss', and
+    /// keep its snapshot at 'snapshot' when given. Select a feed first when
+    /// several are configured.
+    pub fn read_feed_from_stdin(&mut self, snapshot: Option<&str>) -> Result<()> {
+        if self.feeds.len() != 1 {
+            bail!("{} feeds configured, pick the one read from stdin with --feed", self.feeds.len());
+        }
+
+        let feed = &mut self.feeds[0];
+        feed.remote_rss = "-".to_string();
+        feed.source_type = Some("file".to_string());
+        feed.precheck_head = false;
+        if let Some(snapshot) = snapshot {
+            feed.local_rss = Some(snapshot.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Glob of the templates in 'templates_dir', as the renderers take it.
+    pub fn templates(&self) -> String {
+        format!("{}/**/*", self.templates_dir.trim_end_matches('/'))
+    }
+
+    /// The 'seen_retention_days' that applies to 'feed'; None keeps its
+    /// items in the seen store forever.
+    pub fn seen_retention_days(&self, feed: &FeedConfig) -> Option<i64> {
+        feed.seen_retention_days.or(self.state_retention_days)
+    }
+
+    /// The 'max_notifications_per_hour' that applies to 'feed'.
+    pub fn throttle_limit(&self, feed: &FeedConfig) -> Option<u32> {
+        feed.max_notifications_per_hour.or(self.max_notifications_per_hour)
+    }
+
+    /// How long fetches may take, 'timeout_secs' standing in for whichever
+    /// of 'connect_timeout_secs' and 'read_timeout_secs' isn't set.
+    pub fn timeouts(&self) -> Timeouts {
+        let secs = |specific: Option<u64>| specific.or(self.timeout_secs).map(Duration::from_secs);
+        Timeouts {
+            connect: secs(self.connect_timeout_secs),
+            read: secs(self.read_timeout_secs),
+        }
+    }
+}
+
+/// One pipeline of a config file with 'jobs', see 'Config::load_jobs'.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// Name of the job, tagging every log record of it; empty for a file
+    /// without 'jobs'.
+    pub name: String,
+    /// The complete config of the job.
+    pub config: Config,
+}
+
+fn read_value(filename: &str) -> Result<Value> {
+    let mut reader = File::open(filename).chain_err(|| "can't open it")?;
+
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+
+    serde_json::from_str(&content).chain_err(|| "can't parse it")
+}
+
+// the '.json' files in 'dir', sorted.
+fn job_files(dir: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).chain_err(|| "can't list it")? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        bail!("it has no .json file");
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn jobs_from_value(mut value: Value) -> Result<Vec<Job>> {
+    let jobs = match value.as_object_mut().and_then(|map| map.remove("jobs")) {
+        Some(Value::Array(jobs)) => jobs,
+        Some(_) => bail!("'jobs' must be an array of configs"),
+        None => return Ok(vec![Job { name: String::new(), config: Config::from_value(value)? }]),
+    };
+
+    let top = value.as_object().cloned().unwrap_or_default();
+    if let Some(key) = top.keys().find(|key| *key != "log_format") {
+        bail!("only 'log_format' may be set beside 'jobs', not '{}'; it belongs in the jobs", key);
+    }
+    if jobs.is_empty() {
+        bail!("'jobs' has no job");
+    }
+
+    let mut named = Vec::new();
+    for (i, mut job) in jobs.into_iter().enumerate() {
+        let name = match job.as_object_mut().and_then(|map| map.remove("name")) {
+            Some(Value::String(name)) => name,
+            _ => bail!("job {} has no 'name'", i + 1),
+        };
+        if let (Some(map), Some(format)) = (job.as_object_mut(), top.get("log_format")) {
+            map.insert("log_format".to_string(), format.clone());
+        }
+        named.push((name, job));
+    }
+
+    named_jobs(named)
+}
+
+// the jobs of the configs in 'named', each by its name. a job without a
+// 'state_dir' keeps its state in the directory of its name.
+fn named_jobs(named: Vec<(String, Value)>) -> Result<Vec<Job>> {
+    let mut loaded: Vec<Job> = Vec::new();
+    for (name, mut job) in named {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            bail!("job name '{}' must be letters, digits, '-' and '_'", name);
+        }
+        if loaded.iter().any(|other| other.name == name) {
+            bail!("job name '{}' is used twice", name);
+        }
+
+        if let Some(map) = job.as_object_mut() {
+            if !map.contains_key("state_dir") {
+                let state_dir = Path::new(&default_state_dir()).join(&name).display().to_string();
+                map.insert("state_dir".to_string(), Value::String(state_dir));
+            }
+        }
+
+        let config = Config::from_value(job).chain_err(|| format!("in job '{}'", name))?;
+        if let Some(other) = loaded.iter().find(|other| other.config.state_dir == config.state_dir) {
+            bail!("jobs '{}' and '{}' share the state_dir '{}'", other.name, name, config.state_dir);
+        }
+        let lock = StatePaths::resolve(&config).lock;
+        if let Some(other) = loaded.iter().find(|other| StatePaths::resolve(&other.config).lock == lock) {
+            bail!("jobs '{}' and '{}' share the lock file {}", other.name, name, lock.display());
+        }
+        if let Some(other) = loaded.iter().find(|other| other.config.log_format != config.log_format) {
+            bail!("jobs '{}' and '{}' log differently, they all log through one process", other.name, name);
+        }
+        loaded.push(Job { name, config });
+    }
+
+    Ok(loaded)
+}
+
+// fails on two feeds fetching one 'remote_rss' or keeping their state in
+// one file, which notifies twice and mixes up their snapshots.
+fn check_feed_collisions(config: &Config) -> Result<()> {
+    let paths = StatePaths::resolve(config);
+    let mut urls: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut files = BTreeMap::new();
+
+    // files are keyed by the index of their feed, two feeds may share a name.
+    for (i, feed) in config.feeds.iter().enumerate() {
+        if let Some(other) = urls.insert(feed.remote_rss.as_str(), feed.name.as_str()) {
+            bail!("feeds '{}' and '{}' both fetch {}", other, feed.name, feed.remote_rss);
+        }
+
+        let mut state_files = vec![paths.snapshot(feed), paths.validators(feed), paths.pending(feed),
+                                   paths.journal(feed)];
+        if feed.login.is_some() {
+            state_files.push(paths.session(feed));
+        }
+        for file in state_files {
+            if let Some(other) = files.insert(file.clone(), i) {
+                if other != i {
+                    bail!("feeds '{}' and '{}' both keep their state in {}, rename one or set its 'local_rss'",
+                          config.feeds[other].name, feed.name, file.display());
+                }
+                bail!("feed '{}' keeps two kinds of state in {}", feed.name, file.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 'HH:MM' to 'HH:MM' local time, optionally only on some days ("Mon",
+/// "Tue", ...). A window past midnight belongs to the day it starts on.
+#[derive(Deserialize, Debug, Clone)]
+pub struct QuietHours {
+    /// Start of the window, "HH:MM".
+    pub start: String,
+    /// End of the window, "HH:MM", excluded.
+    pub end: String,
+    /// Days the window starts on, every day when empty.
+    #[serde(default)]
+    pub days: Vec<String>,
+}
+
+/// Where the thread of an item is read from: the first capture group of
+/// 'regex' matched against the item's 'field', "link" or "title".
+#[derive(Deserialize, Debug, Clone)]
+pub struct ThreadKey {
+    /// A regular expression with a capture group, like tid=(\d+).
+    pub regex: String,
+    /// What it is matched against, one of 'THREAD_KEY_FIELDS'.
+    #[serde(default = "default_thread_key_field")]
+    pub field: String,
+    /// 'regex' compiled by 'validate'.
+    #[serde(skip_deserializing)]
+    pub compiled: Option<Regex>,
+}
+
+/// Accepted values of 'ThreadKey::field'.
+pub const THREAD_KEY_FIELDS: &[&str] = &["link", "title"];
+
+impl ThreadKey {
+    /// Fails on an unknown field or a regex without a capture group, else
+    /// compiles the regex 'thread' uses.
+    pub fn validate(&mut self) -> Result<()> {
+        if !THREAD_KEY_FIELDS.contains(&self.field.as_str()) {
+            bail!("unknown thread_key field '{}', expected one of {:?}", self.field, THREAD_KEY_FIELDS);
+        }
+
+        match Regex::new(&self.regex) {
+            Ok(ref re) if re.captures_len() > 1 => self.compiled = Some(re.clone()),
+            Ok(_) => bail!("thread_key regex '{}' has no capture group", self.regex),
+            Err(e) => bail!("bad thread_key regex '{}': {}", self.regex, e),
+        }
+
+        Ok(())
+    }
+
+    /// The thread 'text' belongs to, None when the regex doesn't match or
+    /// 'validate' didn't compile it yet.
+    pub fn thread(&self, text: &str) -> Option<String> {
+        let re = match self.compiled {
+            Some(ref re) => re,
+            None => return None,
+        };
+        re.captures(text).and_then(|caps| caps.get(1)).map(|id| id.as_str().to_string())
+    }
+}
+
+fn default_thread_key_field() -> String {
+    "link".to_string()
+}
+
+/// A recipient of the items whose title contains one of the 'include'
+/// keywords, ignoring case, or matches one of 'include_regex', and none of
+/// the excluding ones. A subscription including nothing gets every item
+/// that isn't excluded. With 'also_to' it is a group: every address of it
+/// gets the same mail.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Subscription {
+    /// Address of the recipient.
+    pub to: String,
+    /// Further addresses getting what 'to' gets.
+    #[serde(default)]
+    pub also_to: Vec<String>,
+    /// Name of the group, for the logs and the routing of a dry run.
+    pub group: Option<String>,
+    /// How mails name the recipient, see 'Config::to_name'.
+    pub name: Option<String>,
+    /// Keywords of the wanted titles.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Regular expressions of the wanted titles.
+    #[serde(default)]
+    pub include_regex: Vec<String>,
+    /// Keywords of the unwanted titles.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Regular expressions of the unwanted titles.
+    #[serde(default)]
+    pub exclude_regex: Vec<String>,
+    /// 'include_regex' compiled by 'validate'.
+    #[serde(skip_deserializing)]
+    pub include_compiled: Vec<Regex>,
+    /// 'exclude_regex' compiled by 'validate'.
+    #[serde(skip_deserializing)]
+    pub exclude_compiled: Vec<Regex>,
+}
+
+impl Subscription {
+    /// Fails without an address or on a regular expression that doesn't
+    /// parse, else compiles the regular expressions 'matches' uses.
+    pub fn validate(&mut self) -> Result<()> {
+        if self.to.is_empty() {
+            bail!("a subscription needs a 'to' address");
+        }
+        if self.also_to.iter().any(|to| to.is_empty()) {
+            bail!("empty address in 'also_to' of the subscription of {}", self.to);
+        }
+        if self.group.as_ref().is_some_and(|g| g.is_empty()) {
+            bail!("empty group name in the subscription of {}", self.to);
+        }
+
+        let (include, exclude) = {
+            let compile = |regexes: &Vec<String>| -> Result<Vec<Regex>> {
+                let mut compiled = Vec::new();
+                for re in regexes {
+                    match Regex::new(re) {
+                        Ok(re) => compiled.push(re),
+                        Err(e) => bail!("bad regex '{}' in the subscription of {}: {}", re, self.to, e),
+                    }
+                }
+                Ok(compiled)
+            };
+            (compile(&self.include_regex)?, compile(&self.exclude_regex)?)
+        };
+        self.include_compiled = include;
+        self.exclude_compiled = exclude;
+
+        Ok(())
+    }
+
+    /// Every address of the subscription, 'to' first.
+    pub fn recipients(&self) -> Vec<&str> {
+        let mut recipients = vec![self.to.as_str()];
+        recipients.extend(self.also_to.iter().map(|to| to.as_str()));
+        recipients
+    }
+
+    /// How logs name the subscription: its 'group', else 'to'.
+    pub fn label(&self) -> &str {
+        self.group.as_ref().unwrap_or(&self.to)
+    }
+
+    /// Whether an item titled 'title' goes to this recipient. The regular
+    /// expressions only count once 'validate' compiled them, as 'Config'
+    /// does when it is read.
+    pub fn matches(&self, title: &str) -> bool {
+        let lower = title.to_lowercase();
+        let contains = |keywords: &Vec<String>| keywords.iter().any(|k| lower.contains(&k.to_lowercase()));
+        let matching = |regexes: &Vec<Regex>| regexes.iter().any(|re| re.is_match(title));
+
+        let wanted = (self.include.is_empty() && self.include_regex.is_empty())
+            || contains(&self.include) || matching(&self.include_compiled);
+        wanted && !contains(&self.exclude) && !matching(&self.exclude_compiled)
+    }
+}
+
+/// The feed 'merged_feed' keeps: every item of every feed, categorized by
+/// the feed it came from, until 'max_items' newer ones push it out.
+#[derive(Deserialize, Debug, Clone)]
+pub struct MergedFeed {
+    /// Where the feed is written.
+    pub path: String,
+    /// Channel title.
+    #[serde(default = "default_merged_title")]
+    pub title: String,
+    /// Channel link, RSS 2.0 requires one.
+    pub link: String,
+    /// How many items the feed keeps, the newest by pubDate.
+    #[serde(default = "default_merged_max_items")]
+    pub max_items: usize,
+}
+
+impl MergedFeed {
+    /// Fails on an empty path or link, or a feed that may keep no item.
+    pub fn validate(&self) -> Result<()> {
+        if self.path.is_empty() {
+            bail!("merged_feed needs a path");
+        }
+        if self.link.is_empty() {
+            bail!("merged_feed needs a link");
+        }
+        if self.max_items == 0 {
+            bail!("max_items of merged_feed must be at least 1");
+        }
+
+        Ok(())
+    }
+}
+
+/// Day names as used by 'QuietHours' and 'DigestSchedule'.
+pub const WEEKDAYS: &[&str] = &["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+impl QuietHours {
+    /// Fails on a malformed time or day.
+    pub fn validate(&self) -> Result<()> {
+        for time in &[&self.start, &self.end] {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .chain_err(|| format!("bad quiet_hours time '{}', expected HH:MM", time))?;
+        }
+
+        for day in &self.days {
+            if !WEEKDAYS.contains(&day.as_str()) {
+                bail!("bad quiet_hours day '{}', expected one of {:?}", day, WEEKDAYS);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether 'now' falls into the configured quiet hours.
+pub fn in_quiet_hours(quiet: &Option<QuietHours>, now: DateTime<Local>) -> bool {
+    let quiet = match *quiet {
+        Some(ref quiet) => quiet,
+        None => return false,
+    };
+
+    // checked by 'QuietHours::validate' when the config was loaded.
+    let start = NaiveTime::parse_from_str(&quiet.start, "%H:%M").unwrap();
+    let end = NaiveTime::parse_from_str(&quiet.end, "%H:%M").unwrap();
+    let time = now.time();
+
+    let (inside, started_yesterday) = if start <= end {
+        (time >= start && time < end, false)
+    } else {
+        (time >= start || time < end, time < end)
+    };
+
+    if !inside {
+        return false;
+    }
+
+    if quiet.days.is_empty() {
+        return true;
+    }
+
+    let weekday = if started_yesterday { now.weekday().pred() } else { now.weekday() };
+    let weekday = WEEKDAYS[weekday.num_days_from_monday() as usize];
+    quiet.days.iter().any(|day| day == weekday)
+}
+
+/// Whether 'now' falls on one of the 'active_days' 'days', any day when
+/// there are none.
+pub fn on_active_day(days: &[String], now: DateTime<Local>) -> bool {
+    let weekday = WEEKDAYS[now.weekday().num_days_from_monday() as usize];
+    days.is_empty() || days.iter().any(|day| day == weekday)
+}
+
+/// 's' with "${VAR}" replaced by what 'lookup' gives for VAR;
+/// "${VAR:-default}" falls back to 'default' when VAR is unset, and "$${" is
+/// a plain "${". Any other "$" is kept, so passwords and patterns holding
+/// one read as they are. Fails on variables that are unset without a
+/// default.
+pub fn expand_env<F: Fn(&str) -> Option<String>>(s: &str, lookup: F) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = s;
+
+    while let Some(at) = rest.find("${") {
+        if rest[..at].ends_with('$') {
+            out.push_str(&rest[..at - 1]);
+            out.push_str("${");
+            rest = &rest[at + 2..];
+            continue;
+        }
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 2..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => bail!("unclosed '${{' in '{}'", s),
+        };
+        let inner = &rest[..end];
+        let (name, default) = match inner.find(":-") {
+            Some(sep) => (&inner[..sep], Some(&inner[sep + 2..])),
+            None => (inner, None),
+        };
+        match (lookup(name), default) {
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => bail!("environment variable '{}' is not set", name),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+/// The settings 'expand_env' is run on, the hosts, addresses, URLs and
+/// secrets a deployment may want to keep in its environment. "[]" stands
+/// for every element of an array, "*" for every value of an object.
+pub const EXPANDED_SETTINGS: &[&str] = &[
+    "server", "helo_name", "from", "to", "password", "error_email", "alert_to", "state_key",
+    "listen", "trigger_token", "rocketchat_webhook",
+    "feeds[].remote_rss", "feeds[].login.url", "feeds[].login.form.*",
+    "subscriptions[].to", "subscriptions[].also_to[]",
+];
+
+/// Top level settings taken from an environment variable when the config
+/// has none, as (setting, variable).
+pub const ENV_FALLBACKS: &[(&str, &str)] = &[
+    ("from", "BBSMON_FROM"),
+    ("to", "BBSMON_TO"),
+    ("password", "BBSMON_PASSWORD"),
+    ("server", "BBSMON_SERVER"),
+];
+
+/// Set the 'ENV_FALLBACKS' missing from the config object 'value' to what
+/// 'lookup' gives for their variable. Settings that are present, even
+/// empty, are kept.
+pub fn apply_env_fallbacks<F: Fn(&str) -> Option<String>>(value: &mut Value, lookup: F) {
+    if let Value::Object(ref mut map) = *value {
+        for &(setting, var) in ENV_FALLBACKS {
+            if map.contains_key(setting) {
+                continue;
+            }
+            if let Some(from_env) = lookup(var) {
+                map.insert(setting.to_string(), Value::String(from_env));
+            }
+        }
+    }
+}
+
+// expand the environment variables of the 'EXPANDED_SETTINGS' of the config
+// object 'value', looked up with 'lookup'.
+fn expand_env_values(value: &mut Value, lookup: &dyn Fn(&str) -> Option<String>) -> Result<()> {
+    for setting in EXPANDED_SETTINGS {
+        let path: Vec<&str> = setting.split('.').collect();
+        expand_at(value, &path, "", lookup)?;
+    }
+
+    Ok(())
+}
+
+// expand the strings at 'path' below 'value', 'at' being where 'value' sits
+// in the config, for errors. settings that aren't there are left alone.
+fn expand_at(value: &mut Value, path: &[&str], at: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<()> {
+    let (first, rest) = match path.split_first() {
+        Some(split) => split,
+        None => {
+            if let Value::String(ref mut s) = *value {
+                *s = expand_env(s, lookup).chain_err(|| format!("in '{}'", at))?;
+            }
+            return Ok(());
+        }
+    };
+    let map = match *value {
+        Value::Object(ref mut map) => map,
+        _ => return Ok(()),
+    };
+    let below = |key: &str| if at.is_empty() { key.to_string() } else { format!("{}.{}", at, key) };
+
+    if *first == "*" {
+        for (key, v) in map.iter_mut() {
+            expand_at(v, rest, &below(key), lookup)?;
+        }
+        return Ok(());
+    }
+
+    let (key, each) = match first.strip_suffix("[]") {
+        Some(key) => (key, true),
+        None => (*first, false),
+    };
+    let v = match map.get_mut(key) {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    match *v {
+        Value::Array(ref mut values) if each => {
+            for (i, v) in values.iter_mut().enumerate() {
+                expand_at(v, rest, &format!("{}[{}]", below(key), i), lookup)?;
+            }
+            Ok(())
+        }
+        _ if each => Ok(()),
+        _ => expand_at(v, rest, &below(key), lookup),
+    }
+}
+
+fn default_state_dir() -> String {
+    "state".to_string()
+}
+
+fn default_templates_dir() -> String {
+    "templates".to_string()
+}
+
+fn default_max_concurrent_fetches() -> usize {
+    4
+}
+
+fn default_max_feed_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_max_concurrent_deliveries() -> usize {
+    4
+}
+
+fn default_max_concurrent_transforms() -> usize {
+    4
+}
+
+fn default_transform_timeout_secs() -> u64 {
+    10
+}
+
+fn default_dedupe_items() -> bool {
+    true
+}
+
+fn default_respect_ttl() -> bool {
+    true
+}
+
+fn default_error_email_interval_secs() -> i64 {
+    3600
+}
+
+fn default_max_pages() -> u32 {
+    5
+}
+
+fn default_interval_secs() -> u64 {
+    600
+}
+
+/// Name of the feed configured through the top level 'remote_rss'.
+pub const DEFAULT_FEED_NAME: &str = "default";
+
+/// Section of the feeds without a 'site'.
+pub const OTHER_SITE: &str = "Other";
+
+/// Accepted values of 'digest_order'.
+pub const DIGEST_ORDERS: &[&str] = &["config", "name", "count"];
+
+/// Accepted values of 'verify_recipients'.
+pub const VERIFY_RECIPIENTS_POLICIES: &[&str] = &["warn", "abort"];
+
+/// Accepted values of 'smtp_mode'.
+pub const SMTP_MODES: &[&str] = &["plain", "starttls", "smtps"];
+
+/// What is odd about 'smtp_mode' 'mode' on 'port': servers speak TLS from
+/// the start on 465 and STARTTLS, if at all, on the others.
+pub fn smtp_mode_mismatch(mode: &str, port: u16) -> Option<String> {
+    match (mode, port) {
+        ("smtps", 25) | ("smtps", 587) => {
+            Some(format!("smtp_mode \"smtps\" on port {}, which usually takes STARTTLS", port))
+        }
+        ("plain", 465) | ("starttls", 465) => {
+            Some(format!("smtp_mode \"{}\" on port 465, which usually wants \"smtps\"", mode))
+        }
+        _ => None,
+    }
+}
+
+/// Accepted values of 'diff_strategy'.
+pub const DIFF_STRATEGIES: &[&str] = &["full", "guid", "link", "title", "guid_or_link",
+                                                      "content_hash"];
+
+/// The 'diff_strategy' used when none is configured: 'feed::item_key'.
+pub fn default_diff_strategy() -> String {
+    "guid_or_link".to_string()
+}
+
+/// Accepted values of 'missing_link'.
+pub const MISSING_LINK_POLICIES: &[&str] = &["drop", "keep", "use_guid_as_link"];
+
+/// The 'missing_link' policy used when none is configured.
+pub fn default_missing_link() -> String {
+    "keep".to_string()
+}
+
+fn default_digest_order() -> String {
+    "config".to_string()
+}
+
+fn default_merged_title() -> String {
+    "bbsmon".to_string()
+}
+
+fn default_merged_max_items() -> usize {
+    100
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_date_format() -> String {
+    PUB_DATE_FORMAT.to_string()
+}
+
+fn default_log_format() -> String {
+    "text".to_string()
+}
+
+fn default_simple_format_joiner() -> String {
+    "\n".to_string()
+}
+
+fn default_locale() -> String {
+    "en".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn quiet_hours_past_midnight_belong_to_the_starting_day() {
+        let quiet = Some(QuietHours {
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            days: vec!["Fri".to_string()],
+        });
+
+        // 2016-11-04 is a friday.
+        assert!(in_quiet_hours(&quiet, Local.with_ymd_and_hms(2016, 11, 4, 23, 30, 0).unwrap()));
+        assert!(in_quiet_hours(&quiet, Local.with_ymd_and_hms(2016, 11, 5, 6, 59, 0).unwrap()));
+        assert!(!in_quiet_hours(&quiet, Local.with_ymd_and_hms(2016, 11, 5, 7, 0, 0).unwrap()));
+        assert!(!in_quiet_hours(&quiet, Local.with_ymd_and_hms(2016, 11, 4, 6, 0, 0).unwrap()));
+        assert!(!in_quiet_hours(&quiet, Local.with_ymd_and_hms(2016, 11, 5, 23, 0, 0).unwrap()));
+        assert!(!in_quiet_hours(&None, Local.with_ymd_and_hms(2016, 11, 4, 23, 30, 0).unwrap()));
+    }
+
+    #[test]
+    fn weekends_are_not_active_days() {
+        let weekdays: Vec<String> = ["Mon", "Tue", "Wed", "Thu", "Fri"].iter().map(|d| d.to_string()).collect();
+
+        // 2016-11-04 is a friday.
+        assert!(on_active_day(&weekdays, Local.with_ymd_and_hms(2016, 11, 4, 23, 59, 0).unwrap()));
+        assert!(!on_active_day(&weekdays, Local.with_ymd_and_hms(2016, 11, 5, 0, 0, 0).unwrap()));
+        assert!(!on_active_day(&weekdays, Local.with_ymd_and_hms(2016, 11, 6, 12, 0, 0).unwrap()));
+        assert!(on_active_day(&weekdays, Local.with_ymd_and_hms(2016, 11, 7, 0, 0, 0).unwrap()));
+        assert!(on_active_day(&Vec::new(), Local.with_ymd_and_hms(2016, 11, 6, 12, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn smtps_belongs_on_port_465() {
+        assert_eq!(smtp_mode_mismatch("smtps", 465), None);
+        assert_eq!(smtp_mode_mismatch("starttls", 587), None);
+        assert!(smtp_mode_mismatch("smtps", 587).is_some());
+        assert!(smtp_mode_mismatch("starttls", 465).unwrap().contains("smtps"));
+    }
+
+    #[test]
+    fn weekly_digest_is_due_on_its_day() {
+        let schedule = DigestSchedule {
+            at: "08:00".to_string(),
+            period: "weekly".to_string(),
+            day: "Mon".to_string(),
+        };
+
+        // 2016-11-07 is a monday.
+        assert_eq!(schedule.last_due(Local.with_ymd_and_hms(2016, 11, 9, 12, 0, 0).unwrap()),
+                   Local.with_ymd_and_hms(2016, 11, 7, 8, 0, 0).unwrap());
+        assert_eq!(schedule.last_due(Local.with_ymd_and_hms(2016, 11, 7, 7, 59, 0).unwrap()),
+                   Local.with_ymd_and_hms(2016, 10, 31, 8, 0, 0).unwrap());
+        assert_eq!(schedule.last_due(Local.with_ymd_and_hms(2016, 11, 7, 8, 0, 0).unwrap()),
+                   Local.with_ymd_and_hms(2016, 11, 7, 8, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn digests_at_a_skipped_time_are_due_after_the_gap() {
+        let schedule = DigestSchedule {
+            at: "02:30".to_string(),
+            period: "daily".to_string(),
+            day: "Mon".to_string(),
+        };
+
+        // berlin skipped from 02:00 to 03:00 on 2016-03-27.
+        let berlin = chrono_tz::Europe::Berlin;
+        assert_eq!(schedule.last_due(berlin.with_ymd_and_hms(2016, 3, 27, 12, 0, 0).unwrap()),
+                   berlin.with_ymd_and_hms(2016, 3, 27, 3, 0, 0).unwrap());
+        assert_eq!(schedule.last_due(berlin.with_ymd_and_hms(2016, 3, 28, 2, 29, 0).unwrap()),
+                   berlin.with_ymd_and_hms(2016, 3, 27, 3, 0, 0).unwrap());
+        assert_eq!(schedule.last_due(berlin.with_ymd_and_hms(2016, 3, 28, 12, 0, 0).unwrap()),
+                   berlin.with_ymd_and_hms(2016, 3, 28, 2, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn malformed_digest_schedule_is_rejected() {
+        let schedule = DigestSchedule {
+            at: "8 o'clock".to_string(),
+            period: "daily".to_string(),
+            day: "Mon".to_string(),
+        };
+        assert!(schedule.validate().is_err());
+
+        let schedule = DigestSchedule {
+            at: "08:00".to_string(),
+            period: "monthly".to_string(),
+            day: "Mon".to_string(),
+        };
+        assert!(schedule.validate().is_err());
+    }
+
+    #[test]
+    fn environment_variables_are_expanded() {
+        let lookup = |name: &str| if name == "HOST" { Some("bbs".to_string()) } else { None };
+
+        assert_eq!(expand_env("smtp.${HOST}:25", lookup).unwrap(), "smtp.bbs:25");
+        assert_eq!(expand_env("${PORT:-25} ${HOST:-x}", lookup).unwrap(), "25 bbs");
+        assert_eq!(expand_env("100$ $HOST $$HOST", lookup).unwrap(), "100$ $HOST $$HOST");
+        assert_eq!(expand_env("$${HOST} ${HOST}", lookup).unwrap(), "${HOST} bbs");
+
+        assert!(expand_env("${PORT}", lookup).is_err());
+        assert!(expand_env("${HOST", lookup).is_err());
+    }
+
+    #[test]
+    fn only_expanded_settings_read_the_environment() {
+        let lookup = |name: &str| if name == "HOST" { Some("bbs".to_string()) } else { None };
+        let mut value: Value = serde_json::from_str(r#"{"server": "smtp.${HOST}", "subject": "${HOST}",
+            "feeds": [{"name": "${HOST}", "remote_rss": "http://${HOST}/rss",
+                       "login": {"url": "http://${HOST}/login", "form": {"pwd": "${PW:-x}"}}}],
+            "subscriptions": [{"to": "a@${HOST}", "also_to": ["b@${HOST}"], "include_regex": ["${HOST}"]}]}"#)
+            .unwrap();
+        expand_env_values(&mut value, &lookup).unwrap();
+
+        assert_eq!(value["server"], "smtp.bbs");
+        assert_eq!(value["subject"], "${HOST}");
+        assert_eq!(value["feeds"][0]["name"], "${HOST}");
+        assert_eq!(value["feeds"][0]["remote_rss"], "http://bbs/rss");
+        assert_eq!(value["feeds"][0]["login"]["url"], "http://bbs/login");
+        assert_eq!(value["feeds"][0]["login"]["form"]["pwd"], "x");
+        assert_eq!(value["subscriptions"][0]["to"], "a@bbs");
+        assert_eq!(value["subscriptions"][0]["also_to"][0], "b@bbs");
+        assert_eq!(value["subscriptions"][0]["include_regex"][0], "${HOST}");
+
+        let mut unset: Value = serde_json::from_str(r#"{"feeds": [{"remote_rss": "http://${NOPE}/rss"}]}"#).unwrap();
+        let e = expand_env_values(&mut unset, &lookup).unwrap_err();
+        assert!(error_chain_string(&e).contains("in 'feeds[0].remote_rss'"), "{}", error_chain_string(&e));
+    }
+
+    #[test]
+    fn dollar_signs_in_passwords_and_patterns_are_kept() {
+        let config = Config::from_value(serde_json::from_str(r#"{"subject": "s", "from": "f@bbs", "to": "t@bbs",
+            "password": "p$ss$word", "server": "bbs", "feeds": [{"name": "bbs", "remote_rss": "http://bbs/rss"}],
+            "subscriptions": [{"to": "a@bbs", "include_regex": ["^\\[内核\\]$", "v\\d+$"]}]}"#).unwrap()).unwrap();
+
+        assert_eq!(config.password, "p$ss$word");
+        assert_eq!(config.subscriptions[0].include_regex, vec!["^\\[内核\\]$", "v\\d+$"]);
+        assert!(config.subscriptions[0].matches("[内核]"));
+    }
+
+    #[test]
+    fn subscriptions_match_titles() {
+        let mut kernel: Subscription = serde_json::from_str(r#"{"to": "a@bbs", "include": ["Kernel"],
+                                                               "include_regex": ["^\\[内核\\]"],
+                                                               "exclude": ["招聘"]}"#).unwrap();
+        let mut everything: Subscription = serde_json::from_str(r#"{"to": "b@bbs", "exclude_regex": ["^广告"]}"#)
+            .unwrap();
+        kernel.validate().unwrap();
+        everything.validate().unwrap();
+
+        assert!(kernel.matches("linux kernel 4.9 发布"));
+        assert!(kernel.matches("[内核] 调度器"));
+        assert!(!kernel.matches("kernel 岗位招聘"));
+        assert!(!kernel.matches("frontend 周报"));
+        assert!(everything.matches("frontend 周报"));
+        assert!(!everything.matches("广告：二手显卡"));
+
+        let mut bad: Subscription = serde_json::from_str(r#"{"to": "a@bbs", "include_regex": ["(kernel"]}"#)
+            .unwrap();
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn subscription_groups_mail_every_member() {
+        let config = |subscriptions: &str| {
+            let text = format!(r#"{{"subject": "s", "from": "f@bbs", "to": "t@bbs", "password": "p", "server": "bbs",
+                                   "feeds": [{{"name": "bbs", "remote_rss": "http://bbs/rss"}}],
+                                   "subscriptions": {}}}"#, subscriptions);
+            Config::from_value(serde_json::from_str(&text).unwrap())
+        };
+
+        let groups = config(r#"[{"group": "market", "to": "a@bbs", "also_to": ["b@bbs"], "include": ["出售"]},
+                                {"group": "news", "to": "c@bbs", "include": ["公告"]}]"#).unwrap();
+        assert_eq!(groups.subscriptions[0].recipients(), vec!["a@bbs", "b@bbs"]);
+        assert_eq!(groups.subscriptions[0].label(), "market");
+        assert_eq!(groups.subscriptions[1].recipients(), vec!["c@bbs"]);
+        assert!(groups.subscriptions[1].matches("停电公告"));
+        assert!(!groups.subscriptions[1].matches("出售显示器"));
+
+        assert!(config(r#"[{"group": "news", "to": "a@bbs"}, {"group": "news", "to": "b@bbs"}]"#).is_err());
+        assert!(config(r#"[{"to": "a@bbs", "also_to": [""]}]"#).is_err());
+    }
+
+    #[test]
+    fn missing_mail_settings_come_from_the_environment() {
+        let lookup = |name: &str| match name {
+            "BBSMON_FROM" => Some("bbsmon@bbs".to_string()),
+            "BBSMON_TO" => Some("env@bbs".to_string()),
+            _ => None,
+        };
+        let mut value: Value = serde_json::from_str(r#"{"to": "json@bbs", "server": "smtp.bbs"}"#).unwrap();
+        apply_env_fallbacks(&mut value, lookup);
+
+        let map = value.as_object().unwrap();
+        assert_eq!(map.get("from"), Some(&Value::String("bbsmon@bbs".to_string())));
+        assert_eq!(map.get("to"), Some(&Value::String("json@bbs".to_string())));
+        assert_eq!(map.get("server"), Some(&Value::String("smtp.bbs".to_string())));
+        assert!(map.get("password").is_none());
+    }
+
+    #[test]
+    fn feeds_may_not_share_a_url_or_a_state_file() {
+        let config = |feeds: &str| {
+            let text = format!(r#"{{"subject": "s", "from": "f@bbs", "to": "t@bbs", "password": "p", "server": "bbs",
+                                   "feeds": {}}}"#, feeds);
+            Config::from_value(serde_json::from_str(&text).unwrap())
+        };
+
+        assert!(config(r#"[{"name": "market", "remote_rss": "http://bbs/market"},
+                           {"name": "kernel", "remote_rss": "http://bbs/kernel"}]"#).is_ok());
+
+        let url = config(r#"[{"name": "market", "remote_rss": "http://bbs/rss"},
+                             {"name": "kernel", "remote_rss": "http://bbs/rss"}]"#).unwrap_err();
+        assert!(error_chain_string(&url).contains("both fetch http://bbs/rss"));
+
+        let name = config(r#"[{"name": "market", "remote_rss": "http://bbs/market"},
+                              {"name": "market", "remote_rss": "http://bbs/kernel"}]"#).unwrap_err();
+        assert!(error_chain_string(&name).contains("keep their state in"), "{}", error_chain_string(&name));
+
+        let snapshot = config(r#"[{"name": "market", "remote_rss": "http://bbs/market", "local_rss": "bbs.xml"},
+                                  {"name": "kernel", "remote_rss": "http://bbs/kernel", "local_rss": "bbs.xml"}]"#)
+            .unwrap_err();
+        assert!(error_chain_string(&snapshot).contains("bbs.xml"));
+    }
+
+    #[test]
+    fn jobs_are_complete_configs_with_their_own_state() {
+        let job = |name: &str, extra: &str| {
+            format!(r#"{{"name": "{}", "feeds": [{{"name": "bbs", "remote_rss": "http://bbs/rss"}}],
+                        "subject": "s", "from": "f@bbs", "to": "t@bbs", "password": "p", "server": "bbs"{}}}"#,
+                    name, extra)
+        };
+        let jobs_of = |json: String| jobs_from_value(serde_json::from_str(&json).unwrap());
+
+        let jobs = jobs_of(format!(r#"{{"log_format": "json", "jobs": [{}, {}]}}"#,
+                                   job("market", ""), job("kernel", r#", "state_dir": "/var/lib/kernel""#))).unwrap();
+        assert_eq!(jobs.iter().map(|j| j.name.as_str()).collect::<Vec<&str>>(), vec!["market", "kernel"]);
+        assert_eq!(Path::new(&jobs[0].config.state_dir), Path::new("state").join("market").as_path());
+        assert_eq!(jobs[1].config.state_dir, "/var/lib/kernel");
+        assert!(jobs.iter().all(|j| j.config.log_format == "json"));
+
+        let single = jobs_of(job("ignored", "")).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].name, "");
+
+        assert!(jobs_of(format!(r#"{{"jobs": [{}, {}]}}"#, job("market", ""), job("market", ""))).is_err());
+        assert!(jobs_of(format!(r#"{{"jobs": [{}]}}"#, job("../market", ""))).is_err());
+        assert!(jobs_of(format!(r#"{{"interval_secs": 60, "jobs": [{}]}}"#, job("market", ""))).is_err());
+        assert!(jobs_of(format!(r#"{{"jobs": [{}, {}]}}"#, job("a", r#", "state_dir": "s""#),
+                                job("b", r#", "state_dir": "s""#))).is_err());
+    }
+
+    #[test]
+    fn a_config_dir_has_a_job_per_file() {
+        let dir = env::temp_dir().join(format!("bbsmon-config-dir-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = r#"{"feeds": [{"name": "bbs", "remote_rss": "http://bbs/rss"}],
+                         "subject": "s", "from": "f@bbs", "to": "t@bbs", "password": "p", "server": "bbs"}"#;
+        fs::write(dir.join("market.json"), config).unwrap();
+        fs::write(dir.join("kernel.json"), config).unwrap();
+        fs::write(dir.join("notes.txt"), "not a job").unwrap();
+
+        let jobs = Config::load_job_dir(dir.to_str().unwrap()).unwrap();
+        assert_eq!(jobs.iter().map(|j| j.name.as_str()).collect::<Vec<&str>>(), vec!["kernel", "market"]);
+        assert_eq!(Path::new(&jobs[1].config.state_dir), Path::new("state").join("market").as_path());
+
+        fs::write(dir.join("all.json"), r#"{"jobs": []}"#).unwrap();
+        assert!(Config::load_job_dir(dir.to_str().unwrap()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(Config::load_job_dir(dir.to_str().unwrap()).is_err());
+    }
+}
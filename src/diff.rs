@@ -0,0 +1,541 @@
+//! What changed between two fetches of a feed.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+
+use rss::Item;
+
+use serde_json;
+
+use crate::error::*;
+use crate::feed::RssContext;
+use crate::feed::item_key;
+use crate::feed::item_note;
+use crate::feed::set_item_note;
+use crate::render::SerItem;
+use crate::render::convert_to_ser_items;
+
+/// Items of a feed sorted by how they changed since the last run.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// Items the snapshot did not have.
+    pub new_items: Vec<Item>,
+    /// Items the snapshot had with a different content.
+    pub updated_items: Vec<Item>,
+    /// Items of the snapshot the feed dropped.
+    pub removed_items: Vec<Item>,
+    /// Channel title, link or description that changed.
+    pub channel_meta: Vec<MetaChange>,
+}
+
+/// A changed field of the channel itself.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MetaChange {
+    /// "title", "link" or "description".
+    pub field: String,
+    /// Value in the snapshot.
+    pub old: String,
+    /// Value in the fetched feed.
+    pub new: String,
+}
+
+impl Changes {
+    /// What a notification is sent for; removed items only ride along.
+    pub fn notified(&self) -> Vec<&Item> {
+        self.new_items.iter().chain(self.updated_items.iter()).collect()
+    }
+
+    /// Split into the changes matching 'pred' and the rest, which keeps
+    /// 'channel_meta'.
+    pub fn partition<F: Fn(&Item) -> bool>(self, pred: F) -> (Changes, Changes) {
+        let (new_a, new_b) = self.new_items.into_iter().partition(|i| pred(i));
+        let (updated_a, updated_b) = self.updated_items.into_iter().partition(|i| pred(i));
+        let (removed_a, removed_b) = self.removed_items.into_iter().partition(|i| pred(i));
+
+        (Changes { new_items: new_a, updated_items: updated_a, removed_items: removed_a,
+                          channel_meta: Vec::new() },
+                Changes { new_items: new_b, updated_items: updated_b, removed_items: removed_b,
+                          channel_meta: self.channel_meta })
+    }
+}
+
+impl RssContext {
+    /// Return item a vector of Items which are in 'a' but not in 'b'.
+    pub fn diff<'a>(ctx_a: &'a RssContext, ctx_b: &RssContext) -> Vec<&'a Item> {
+        let a = &ctx_a.channel.items;
+        let b = &ctx_b.channel.items;
+        
+        let mut c = Vec::new();
+        
+        for item_a in a {
+            if !b.contains(item_a) {
+                c.push(item_a);
+            }
+        }
+
+        c
+    }
+
+    /// Split the difference between 'new' and 'old' by item identity, as
+    /// 'diff_key' with 'strategy' tells it: items only in 'new', items in
+    /// both whose content changed, items only in 'old'.
+    pub fn changes(new: &RssContext, old: &RssContext, strategy: &str) -> Changes {
+        let old_items: HashMap<String, &Item> = old.channel.items.iter()
+            .map(|item| (diff_key(item, strategy), item))
+            .collect();
+        let new_keys: HashSet<String> = new.channel.items.iter().map(|item| diff_key(item, strategy)).collect();
+
+        let mut changes = Changes::default();
+
+        for item in &new.channel.items {
+            match old_items.get(&diff_key(item, strategy)) {
+                None => changes.new_items.push(item.clone()),
+                Some(old_item) if *old_item != item => {
+                    let mut item = item.clone();
+                    let previous = old_item.description.as_ref().map(|d| strip_tags(d)).unwrap_or_default();
+                    set_item_note(&mut item, PREVIOUS_NOTE, cut_chars(&previous, MAX_PREVIOUS_CHARS).to_string());
+                    changes.updated_items.push(item);
+                }
+                Some(_) => {}
+            }
+        }
+
+        for item in &old.channel.items {
+            if !new_keys.contains(&diff_key(item, strategy)) {
+                changes.removed_items.push(item.clone());
+            }
+        }
+
+        let fields = [("title", &old.channel.title, &new.channel.title),
+                      ("link", &old.channel.link, &new.channel.link),
+                      ("description", &old.channel.description, &new.channel.description)];
+        for &(field, old_value, new_value) in &fields {
+            if old_value.trim() != new_value.trim() {
+                changes.channel_meta.push(MetaChange {
+                    field: field.to_string(),
+                    old: old_value.clone(),
+                    new: new_value.clone(),
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+/// Identity of 'item' across fetches under 'strategy', one of
+/// 'DIFF_STRATEGIES':
+///
+/// - "guid_or_link", the default, is 'item_key': the guid, else the link,
+///   else the title. Edits are updated items as long as the board keeps
+///   its guids or links stable.
+/// - "guid" and "link" only look at that element. Items without it all
+///   count as one, so they suit feeds whose every item has one; "link"
+///   also serves boards that hand out a fresh guid on every fetch.
+/// - "title" is for feeds with neither; two postings of the same title
+///   are one item, and a renamed item is removed and new.
+/// - "content_hash" hashes the title and description, for boards that
+///   move items between links. An edit makes a new item.
+/// - "full" hashes every field shown, so any change at all is a new item
+///   and the old one removed; nothing is ever an updated item.
+pub fn diff_key(item: &Item, strategy: &str) -> String {
+    let text = |field: &Option<String>| field.clone().unwrap_or_default();
+
+    match strategy {
+        "guid" => item.guid.as_ref().map(|g| g.value.clone()).unwrap_or_default(),
+        "link" => text(&item.link),
+        "title" => text(&item.title),
+        "content_hash" => hash(&[text(&item.title), text(&item.description)]),
+        "full" => hash(&[text(&item.title), text(&item.link), text(&item.description), text(&item.author),
+                         text(&item.pub_date), item.guid.as_ref().map(|g| g.value.clone()).unwrap_or_default()]),
+        _ => item_key(item),
+    }
+}
+
+// hex SHA-256 of 'fields', each ended by a NUL so that they can't run
+// into each other.
+fn hash(fields: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for field in fields {
+        hasher.input_str(field);
+        hasher.input(&[0]);
+    }
+    hasher.result_str()
+}
+
+/// Most characters of the previous description an updated item keeps for
+/// 'item_diff'; the descriptions are compared up to there.
+pub const MAX_PREVIOUS_CHARS: usize = 4000;
+
+/// Longest diff, in characters, shown whole; in longer ones the unchanged
+/// runs are cut down to 'DIFF_CONTEXT_CHARS' around each change.
+pub const LARGE_DIFF_CHARS: usize = 600;
+
+/// Unchanged characters kept next to a change in a large diff.
+pub const DIFF_CONTEXT_CHARS: usize = 40;
+
+// the note 'changes' keeps the previous description of an updated item in.
+const PREVIOUS_NOTE: &str = "previous";
+
+// most cells of the table 'word_diff' fills; changes past that are shown
+// as the old text deleted and the new one inserted.
+const MAX_DIFF_CELLS: usize = 1000000;
+
+/// A run of words in the diff of a description, see 'word_diff'.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiffSegment {
+    /// "equal", "inserted" or "deleted".
+    pub kind: String,
+    /// The words of the run, with the spaces between them.
+    pub text: String,
+}
+
+/// 'html' as plain text: without tags, the common entities decoded and
+/// every run of whitespace made one space.
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    let text = text.replace("&nbsp;", " ").replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"")
+        .replace("&#39;", "'").replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// the first 'max' characters of 'text'.
+fn cut_chars(text: &str, max: usize) -> &str {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+// a word is a run of letters and digits; CJK characters, which aren't
+// spaced apart, are words of their own like punctuation is.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() && (c as u32) < 0x2E80
+}
+
+// 'text' split into words, runs of spaces and single other characters.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let (end, joined) = match chars.peek() {
+            Some(&(end, next)) => {
+                (end, (is_word_char(c) && is_word_char(next)) || (c.is_whitespace() && next.is_whitespace()))
+            }
+            None => (text.len(), false),
+        };
+        if !joined {
+            tokens.push(&text[start..end]);
+            start = end;
+        }
+    }
+    tokens
+}
+
+// append 'tokens' to 'segments' as 'kind', into the last segment when it
+// is of the same kind.
+fn push_tokens(segments: &mut Vec<DiffSegment>, kind: &str, tokens: &[&str]) {
+    if tokens.is_empty() {
+        return;
+    }
+
+    let text = tokens.concat();
+    if let Some(last) = segments.last_mut() {
+        if last.kind == kind {
+            last.text.push_str(&text);
+            return;
+        }
+    }
+    segments.push(DiffSegment { kind: kind.to_string(), text });
+}
+
+// unchanged runs of a large diff cut down to the context of the changes
+// next to them.
+fn collapse_equal(segments: Vec<DiffSegment>) -> Vec<DiffSegment> {
+    let total: usize = segments.iter().map(|s| s.text.chars().count()).sum();
+    if total <= LARGE_DIFF_CHARS {
+        return segments;
+    }
+
+    let last = segments.len() - 1;
+    segments.into_iter().enumerate().map(|(i, segment)| {
+        let chars: Vec<char> = segment.text.chars().collect();
+        if segment.kind != "equal" || chars.len() <= 2 * DIFF_CONTEXT_CHARS + 1 {
+            return segment;
+        }
+
+        let head: String = if i > 0 { chars[..DIFF_CONTEXT_CHARS].iter().cloned().collect() } else { String::new() };
+        let tail: String = if i < last { chars[chars.len() - DIFF_CONTEXT_CHARS..].iter().cloned().collect() }
+                           else { String::new() };
+        DiffSegment { kind: segment.kind, text: format!("{}…{}", head, tail) }
+    }).collect()
+}
+
+/// The words that stayed, were deleted from 'old' and were inserted in
+/// 'new', in the order of the text. A change too large to compare word by
+/// word is the old text deleted and the new one inserted; a large diff
+/// has its unchanged runs collapsed to "…".
+pub fn word_diff(old: &str, new: &str) -> Vec<DiffSegment> {
+    let old = tokens(old);
+    let new = tokens(new);
+    let prefix = old.iter().zip(new.iter()).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|&(a, b)| a == b).count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut segments = Vec::new();
+    push_tokens(&mut segments, "equal", &old[..prefix]);
+    if old_mid.len() * new_mid.len() > MAX_DIFF_CELLS {
+        push_tokens(&mut segments, "deleted", old_mid);
+        push_tokens(&mut segments, "inserted", new_mid);
+    } else {
+        // lengths[i][j]: longest common subsequence of old_mid[i..] and
+        // new_mid[j..].
+        let (n, m) = (old_mid.len(), new_mid.len());
+        let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i][j] = if old_mid[i] == new_mid[j] { lengths[i + 1][j + 1] + 1 }
+                                else { cmp::max(lengths[i + 1][j], lengths[i][j + 1]) };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if old_mid[i] == new_mid[j] {
+                push_tokens(&mut segments, "equal", &old_mid[i..i + 1]);
+                i += 1;
+                j += 1;
+            } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+                push_tokens(&mut segments, "deleted", &old_mid[i..i + 1]);
+                i += 1;
+            } else {
+                push_tokens(&mut segments, "inserted", &new_mid[j..j + 1]);
+                j += 1;
+            }
+        }
+        push_tokens(&mut segments, "deleted", &old_mid[i..]);
+        push_tokens(&mut segments, "inserted", &new_mid[j..]);
+    }
+    push_tokens(&mut segments, "equal", &old[old.len() - suffix..]);
+
+    collapse_equal(segments)
+}
+
+/// The word diff of the description of an updated item against the one
+/// it had, as far as 'changes' kept it. Empty for other items and for
+/// edits that left the text as it was.
+pub fn item_diff(item: &Item) -> Vec<DiffSegment> {
+    let previous = match item_note(item, PREVIOUS_NOTE) {
+        Some(previous) => previous,
+        None => return Vec::new(),
+    };
+
+    let current = item.description.as_ref().map(|d| strip_tags(d)).unwrap_or_default();
+    let current = cut_chars(&current, MAX_PREVIOUS_CHARS);
+    if previous == current {
+        return Vec::new();
+    }
+    word_diff(&previous, current)
+}
+
+/// 'segments' as text, a line each: deleted ones prefixed "- ", inserted
+/// ones "+ " and unchanged ones "  ".
+pub fn diff_text(segments: &[DiffSegment]) -> String {
+    segments.iter().map(|segment| {
+        let prefix = match segment.kind.as_str() {
+            "deleted" => "- ",
+            "inserted" => "+ ",
+            _ => "  ",
+        };
+        format!("{}{}", prefix, segment.text.trim())
+    }).collect::<Vec<String>>().join("\n")
+}
+
+/// Exit code of a successful command.
+pub const EXIT_OK: i32 = 0;
+/// Exit code of 'bbsmon diff' when both files hold the same items.
+pub const EXIT_NO_CHANGES: i32 = 2;
+
+fn print_ser_items(label: &str, items: &[SerItem]) {
+    if items.is_empty() {
+        return;
+    }
+
+    println!("{} ({}):", label, items.len());
+    for item in items {
+        println!("  {} {}",
+                 item.title.as_deref().unwrap_or(""),
+                 item.link.as_deref().unwrap_or(""));
+    }
+}
+
+/// The same comparison a run makes, between two files on disk.
+pub fn diff_files(newer: &str, older: &str, strategy: &str, fields: &Option<Vec<String>>, missing_link: &str,
+                  json: bool) -> Result<i32> {
+    let new_ctx = RssContext::from_file(newer).chain_err(|| format!("can't load newer file {}", newer))?;
+    let old_ctx = RssContext::from_file(older).chain_err(|| format!("can't load older file {}", older))?;
+
+    let changes = RssContext::changes(&new_ctx, &old_ctx, strategy);
+
+    let new_items = convert_to_ser_items(&changes.new_items, fields, missing_link);
+    let updated_items = convert_to_ser_items(&changes.updated_items, fields, missing_link);
+    let removed_items = convert_to_ser_items(&changes.removed_items, fields, missing_link);
+
+    if json {
+        let mut out = BTreeMap::new();
+        out.insert("new_items", &new_items);
+        out.insert("updated_items", &updated_items);
+        out.insert("removed_items", &removed_items);
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        print_ser_items("new", &new_items);
+        print_ser_items("updated", &updated_items);
+        print_ser_items("removed", &removed_items);
+    }
+
+    if new_items.is_empty() && updated_items.is_empty() && removed_items.is_empty() {
+        return Ok(EXIT_NO_CHANGES);
+    }
+
+    Ok(EXIT_OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(items: &str) -> RssContext {
+        let xml = format!("<rss version=\"2.0\"><channel><title>t</title><link>l</link>\
+                           <description>d</description>{}</channel></rss>", items);
+        RssContext::from_reader(xml.as_bytes()).unwrap()
+    }
+
+    fn titles<'a, I: IntoIterator<Item = &'a Item>>(items: I) -> Vec<String> {
+        items.into_iter().map(|i| i.title.clone().unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn changes_tell_new_updated_and_removed_items_apart() {
+        let old = channel("<item><guid>1</guid><title>kept</title></item>\
+                           <item><guid>2</guid><title>edited</title></item>\
+                           <item><guid>3</guid><title>gone</title></item>");
+        let new = channel("<item><guid>1</guid><title>kept</title></item>\
+                           <item><guid>2</guid><title>edited again</title></item>\
+                           <item><guid>4</guid><title>fresh</title></item>");
+
+        let changes = RssContext::changes(&new, &old, "guid_or_link");
+
+        assert_eq!(titles(&changes.new_items), vec!["fresh"]);
+        assert_eq!(titles(&changes.updated_items), vec!["edited again"]);
+        assert_eq!(titles(&changes.removed_items), vec!["gone"]);
+        assert_eq!(titles(changes.notified()), vec!["fresh", "edited again"]);
+    }
+
+    #[test]
+    fn same_feed_has_no_changes() {
+        let feed = "<item><guid>1</guid><title>kept</title></item>";
+        let changes = RssContext::changes(&channel(feed), &channel(feed), "guid_or_link");
+
+        assert!(changes.notified().is_empty());
+        assert!(changes.removed_items.is_empty());
+        assert!(changes.channel_meta.is_empty());
+    }
+
+    #[test]
+    fn repeated_items_are_notified_once() {
+        let old = channel("<item><guid>1</guid><title>kept</title></item>");
+        let mut new = channel("<item><guid>2</guid><title>fresh</title></item>\
+                               <item><guid>1</guid><title>kept</title></item>\
+                               <item><guid>2</guid><title>fresh again</title></item>\
+                               <item><link>http://bbs/3</link><title>linked</title></item>\
+                               <item><link>http://bbs/3</link><title>linked again</title></item>");
+
+        assert_eq!(new.dedupe(), 2);
+        assert_eq!(titles(&RssContext::changes(&new, &old, "guid_or_link").new_items), vec!["fresh", "linked"]);
+    }
+
+    #[test]
+    fn renamed_channel_is_a_meta_change() {
+        let old = channel("");
+        let new = RssContext::from_reader(&b"<rss version=\"2.0\"><channel><title>renamed</title>\
+                                             <link>l</link><description>d</description></channel></rss>"[..])
+            .unwrap();
+
+        let changes = RssContext::changes(&new, &old, "guid_or_link");
+
+        assert_eq!(changes.channel_meta, vec![MetaChange {
+            field: "title".to_string(),
+            old: "t".to_string(),
+            new: "renamed".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn strategies_decide_what_is_the_same_item() {
+        let old = channel("<item><guid>1</guid><link>http://bbs/1</link><title>moved</title></item>\
+                           <item><guid>2</guid><link>http://bbs/2</link><title>edited</title></item>");
+        let new = channel("<item><guid>1</guid><link>http://bbs/t/1</link><title>moved</title></item>\
+                           <item><guid>2</guid><link>http://bbs/2</link><title>edited again</title></item>");
+
+        let by_guid = RssContext::changes(&new, &old, "guid");
+        assert!(by_guid.new_items.is_empty());
+        assert_eq!(titles(&by_guid.updated_items), vec!["moved", "edited again"]);
+
+        let by_link = RssContext::changes(&new, &old, "link");
+        assert_eq!(titles(&by_link.new_items), vec!["moved"]);
+        assert_eq!(titles(&by_link.updated_items), vec!["edited again"]);
+
+        let by_content = RssContext::changes(&new, &old, "content_hash");
+        assert_eq!(titles(&by_content.new_items), vec!["edited again"]);
+        assert_eq!(titles(&by_content.updated_items), vec!["moved"]);
+
+        let by_everything = RssContext::changes(&new, &old, "full");
+        assert_eq!(titles(&by_everything.new_items), vec!["moved", "edited again"]);
+        assert_eq!(titles(&by_everything.removed_items), vec!["moved", "edited"]);
+        assert!(by_everything.updated_items.is_empty());
+    }
+
+    #[test]
+    fn updated_items_carry_a_word_diff_of_their_text() {
+        let old = channel("<item><guid>1</guid>\
+                             <description>&lt;p&gt;九成新的自行车, 200 元&lt;/p&gt;</description></item>\
+                           <item><guid>2</guid><description>&lt;b&gt;出&lt;/b&gt; 书</description></item>");
+        let new = channel("<item><guid>1</guid>\
+                             <description>&lt;p&gt;九成新的自行车, 150 元, 已售&lt;/p&gt;</description></item>\
+                           <item><guid>2</guid><description>&lt;i&gt;出&lt;/i&gt; 书</description></item>");
+        let changes = RssContext::changes(&new, &old, "guid_or_link");
+
+        let segment = |kind: &str, text: &str| DiffSegment { kind: kind.to_string(), text: text.to_string() };
+        let diff = item_diff(&changes.updated_items[0]);
+        assert_eq!(diff, vec![segment("equal", "九成新的自行车, "), segment("deleted", "200"),
+                              segment("inserted", "150"), segment("equal", " 元"), segment("inserted", ", 已售")]);
+        assert_eq!(diff_text(&diff), "  九成新的自行车,\n- 200\n+ 150\n  元\n+ , 已售");
+        assert!(item_diff(&changes.updated_items[1]).is_empty());
+        assert!(item_diff(&new.channel.items[0]).is_empty());
+
+        let same: String = std::iter::repeat_n("a ", 400).collect();
+        let diff = word_diff(&format!("{}x", same), &format!("{}y", same));
+        assert_eq!(diff[0].text, format!("…{}", &same[..DIFF_CONTEXT_CHARS]));
+        assert_eq!(diff[1..].to_vec(), vec![segment("deleted", "x"), segment("inserted", "y")]);
+    }
+}
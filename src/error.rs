@@ -0,0 +1,153 @@
+//! The error type every fallible bbsmon function returns.
+
+error_chain! {
+    errors {
+        /// The config can't be read or is invalid.
+        Config(file: String) {
+            description("bad config")
+            display("bad config {}", file)
+        }
+        /// An HTTP server answered with an error status.
+        HttpStatus(url: String, code: u16) {
+            description("HTTP error status")
+            display("{} answered {}", url, code)
+        }
+        /// Logging in to get a feed's session failed.
+        LoginFailed(url: String, reason: String) {
+            description("login failed")
+            display("login at {} failed: {}", url, reason)
+        }
+        /// Some feeds or notifiers failed after the others were processed.
+        RunIncomplete(failed: Vec<String>) {
+            description("run incomplete")
+            display("run incomplete, failed: {}", failed.join(", "))
+        }
+        /// 'max_run_seconds' passed before every feed was handled.
+        DeadlineExceeded(skipped: Vec<String>) {
+            description("run deadline exceeded")
+            display("max_run_seconds passed, skipped: {}", skipped.join(", "))
+        }
+    }
+
+    foreign_links {
+        Io(::std::io::Error);
+        Http(::reqwest::Error);
+        Rss(::rss::Error);
+        Json(::serde_json::Error);
+        Date(::chrono::ParseError);
+        Render(::tera::Error);
+        Mail(::lettre::error::Error);
+        Address(::lettre::address::AddressError);
+        Tranport(::lettre::transport::smtp::Error);
+    }
+}
+
+/// "outer: inner: innermost" for an error and its causes.
+pub fn error_chain_string(e: &Error) -> String {
+    e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ")
+}
+
+/// An error and each of its causes on a line of its own, as bbsmon prints
+/// it when it fails:
+///
+/// ```text
+/// error: outer
+///   caused by: inner
+/// ```
+pub fn error_report(e: &Error) -> String {
+    let mut report = format!("error: {}", e);
+    for cause in e.iter().skip(1) {
+        report.push_str(&format!("\n  caused by: {}", cause));
+    }
+
+    report
+}
+
+/// Exit code of an unexpected failure.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code when the config can't be read or is invalid.
+pub const EXIT_CONFIG: i32 = 3;
+/// Exit code of a run in which some feeds or notifiers failed.
+pub const EXIT_INCOMPLETE: i32 = 4;
+/// Exit code of a run cut short by 'max_run_seconds'.
+pub const EXIT_DEADLINE: i32 = 5;
+
+/// The exit code bbsmon fails with on 'e'.
+pub fn exit_code(e: &Error) -> i32 {
+    match *e.kind() {
+        ErrorKind::Config(_) => EXIT_CONFIG,
+        ErrorKind::RunIncomplete(_) => EXIT_INCOMPLETE,
+        ErrorKind::DeadlineExceeded(_) => EXIT_DEADLINE,
+        _ => EXIT_FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    fn chained() -> Error {
+        let io: Result<()> = Err(io::Error::new(io::ErrorKind::NotFound, "no such file").into());
+        io.chain_err(|| "can't read snapshots/bbs.xml")
+            .chain_err(|| "bbs")
+            .unwrap_err()
+    }
+
+    #[test]
+    fn report_lists_every_cause_once() {
+        assert_eq!(error_report(&chained()),
+                   "error: bbs\n  caused by: can't read snapshots/bbs.xml\n  caused by: no such file");
+        assert_eq!(error_chain_string(&chained()), "bbs: can't read snapshots/bbs.xml: no such file");
+    }
+
+    // an error of 'kind' caused by a missing file.
+    fn around_cause(kind: ErrorKind) -> Error {
+        let io: Result<()> = Err(io::Error::new(io::ErrorKind::NotFound, "no such file").into());
+        io.chain_err(|| kind).unwrap_err()
+    }
+
+    #[test]
+    fn config_error_reports_its_cause() {
+        let e = around_cause(ErrorKind::Config("bbsmon.json".to_string()));
+        assert_eq!(error_report(&e), "error: bad config bbsmon.json\n  caused by: no such file");
+        assert_eq!(exit_code(&e), EXIT_CONFIG);
+    }
+
+    #[test]
+    fn http_status_reports_its_cause() {
+        let e = around_cause(ErrorKind::HttpStatus("http://bbs/rss".to_string(), 503));
+        assert_eq!(error_report(&e), "error: http://bbs/rss answered 503\n  caused by: no such file");
+        assert_eq!(exit_code(&e), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn login_failure_reports_its_cause() {
+        let e = around_cause(ErrorKind::LoginFailed("http://bbs/login".to_string(), "no session cookie".to_string()));
+        assert_eq!(error_report(&e),
+                   "error: login at http://bbs/login failed: no session cookie\n  caused by: no such file");
+        assert_eq!(exit_code(&e), EXIT_FAILURE);
+    }
+
+    #[test]
+    fn incomplete_run_reports_its_cause() {
+        let e = around_cause(ErrorKind::RunIncomplete(vec!["bbs".to_string(), "mail".to_string()]));
+        assert_eq!(error_report(&e), "error: run incomplete, failed: bbs, mail\n  caused by: no such file");
+        assert_eq!(exit_code(&e), EXIT_INCOMPLETE);
+    }
+
+    #[test]
+    fn exceeded_deadline_reports_its_cause() {
+        let e = around_cause(ErrorKind::DeadlineExceeded(vec!["market".to_string()]));
+        assert_eq!(error_report(&e), "error: max_run_seconds passed, skipped: market\n  caused by: no such file");
+        assert_eq!(exit_code(&e), EXIT_DEADLINE);
+    }
+
+    #[test]
+    fn plain_error_has_no_cause() {
+        let e: Error = "no feed configured".into();
+        assert_eq!(e.iter().count(), 1);
+        assert_eq!(error_report(&e), "error: no feed configured");
+    }
+}
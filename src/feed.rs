@@ -0,0 +1,599 @@
+//! Fetching and parsing feeds.
+
+use std::cmp::Reverse;
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::path::Path;
+use std::collections::HashSet;
+
+use chrono::DateTime;
+use chrono::Local;
+
+use rss::Channel;
+use rss::Item;
+use rss::extension::Extension;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use reqwest::Url;
+use reqwest::Client;
+use reqwest::header::ETAG;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderValue;
+use reqwest::header::LAST_MODIFIED;
+
+use regex::Regex;
+
+use tokio::sync::Semaphore;
+
+use reqwest;
+use serde_json;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::config::FeedConfig;
+use crate::retry::RetryPolicy;
+use crate::source::Timeouts;
+use crate::source::feed_source;
+use crate::log::Record;
+use crate::diff::Changes;
+use crate::state::is_gz;
+use crate::state::write_atomic;
+use crate::state::read_state_file;
+use crate::state::write_state_file;
+use crate::state::load_snapshot;
+use crate::source::FeedSource;
+use crate::runtime::block_on;
+use crate::timings::timed;
+use crate::timings::timed_async;
+
+/// Largest document 'RssContext::from_reader' parses, and so the most
+/// 'max_feed_bytes' may allow.
+pub const MAX_FEED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Deepest element nesting 'RssContext::from_reader' parses; the rss parser
+/// recurses into extension elements.
+pub const MAX_FEED_DEPTH: usize = 64;
+
+/// A parsed feed together with the document it was parsed from.
+pub struct RssContext {
+    /// The document as fetched, what snapshots store.
+    pub raw: String,
+    /// The parsed channel.
+    pub channel: Channel,
+}
+
+impl RssContext {
+    /// Fetch and parse the feed at 'url'.
+    pub fn from_url(url: &str) -> Result<RssContext> {
+        let resp = reqwest::blocking::get(url).chain_err(|| format!("can't fetch {}", url))?;
+        RssContext::from_reader(resp).chain_err(|| format!("can't parse {}", url))
+    }
+
+    /// Plain or gzip-compressed snapshots, told apart by magic bytes.
+    pub fn from_file<P: AsRef<Path>>(filename: P) -> Result<RssContext> {
+        let filename = filename.as_ref();
+        let bytes = read_state_file(filename).chain_err(|| format!("can't read {}", filename.display()))?;
+        RssContext::from_reader(&bytes[..]).chain_err(|| format!("can't parse {}", filename.display()))
+    }
+
+    /// Store the document as it was fetched, gzipped when 'filename' ends
+    /// with ".gz".
+    pub fn to_file<P: AsRef<Path>>(&self, filename: P) -> Result<()> {
+        let filename = filename.as_ref();
+        write_state_file(filename, self.raw.as_bytes(), is_gz(filename))
+    }
+
+    /// Drop items whose guid, or link when they have no guid, an earlier
+    /// item already has. Returns how many were dropped.
+    pub fn dedupe(&mut self) -> usize {
+        let mut seen = HashSet::new();
+        let before = self.channel.items.len();
+
+        self.channel.items.retain(|item| {
+            let key = match item.guid {
+                Some(ref guid) => Some(guid.value.clone()),
+                None => item.link.clone(),
+            };
+            key.is_none_or(|key| seen.insert(key))
+        });
+
+        before - self.channel.items.len()
+    }
+
+    /// Drop all but the 'max_items' newest items by pub_date, keeping the
+    /// order of the rest; items without a readable date count as oldest.
+    /// Returns how many were dropped.
+    pub fn keep_newest(&mut self, max_items: usize) -> usize {
+        let before = self.channel.items.len();
+        if before <= max_items {
+            return 0;
+        }
+
+        let mut by_date: Vec<(usize, Option<i64>)> = self.channel.items.iter().enumerate()
+            .map(|(i, item)| (i, item.pub_date.as_ref()
+                .and_then(|d| DateTime::parse_from_rfc2822(d).ok()).map(|d| d.timestamp())))
+            .collect();
+        by_date.sort_by_key(|&(_, date)| Reverse(date));
+        let newest: HashSet<usize> = by_date.iter().take(max_items).map(|&(i, _)| i).collect();
+
+        let mut i = 0;
+        self.channel.items.retain(|_| {
+            i += 1;
+            newest.contains(&(i - 1))
+        });
+
+        before - self.channel.items.len()
+    }
+
+    /// Parse a document read from 'reader'. Documents over 'MAX_FEED_BYTES'
+    /// or nested deeper than 'MAX_FEED_DEPTH' are refused before parsing.
+    pub fn from_reader<R: Read>(reader: R) -> Result<RssContext> {
+        let mut body = String::new();
+        reader.take(MAX_FEED_BYTES + 1).read_to_string(&mut body)?;
+        if body.len() as u64 > MAX_FEED_BYTES {
+            bail!("document is larger than {} bytes", MAX_FEED_BYTES);
+        }
+        check_depth(&body)?;
+
+        let channel: Channel = body.parse()?;
+
+        Ok(RssContext {
+            raw: body,
+            channel,
+        })
+    }
+}
+
+// fail on documents nested deeper than 'MAX_FEED_DEPTH'; malformed ones are
+// left to the rss parser to report.
+fn check_depth(body: &str) -> Result<()> {
+    let mut reader = Reader::from_str(body);
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(_)) => {
+                depth += 1;
+                if depth > MAX_FEED_DEPTH {
+                    bail!("elements are nested deeper than {}", MAX_FEED_DEPTH);
+                }
+            }
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// HTTP validators of the last fetch of a feed, sent back so that an
+/// unchanged feed answers 304 instead of the whole document.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Validators {
+    /// ETag of the last response.
+    pub etag: Option<String>,
+    /// Last-Modified of the last response.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// The validators of a response with 'headers'.
+    pub fn from_headers(headers: &HeaderMap) -> Validators {
+        let header = |name| headers.get(name).and_then(|v: &HeaderValue| v.to_str().ok()).map(|v| v.to_string());
+        Validators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// The validators 'url' answers a HEAD request with.
+    pub async fn head(client: &Client, url: &str) -> Result<Validators> {
+        let resp = client.head(url).send().await.chain_err(|| format!("can't check {}", url))?;
+        if !resp.status().is_success() {
+            bail!("{} answered {} to HEAD", url, resp.status());
+        }
+
+        Ok(Validators::from_headers(resp.headers()))
+    }
+
+    /// Whether 'other' stands for the same document, by ETag when both have
+    /// one and else by Last-Modified.
+    pub fn same_document(&self, other: &Validators) -> bool {
+        if self.etag.is_some() && other.etag.is_some() {
+            return self.etag == other.etag;
+        }
+
+        self.last_modified.is_some() && self.last_modified == other.last_modified
+    }
+
+    /// The validators stored at 'path', None when there are none.
+    pub fn load(path: &Path) -> Result<Option<Validators>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Replace what is stored at 'path' with these validators.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+}
+
+// the extension prefix of what bbsmon notes in items of changes, see
+// 'item_note'. changes are cloned out of the documents, so notes are never
+// written to a snapshot.
+const NOTE_PREFIX: &str = "bbsmon";
+
+/// What 'set_item_note' noted in 'item' under 'name'.
+pub fn item_note(item: &Item, name: &str) -> Option<String> {
+    item.extensions.get(NOTE_PREFIX)
+        .and_then(|map| map.get(name))
+        .and_then(|exts| exts.first())
+        .and_then(|ext| ext.value.clone())
+}
+
+/// Note 'value' in 'item' under 'name', for a later step to find with
+/// 'item_note'; kept as an item extension.
+pub fn set_item_note(item: &mut Item, name: &str, value: String) {
+    let ext = Extension {
+        name: format!("{}:{}", NOTE_PREFIX, name),
+        value: Some(value),
+        ..Default::default()
+    };
+    item.extensions.entry(NOTE_PREFIX.to_string()).or_default()
+        .insert(name.to_string(), vec![ext]);
+}
+
+/// Identity of an item across fetches: its guid, else its link, else its title.
+pub fn item_key(item: &Item) -> String {
+    if let Some(ref guid) = item.guid {
+        return guid.value.clone();
+    }
+
+    item.link.clone()
+        .or(item.title.clone())
+        .unwrap_or_default()
+}
+
+/// The reply count of 'item': its slash:comments, else the first capture
+/// group of 'regex' in its title, then its description. None when neither
+/// gives a number.
+pub fn reply_count(item: &Item, regex: &Option<Regex>) -> Option<u64> {
+    let comments = item.extensions.get("slash")
+        .and_then(|slash| slash.get("comments"))
+        .and_then(|comments| comments.first())
+        .and_then(|comments| comments.value.as_ref())
+        .and_then(|value| value.trim().parse().ok());
+    if comments.is_some() {
+        return comments;
+    }
+
+    let re = regex.as_ref()?;
+    item.title.iter().chain(item.description.iter())
+        .filter_map(|text| re.captures(text).and_then(|caps| caps.get(1)).and_then(|n| n.as_str().parse().ok()))
+        .next()
+}
+
+/// Namespace of the atom:link elements 'next_link' reads.
+pub const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// The href of the atom:link with rel="next" of 'channel', its next page
+/// as RFC 5005 pages feeds, resolved against 'base', the page's URL.
+pub fn next_link(channel: &Channel, base: &str) -> Option<String> {
+    let prefix = channel.namespaces.iter().find(|&(_, uri)| uri == ATOM_NAMESPACE)
+        .map_or("atom", |(prefix, _)| prefix.as_str());
+    let links = channel.extensions.get(prefix).and_then(|map| map.get("link"))?;
+
+    let href = match links.iter().find(|link| link.attrs.get("rel").is_some_and(|rel| rel == "next")) {
+        Some(link) => link.attrs.get("href"),
+        None => return None,
+    };
+
+    href.map(|href| Url::parse(base).and_then(|base| base.join(href)).map_or(href.clone(), |u| u.to_string()))
+}
+
+// add the items of the pages after 'ctx' to it, following next links up
+// to the 'max_pages' of 'source', and rewrite its document to match. The
+// first page whose items 'old' all has is the last one read; one that
+// fails ends it too, keeping what was read until then.
+async fn follow_next(client: &Client, source: &dyn FeedSource, sem: &Semaphore, ctx: &mut RssContext,
+                     old: Option<&RssContext>) -> Result<()> {
+    let known: HashSet<String> = old.map_or(HashSet::new(), |old| old.channel.items.iter().map(item_key).collect());
+    let mut have: HashSet<String> = ctx.channel.items.iter().map(item_key).collect();
+    let mut followed = HashSet::new();
+    let mut next = next_link(&ctx.channel, &source.describe());
+    let mut pages = 1;
+
+    while let Some(url) = next {
+        if pages >= source.max_pages() || !followed.insert(url.clone()) {
+            break;
+        }
+
+        let page = {
+            let _permit = timed_async("wait", sem.acquire()).await.expect("the fetch semaphore stays open");
+            timed_async("fetch", source.fetch_page(client, &url)).await
+        }.and_then(|raw| {
+            timed("parse", || RssContext::from_reader(&raw.body[..])).chain_err(|| format!("can't parse {}", url))
+        });
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                Record::warn("can't read the next page").field("url", &url).field("error", error_chain_string(&e))
+                    .log(format!("{}: warning: can't read the next page: {}", url, error_chain_string(&e)));
+                break;
+            }
+        };
+
+        // a page of items seen before is kept, but the ones after it are too old.
+        let all_known = page.channel.items.iter().all(|item| known.contains(&item_key(item)));
+        next = if all_known { None } else { next_link(&page.channel, &url) };
+        pages += 1;
+        for item in page.channel.items {
+            if have.insert(item_key(&item)) {
+                ctx.channel.items.push(item);
+            }
+        }
+    }
+
+    if pages > 1 {
+        let xml = ctx.channel.write_to(Vec::new())?;
+        ctx.raw = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", String::from_utf8_lossy(&xml));
+    }
+
+    Ok(())
+}
+
+/// How 'fetch_diff_items' compares a feed with its snapshot.
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    pub dedupe: bool,
+    pub max_items: Option<usize>,
+    pub diff_strategy: String,
+    /// the 'state_key' the snapshot is signed with.
+    pub key: Option<String>,
+}
+
+impl DiffOptions {
+    /// What 'config' asks of the comparisons of 'feed'.
+    pub fn of(config: &Config, feed: &FeedConfig) -> DiffOptions {
+        DiffOptions {
+            dedupe: config.dedupe_items,
+            max_items: feed.max_feed_items,
+            diff_strategy: config.diff_strategy.clone(),
+            key: config.state_key.clone(),
+        }
+    }
+}
+
+/// Fetch 'remote' and compare it with the snapshot in 'local'. The changes are
+/// 'None' when there is no usable snapshot to compare against: on the first
+/// run, or when the stored one failed verification.
+///
+/// 'source' is given the 'validators' file when there is a snapshot to fall
+/// back on; a document it reports unchanged gives the snapshot back without
+/// changes. The returned validators are to be saved once the changes are
+/// handled, they are None when there is nothing new to save.
+///
+/// With 'dedupe' of 'options' both documents lose their repeated items
+/// before they are compared, see 'RssContext::dedupe', and with 'max_items'
+/// all but the newest ones, see 'RssContext::keep_newest'. Items are told
+/// apart as 'diff_strategy' says, see 'diff::diff_key'. A 'source' reading several
+/// pages adds the items of the ones after the first to the fetched
+/// document. At most as many fetches as 'sem' has permits run at once.
+pub async fn fetch_diff_items(client: &Client, local: &Path, validators: &Path, source: &dyn FeedSource,
+                              options: &DiffOptions, sem: &Semaphore)
+                              -> Result<(Option<Changes>, RssContext, Option<Validators>)> {
+    let DiffOptions { dedupe, max_items, ref diff_strategy, ref key } = *options;
+    let mut old_ctx = timed("parse", || load_snapshot(local, key)).chain_err(|| "can't load the stored snapshot")?;
+    if let (true, Some(ref mut old_ctx)) = (dedupe, old_ctx.as_mut()) {
+        old_ctx.dedupe();
+    }
+    if let (Some(max_items), Some(ref mut old_ctx)) = (max_items, old_ctx.as_mut()) {
+        old_ctx.keep_newest(max_items);
+    }
+
+    let stored = match old_ctx {
+        Some(_) => Validators::load(validators)?,
+        None => None,
+    };
+
+    let fetched = {
+        let _permit = timed_async("wait", sem.acquire()).await.expect("the fetch semaphore stays open");
+        timed_async("fetch", source.fetch(client, stored.as_ref())).await?
+    };
+
+    let raw = match fetched {
+        Some(raw) => raw,
+        None => match old_ctx {
+            Some(old_ctx) => return Ok((Some(Changes::default()), old_ctx, None)),
+            None => bail!("{} reported no change to an unconditional fetch", source.describe()),
+        },
+    };
+
+    let mut new_ctx = timed("parse", || RssContext::from_reader(&raw.body[..]))
+        .chain_err(|| format!("can't parse {}", source.describe()))?;
+    if source.max_pages() > 1 {
+        follow_next(client, source, sem, &mut new_ctx, old_ctx.as_ref()).await?;
+    }
+    if dedupe {
+        let dropped = new_ctx.dedupe();
+        if dropped > 0 {
+            Record::info("dropped repeated items").field("url", source.describe()).field("items", dropped)
+                .log(format!("{}: dropped {} repeated items.", source.describe(), dropped));
+        }
+    }
+    if let Some(max_items) = max_items {
+        let skipped = new_ctx.keep_newest(max_items);
+        if skipped > 0 {
+            Record::info("skipped items past max_feed_items").field("url", source.describe()).field("items", skipped)
+                .log(format!("{}: skipped {} items past max_feed_items.", source.describe(), skipped));
+        }
+    }
+    let new_validators = raw.validators;
+
+    let old_ctx = match old_ctx {
+        Some(old_ctx) => old_ctx,
+        None => return Ok((None, new_ctx, Some(new_validators))),
+    };
+
+    let changes = timed("diff", || RssContext::changes(&new_ctx, &old_ctx, diff_strategy));
+
+    Ok((Some(changes), new_ctx, Some(new_validators)))
+}
+
+/// Which syndication format a document is, from its root element: "RSS
+/// 2.0", "Atom", "unknown XML", ...
+pub fn sniff_format(body: &str) -> &'static str {
+    let trimmed = body.trim_start_matches('\u{feff}').trim_start();
+    if trimmed.starts_with("{") {
+        return if trimmed.contains("jsonfeed.org") { "JSON Feed" } else { "unknown JSON" };
+    }
+
+    let mut rest = trimmed;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if rest.starts_with("?") || rest.starts_with("!") {
+            continue;
+        }
+
+        let root_end = rest.find('>').unwrap_or(rest.len());
+        let root = &rest[..root_end];
+
+        if root.starts_with("rss") {
+            return if root.contains("version=\"2.0\"") || root.contains("version='2.0'") {
+                "RSS 2.0"
+            } else {
+                "RSS 0.9x"
+            };
+        }
+        if root.starts_with("rdf:RDF") {
+            return "RSS 1.0";
+        }
+        if root.starts_with("feed") {
+            return "Atom";
+        }
+
+        return "unknown XML";
+    }
+
+    "unknown"
+}
+
+// the encoding named by the XML declaration, if any.
+fn declared_encoding(body: &str) -> Option<String> {
+    let decl_end = match body.find("?>") {
+        Some(end) if body.trim_start().starts_with("<?xml") => end,
+        _ => return None,
+    };
+    let decl = &body[..decl_end];
+
+    let start = decl.find("encoding=")? + "encoding=".len();
+    let value = decl[start..].trim_matches(|c| c == '"' || c == '\'' || c == ' ');
+    let end = value.find(['"', '\'']).unwrap_or(value.len());
+
+    Some(value[..end].to_string())
+}
+
+// a feed that isn't configured, at 'source'.
+fn unconfigured_feed(source: &str) -> Result<FeedConfig> {
+    let mut feed = Map::new();
+    feed.insert("name".to_string(), Value::String(source.to_string()));
+    feed.insert("remote_rss".to_string(), Value::String(source.to_string()));
+    Ok(serde_json::from_value(Value::Object(feed))?)
+}
+
+/// Fetch or read 'source' and report how well bbsmon can work with it.
+/// It is fetched the way a run fetches it: with the retry, timeouts and
+/// 'max_feed_bytes' of 'config', and with the login and cookies of the
+/// configured feed that 'source' names or is the 'remote_rss' of.
+/// Warnings are printed, only problems that make the feed unusable fail.
+pub fn validate(source: &str, config: Option<&Config>) -> Result<()> {
+    let feed = match config.and_then(|c| c.feeds.iter().find(|f| f.name == source || f.remote_rss == source)) {
+        Some(feed) => feed.clone(),
+        None => unconfigured_feed(source)?,
+    };
+    let (retry, max_bytes, timeouts) = match config {
+        Some(config) => (config.retry.clone(), config.max_feed_bytes, config.timeouts()),
+        None => (RetryPolicy::default(), MAX_FEED_BYTES, Timeouts::default()),
+    };
+
+    let raw = block_on(feed_source(&feed, &retry, max_bytes, timeouts, None)?.fetch(&timeouts.client()?, None))?
+        .expect("an unconditional fetch answers");
+    let content_type = raw.content_type;
+    let mut validators = Vec::new();
+    if raw.validators.etag.is_some() {
+        validators.push("ETag");
+    }
+    if raw.validators.last_modified.is_some() {
+        validators.push("Last-Modified");
+    }
+    let body = String::from_utf8(raw.body).chain_err(|| format!("{} is not UTF-8", source))?;
+
+    let format = sniff_format(&body);
+    let encoding = declared_encoding(&body)
+        .or(content_type.as_ref().and_then(|ct| {
+            ct.find("charset=").map(|i| ct[i + "charset=".len()..].trim().to_string())
+        }))
+        .unwrap_or("UTF-8 (default)".to_string());
+
+    println!("source:     {}", source);
+    println!("format:     {}", format);
+    println!("encoding:   {}", encoding);
+    if validators.is_empty() {
+        println!("validators: none (conditional GET not possible)");
+    } else {
+        println!("validators: {}", validators.join(", "));
+    }
+
+    if format != "RSS 2.0" {
+        bail!("bbsmon only reads RSS 2.0 feeds, this is {}", format);
+    }
+
+    let channel: Channel = body.parse().chain_err(|| format!("{} is not valid RSS", source))?;
+    println!("items:      {}", channel.items.len());
+
+    let mut warnings = Vec::new();
+    let mut dates = Vec::new();
+
+    for (i, item) in channel.items.iter().enumerate() {
+        let label = item.title.clone().unwrap_or(format!("item #{}", i + 1));
+
+        if item.guid.is_none() {
+            warnings.push(format!("'{}' has no guid", label));
+        }
+
+        match item.pub_date {
+            None => warnings.push(format!("'{}' has no pubDate", label)),
+            Some(ref date) => match DateTime::parse_from_rfc2822(date) {
+                Ok(date) => dates.push(date),
+                Err(_) => warnings.push(format!("'{}' has an unparsable pubDate '{}'", label, date)),
+            },
+        }
+    }
+
+    if let (Some(first), Some(last)) = (dates.iter().min(), dates.iter().max()) {
+        println!("dates:      {} .. {}",
+                 first.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+                 last.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"));
+    }
+
+    for warning in &warnings {
+        println!("warning: {}", warning);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! What a feed says about polling it: the <ttl>, <skipHours> and
+//! <skipDays> of its channel, followed by 'bbsmon daemon' unless
+//! 'respect_ttl' is off.
+
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::Timelike;
+use chrono::Utc;
+
+use rss::Channel;
+
+// how much shorter than a ttl the time since the last check may be and
+// still count as over it, for the seconds the stored times are rounded to.
+const TTL_SLACK_SECS: i64 = 5;
+
+/// The polling hints of a channel, ignoring those that don't parse.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PublisherHints {
+    /// <ttl>, given in minutes by the feed.
+    pub ttl_secs: Option<u64>,
+    /// Hours of <skipHours>, 0 to 23 in UTC.
+    pub skip_hours: Vec<u32>,
+    /// Days of <skipDays>, like "Saturday", in UTC.
+    pub skip_days: Vec<String>,
+}
+
+impl PublisherHints {
+    /// The hints of 'channel'. An hour of 24, as RSS 0.91 counted them, is
+    /// taken for 0.
+    pub fn of(channel: &Channel) -> PublisherHints {
+        PublisherHints {
+            ttl_secs: channel.ttl.as_ref().and_then(|ttl| ttl.trim().parse::<u64>().ok()).map(|minutes| minutes * 60),
+            skip_hours: channel.skip_hours.iter()
+                .filter_map(|hour| hour.trim().parse::<u32>().ok())
+                .filter(|hour| *hour <= 24)
+                .map(|hour| hour % 24)
+                .collect(),
+            skip_days: channel.skip_days.iter()
+                .map(|day| day.trim().to_string())
+                .filter(|day| !day.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Why the feed isn't fetched at 'now', None when it is: 'now' is in
+    /// one of the skipped hours or days, or the ttl isn't over since
+    /// 'last_check'. A ttl no longer than 'interval_secs' changes nothing.
+    pub fn skip_reason(&self, interval_secs: u64, last_check: Option<DateTime<FixedOffset>>, now: DateTime<Utc>)
+                       -> Option<String> {
+        if self.skip_hours.contains(&now.hour()) {
+            return Some(format!("skipHours has {:02}:00 UTC", now.hour()));
+        }
+
+        let day = now.format("%A").to_string();
+        if self.skip_days.iter().any(|d| d.to_lowercase() == day.to_lowercase()) {
+            return Some(format!("skipDays has {}", day));
+        }
+
+        if let (Some(ttl), Some(last_check)) = (self.ttl_secs, last_check) {
+            let elapsed = now.timestamp() - last_check.timestamp();
+            if ttl > interval_secs && elapsed + TTL_SLACK_SECS < ttl as i64 {
+                return Some(format!("its ttl of {} minutes isn't over", ttl / 60));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn hints_stretch_the_interval_and_skip_hours_and_days() {
+        let channel = Channel {
+            ttl: Some("30".to_string()),
+            skip_hours: vec!["3".to_string(), "24".to_string(), "late".to_string()],
+            skip_days: vec!["Sunday".to_string()],
+            ..Channel::default()
+        };
+        let hints = PublisherHints::of(&channel);
+        assert_eq!(hints.ttl_secs, Some(1800));
+        assert_eq!(hints.skip_hours, vec![3, 0]);
+
+        // 2016-11-05 was a Saturday.
+        let last_check = DateTime::parse_from_rfc3339("2016-11-05T17:00:00+08:00").unwrap();
+        let at = |day: u32, hour: u32, min: u32| Utc.with_ymd_and_hms(2016, 11, day, hour, min, 0).unwrap();
+        assert!(hints.skip_reason(600, Some(last_check), at(5, 9, 20)).is_some());
+        assert_eq!(hints.skip_reason(600, Some(last_check), at(5, 9, 30)), None);
+        assert_eq!(hints.skip_reason(3600, Some(last_check), at(5, 9, 20)), None);
+        assert_eq!(hints.skip_reason(600, None, at(5, 9, 20)), None);
+
+        assert!(hints.skip_reason(600, None, at(5, 3, 10)).unwrap().contains("03:00 UTC"));
+        assert!(hints.skip_reason(600, None, at(5, 0, 10)).is_some());
+        assert!(hints.skip_reason(600, None, at(6, 12, 0)).unwrap().contains("Sunday"));
+    }
+}
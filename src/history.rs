@@ -0,0 +1,313 @@
+//! The archive of notified items, and sending them again.
+
+use std::path::Path;
+use std::collections::HashSet;
+
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::Local;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use chrono::TimeZone;
+
+use serde_json;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::feed::RssContext;
+use crate::notify::Notifier;
+use crate::notify::Mail;
+#[cfg(feature = "sink-webhook")]
+use crate::notify::RocketChat;
+use crate::queue::PendingItem;
+use crate::render::SerItem;
+use crate::render::convert_to_ser_items;
+use crate::render::restrict_fields;
+use crate::runtime::block_on;
+use crate::state::StatePaths;
+use crate::state::read_state_file;
+use crate::state::write_state_file;
+
+/// One line of the 'history' archive.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryEntry {
+    /// Name of the feed.
+    pub feed: String,
+    /// Identity of the item, see 'item_key'.
+    pub key: String,
+    /// When the item was sent.
+    pub notified_at: String,
+    /// The item as it was sent, before 'include_fields'.
+    pub item: SerItem<'static>,
+}
+
+impl HistoryEntry {
+    /// Entries for 'items' of 'feed' notified now.
+    pub fn from_pending(feed: &str, items: &[PendingItem]) -> Vec<HistoryEntry> {
+        let now = Local::now().to_rfc3339();
+
+        items.iter().map(|p| HistoryEntry {
+            feed: feed.to_string(),
+            key: p.key.clone(),
+            notified_at: now.clone(),
+            item: p.item.clone(),
+        }).collect()
+    }
+
+    /// Every archived entry, in the order they were added.
+    pub fn load(path: &Path) -> Result<Vec<HistoryEntry>> {
+        let mut entries = Vec::new();
+
+        if !path.exists() {
+            return Ok(entries);
+        }
+
+        let content = String::from_utf8(read_state_file(path)?)
+            .chain_err(|| format!("{} is not UTF-8", path.display()))?;
+
+        for (n, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: HistoryEntry = serde_json::from_str(line)
+                .chain_err(|| format!("{}:{}: bad history entry", path.display(), n + 1))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Add 'entries' whose (feed, key) is not archived yet. The whole file is
+    /// rewritten atomically so a run is recorded entirely or not at all,
+    /// gzipped with 'compress'.
+    pub fn append(path: &Path, entries: Vec<HistoryEntry>, compress: bool) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut archived = HistoryEntry::load(path)?;
+        let mut seen: HashSet<(String, String)> = archived.iter()
+            .map(|e| (e.feed.clone(), e.key.clone()))
+            .collect();
+
+        for entry in entries {
+            if seen.insert((entry.feed.clone(), entry.key.clone())) {
+                archived.push(entry);
+            }
+        }
+
+        HistoryEntry::save(path, &archived, compress)
+    }
+
+    /// Replace what is stored at 'path' with 'entries', atomically and
+    /// gzipped with 'compress'.
+    pub fn save(path: &Path, entries: &Vec<HistoryEntry>, compress: bool) -> Result<()> {
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+
+        write_state_file(path, content.as_bytes(), compress)
+    }
+}
+
+/// Print the archived entries notified since 'since', see 'parse_since',
+/// whose text contains 'grep'.
+pub fn history(config: &Config, since: Option<&str>, grep: Option<&str>, json: bool) -> Result<()> {
+    let path = match config.history {
+        Some(ref path) => Path::new(path),
+        None => bail!("no 'history' file configured"),
+    };
+
+    let since = match since {
+        Some(since) => Some(parse_since(since).chain_err(|| "bad --since")?),
+        None => None,
+    };
+    let grep = grep.map(|g| g.to_lowercase());
+
+    for entry in HistoryEntry::load(path)? {
+        let notified_at = DateTime::parse_from_rfc3339(&entry.notified_at)
+            .chain_err(|| format!("bad timestamp '{}' in history", entry.notified_at))?
+            .with_timezone(&Local);
+
+        if let Some(since) = since {
+            if notified_at.timestamp() < since.timestamp() {
+                continue;
+            }
+        }
+
+        if let Some(ref grep) = grep {
+            let item = &entry.item;
+            let matched = [&item.title, &item.link, &item.description, &item.author]
+                .iter()
+                .any(|f| f.as_ref().is_some_and(|v| v.to_lowercase().contains(grep.as_str())));
+            if !matched {
+                continue;
+            }
+        }
+
+        if json {
+            println!("{}", serde_json::to_string(&entry)?);
+        } else {
+            println!("{} [{}] {} {}",
+                     notified_at.format("%Y-%m-%d %H:%M:%S"),
+                     entry.feed,
+                     entry.item.title.as_deref().unwrap_or(""),
+                     entry.item.link.as_deref().unwrap_or(""));
+        }
+    }
+
+    Ok(())
+}
+
+/// What 'bbsmon replay --sink' accepts, depending on the features built in.
+#[cfg(feature = "sink-webhook")]
+pub const SINKS: &[&str] = &["email", "rocketchat"];
+/// What 'bbsmon replay --sink' accepts, depending on the features built in.
+#[cfg(not(feature = "sink-webhook"))]
+pub const SINKS: &[&str] = &["email"];
+
+// the items of 'entries' by feed, only those of 'feed' when given.
+fn batch_entries(config: &Config, entries: Vec<HistoryEntry>, feed: Option<&str>)
+                 -> Vec<(String, Vec<SerItem<'static>>)> {
+    let mut batches: Vec<(String, Vec<SerItem<'static>>)> = Vec::new();
+
+    for entry in entries {
+        if feed.is_some_and(|name| name != entry.feed) {
+            continue;
+        }
+
+        let (name, item) = (entry.feed, restrict_fields(entry.item, &config.include_fields));
+        match batches.iter().position(|b| b.0 == name) {
+            Some(i) => batches[i].1.push(item),
+            None => batches.push((name, vec![item])),
+        }
+    }
+
+    batches
+}
+
+/// "2024-01-01" (local midnight) or a full RFC 3339 timestamp.
+pub fn parse_since(since: &str) -> Result<DateTime<FixedOffset>> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(since) {
+        return Ok(ts);
+    }
+
+    let date = NaiveDate::parse_from_str(since, "%Y-%m-%d")
+        .chain_err(|| format!("bad timestamp '{}', expected YYYY-MM-DD or RFC 3339", since))?;
+    let midnight = Local.from_local_datetime(&date.and_time(NaiveTime::MIN)).earliest()
+        .ok_or(format!("{} has no local midnight", since))?;
+
+    Ok(DateTime::parse_from_rfc3339(&midnight.to_rfc3339())?)
+}
+
+/// Arguments of 'bbsmon replay'.
+pub struct ReplayOptions<'a> {
+    /// Replay what was notified since then, see 'parse_since'.
+    pub from: Option<&'a str>,
+    /// Replay what the last run that notified anything notified, instead
+    /// of the history or a snapshot.
+    pub last: bool,
+    /// Only this feed.
+    pub feed: Option<&'a str>,
+    /// One of 'SINKS', "email" when absent.
+    pub sink: Option<&'a str>,
+    /// Replay the difference to this rotated snapshot instead of the history.
+    pub snapshot: Option<&'a str>,
+    /// Only list what would be sent.
+    pub dry_run: bool,
+}
+
+/// Re-send already notified items, from the history archive, from the
+/// difference between a rotated snapshot and the current one, or as the
+/// last run notified them. They are rendered anew, so a fixed template
+/// shows on real data. Nothing is recorded as new history.
+pub fn replay(config: &Config, paths: &StatePaths, opts: &ReplayOptions) -> Result<()> {
+    if let Some(sink) = opts.sink {
+        if !SINKS.contains(&sink) {
+            bail!("unknown sink '{}', expected one of {:?}", sink, SINKS);
+        }
+    }
+
+    let mut batches: Vec<(String, Vec<SerItem>)> = Vec::new();
+
+    if opts.last {
+        if !paths.last_run.exists() {
+            bail!("no run notified anything yet, nothing to replay");
+        }
+
+        let entries = HistoryEntry::load(&paths.last_run)?;
+        batches = batch_entries(config, entries, opts.feed);
+    } else if let Some(rotated) = opts.snapshot {
+        let feed = match opts.feed {
+            Some(name) => config.feeds.iter().find(|f| f.name == name),
+            None if config.feeds.len() == 1 => config.feeds.first(),
+            None => bail!("--snapshot needs --feed when several feeds are configured"),
+        };
+        let feed = match feed {
+            Some(feed) => feed,
+            None => bail!("no feed named '{}'", opts.feed.unwrap_or_default()),
+        };
+
+        let current = RssContext::from_file(paths.snapshot(feed))?;
+        let old = RssContext::from_file(rotated)?;
+        let items = RssContext::diff(&current, &old);
+
+        let items = convert_to_ser_items(items, &config.include_fields, &config.missing_link);
+        batches.push((feed.name.clone(), items.into_iter().map(SerItem::into_owned).collect()));
+    } else {
+        let path = match config.history {
+            Some(ref path) => Path::new(path),
+            None => bail!("no 'history' file configured, use --snapshot or --last"),
+        };
+        let from = match opts.from {
+            Some(from) => parse_since(from)?,
+            None => bail!("--from is needed to replay from the history"),
+        };
+
+        let mut entries = Vec::new();
+        for entry in HistoryEntry::load(path)? {
+            let notified_at = DateTime::parse_from_rfc3339(&entry.notified_at)
+                .chain_err(|| format!("bad timestamp '{}' in history", entry.notified_at))?;
+            if notified_at >= from {
+                entries.push(entry);
+            }
+        }
+        batches = batch_entries(config, entries, opts.feed);
+    }
+
+    let subject = format!("[replay] {}", config.subject);
+
+    for (feed, items) in batches {
+        if items.is_empty() {
+            continue;
+        }
+
+        if opts.dry_run {
+            println!("{}: would replay {} items as '{}'", feed, items.len(), subject);
+            for item in &items {
+                println!("  {} {}",
+                         item.title.as_deref().unwrap_or(""),
+                         item.link.as_deref().unwrap_or(""));
+            }
+            continue;
+        }
+
+        let notifier: Box<dyn Notifier> = match opts.sink {
+            #[cfg(feature = "sink-webhook")]
+            Some("rocketchat") => match config.rocketchat_webhook {
+                Some(ref webhook) => Box::new(RocketChat::new(webhook, config.webhook_summary_only,
+                                                                    config.push_description_chars, &config.retry)),
+                None => bail!("no 'rocketchat_webhook' configured"),
+            },
+            _ => Box::new(Mail::new(config, &subject)),
+        };
+        block_on(notifier.notify(&format!("[replay] {}", feed), &items))
+            .chain_err(|| format!("can't replay {} to {}", feed, notifier.name()))?;
+        println!("{}: replayed {} items.", feed, items.len());
+    }
+
+    Ok(())
+}
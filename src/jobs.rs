@@ -0,0 +1,125 @@
+//! Several independent pipelines in one process, for a config with
+//! 'jobs', see 'Config::load_jobs'. Every job runs with its own config and
+//! state and tags its log records with its name.
+
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+use serde_json;
+
+use crate::error::*;
+use crate::config::Job;
+use crate::log;
+use crate::log::Record;
+use crate::pipeline::Pipeline;
+use crate::status::status;
+use crate::status::status_json;
+
+// what went wrong with a job, from its result or its panic.
+fn job_error<T>(result: thread::Result<Result<T>>) -> Option<String> {
+    match result {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(error_chain_string(&e)),
+        Err(_) => Some("it panicked".to_string()),
+    }
+}
+
+// how bad a job's failed 'result' was, the worst decides how
+// 'run_jobs_once' fails. a panic counts as an unexpected error.
+fn severity(result: &thread::Result<Result<()>>) -> u32 {
+    match *result {
+        Ok(Err(ref e)) => match *e.kind() {
+            ErrorKind::RunIncomplete(_) => 1,
+            ErrorKind::DeadlineExceeded(_) => 2,
+            ErrorKind::Config(_) => 3,
+            _ => 4,
+        },
+        _ => 4,
+    }
+}
+
+/// Run every job once, one after another. A job that fails is logged and
+/// the ones after it run all the same. The error names every failed job
+/// and has the kind of the worst failure, so that 'exit_code' tells an
+/// incomplete run or a passed deadline just like with a single job.
+pub fn run_jobs_once(jobs: &Vec<Job>) -> Result<()> {
+    let mut failed = Vec::new();
+    let mut worst = 0;
+    for job in jobs {
+        log::set_job(Some(job.name.clone()));
+        let pipeline = Pipeline::new(job.config.clone());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| pipeline.run_once()));
+        let how_bad = severity(&result);
+        if let Some(error) = job_error(result) {
+            Record::error("job failed").field("error", &error)
+                .log(format!("job failed: {}", error));
+            failed.push(job.name.clone());
+            worst = worst.max(how_bad);
+        }
+        log::set_job(None);
+    }
+
+    match worst {
+        0 => Ok(()),
+        1 => bail!(ErrorKind::RunIncomplete(failed)),
+        2 => bail!(ErrorKind::DeadlineExceeded(failed)),
+        3 => bail!(ErrorKind::Config(format!("of the jobs {}", failed.join(", ")))),
+        _ => bail!("{} of {} jobs failed: {}", failed.len(), jobs.len(), failed.join(", ")),
+    }
+}
+
+/// Run 'Pipeline::daemon' of every job, each in a thread of its own. A job
+/// whose daemon fails or panics is logged and started again after its
+/// 'interval_secs'; the other jobs don't notice. Only returns once every
+/// job thread is gone.
+pub fn daemon_jobs(jobs: Vec<Job>) -> Result<()> {
+    let mut threads = Vec::new();
+    for job in jobs {
+        threads.push(thread::spawn(move || {
+            log::set_job(Some(job.name.clone()));
+            let interval = job.config.interval_secs;
+            loop {
+                let pipeline = Pipeline::new(job.config.clone());
+                let error = match job_error(panic::catch_unwind(AssertUnwindSafe(|| pipeline.daemon()))) {
+                    Some(error) => error,
+                    None => return,
+                };
+                Record::error("job stopped, restarting it").field("error", &error).field("restart_in_secs", interval)
+                    .log(format!("job stopped: {}; restarting it in {} s.", error, interval));
+                thread::sleep(Duration::from_secs(interval));
+            }
+        }));
+    }
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+    Ok(())
+}
+
+/// 'status' of every job under a heading of its name, or as JSON one
+/// object holding what 'status_json' says of each job, by name.
+pub fn jobs_status(jobs: &Vec<Job>, json: bool) -> Result<()> {
+    if json {
+        let mut out = BTreeMap::new();
+        for job in jobs {
+            let pipeline = Pipeline::new(job.config.clone());
+            out.insert(job.name.clone(), status_json(pipeline.config(), pipeline.paths())?);
+        }
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    for (i, job) in jobs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("== job {} ==", job.name);
+        let pipeline = Pipeline::new(job.config.clone());
+        status(pipeline.config(), pipeline.paths(), false).chain_err(|| format!("in job '{}'", job.name))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! bbsmon watches RSS feeds, mails what changed and keeps enough state on
+//! disk to only ever tell about an item once.
+//!
+//! The binary is a thin layer over this crate: `Config::load` reads
+//! `bbsmon.json`, `Pipeline::run_once` checks every feed once, and a
+//! `Notifier` added with `Pipeline::add_notifier` is told about everything a
+//! run notified.
+
+#![recursion_limit = "1024"]
+
+#[macro_use]
+extern crate error_chain;
+
+extern crate rss;
+extern crate reqwest;
+extern crate lettre;
+extern crate chrono;
+extern crate flate2;
+extern crate fs2;
+extern crate quick_xml;
+extern crate crypto;
+extern crate scraper;
+extern crate regex;
+extern crate hickory_resolver;
+extern crate tokio;
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+extern crate serde_json;
+
+extern crate tera;
+
+pub mod error;
+pub mod log;
+pub mod retry;
+pub mod runtime;
+pub mod config;
+pub mod state;
+pub mod feed;
+pub mod hints;
+pub mod login;
+pub mod scrape;
+pub mod source;
+pub mod archive;
+pub mod transform;
+pub mod diff;
+pub mod messages;
+pub mod render;
+pub mod queue;
+pub mod notify;
+pub mod history;
+pub mod notification_log;
+pub mod report;
+pub mod merged;
+pub mod seen;
+pub mod opml;
+pub mod status;
+pub mod timings;
+pub mod server;
+pub mod pipeline;
+pub mod jobs;
+pub mod watch;
+pub mod preview;
+
+pub use crate::config::Config;
+pub use crate::notify::Notifier;
+pub use crate::pipeline::Pipeline;
@@ -0,0 +1,150 @@
+//! Log records of a run, printed as text lines or written as JSON objects.
+
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use chrono::Local;
+
+use serde::Serialize;
+use serde_json;
+use serde_json::Map;
+use serde_json::Value;
+
+/// What 'log_format' may be.
+pub const LOG_FORMATS: &[&str] = &["text", "json"];
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// the job the records of this thread belong to, see 'set_job'.
+thread_local!(static JOB: RefCell<Option<String>> = const { RefCell::new(None) });
+
+/// Write records as 'format' says from now on: "json", or text for
+/// anything else.
+pub fn set_format(format: &str) {
+    JSON.store(format == "json", Ordering::Relaxed);
+}
+
+/// Tag the records of this thread with 'job' from now on, None for none.
+/// A thread spawned while working for a job starts untagged, so it is
+/// handed 'job()' of the thread that spawned it.
+pub fn set_job(job: Option<String>) {
+    JOB.with(|current| *current.borrow_mut() = job);
+}
+
+/// The job records of this thread are tagged with, see 'set_job'.
+pub fn job() -> Option<String> {
+    JOB.with(|current| current.borrow().clone())
+}
+
+/// A log record. In text mode the line handed to 'log' goes to stdout; in
+/// JSON mode one object per record goes to stderr, holding 'timestamp',
+/// 'level', 'message' and the fields. A record of a job carries its name,
+/// as the field 'job' and in front of the text line.
+pub struct Record {
+    level: &'static str,
+    message: &'static str,
+    fields: Map<String, Value>,
+}
+
+impl Record {
+    /// A record of something that went as expected.
+    pub fn info(message: &'static str) -> Record {
+        Record::new("info", message)
+    }
+
+    /// A record of something that went wrong but didn't stop the step.
+    pub fn warn(message: &'static str) -> Record {
+        Record::new("warn", message)
+    }
+
+    /// A record of a failed step.
+    pub fn error(message: &'static str) -> Record {
+        Record::new("error", message)
+    }
+
+    fn new(level: &'static str, message: &'static str) -> Record {
+        let mut fields = Map::new();
+        if let Some(job) = job() {
+            fields.insert("job".to_string(), Value::String(job));
+        }
+
+        Record {
+            level,
+            message,
+            fields,
+        }
+    }
+
+    /// The record with field 'name' set to 'value'.
+    pub fn field<T: Serialize>(mut self, name: &str, value: T) -> Record {
+        self.fields.insert(name.to_string(), serde_json::to_value(&value).unwrap_or(Value::Null));
+        self
+    }
+
+    /// Write the record, as 'text' in text mode.
+    pub fn log(self, text: String) {
+        if JSON.load(Ordering::Relaxed) {
+            self.write_json();
+        } else {
+            match job() {
+                Some(job) => println!("[{}] {}", job, text),
+                None => println!("{}", text),
+            }
+        }
+    }
+
+    /// Write the record in JSON mode; text mode has no line for it.
+    pub fn log_json(self) {
+        if JSON.load(Ordering::Relaxed) {
+            self.write_json();
+        }
+    }
+
+    fn write_json(self) {
+        let _ = writeln!(io::stderr(), "{}", self.json_line());
+    }
+
+    fn json_line(self) -> String {
+        let mut object = self.fields;
+        object.insert("timestamp".to_string(), Value::String(Local::now().to_rfc3339()));
+        object.insert("level".to_string(), Value::String(self.level.to_string()));
+        object.insert("message".to_string(), Value::String(self.message.to_string()));
+
+        serde_json::to_string(&Value::Object(object)).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn json_records_carry_their_fields() {
+        let line = Record::info("feed checked").field("feed", "bbs").field("items", 3).json_line();
+        let record: BTreeMap<String, Value> = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(record["level"], Value::String("info".to_string()));
+        assert_eq!(record["message"], Value::String("feed checked".to_string()));
+        assert_eq!(record["feed"], Value::String("bbs".to_string()));
+        assert_eq!(record["items"], Value::from(3));
+        assert!(record.contains_key("timestamp"));
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn records_of_a_job_carry_its_name() {
+        set_job(Some("market".to_string()));
+        let line = Record::warn("feed failed").json_line();
+        set_job(None);
+        let record: BTreeMap<String, Value> = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["job"], Value::String("market".to_string()));
+
+        let line = Record::warn("feed failed").json_line();
+        assert!(!line.contains("\"job\""));
+    }
+}
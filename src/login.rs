@@ -0,0 +1,195 @@
+//! Logging in to boards whose feeds need a session cookie.
+
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Local;
+
+use reqwest::Url;
+use reqwest::Client;
+use reqwest::header::SET_COOKIE;
+use reqwest::redirect::Policy;
+
+use serde_json;
+
+use tokio::sync::Mutex;
+
+use crate::error::*;
+use crate::config::LoginConfig;
+use crate::feed::sniff_format;
+use crate::state::write_atomic;
+
+/// Session cookies a login got, as kept in the state directory.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Session {
+    /// The value of the Cookie header, "a=1; b=2".
+    pub cookie: String,
+    /// When the login happened.
+    pub obtained_at: String,
+}
+
+/// The session of one feed: logged in at most once per run, or once per
+/// 'ttl_secs' when the session is kept at 'cache'.
+pub struct Login {
+    /// How to log in.
+    pub config: LoginConfig,
+    /// Where the session is kept between runs; nothing is written when
+    /// None, nor without 'ttl_secs'.
+    pub cache: Option<PathBuf>,
+    current: Mutex<Option<String>>,
+}
+
+impl Login {
+    /// A login that didn't happen yet.
+    pub fn new(config: LoginConfig, cache: Option<PathBuf>) -> Login {
+        Login {
+            config,
+            cache,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// The Cookie header to send with the feed request. 'fresh' drops the
+    /// session at hand and logs in again, for when the feed answered with
+    /// the login page. Pages of the feed fetched at once wait for the
+    /// same login.
+    pub async fn cookie(&self, fresh: bool) -> Result<String> {
+        let mut current = self.current.lock().await;
+        if !fresh {
+            if let Some(ref cookie) = *current {
+                return Ok(cookie.clone());
+            }
+            if let Some(session) = self.cached()? {
+                *current = Some(session.cookie.clone());
+                return Ok(session.cookie);
+            }
+        }
+
+        let cookie = log_in(&self.config).await?;
+        if let (Some(cache), Some(_)) = (self.cache.as_ref(), self.config.ttl_secs) {
+            let session = Session {
+                cookie: cookie.clone(),
+                obtained_at: Local::now().to_rfc3339(),
+            };
+            write_atomic(cache, |writer| {
+                writer.write_all(serde_json::to_string_pretty(&session)?.as_bytes())?;
+                Ok(())
+            })?;
+        }
+
+        *current = Some(cookie.clone());
+        Ok(cookie)
+    }
+
+    // the kept session, if it is younger than 'ttl_secs'.
+    fn cached(&self) -> Result<Option<Session>> {
+        let (cache, ttl) = match (self.cache.as_ref(), self.config.ttl_secs) {
+            (Some(cache), Some(ttl)) => (cache, ttl),
+            _ => return Ok(None),
+        };
+        if !cache.exists() {
+            return Ok(None);
+        }
+
+        let mut content = String::new();
+        File::open(cache)?.read_to_string(&mut content)?;
+        // a session that can't be read is simply logged in again.
+        let session: Session = match serde_json::from_str(&content) {
+            Ok(session) => session,
+            Err(_) => return Ok(None),
+        };
+
+        let obtained_at = DateTime::parse_from_rfc3339(&session.obtained_at)?;
+        if Local::now().timestamp() - obtained_at.timestamp() >= ttl {
+            return Ok(None);
+        }
+
+        Ok(Some(session))
+    }
+
+    /// Whether 'body', fetched instead of the feed, is the login page: it
+    /// holds 'login_page_marker', or isn't RSS 2.0 when there is none.
+    pub fn is_login_page(&self, body: &[u8]) -> bool {
+        let body = String::from_utf8_lossy(body);
+        match self.config.login_page_marker {
+            Some(ref marker) => body.contains(marker.as_str()),
+            None => sniff_format(&body) != "RSS 2.0",
+        }
+    }
+}
+
+/// "name=value" of a Set-Cookie header, if 'wanted' is empty or names it.
+pub fn cookie_pair(set_cookie: &str, wanted: &[String]) -> Option<String> {
+    let pair = set_cookie.split(';').next().unwrap_or("").trim();
+    let name = match pair.find('=') {
+        Some(i) => &pair[..i],
+        None => return None,
+    };
+
+    if !wanted.is_empty() && !wanted.iter().any(|w| w == name) {
+        return None;
+    }
+
+    Some(pair.to_string())
+}
+
+/// Send the login form of 'login' and return the Cookie header of the
+/// session it got. Fails with 'ErrorKind::LoginFailed' when the server
+/// refuses the login or sets none of the wanted cookies.
+pub async fn log_in(login: &LoginConfig) -> Result<String> {
+    // the cookies come with the response to the form, often a redirect.
+    let client = Client::builder().redirect(Policy::none()).build()?;
+
+    let failed = |reason: String| ErrorKind::LoginFailed(login.url.clone(), reason);
+
+    let sent = if login.method == "GET" {
+        let mut url = Url::parse(&login.url).chain_err(|| failed("bad url".to_string()))?;
+        url.query_pairs_mut().extend_pairs(login.form.iter());
+        client.get(url.as_str()).send().await
+    } else {
+        client.post(login.url.as_str()).form(&login.form).send().await
+    };
+    let resp = sent.chain_err(|| failed("no answer".to_string()))?;
+
+    // a redirect is how most boards answer a good login.
+    let code = resp.status().as_u16();
+    if !(200..400).contains(&code) {
+        bail!(failed(format!("answered {}", code)));
+    }
+
+    let pairs: Vec<String> = resp.headers().get_all(SET_COOKIE).iter()
+        .filter_map(|c| c.to_str().ok())
+        .filter_map(|c| cookie_pair(c, &login.cookies))
+        .collect();
+
+    let body = resp.text().await.unwrap_or_default();
+    if let Some(ref marker) = login.failure_marker {
+        if body.contains(marker.as_str()) {
+            bail!(failed(format!("the answer says '{}'", marker)));
+        }
+    }
+
+    if pairs.is_empty() {
+        bail!(failed("no session cookie was set".to_string()));
+    }
+
+    Ok(pairs.join("; "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_wanted_cookies_are_kept() {
+        let wanted = vec!["winduser".to_string()];
+
+        assert_eq!(cookie_pair("winduser=abc%3D; path=/; HttpOnly", &wanted), Some("winduser=abc%3D".to_string()));
+        assert_eq!(cookie_pair("lastvisit=1478272200; path=/", &wanted), None);
+        assert_eq!(cookie_pair("lastvisit=1478272200", &Vec::new()), Some("lastvisit=1478272200".to_string()));
+        assert_eq!(cookie_pair("garbage", &Vec::new()), None);
+    }
+}
@@ -1,231 +1,490 @@
-#![recursion_limit = "1024"]
-
-#[macro_use]
-extern crate error_chain;
-
-extern crate rss;
-extern crate reqwest;
-extern crate lettre;
-extern crate chrono;
-
-#[macro_use]
-extern crate serde_derive;
-
-extern crate serde_json;
-
-#[macro_use]
-extern crate tera;
-
-
-
-use std::io::Read;
-use std::io::Write;
-
-use std::fs::File;
-
-use rss::Channel;
-use rss::Item;
-
-use lettre::email::EmailBuilder;
-use lettre::transport::smtp::SmtpTransportBuilder;
-use lettre::transport::smtp::authentication::Mechanism;
-use lettre::transport::EmailTransport;
-
-use tera::Tera;
-
-use chrono::DateTime;
-use chrono::Local;
-
-
-mod errors {
-    error_chain! {
-        foreign_links {
-            Io(::std::io::Error);
-            Http(::reqwest::Error);
-            Rss(::rss::Error);
-            Json(::serde_json::Error);
-            Render(::tera::Error);
-            Mail(::lettre::email::error::Error);
-            Tranport(::lettre::transport::smtp::error::Error);
-        }
-    }
-}
-
-use errors::*;
-
-
-#[derive(Serialize, Debug)]
-struct SerItem {
-    title: Option<String>,
-    link: Option<String>,
-    description: Option<String>,
-    author: Option<String>,
-    pub_date: Option<String>,
-}
-
-#[derive(Deserialize, Debug)]
-struct Config {
-    local_rss: String,
-    remote_rss: String,
-    
-    subject: String,
-    from: String,
-    to: String,
-    password: String,
-    server: String,
-}
-
-struct RssContext {
-    raw: String,
-    channel: Channel,
-}
-
-impl RssContext {
-    pub fn from_url(url: &str) -> Result<RssContext> {
-        let resp = reqwest::get(url)?;
-        return RssContext::from_reader(resp);
-    }
-
-    pub fn from_file(filename: &str) -> Result<RssContext> {
-        let reader = File::open(filename)?;
-        return RssContext::from_reader(reader);
-    }
-
-    pub fn to_file(&self, filename: &str) -> Result<()> {
-        let mut writer = File::create(filename)?;
-        writer.write_all(self.raw.as_bytes())?;
-
-        return Ok(());
-    }
-
-    // return item a vector of Items which are in 'a' but not in 'b'.
-    pub fn diff(ctx_a: &RssContext, ctx_b: &RssContext) -> Vec<Item> {
-        let a = &ctx_a.channel.items;
-        let b = &ctx_b.channel.items;
-        
-        let mut c = Vec::new();
-        
-        for item_a in a {
-            if !b.contains(item_a) {
-                c.push(item_a.clone());
-            }
-        }
-
-        return c;
-    }
-
-    fn from_reader<R: Read>(mut reader: R) -> Result<RssContext> {
-        let mut body = String::new();
-        reader.read_to_string(&mut body)?;
-        
-        let channel: rss::Channel = body.parse()?;
-
-        return Ok(RssContext {
-            raw: body,
-            channel: channel,
-        });
-    }
-}
-
-fn convert_pub_date(old: &Option<String>) -> Option<String> {
-    if let &Some(ref date_str) = old {
-        if let Ok(date) =  DateTime::parse_from_rfc2822(&date_str) {
-            return Some(date
-                        .with_timezone(&Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string());
-        }
-    }
-
-    return old.clone();
-}
-
-fn convert_to_ser_items(items: &Vec<Item>) -> Vec<SerItem> {
-    let mut ser_items = Vec::new();
-    
-    for item in items {
-        ser_items.push(SerItem {
-            title: item.title.clone(),
-            link: item.link.clone(),
-            description: item.description.clone(),
-            author: item.author.clone(),
-            pub_date: convert_pub_date(&item.pub_date),
-        })
-    }
-
-    return ser_items;
-}
-
-fn load_config(filename: &str) -> Result<Config> {
-    let mut reader = File::open(filename)?;
-
-    let mut content = String::new();
-    reader.read_to_string(&mut content)?;
-
-    let config: Config = serde_json::from_str(&content)?;
-    return Ok(config);
-}
-
-fn fetch_diff_items(local: &str, remote: &str) -> Result<(Vec<SerItem>, RssContext)> {
-    let new_ctx = RssContext::from_url(remote)?;
-    let old_ctx = RssContext::from_file(local)?;
-
-    let new_items = RssContext::diff(&new_ctx, &old_ctx);
-
-    if new_items.len() <= 0 {
-        return Ok((Vec::new(), new_ctx));
-    } else {
-        return Ok((convert_to_ser_items(&new_items), new_ctx));
-    }
-}
-
-fn render(templates: &str, tmpl_file: &str, items: &Vec<SerItem>) -> Result<String> {
-    let tera = compile_templates!(templates);
-    
-    let mut tctx = tera::Context::new();
-    tctx.add("items", &items);
-
-    let content =  tera.render(tmpl_file, tctx)?;
-
-    return Ok(content);
-}
-
-fn send_mail(c: &Config, content: &String) -> Result<()> {
-    let email = EmailBuilder::new()
-        .subject(&c.subject)
-        .from(c.from.as_str())
-        .to((c.to.as_str(), "BBS Notification Receiver"))
-        .header(("Content-Type", "text/html; charset=UTF-8"))
-        .body(content)
-        .build()?;
-
-    let mut sender = SmtpTransportBuilder::new((c.server.as_str(), 25))?
-        .credentials(&c.from, &c.password)
-        .smtp_utf8(true)
-        .authentication_mechanism(Mechanism::Plain)
-        .build();
-    
-    sender.send(email)?;
-
-    return Ok(());
-}
-
-fn run() -> Result<()> {
-    let config = load_config("bbsmon.json")?;
-
-    let (items, new_ctx) = fetch_diff_items(&config.local_rss, &config.remote_rss)?;
-    if items.len() <= 0 {
-        println!("new and old rss are same.");
-        return Ok(());
-    }
-    
-    let content = render("templates/**/*", "mail.html", &items)?;
-
-    send_mail(&config, &content)?;
-    
-    new_ctx.to_file("old-rss.xml")?;
-
-    return Ok(());
-}
-
-quick_main!(run);
+#[macro_use]
+extern crate clap;
+extern crate ctrlc;
+
+extern crate bbsmon;
+
+use std::env;
+use std::io;
+use std::io::Write;
+use std::process;
+
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use bbsmon::Config;
+use bbsmon::Pipeline;
+use bbsmon::error::*;
+use bbsmon::config::default_diff_strategy;
+use bbsmon::config::default_missing_link;
+use bbsmon::diff::EXIT_OK;
+use bbsmon::diff::diff_files;
+use bbsmon::feed::validate;
+use bbsmon::history::ReplayOptions;
+use bbsmon::history::history;
+use bbsmon::history::replay;
+use bbsmon::jobs::daemon_jobs;
+use bbsmon::jobs::jobs_status;
+use bbsmon::jobs::run_jobs_once;
+use bbsmon::log;
+use bbsmon::report::report;
+use bbsmon::notify::retry_failed;
+use bbsmon::notification_log::log_show;
+use bbsmon::opml::export_opml;
+use bbsmon::opml::import_opml;
+use bbsmon::pipeline::stop_daemons;
+use bbsmon::preview::DEFAULT_PREVIEW_PORT;
+use bbsmon::preview::preview;
+use bbsmon::scrape::validate_scrape;
+use bbsmon::seen::migrate_state;
+use bbsmon::seen::prune_seen;
+use bbsmon::state::write_atomic;
+use bbsmon::status::status;
+use bbsmon::timings;
+use bbsmon::watch::watch;
+
+// the config read when neither --config nor --config-dir is given.
+const DEFAULT_CONFIG: &str = "bbsmon.json";
+
+fn cli() -> App<'static, 'static> {
+    App::new("bbsmon")
+        .version(crate_version!())
+        .about("watches a phpwind bbs rss feed and mails new posts")
+        .arg(Arg::with_name("config")
+             .long("config")
+             .takes_value(true)
+             .value_name("file")
+             .help("config file to use, bbsmon.json when absent"))
+        .arg(Arg::with_name("config-dir")
+             .long("config-dir")
+             .takes_value(true)
+             .value_name("dir")
+             .conflicts_with("config")
+             .help("run a job for each .json file in this directory, named after the file"))
+        .arg(Arg::with_name("feed")
+             .long("feed")
+             .takes_value(true)
+             .multiple(true)
+             .number_of_values(1)
+             .help("only process the feed with this name or url, may be repeated"))
+        .arg(Arg::with_name("job")
+             .long("job")
+             .takes_value(true)
+             .help("only use the job with this name of a config with 'jobs' or of --config-dir"))
+        .arg(Arg::with_name("stdin-feed")
+             .long("stdin-feed")
+             .help("read the feed from stdin instead of fetching it, e.g. from curl"))
+        .arg(Arg::with_name("state")
+             .long("state")
+             .takes_value(true)
+             .requires("stdin-feed")
+             .help("snapshot to diff the feed read from stdin against, instead of the configured one"))
+        .arg(Arg::with_name("dry-run")
+             .long("dry-run")
+             .help("print what every enabled backend would be sent, without sending or saving anything"))
+        .arg(Arg::with_name("timings")
+             .long("timings")
+             .help("print how long fetching, parsing, diffing, rendering and sending took, by feed, after each run"))
+        .arg(Arg::with_name("debug")
+             .long("debug")
+             .help("print a backtrace along with a failure"))
+        .subcommand(SubCommand::with_name("status")
+                    .about("prints where state is kept and how the last runs went")
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["text", "json"])
+                         .default_value("text")))
+        .subcommand(SubCommand::with_name("daemon")
+                    .about("checks the feeds every 'interval_secs' until killed"))
+        .subcommand(SubCommand::with_name("replay")
+                    .about("re-sends items notified since a point in time, rendered anew")
+                    .arg(Arg::with_name("from")
+                         .long("from")
+                         .takes_value(true)
+                         .required_unless("last")
+                         .help("YYYY-MM-DD or RFC 3339 timestamp"))
+                    .arg(Arg::with_name("last")
+                         .long("last")
+                         .conflicts_with_all(&["from", "snapshot"])
+                         .help("replay what the last run that notified anything notified"))
+                    .arg(Arg::with_name("feed")
+                         .long("feed")
+                         .takes_value(true))
+                    .arg(Arg::with_name("sink")
+                         .long("sink")
+                         .takes_value(true))
+                    .arg(Arg::with_name("snapshot")
+                         .long("snapshot")
+                         .takes_value(true)
+                         .help("replay the items added since this rotated snapshot instead of the history"))
+                    .arg(Arg::with_name("dry-run")
+                         .long("dry-run")
+                         .help("only list what would be resent")))
+        .subcommand(SubCommand::with_name("retry-failed")
+                    .about("re-sends every notification queued after a delivery failure"))
+        .subcommand(SubCommand::with_name("diff")
+                    .about("prints what changed between two saved feed files")
+                    .arg(Arg::with_name("newer")
+                         .required(true)
+                         .index(1))
+                    .arg(Arg::with_name("older")
+                         .required(true)
+                         .index(2))
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["text", "json"])
+                         .default_value("text"))
+                    .arg(Arg::with_name("apply-filters")
+                         .long("apply-filters")
+                         .help("compare and restrict the output as configured")))
+        .subcommand(SubCommand::with_name("validate")
+                    .about("checks a feed url or file before adding it to the config, or what a scrape feed finds")
+                    .arg(Arg::with_name("source")
+                         .required(true)
+                         .index(1)))
+        .subcommand(SubCommand::with_name("import-opml")
+                    .about("adds the feeds of an OPML file to the config file")
+                    .arg(Arg::with_name("file")
+                         .required(true)
+                         .index(1))
+                    .arg(Arg::with_name("merge")
+                         .long("merge")
+                         .help("keep the configured feeds instead of replacing them"))
+                    .arg(Arg::with_name("flatten")
+                         .long("flatten")
+                         .help("don't keep the OPML folders as feed groups"))
+                    .arg(Arg::with_name("dry-run")
+                         .long("dry-run")
+                         .help("only list the feeds that would be added")))
+        .subcommand(SubCommand::with_name("export-opml")
+                    .about("writes the configured feeds as OPML")
+                    .arg(Arg::with_name("output")
+                         .long("output")
+                         .takes_value(true)
+                         .help("file to write instead of stdout")))
+        .subcommand(SubCommand::with_name("history")
+                    .about("searches the archive of notified items")
+                    .arg(Arg::with_name("since")
+                         .long("since")
+                         .takes_value(true)
+                         .help("only items notified since then, YYYY-MM-DD or RFC 3339 timestamp"))
+                    .arg(Arg::with_name("grep")
+                         .long("grep")
+                         .takes_value(true)
+                         .help("only items whose title, link, description or author contain this"))
+                    .arg(Arg::with_name("format")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["text", "json"])
+                         .default_value("text")))
+        .subcommand(SubCommand::with_name("log")
+                    .about("queries the log of every item sent")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("show")
+                                .about("prints the items sent since a point in time, oldest first")
+                                .arg(Arg::with_name("since")
+                                     .long("since")
+                                     .takes_value(true)
+                                     .help("only items sent since then, YYYY-MM-DD or RFC 3339 timestamp"))
+                                .arg(Arg::with_name("format")
+                                     .long("format")
+                                     .takes_value(true)
+                                     .possible_values(&["text", "json"])
+                                     .default_value("text"))))
+        .subcommand(SubCommand::with_name("report")
+                    .about("prints the activity report of a period from the archive of notified items")
+                    .arg(Arg::with_name("since")
+                         .long("since")
+                         .takes_value(true)
+                         .required(true)
+                         .help("YYYY-MM-DD or RFC 3339 timestamp"))
+                    .arg(Arg::with_name("until")
+                         .long("until")
+                         .takes_value(true)
+                         .help("end of the period, excluded, now when absent")))
+        .subcommand(SubCommand::with_name("watch")
+                    .about("previews a feed file as it would be mailed, again whenever it, the config or a template \
+                            changes; sends and saves nothing")
+                    .arg(Arg::with_name("fixture")
+                         .required(true)
+                         .index(1)
+                         .help("feed file whose items are all shown as new"))
+                    .arg(Arg::with_name("feed")
+                         .long("feed")
+                         .takes_value(true)
+                         .help("feed whose settings apply, the first configured one when absent")))
+        .subcommand(SubCommand::with_name("preview")
+                    .about("serves mail.html rendered with the last run's items, a fixture or samples on localhost, \
+                            reloading it when a template changes; sends and saves nothing")
+                    .arg(Arg::with_name("port")
+                         .long("port")
+                         .takes_value(true)
+                         .help("port on 127.0.0.1 to serve on, 8788 when absent"))
+                    .arg(Arg::with_name("fixture")
+                         .long("fixture")
+                         .takes_value(true)
+                         .help("feed file whose items are all shown as new")))
+        .subcommand(SubCommand::with_name("migrate-state")
+                    .about("records the items of every snapshot in the seen store, safe to run again"))
+        .subcommand(SubCommand::with_name("prune-seen")
+                    .alias("prune")
+                    .about("drops items of the seen store past the retention of their feed that are no longer in it")
+                    .arg(Arg::with_name("older-than")
+                         .long("older-than")
+                         .takes_value(true)
+                         .value_name("days")
+                         .help("drop items last seen more days ago than this, whatever the feeds say"))
+                    .arg(Arg::with_name("dry-run")
+                         .long("dry-run")
+                         .help("only list what would be dropped")))
+}
+
+// have Ctrl-C, on windows the console event of it, stop the daemons: the
+// fetches in flight are aborted, but nothing is killed while it writes
+// the state or sends a notification.
+fn stop_on_ctrl_c() -> Result<()> {
+    ctrlc::set_handler(|| {
+        let _ = writeln!(io::stderr(), "stopping, aborting the fetches in flight.");
+        stop_daemons();
+    }).map_err(|e| format!("can't handle Ctrl-C: {:?}", e).into())
+}
+
+// the config file of the commands that read or edit a single one.
+fn config_file<'a>(matches: &'a ArgMatches) -> Result<&'a str> {
+    if matches.is_present("config-dir") {
+        return Err(format!("'{}' uses a single config file, pass it with --config instead of --config-dir",
+                           matches.subcommand_name().unwrap_or("bbsmon")).into());
+    }
+    Ok(matches.value_of("config").unwrap_or(DEFAULT_CONFIG))
+}
+
+fn run(matches: &ArgMatches) -> Result<i32> {
+    if let Some(m) = matches.subcommand_matches("validate") {
+        let source = m.value_of("source").unwrap();
+
+        // configured feeds are named or given by url; scraped ones have their
+        // selectors in the config, the others their login and cookies.
+        let config = config_file(matches).ok().and_then(|file| Config::load(file).ok());
+        if let Some(ref config) = config {
+            let scraped = config.feeds.iter()
+                .find(|f| f.scrape.is_some() && (f.name == source || f.remote_rss == source));
+            if let Some(feed) = scraped {
+                validate_scrape(feed, &config.retry, config.max_feed_bytes, config.timeouts())?;
+                return Ok(EXIT_OK);
+            }
+        }
+
+        validate(source, config.as_ref())?;
+        return Ok(EXIT_OK);
+    }
+
+    // edits the config file itself, so it must not need a valid one.
+    if let Some(m) = matches.subcommand_matches("import-opml") {
+        import_opml(config_file(matches)?, m.value_of("file").unwrap(), m.is_present("merge"),
+                    m.is_present("flatten"), m.is_present("dry-run"))?;
+        return Ok(EXIT_OK);
+    }
+
+    // keeps going on a config that doesn't load, it is what is being edited.
+    if let Some(m) = matches.subcommand_matches("watch") {
+        watch(config_file(matches)?, m.value_of("fixture").unwrap(), m.value_of("feed"))?;
+        return Ok(EXIT_OK);
+    }
+
+    // like 'watch', a config that doesn't load is shown on the page.
+    if let Some(m) = matches.subcommand_matches("preview") {
+        let port = match m.value_of("port") {
+            Some(port) => port.parse::<u16>().map_err(|_| format!("--port takes a port number, not '{}'", port))?,
+            None => DEFAULT_PREVIEW_PORT,
+        };
+        preview(config_file(matches)?, m.value_of("fixture"), port)?;
+        return Ok(EXIT_OK);
+    }
+
+    // works offline and without a config unless filters are asked for.
+    if let Some(m) = matches.subcommand_matches("diff") {
+        let (strategy, fields, missing_link) = if m.is_present("apply-filters") {
+            let config = Config::load(config_file(matches)?)?;
+            (config.diff_strategy, config.include_fields, config.missing_link)
+        } else {
+            (default_diff_strategy(), None, default_missing_link())
+        };
+
+        return diff_files(m.value_of("newer").unwrap(), m.value_of("older").unwrap(), &strategy,
+                          &fields, &missing_link, m.value_of("format") == Some("json"));
+    }
+
+    let (mut jobs, source) = match matches.value_of("config-dir") {
+        Some(dir) => (Config::load_job_dir(dir)?, dir),
+        None => {
+            let file = config_file(matches)?;
+            (Config::load_jobs(file)?, file)
+        }
+    };
+    if let Some(name) = matches.value_of("job") {
+        jobs.retain(|job| job.name == name);
+        if jobs.is_empty() {
+            return Err(format!("no job named '{}' in {}", name, source).into());
+        }
+    }
+
+    // every job at once only for a run, the daemon and the status.
+    if jobs.len() > 1 {
+        if let Some(m) = matches.subcommand_matches("status") {
+            jobs_status(&jobs, m.value_of("format") == Some("json"))?;
+            return Ok(EXIT_OK);
+        }
+
+        let single_run = matches.subcommand_name().is_none() && !matches.is_present("dry-run");
+        let daemon = matches.subcommand_matches("daemon").is_some();
+        if !(single_run || daemon) || matches.is_present("feed") || matches.is_present("stdin-feed") {
+            return Err(format!("{} has several jobs, pick one with --job", source).into());
+        }
+
+        if matches.is_present("timings") {
+            timings::enable();
+        }
+        if daemon {
+            stop_on_ctrl_c()?;
+            daemon_jobs(jobs)?;
+        } else {
+            run_jobs_once(&jobs)?;
+        }
+        return Ok(EXIT_OK);
+    }
+
+    let job = jobs.remove(0);
+    if !job.name.is_empty() {
+        log::set_job(Some(job.name));
+    }
+    let mut config = job.config;
+    if let Some(wanted) = matches.values_of("feed") {
+        config.select_feeds(wanted.collect())?;
+    }
+    if matches.is_present("stdin-feed") {
+        if let Some(name) = matches.subcommand_name() {
+            return Err(format!("--stdin-feed only works on a single run, not with '{}'", name).into());
+        }
+        config.read_feed_from_stdin(matches.value_of("state"))?;
+    }
+
+    if matches.is_present("timings") {
+        timings::enable();
+    }
+
+    let pipeline = Pipeline::new(config);
+    let config = pipeline.config();
+    let paths = pipeline.paths();
+
+    if let Some(m) = matches.subcommand_matches("status") {
+        status(config, paths, m.value_of("format") == Some("json"))?;
+        return Ok(EXIT_OK);
+    }
+
+    if let Some(m) = matches.subcommand_matches("export-opml") {
+        let opml = export_opml(config);
+        match m.value_of("output") {
+            Some(output) => write_atomic(output, |writer| {
+                writer.write_all(opml.as_bytes())?;
+                Ok(())
+            })?,
+            None => print!("{}", opml),
+        }
+        return Ok(EXIT_OK);
+    }
+
+    if let Some(m) = matches.subcommand_matches("history") {
+        history(config, m.value_of("since"), m.value_of("grep"),
+                m.value_of("format") == Some("json"))?;
+        return Ok(EXIT_OK);
+    }
+
+    if let Some(m) = matches.subcommand_matches("log").and_then(|m| m.subcommand_matches("show")) {
+        log_show(paths, m.value_of("since"), m.value_of("format") == Some("json"))?;
+        return Ok(EXIT_OK);
+    }
+
+    if let Some(m) = matches.subcommand_matches("report") {
+        report(config, m.value_of("since").unwrap(), m.value_of("until"))?;
+        return Ok(EXIT_OK);
+    }
+
+    if matches.subcommand_name().is_none() && matches.is_present("dry-run") {
+        pipeline.dry_run()?;
+        return Ok(EXIT_OK);
+    }
+
+    paths.create_dirs(&config.feeds)?;
+
+    if matches.subcommand_matches("migrate-state").is_some() {
+        let _lock = paths.lock()?;
+        migrate_state(config, paths)?;
+        return Ok(EXIT_OK);
+    }
+
+    if let Some(m) = matches.subcommand_matches("prune-seen") {
+        let older_than = match m.value_of("older-than") {
+            Some(days) => match days.parse::<i64>() {
+                Ok(days) if days > 0 => Some(days),
+                _ => return Err(format!("--older-than takes a number of days, not '{}'", days).into()),
+            },
+            None => None,
+        };
+        let _lock = paths.lock()?;
+        prune_seen(config, paths, older_than, m.is_present("dry-run"))?;
+        return Ok(EXIT_OK);
+    }
+
+    if matches.subcommand_matches("retry-failed").is_some() {
+        let _lock = paths.lock()?;
+        retry_failed(config, paths)?;
+        return Ok(EXIT_OK);
+    }
+
+    if let Some(m) = matches.subcommand_matches("replay") {
+        replay(config, paths, &ReplayOptions {
+            from: m.value_of("from"),
+            last: m.is_present("last"),
+            feed: m.value_of("feed"),
+            sink: m.value_of("sink"),
+            snapshot: m.value_of("snapshot"),
+            dry_run: m.is_present("dry-run"),
+        })?;
+        return Ok(EXIT_OK);
+    }
+
+    if matches.subcommand_matches("daemon").is_some() {
+        stop_on_ctrl_c()?;
+        pipeline.daemon()?;
+        return Ok(EXIT_OK);
+    }
+
+    pipeline.run_once()?;
+    Ok(EXIT_OK)
+}
+
+fn main() {
+    let matches = cli().get_matches();
+
+    // errors only capture a backtrace when this is set as they are created.
+    let debug = matches.is_present("debug");
+    if debug {
+        env::set_var("RUST_BACKTRACE", "1");
+    }
+
+    match run(&matches) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "{}", error_report(&e));
+            if let (true, Some(backtrace)) = (debug, e.backtrace()) {
+                let _ = writeln!(io::stderr(), "stack backtrace:\n{:?}", backtrace);
+            }
+            process::exit(exit_code(&e));
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! The merged feed: the latest items of all feeds in one RSS 2.0 document
+//! to subscribe to from any reader.
+
+use std::io::Write;
+use std::path::Path;
+use std::collections::HashMap;
+
+use chrono::DateTime;
+use chrono::Local;
+
+use rss::Category;
+use rss::Channel;
+use rss::Guid;
+use rss::Item;
+
+use crate::error::*;
+use crate::config::MergedFeed;
+use crate::feed::RssContext;
+use crate::feed::item_key;
+use crate::state::write_atomic;
+
+/// Namespace of the content:encoded element items may carry.
+pub const CONTENT_NAMESPACE: &str = "http://purl.org/rss/1.0/modules/content/";
+
+// the identity of an item of the merged feed: its feed and its guid.
+fn merged_key(item: &Item) -> (String, String) {
+    let feed = item.categories.first().map(|c| c.name.clone()).unwrap_or_default();
+    let guid = item.guid.as_ref().map(|g| g.value.clone()).unwrap_or_default();
+    (feed, guid)
+}
+
+fn has_date(item: &Item) -> bool {
+    item.pub_date.as_ref().is_some_and(|d| DateTime::parse_from_rfc2822(d).is_ok())
+}
+
+/// 'item' of 'feed' as the merged feed keeps it, None when RSS 2.0 doesn't
+/// allow it, having neither a title nor a description. Its only category
+/// is 'feed'; without a guid its link, else its title, stands for one. An
+/// author that isn't an email address becomes a dc:creator, extensions
+/// other than Dublin Core are left out.
+pub fn merged_item(feed: &str, item: &Item) -> Option<Item> {
+    if item.title.is_none() && item.description.is_none() {
+        return None;
+    }
+
+    let mut merged = item.clone();
+    merged.categories = vec![Category {
+        name: feed.to_string(),
+        domain: None,
+    }];
+    if merged.guid.is_none() {
+        merged.guid = Some(Guid {
+            value: item_key(item),
+            permalink: item.link.is_some(),
+        });
+    }
+
+    if let Some(author) = merged.author.take() {
+        if author.contains('@') {
+            merged.author = Some(author);
+        } else {
+            let mut dc = merged.dublin_core_ext.take().unwrap_or_default();
+            if !dc.creators.contains(&author) {
+                dc.creators.push(author);
+            }
+            merged.dublin_core_ext = Some(dc);
+        }
+    }
+
+    merged.extensions = Default::default();
+    merged.itunes_ext = None;
+    Some(merged)
+}
+
+/// Add the 'items' fetched from 'feed' to 'channel'. An item already there
+/// is replaced, keeping its pubDate when the new one has no RFC 2822 date;
+/// new items without one get 'now', so that they stay where they first
+/// showed up.
+pub fn merge_items(channel: &mut Channel, feed: &str, items: &Vec<Item>, now: DateTime<Local>) {
+    let mut known: HashMap<(String, String), usize> = HashMap::new();
+    for (i, item) in channel.items.iter().enumerate() {
+        known.insert(merged_key(item), i);
+    }
+
+    for item in items {
+        let mut merged = match merged_item(feed, item) {
+            Some(merged) => merged,
+            None => continue,
+        };
+
+        match known.get(&merged_key(&merged)).cloned() {
+            Some(i) => {
+                if !has_date(&merged) {
+                    merged.pub_date = channel.items[i].pub_date.clone();
+                }
+                channel.items[i] = merged;
+            }
+            None => {
+                if !has_date(&merged) {
+                    merged.pub_date = Some(now.to_rfc2822());
+                }
+                known.insert(merged_key(&merged), channel.items.len());
+                channel.items.push(merged);
+            }
+        }
+    }
+}
+
+/// Order the items of 'channel' newest first and drop all but 'max_items'.
+pub fn cap_items(channel: &mut Channel, max_items: usize) {
+    // every item has a date once merged; one edited by hand sorts last.
+    channel.items.sort_by(|a, b| {
+        let a = a.pub_date.as_ref().and_then(|d| DateTime::parse_from_rfc2822(d).ok()).map(|d| d.timestamp());
+        let b = b.pub_date.as_ref().and_then(|d| DateTime::parse_from_rfc2822(d).ok()).map(|d| d.timestamp());
+        b.cmp(&a)
+    });
+    channel.items.truncate(max_items);
+}
+
+/// The merged feed as stored at 'merged.path', an empty channel when there
+/// is none yet.
+pub fn load_merged(merged: &MergedFeed) -> Result<Channel> {
+    if Path::new(&merged.path).exists() {
+        return Ok(RssContext::from_file(&merged.path)?.channel);
+    }
+
+    Ok(Channel::default())
+}
+
+/// Add the items of each fetched '(feed name, items)' to the merged feed
+/// and write it anew. Items stay after they left their feed, until
+/// 'max_items' newer ones push them out.
+pub fn update_merged(merged: &MergedFeed, fetched: &Vec<(String, Vec<Item>)>) -> Result<()> {
+    let mut channel = load_merged(merged).chain_err(|| format!("can't load the merged feed {}", merged.path))?;
+    let now = Local::now();
+
+    for (feed, items) in fetched {
+        merge_items(&mut channel, feed, items, now);
+    }
+    cap_items(&mut channel, merged.max_items);
+
+    channel.title = merged.title.clone();
+    channel.link = merged.link.clone();
+    channel.description = format!("latest {} items of the boards watched by bbsmon", merged.max_items);
+    channel.last_build_date = Some(now.to_rfc2822());
+    channel.generator = Some("bbsmon".to_string());
+    if channel.items.iter().any(|item| item.content.is_some()) {
+        channel.namespaces.insert("content".to_string(), CONTENT_NAMESPACE.to_string());
+    }
+
+    let xml = channel.write_to(Vec::new())?;
+    write_atomic(&merged.path, |writer| {
+        writer.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n")?;
+        writer.write_all(&xml)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn item(guid: Option<&str>, title: &str, date: Option<&str>) -> Item {
+        Item {
+            title: Some(title.to_string()),
+            link: Some(format!("http://bbs.example/{}", title)),
+            guid: guid.map(|g| Guid { value: g.to_string(), permalink: false }),
+            pub_date: date.map(|d| d.to_string()),
+            ..Item::default()
+        }
+    }
+
+    #[test]
+    fn items_stay_until_the_cap_pushes_them_out() {
+        let now = Local.with_ymd_and_hms(2016, 11, 6, 12, 0, 0).unwrap();
+        let mut channel = Channel::default();
+
+        merge_items(&mut channel, "二手", &vec![
+            item(Some("t1"), "bike", Some("Sat, 05 Nov 2016 08:00:00 +0800")),
+            item(None, "lamp", Some("昨天")),
+        ], now);
+        merge_items(&mut channel, "聚餐", &vec![
+            item(Some("t1"), "dinner", Some("Fri, 04 Nov 2016 20:00:00 +0800")),
+        ], now);
+        // the lamp scrolled out of its feed, the bike got a new title.
+        merge_items(&mut channel, "二手", &vec![
+            item(Some("t1"), "bike (sold)", Some("Sat, 05 Nov 2016 08:00:00 +0800")),
+        ], now);
+
+        assert_eq!(channel.items.len(), 3);
+        assert_eq!(channel.items[0].title, Some("bike (sold)".to_string()));
+        assert_eq!(channel.items[0].categories[0].name, "二手");
+        assert_eq!(channel.items[1].guid.as_ref().unwrap().value, "http://bbs.example/lamp");
+        assert_eq!(channel.items[1].pub_date, Some(now.to_rfc2822()));
+        assert_eq!(channel.items[2].categories[0].name, "聚餐");
+
+        cap_items(&mut channel, 2);
+        let titles: Vec<_> = channel.items.iter().map(|i| i.title.clone().unwrap()).collect();
+        assert_eq!(titles, vec!["lamp", "bike (sold)"]);
+    }
+
+    #[test]
+    fn merged_items_are_valid_rss() {
+        let mut scraped = item(Some("http://bbs.example/read.php?tid=1"), "t", None);
+        scraped.author = Some("mingzai".to_string());
+        let merged = merged_item("board", &scraped).unwrap();
+
+        assert_eq!(merged.author, None);
+        assert_eq!(merged.dublin_core_ext.unwrap().creators, vec!["mingzai"]);
+        assert_eq!(merged.guid.unwrap().value, "http://bbs.example/read.php?tid=1");
+
+        let untitled = Item { link: Some("http://bbs.example/".to_string()), ..Item::default() };
+        assert!(merged_item("board", &untitled).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+//! The built-in strings of mails, by language. Languages of 'LOCALES'
+//! without a translation here get the English ones.
+
+use crate::render::locale_language;
+
+// key, English, Chinese. Placeholders are filled by 'fill'.
+const MESSAGES: &[(&str, &str, &str)] = &[
+    ("recipient", "BBS Notification Receiver", "论坛通知订阅者"),
+    ("new", "{subject}: {total} new ({feeds})", "{subject}：{total} 条新帖（{feeds}）"),
+    ("new_on_sites", "{subject}: {total} new on {sites} {sites_word}, {boards} {boards_word} ({feeds})",
+     "{subject}：{total} 条新帖，{sites} {sites_word}、{boards} {boards_word}（{feeds}）"),
+    ("site", "site", "个站点"),
+    ("sites", "sites", "个站点"),
+    ("board", "board", "个版面"),
+    ("boards", "boards", "个版面"),
+    ("other_site", "Other", "其他"),
+    ("separator", ", ", "，"),
+    ("meta_changed", "{subject} ({feed} changed its {fields})", "{subject}（{feed} 的 {fields} 有变化）"),
+    ("held_back", "{subject} ({count} items held back by max_notifications_per_hour)",
+     "{subject}（另有 {count} 条因 max_notifications_per_hour 暂缓发送）"),
+    ("digest", "{subject} ({feed} digest)", "{subject}（{feed} 摘要）"),
+    ("daily_report", "{subject} (daily report)", "{subject}（日报）"),
+    ("weekly_report", "{subject} (weekly report)", "{subject}（周报）"),
+    ("heartbeat", "{subject} (still running)", "{subject}（运行正常）"),
+    ("feeds_failed", "{subject}: {count} feeds failed", "{subject}：{count} 个版面抓取失败"),
+    ("failing", "{subject}: {name} keeps failing", "{subject}：{name} 持续失败"),
+    ("recovered", "{subject}: {name} works again", "{subject}：{name} 已恢复"),
+];
+
+/// The text of 'key' in the language of 'locale'.
+pub fn message(locale: &str, key: &str) -> &'static str {
+    let &(_, en, zh) = MESSAGES.iter().find(|&&(k, _, _)| k == key).expect("every message key is in MESSAGES");
+    if locale_language(locale) == "zh" { zh } else { en }
+}
+
+/// 'message' of 'key' with each "{name}" of 'args' put in.
+pub fn fill(locale: &str, key: &str, args: &[(&str, &str)]) -> String {
+    let mut text = message(locale, key).to_string();
+    for &(name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_fall_back_to_english() {
+        assert_eq!(fill("zh_CN", "digest", &[("subject", "BBS"), ("feed", "二手")]), "BBS（二手 摘要）");
+        assert_eq!(fill("de", "digest", &[("subject", "BBS"), ("feed", "Markt")]), "BBS (Markt digest)");
+        assert_eq!(message("zh-CN", "recipient"), "论坛通知订阅者");
+    }
+}
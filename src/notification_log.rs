@@ -0,0 +1,170 @@
+//! The notification log: a line for every item a run sent, appended once
+//! the send went through and never rewritten. Unlike the snapshots it
+//! keeps what older runs sent, and unlike the 'history' archive it is
+//! always written and keeps an item sent twice twice.
+
+use std::io::Write;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::Local;
+
+use serde_json;
+
+use crate::error::*;
+use crate::history::HistoryEntry;
+use crate::history::parse_since;
+use crate::state::StatePaths;
+
+/// One line of the notification log.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct LoggedItem {
+    /// When the item was sent.
+    pub notified_at: String,
+    /// Name of the feed.
+    pub feed: String,
+    /// Title of the item.
+    pub title: Option<String>,
+    /// Link of the item.
+    pub link: Option<String>,
+}
+
+impl LoggedItem {
+    /// The line logged for 'entry'.
+    pub fn of(entry: &HistoryEntry) -> LoggedItem {
+        LoggedItem {
+            notified_at: entry.notified_at.clone(),
+            feed: entry.feed.clone(),
+            title: entry.item.title.as_deref().map(str::to_string),
+            link: entry.item.link.as_deref().map(str::to_string),
+        }
+    }
+
+    /// Add a line for each of 'entries' to the end of the log at 'path'.
+    pub fn append(path: &Path, entries: &[HistoryEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&serde_json::to_string(&LoggedItem::of(entry))?);
+            content.push('\n');
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)
+            .chain_err(|| format!("can't open {}", path.display()))?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Every logged item, oldest first.
+    pub fn load(path: &Path) -> Result<Vec<LoggedItem>> {
+        let mut items = Vec::new();
+
+        if !path.exists() {
+            return Ok(items);
+        }
+
+        let content = ::std::fs::read_to_string(path).chain_err(|| format!("can't read {}", path.display()))?;
+        for (n, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let item: LoggedItem = serde_json::from_str(line)
+                .chain_err(|| format!("{}:{}: bad notification log line", path.display(), n + 1))?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+}
+
+/// The 'items' sent at 'since' or later; those with a bad timestamp too.
+pub fn logged_since(items: Vec<LoggedItem>, since: Option<DateTime<FixedOffset>>) -> Vec<LoggedItem> {
+    let since = match since {
+        Some(since) => since,
+        None => return items,
+    };
+
+    items.into_iter().filter(|item| {
+        DateTime::parse_from_rfc3339(&item.notified_at).map_or(true, |at| at >= since)
+    }).collect()
+}
+
+/// Print the logged items sent since 'since', see 'parse_since', every
+/// one when absent.
+pub fn log_show(paths: &StatePaths, since: Option<&str>, json: bool) -> Result<()> {
+    let since = match since {
+        Some(since) => Some(parse_since(since).chain_err(|| "bad --since")?),
+        None => None,
+    };
+
+    for item in logged_since(LoggedItem::load(&paths.notification_log)?, since) {
+        if json {
+            println!("{}", serde_json::to_string(&item)?);
+            continue;
+        }
+
+        let at = DateTime::parse_from_rfc3339(&item.notified_at)
+            .map(|at| at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or(item.notified_at.clone());
+        println!("{} [{}] {} {}", at, item.feed, item.title.as_ref().map_or("", |t| t.as_str()),
+                 item.link.as_ref().map_or("", |l| l.as_str()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    use crate::render::SerItem;
+
+    fn entry(title: &str, notified_at: &str) -> HistoryEntry {
+        HistoryEntry {
+            feed: "二手".to_string(),
+            key: title.to_string(),
+            notified_at: notified_at.to_string(),
+            item: SerItem {
+                title: Some(title.to_string().into()),
+                link: Some(format!("http://bbs.example/{}", title).into()),
+                description: Some("not logged".into()),
+                author: None,
+                pub_date: None,
+                thumbnail: None,
+                categories: Vec::new().into(),
+                replies: Vec::new().into(),
+                archive_link: None,
+                diff: Vec::new().into(),
+            },
+        }
+    }
+
+    #[test]
+    fn every_send_is_appended_and_kept() {
+        let path = env::temp_dir().join(format!("bbsmon-notified-{}.jsonl", ::std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        LoggedItem::append(&path, &[entry("bike", "2016-11-06T09:00:00+08:00")]).unwrap();
+        LoggedItem::append(&path, &[entry("lamp", "2016-11-07T09:00:00+08:00"),
+                                    entry("bike", "2016-11-08T09:00:00+08:00")]).unwrap();
+
+        let items = LoggedItem::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(items.iter().map(|i| i.title.as_ref().unwrap().as_str()).collect::<Vec<&str>>(),
+                   vec!["bike", "lamp", "bike"]);
+        assert_eq!(items[1].link, Some("http://bbs.example/lamp".to_string()));
+
+        let since = DateTime::parse_from_rfc3339("2016-11-07T09:00:00+08:00").unwrap();
+        assert_eq!(logged_since(items, Some(since)).len(), 2);
+    }
+}
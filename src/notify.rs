@@ -0,0 +1,770 @@
+//! Where notifications go: mail, the spool of failed mails, webhooks and
+//! error reports.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::Local;
+
+use hickory_resolver::Resolver;
+use hickory_resolver::error::ResolveErrorKind;
+
+use lettre::Address;
+use lettre::Message;
+use lettre::SmtpTransport;
+use lettre::Transport;
+use lettre::address::Envelope;
+use lettre::message::Body;
+use lettre::message::Mailbox;
+use lettre::message::header::ContentTransferEncoding;
+use lettre::message::header::HeaderName;
+use lettre::message::header::HeaderValue;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::authentication::Mechanism;
+use lettre::transport::smtp::client::Tls;
+use lettre::transport::smtp::client::TlsParameters;
+use lettre::transport::smtp::extension::ClientId;
+
+#[cfg(feature = "sink-webhook")]
+use reqwest;
+use serde_json;
+use tera;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::render::SerItem;
+#[cfg(feature = "sink-webhook")]
+use crate::retry::RetryPolicy;
+use crate::retry::is_transient;
+use crate::runtime::BoxFuture;
+#[cfg(feature = "sink-webhook")]
+use crate::runtime::block_on;
+use crate::runtime::blocking;
+use crate::render::render;
+use crate::render::render_context;
+use crate::state::StatePaths;
+use crate::log::Record;
+use crate::messages::fill;
+use crate::messages::message;
+use crate::state::write_atomic;
+
+/// A backend told about the items a run notified, besides the mail a run
+/// always sends. 'Pipeline::add_notifier' plugs one in; runs tell the
+/// notifiers side by side on their runtime, see 'runtime'. One that has
+/// to block does so in 'runtime::blocking'.
+pub trait Notifier: Send + Sync {
+    /// Short name used in logs and failure reports.
+    fn name(&self) -> &str;
+
+    /// Tell about 'items' of 'feed'.
+    fn notify<'a>(&'a self, feed: &'a str, items: &'a [SerItem]) -> BoxFuture<'a, ()>;
+}
+
+/// Mails items rendered with 'mail.html' to the configured recipient.
+pub struct Mail {
+    config: Config,
+    subject: String,
+}
+
+impl Mail {
+    /// Mail with 'subject' through the SMTP settings of 'config'.
+    pub fn new(config: &Config, subject: &str) -> Mail {
+        Mail {
+            config: config.clone(),
+            subject: subject.to_string(),
+        }
+    }
+}
+
+impl Notifier for Mail {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn notify<'a>(&'a self, _feed: &'a str, items: &'a [SerItem]) -> BoxFuture<'a, ()> {
+        let (config, subject) = (self.config.clone(), self.subject.clone());
+        let items: Vec<SerItem<'static>> = items.iter().map(|item| item.clone().into_owned()).collect();
+        blocking(move || {
+            let content = render(&config, &config.templates(), "mail.html", &items)?;
+            send_mail(&config, &subject, &content)
+        })
+    }
+}
+
+/// Posts items to a Rocket.Chat incoming webhook.
+#[cfg(feature = "sink-webhook")]
+pub struct RocketChat {
+    webhook: String,
+    summary_only: bool,
+    description_chars: Option<usize>,
+    retry: RetryPolicy,
+}
+
+#[cfg(feature = "sink-webhook")]
+impl RocketChat {
+    /// Post to 'webhook', only the item counts with 'summary_only', and
+    /// retry failed posts as 'retry' says. Descriptions are cut to
+    /// 'description_chars', see 'truncate_chars'.
+    pub fn new(webhook: &str, summary_only: bool, description_chars: Option<usize>, retry: &RetryPolicy)
+               -> RocketChat {
+        RocketChat {
+            webhook: webhook.to_string(),
+            summary_only,
+            description_chars,
+            retry: retry.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "sink-webhook")]
+impl Notifier for RocketChat {
+    fn name(&self) -> &str {
+        "rocketchat"
+    }
+
+    fn notify<'a>(&'a self, feed: &'a str, items: &'a [SerItem]) -> BoxFuture<'a, ()> {
+        Box::pin(send_rocketchat(&self.webhook, feed, items, self.summary_only, self.description_chars,
+                                 &self.retry))
+    }
+}
+
+/// The templates make HTML; anything else, like a 'simple_format' mail, is
+/// sent as plain text.
+pub fn content_type(content: &str) -> &'static str {
+    if content.trim_start().starts_with('<') {
+        return "text/html; charset=UTF-8";
+    }
+    "text/plain; charset=UTF-8"
+}
+
+/// Longest RFC 2047 encoded-word.
+pub const MAX_ENCODED_WORD: usize = 75;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().fold(0u32, |n, b| (n << 8) | *b as u32) << (8 * (3 - chunk.len()));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// 'text' fit for a mail header: as it is when ASCII, else as RFC 2047
+/// "B" encoded-words of its UTF-8, none longer than 'MAX_ENCODED_WORD'
+/// and none splitting a character.
+pub fn encode_header(text: &str) -> String {
+    if text.bytes().all(|b| b < 0x80) {
+        return text.to_string();
+    }
+
+    // "=?UTF-8?B?" and "?=" leave 63 characters, 45 bytes of base64.
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", base64(chunk.as_bytes())));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    if !chunk.is_empty() {
+        words.push(format!("=?UTF-8?B?{}?=", base64(chunk.as_bytes())));
+    }
+
+    words.join(" ")
+}
+
+/// How mails to 'to' name their recipient: the 'name' of a subscription
+/// it is an address of, 'to_name' for 'to', else the "recipient" message
+/// of 'locale'.
+pub fn recipient_name(c: &Config, to: &str) -> String {
+    if let Some(name) = c.subscriptions.iter().filter(|s| s.recipients().contains(&to))
+        .filter_map(|s| s.name.clone()).next() {
+        return name;
+    }
+    if let (true, Some(name)) = (to == c.to, c.to_name.as_ref()) {
+        return name.clone();
+    }
+
+    message(&c.locale, "recipient").to_string()
+}
+
+/// Mail 'content' to the configured recipient.
+pub fn send_mail(c: &Config, subject: &str, content: &str) -> Result<()> {
+    send_mail_to(c, &c.to, subject, content)
+}
+
+/// Fails when the domain of 'to' has no MX record, asking the resolvers
+/// of the system.
+pub fn verify_recipient(to: &str) -> Result<()> {
+    let domain = match to.rfind('@') {
+        Some(at) => to[at + 1..].trim_end_matches('>'),
+        None => bail!("recipient '{}' has no domain", to),
+    };
+
+    let resolver = Resolver::from_system_conf().chain_err(|| "can't read the resolver configuration")?;
+    let found = match resolver.mx_lookup(format!("{}.", domain)) {
+        Ok(mx) => mx.iter().next().is_some(),
+        Err(ref e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => false,
+        Err(e) => bail!("can't look up the MX records of '{}' for recipient '{}': {}", domain, to, e),
+    };
+    if !found {
+        bail!("domain '{}' of recipient '{}' has no MX record", domain, to);
+    }
+
+    Ok(())
+}
+
+/// Mail 'content' to 'to', after 'verify_recipient' when 'verify_recipients'
+/// asks for it.
+pub fn send_mail_to(c: &Config, to: &str, subject: &str, content: &str) -> Result<()> {
+    send_mails(c, subject, &[(to.to_string(), content.to_string())]).remove(0)
+}
+
+// warn about 'to', or refuse it, as 'verify_recipients' says.
+fn check_recipient(c: &Config, to: &str) -> Result<()> {
+    if let Some(ref verify) = c.verify_recipients {
+        if let Err(e) = verify_recipient(to) {
+            if verify == "abort" {
+                return Err(e).chain_err(|| format!("not mailing {}", to));
+            }
+            Record::warn("recipient doesn't verify").field("error", error_chain_string(&e))
+                .log(format!("warning: {}", error_chain_string(&e)));
+        }
+    }
+
+    Ok(())
+}
+
+fn smtp_transport(c: &Config) -> Result<SmtpTransport> {
+    let tls = match c.smtp_mode.as_deref() {
+        Some("plain") => Tls::None,
+        Some("starttls") => Tls::Required(TlsParameters::new(c.server.clone())?),
+        Some("smtps") => Tls::Wrapper(TlsParameters::new(c.server.clone())?),
+        _ => Tls::Opportunistic(TlsParameters::new(c.server.clone())?),
+    };
+    let mut builder = SmtpTransport::builder_dangerous(c.server.as_str())
+        .port(c.smtp_port)
+        .tls(tls)
+        .credentials(Credentials::new(c.from.clone(), c.password.clone()))
+        .authentication(vec![Mechanism::Plain]);
+    if let Some(ref helo_name) = c.helo_name {
+        builder = builder.hello_name(ClientId::Domain(helo_name.clone()));
+    }
+
+    Ok(builder.build())
+}
+
+// a header whose value 'encode_header' already made fit for a mail.
+fn encoded_header(name: &'static str, value: String) -> HeaderValue {
+    HeaderValue::dangerous_new_pre_encoded(HeaderName::new_from_ascii_str(name), value.clone(), value)
+}
+
+// 'content' from 'c.from' to 'to', its headers encoded as 'encode_header'
+// says and its body sent 8-bit, as the server takes SMTPUTF8.
+fn build_mail(c: &Config, to: &str, subject: &str, content: &str) -> Result<Message> {
+    let (from, to_address) = (c.from.parse::<Mailbox>()?, to.parse::<Address>()?);
+    let to_header = format!("{} <{}>", encode_header(&recipient_name(c, to)), to_address);
+    let body = Body::new_with_encoding(content.to_string(), ContentTransferEncoding::EightBit)
+        .unwrap_or_else(|_| Body::new(content.to_string()));
+
+    Ok(Message::builder()
+        .envelope(Envelope::new(Some(from.email.clone()), vec![to_address])?)
+        .from(from)
+        .raw_header(encoded_header("To", to_header))
+        .raw_header(encoded_header("Subject", encode_header(subject)))
+        .raw_header(encoded_header("Content-Type", content_type(content).to_string()))
+        .body(body)?)
+}
+
+/// Mail each '(to, content)' of 'mails' with 'subject' over one SMTP
+/// session, see 'send_mail_to'. The results come in the order of 'mails'.
+pub fn send_mails(c: &Config, subject: &str, mails: &[(String, String)]) -> Vec<Result<()>> {
+    let mut sender: Option<SmtpTransport> = None;
+
+    mails.iter().map(|(to, content)| -> Result<()> {
+        check_recipient(c, to)?;
+
+        c.retry.run(is_transient, || {
+            let email = build_mail(c, to, subject, content)?;
+
+            if sender.is_none() {
+                sender = Some(smtp_transport(c)?);
+            }
+            sender.as_ref().unwrap().send(&email)?;
+            Ok(())
+        }).chain_err(|| format!("can't mail {} through {}", to, c.server))
+    }).collect()
+}
+
+/// A rendered notification that could not be delivered, kept in the spool
+/// directory until 'retry-failed' gets it out.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeadLetter {
+    /// Recipient of the mail; 'to' for letters queued before there were
+    /// subscriptions.
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Subject of the mail.
+    pub subject: String,
+    /// Rendered body of the mail.
+    pub content: String,
+    /// When sending failed.
+    pub failed_at: String,
+    /// Why it failed.
+    pub error: String,
+}
+
+impl DeadLetter {
+    /// Queue in 'spool', returning the file it was written to.
+    pub fn save(&self, spool: &Path) -> Result<PathBuf> {
+        let name = format!("{}.json", Local::now().format("%Y%m%d%H%M%S%f"));
+        let path = spool.join(name);
+
+        write_atomic(&path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })?;
+
+        Ok(path)
+    }
+
+    /// Every queued entry, oldest first.
+    pub fn load_all(spool: &Path) -> Result<Vec<(PathBuf, DeadLetter)>> {
+        let mut files = Vec::new();
+
+        if !spool.exists() {
+            return Ok(Vec::new());
+        }
+
+        for entry in fs::read_dir(spool)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        let mut letters = Vec::new();
+        for path in files {
+            let mut content = String::new();
+            File::open(&path)?.read_to_string(&mut content)?;
+            let letter: DeadLetter = serde_json::from_str(&content)?;
+            letters.push((path, letter));
+        }
+
+        Ok(letters)
+    }
+}
+
+/// Re-send every queued notification, dropping the ones that went through.
+pub fn retry_failed(config: &Config, paths: &StatePaths) -> Result<()> {
+    let letters = DeadLetter::load_all(&paths.spool)?;
+
+    let mut sent = 0;
+    let mut failed = 0;
+
+    for (path, letter) in letters {
+        let to = letter.to.as_ref().unwrap_or(&config.to);
+        match send_mail_to(config, to, &letter.subject, &letter.content) {
+            Ok(()) => {
+                fs::remove_file(&path)?;
+                sent += 1;
+            }
+            Err(e) => {
+                Record::warn("queued notification still failing").field("path", path.display().to_string())
+                    .field("error", error_chain_string(&e))
+                    .log(format!("{}: still failing: {}", path.display(), e));
+                failed += 1;
+            }
+        }
+    }
+
+    Record::info("retried queued notifications").field("items", sent + failed).field("sent", sent)
+        .field("queued", failed)
+        .log(format!("retried {} queued notifications: {} sent, {} still queued.", sent + failed, sent, failed));
+
+    Ok(())
+}
+
+/// Rocket.Chat refuses overly long messages, so items go out in chunks.
+#[cfg(feature = "sink-webhook")]
+pub const ROCKETCHAT_BATCH: usize = 20;
+
+/// Payload of a Rocket.Chat webhook.
+#[cfg(feature = "sink-webhook")]
+#[derive(Serialize, Debug)]
+pub struct ChatMessage {
+    /// Message text, the feed and its item count.
+    pub text: String,
+    /// One per item, none in a summary.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ChatAttachment>,
+}
+
+/// An item in a 'ChatMessage'.
+#[cfg(feature = "sink-webhook")]
+#[derive(Serialize, Debug)]
+pub struct ChatAttachment {
+    /// Title of the item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Link of the item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_link: Option<String>,
+    /// Author of the item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    /// Description of the item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Picture of the item, shown next to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumb_url: Option<String>,
+}
+
+/// The first 'max' characters of 'text' and an ellipsis, or all of it
+/// when it isn't longer.
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// The webhook payloads 'items' of 'feed' are posted as, 'ROCKETCHAT_BATCH'
+/// items per message. A 'summary_only' message just counts them, and
+/// descriptions are cut to 'description_chars' when given.
+#[cfg(feature = "sink-webhook")]
+pub fn rocketchat_messages(feed: &str, items: &[SerItem], summary_only: bool, description_chars: Option<usize>)
+                           -> Vec<ChatMessage> {
+    if summary_only {
+        return vec![ChatMessage {
+            text: format!("{} new items on {}", items.len(), feed),
+            attachments: Vec::new(),
+        }];
+    }
+
+    let chunks = items.len().div_ceil(ROCKETCHAT_BATCH);
+    let mut messages = Vec::new();
+
+    for (i, chunk) in items.chunks(ROCKETCHAT_BATCH).enumerate() {
+        let text = if chunks > 1 {
+            format!("{}: {} items ({}/{})", feed, items.len(), i + 1, chunks)
+        } else {
+            format!("{}: {} items", feed, items.len())
+        };
+
+        messages.push(ChatMessage {
+            text,
+            attachments: chunk.iter().map(|item| ChatAttachment {
+                title: item.title.as_deref().map(str::to_string),
+                title_link: item.link.as_deref().map(str::to_string),
+                author_name: item.author.as_deref().map(str::to_string),
+                text: match description_chars {
+                    Some(max) => item.description.as_deref().map(|d| truncate_chars(d, max)),
+                    None => item.description.as_deref().map(str::to_string),
+                },
+                thumb_url: item.thumbnail.as_deref().map(str::to_string),
+            }).collect(),
+        });
+    }
+
+    messages
+}
+
+/// Post 'items' of 'feed' to 'webhook'.
+#[cfg(feature = "sink-webhook")]
+pub async fn send_rocketchat(webhook: &str, feed: &str, items: &[SerItem<'_>], summary_only: bool,
+                             description_chars: Option<usize>, retry: &RetryPolicy) -> Result<()> {
+    let client = reqwest::Client::builder().build()?;
+
+    for message in rocketchat_messages(feed, items, summary_only, description_chars) {
+        post_chat_message(&client, webhook, &message, retry).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "sink-webhook")]
+async fn post_chat_message(client: &reqwest::Client, webhook: &str, message: &ChatMessage, retry: &RetryPolicy)
+                           -> Result<()> {
+    retry.run_async(is_transient, || async {
+        let resp = client.post(webhook).json(message).send().await?;
+        if !resp.status().is_success() {
+            bail!(ErrorKind::HttpStatus(webhook.to_string(), resp.status().as_u16()));
+        }
+        Ok(())
+    }).await.chain_err(|| format!("can't post to {}", webhook))
+}
+
+/// Send a notification, or queue it in the spool when that fails. The send
+/// error is handed back once the notification is queued; Err means it could
+/// neither be sent nor queued.
+pub fn deliver(config: &Config, paths: &StatePaths, subject: &str, content: String)
+               -> Result<Option<Error>> {
+    deliver_all(config, paths, subject, vec![(config.to.clone(), content)])
+}
+
+/// 'deliver' each '(to, content)' of 'mails' over one SMTP session. The
+/// first send error is handed back once every failed mail is queued.
+pub fn deliver_all(config: &Config, paths: &StatePaths, subject: &str, mails: Vec<(String, String)>)
+                   -> Result<Option<Error>> {
+    let results = send_mails(config, subject, &mails);
+
+    let mut failed = None;
+    for ((to, content), result) in mails.into_iter().zip(results) {
+        if let Err(e) = result {
+            let letter = DeadLetter {
+                to: Some(to),
+                subject: subject.to_string(),
+                content,
+                failed_at: Local::now().to_rfc3339(),
+                error: e.to_string(),
+            };
+            let queued = letter.save(&paths.spool)?;
+
+            let e: Result<()> = Err(e).chain_err(|| {
+                format!("not sent, queued as {} for 'bbsmon retry-failed'; the state moved on as if it was",
+                        queued.display())
+            });
+            let e = e.unwrap_err();
+            if failed.is_none() {
+                failed = Some(e);
+            } else {
+                Record::error("mail not sent").field("error", error_chain_string(&e))
+                    .log(error_chain_string(&e));
+            }
+        }
+    }
+
+    Ok(failed)
+}
+
+/// A feed, or another step of a run, that failed.
+#[derive(Serialize, Debug)]
+pub struct Failure {
+    /// Name of the feed or step.
+    pub feed: String,
+    /// The error and its causes.
+    pub error: String,
+    /// When it failed.
+    pub time: String,
+}
+
+/// Runs in a row a feed, or another step of a run, failed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FailureStreak {
+    /// How many runs.
+    pub count: u32,
+    /// When the first of them failed, RFC 3339.
+    pub since: String,
+    /// The error of the last one.
+    pub error: String,
+    /// Whether the alert about it went out.
+    #[serde(default)]
+    pub alerted: bool,
+}
+
+/// Streaks with the name of the feed or step they are of.
+pub type NamedStreaks = Vec<(String, FailureStreak)>;
+
+/// When the last error report went out, and what keeps failing.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AlertState {
+    /// Time of the last report.
+    pub last_error_email: Option<String>,
+    /// Failing feeds and steps by name, see 'AlertState::track'.
+    #[serde(default)]
+    pub streaks: BTreeMap<String, FailureStreak>,
+}
+
+impl AlertState {
+    /// The state stored at 'path', empty when there is none.
+    pub fn load(path: &Path) -> Result<AlertState> {
+        if !path.exists() {
+            return Ok(AlertState::default());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Replace what is stored at 'path' with this state.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+
+    /// Count the 'failures' of a run in which the feeds and steps named
+    /// 'checked' ran. Returns the streaks due an alert, 'threshold' runs
+    /// long and not alerted yet, and the alerted ones that ended, which
+    /// are forgotten.
+    pub fn track(&mut self, checked: &Vec<String>, failures: &Vec<Failure>, threshold: u32, now: DateTime<Local>)
+                 -> (NamedStreaks, NamedStreaks) {
+        let mut recovered = Vec::new();
+        for name in checked {
+            if failures.iter().any(|f| &f.feed == name) {
+                continue;
+            }
+            if let Some(streak) = self.streaks.remove(name) {
+                if streak.alerted {
+                    recovered.push((name.clone(), streak));
+                }
+            }
+        }
+
+        let mut due = Vec::new();
+        for failure in failures {
+            let streak = self.streaks.entry(failure.feed.clone()).or_insert(FailureStreak {
+                count: 0,
+                since: now.to_rfc3339(),
+                error: String::new(),
+                alerted: false,
+            });
+            streak.count += 1;
+            streak.error = failure.error.clone();
+            if streak.count >= threshold && !streak.alerted {
+                due.push((failure.feed.clone(), streak.clone()));
+            }
+        }
+
+        (due, recovered)
+    }
+}
+
+// "3 h 20 min" since 'since', for alerts.
+fn failing_for(since: &str, now: DateTime<Local>) -> String {
+    let minutes = DateTime::parse_from_rfc3339(since).map(|since| (now.timestamp() - since.timestamp()) / 60)
+        .unwrap_or(0);
+    if minutes < 60 {
+        return format!("{} min", minutes);
+    }
+    format!("{} h {} min", minutes / 60, minutes % 60)
+}
+
+// tell 'subject' and 'text' by mail to 'alert_to', else 'error_email', else
+// 'to', and to the webhook; fine when either got it.
+fn send_alert(config: &Config, subject: &str, text: &str) -> Result<()> {
+    let to = config.alert_to.as_ref().or(config.error_email.as_ref()).unwrap_or(&config.to);
+    let mailed = send_mail_to(config, to, subject, text);
+
+    #[cfg(feature = "sink-webhook")]
+    {
+        if let Some(ref webhook) = config.rocketchat_webhook {
+            let message = ChatMessage { text: format!("{}\n{}", subject, text), attachments: Vec::new() };
+            let posted = block_on(async {
+                let client = reqwest::Client::builder().build()?;
+                post_chat_message(&client, webhook, &message, &config.retry).await
+            });
+            if posted.is_ok() {
+                if let Err(e) = mailed {
+                    Record::warn("alert not mailed").field("error", error_chain_string(&e))
+                        .log(format!("alert not mailed: {}", error_chain_string(&e)));
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    mailed
+}
+
+/// Alert about the feeds and steps that failed 'alert_after_failures'
+/// runs in a row, once for each streak, and tell when an alerted one
+/// works again; see 'AlertState::track'. Alerts that can't be sent are
+/// tried again next run.
+pub fn send_alerts(config: &Config, paths: &StatePaths, checked: &Vec<String>, failures: &Vec<Failure>)
+                   -> Result<()> {
+    let threshold = match config.alert_after_failures {
+        Some(threshold) => threshold,
+        None => return Ok(()),
+    };
+
+    let mut alerts = AlertState::load(&paths.alerts)?;
+    let now = Local::now();
+    let (due, recovered) = alerts.track(checked, failures, threshold, now);
+
+    let mut failed = None;
+    for (name, streak) in due {
+        let subject = fill(&config.locale, "failing", &[("subject", &config.subject), ("name", &name)]);
+        let text = format!("{} failed {} runs in a row, for {} since {}.\nLast error: {}\n",
+                           name, streak.count, failing_for(&streak.since, now), streak.since, streak.error);
+        match send_alert(config, &subject, &text) {
+            Ok(()) => {
+                if let Some(streak) = alerts.streaks.get_mut(&name) {
+                    streak.alerted = true;
+                }
+            }
+            Err(e) => failed = Some(e),
+        }
+    }
+
+    for (name, streak) in recovered {
+        let subject = fill(&config.locale, "recovered", &[("subject", &config.subject), ("name", &name)]);
+        let text = format!("{} works again after failing {} runs in a row since {}.\n",
+                           name, streak.count, streak.since);
+        if let Err(e) = send_alert(config, &subject, &text) {
+            failed = Some(e);
+        }
+    }
+
+    alerts.save(&paths.alerts)?;
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Mail 'failures' to 'error_email', unless a report went out less than
+/// 'error_email_interval_secs' ago.
+pub fn report_failures(config: &Config, paths: &StatePaths, failures: &Vec<Failure>) -> Result<()> {
+    let to = match config.error_email {
+        Some(ref to) => to,
+        None => return Ok(()),
+    };
+
+    let mut alerts = AlertState::load(&paths.alerts)?;
+    let now = Local::now();
+
+    if let Some(ref last) = alerts.last_error_email {
+        if let Ok(last) = DateTime::parse_from_rfc3339(last) {
+            if now.timestamp() - last.timestamp() < config.error_email_interval_secs {
+                Record::info("error report suppressed").field("last_sent", last.to_rfc3339())
+                    .log(format!("error report suppressed, last one sent at {}.", last));
+                return Ok(());
+            }
+        }
+    }
+
+    let mut tctx = tera::Context::new();
+    tctx.insert("failures", failures);
+    let content = render_context(&config.templates(), "error.html", tctx)?;
+
+    let subject = fill(&config.locale, "feeds_failed", &[("subject", &config.subject),
+                                                           ("count", &failures.len().to_string())]);
+    send_mail_to(config, to, &subject, &content)?;
+
+    alerts.last_error_email = Some(now.to_rfc3339());
+    alerts.save(&paths.alerts)?;
+
+    Ok(())
+}
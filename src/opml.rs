@@ -0,0 +1,373 @@
+//! Importing and exporting the feed list as OPML.
+
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::collections::HashSet;
+
+use chrono::Local;
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use serde_json;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::config::FeedConfig;
+use crate::config::DEFAULT_FEED_NAME;
+use crate::state::write_atomic;
+
+/// A feed found in an OPML file.
+#[derive(Debug)]
+pub struct OpmlFeed {
+    /// The outline's 'text', or its 'title'.
+    pub name: String,
+    /// The outline's 'xmlUrl'.
+    pub url: String,
+    /// Folders the outline is nested in, joined with " / ".
+    pub group: Option<String>,
+}
+
+// line number of byte 'offset' of 'content', for error messages.
+fn line_of(content: &str, offset: usize) -> usize {
+    let offset = ::std::cmp::min(offset, content.len());
+    content.as_bytes()[..offset].iter().filter(|b| **b == b'\n').count() + 1
+}
+
+/// Every outline with an 'xmlUrl', named after its 'text' (or 'title'). The
+/// folders it is nested in become its group, unless 'flatten' is set.
+pub fn parse_opml(content: &str, flatten: bool) -> Result<Vec<OpmlFeed>> {
+    let mut reader = Reader::from_str(content);
+    reader.config_mut().trim_text(true);
+    // <outline/> is read as a start and an end, like <outline></outline>.
+    reader.config_mut().expand_empty_elements = true;
+    let mut feeds = Vec::new();
+    let mut in_opml = false;
+    // one entry per open outline, Some(folder name) for folders.
+    let mut outlines: Vec<Option<String>> = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                match element.name().as_ref() {
+                    b"opml" => in_opml = true,
+                    b"outline" if in_opml => {
+                        let mut text = None;
+                        let mut title = None;
+                        let mut url = None;
+
+                        for attr in element.attributes().with_checks(false).flatten() {
+                            if let Ok(value) = attr.unescape_value() {
+                                let value = value.into_owned();
+                                match attr.key.as_ref() {
+                                    b"text" => text = Some(value),
+                                    b"title" => title = Some(value),
+                                    b"xmlUrl" => url = Some(value),
+                                    _ => {}
+                                }
+                            }
+                        }
+
+                        let label = text.or(title);
+                        match url {
+                            Some(url) => {
+                                let folders: Vec<String> = outlines.iter()
+                                    .filter_map(|o| o.clone())
+                                    .collect();
+                                let group = if flatten || folders.is_empty() {
+                                    None
+                                } else {
+                                    Some(folders.join(" / "))
+                                };
+
+                                feeds.push(OpmlFeed {
+                                    name: label.unwrap_or(url.clone()),
+                                    url,
+                                    group,
+                                });
+                                outlines.push(None);
+                            }
+                            None => outlines.push(Some(label.unwrap_or_default())),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::End(element))
+                if element.name().as_ref() == b"outline" => {
+                    outlines.pop();
+                }
+            Ok(Event::Eof) => break,
+            Err(err) => {
+                bail!("malformed OPML at line {}: {}", line_of(content, reader.error_position() as usize), err);
+            }
+            _ => {}
+        }
+    }
+
+    if !in_opml {
+        bail!("not an OPML document, no <opml> element found");
+    }
+
+    Ok(feeds)
+}
+
+/// Add the feeds of an OPML file to the 'feeds' list of the config file,
+/// leaving every other setting alone. Without 'merge' the list is replaced.
+pub fn import_opml(config_file: &str, opml_file: &str, merge: bool, flatten: bool, dry_run: bool)
+                   -> Result<()> {
+    let mut opml = String::new();
+    File::open(opml_file)?.read_to_string(&mut opml)?;
+    let found = parse_opml(&opml, flatten).chain_err(|| format!("can't import {}", opml_file))?;
+
+    let mut content = String::new();
+    File::open(config_file)?.read_to_string(&mut content)?;
+    let mut root: Value = serde_json::from_str(&content)?;
+
+    let added = {
+        let object = match root.as_object_mut() {
+            Some(object) => object,
+            None => bail!("{} is not a JSON object", config_file),
+        };
+
+        let mut urls: HashSet<String> = HashSet::new();
+        let mut names: HashSet<String> = HashSet::new();
+
+        if let Some(remote) = object.get("remote_rss").and_then(|v| v.as_str()) {
+            urls.insert(remote.to_string());
+            names.insert(DEFAULT_FEED_NAME.to_string());
+        }
+
+        let mut feeds = match object.get("feeds").and_then(|v| v.as_array()) {
+            Some(feeds) if merge => feeds.clone(),
+            _ => Vec::new(),
+        };
+
+        for feed in &feeds {
+            if let Some(url) = feed.get("remote_rss").and_then(|v| v.as_str()) {
+                urls.insert(url.to_string());
+            }
+            if let Some(name) = feed.get("name").and_then(|v| v.as_str()) {
+                names.insert(name.to_string());
+            }
+        }
+
+        let mut added = Vec::new();
+        for feed in found {
+            if !urls.insert(feed.url.clone()) {
+                continue;
+            }
+
+            let mut name = feed.name.clone();
+            let mut n = 2;
+            while !names.insert(name.clone()) {
+                name = format!("{} ({})", feed.name, n);
+                n += 1;
+            }
+
+            let mut entry = Map::new();
+            entry.insert("name".to_string(), Value::String(name.clone()));
+            entry.insert("remote_rss".to_string(), Value::String(feed.url.clone()));
+            if let Some(group) = feed.group {
+                entry.insert("group".to_string(), Value::String(group));
+            }
+            feeds.push(Value::Object(entry));
+
+            added.push((name, feed.url));
+        }
+
+        object.insert("feeds".to_string(), Value::Array(feeds));
+        added
+    };
+
+    for (name, url) in &added {
+        println!("{} {}: {}", if dry_run { "would add" } else { "added" }, name, url);
+    }
+
+    if dry_run || added.is_empty() {
+        return Ok(());
+    }
+
+    write_atomic(config_file, |writer| {
+        writer.write_all(serde_json::to_string_pretty(&root)?.as_bytes())?;
+        Ok(())
+    })
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn opml_outline(out: &mut String, indent: &str, feed: &FeedConfig) {
+    let url = &feed.remote_rss;
+    let comment = if url.starts_with("http://") || url.starts_with("https://") {
+        ""
+    } else {
+        " comment=\"non-HTTP source\""
+    };
+
+    out.push_str(&format!("{}<outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\"{}/>\n",
+                          indent, xml_escape(&feed.name), xml_escape(&feed.name),
+                          xml_escape(url), comment));
+}
+
+/// The configured feeds as an OPML 2.0 document, grouped into folders by
+/// their 'group'. 'import-opml' reads it back into the same feeds.
+pub fn export_opml(config: &Config) -> String {
+    let mut out = String::new();
+
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<opml version=\"2.0\">\n");
+    out.push_str("  <head>\n");
+    out.push_str("    <title>bbsmon feeds</title>\n");
+    out.push_str(&format!("    <dateCreated>{}</dateCreated>\n", Local::now().to_rfc2822()));
+    out.push_str("  </head>\n");
+    out.push_str("  <body>\n");
+
+    let mut groups: Vec<&str> = Vec::new();
+    for feed in &config.feeds {
+        match feed.group {
+            Some(ref group) => if !groups.contains(&group.as_str()) {
+                groups.push(group);
+            },
+            None => opml_outline(&mut out, "    ", feed),
+        }
+    }
+
+    for group in groups {
+        out.push_str(&format!("    <outline text=\"{}\" title=\"{}\">\n",
+                              xml_escape(group), xml_escape(group)));
+        for feed in config.feeds.iter().filter(|f| f.group.as_ref().is_some_and(|g| g == group)) {
+            opml_outline(&mut out, "      ", feed);
+        }
+        out.push_str("    </outline>\n");
+    }
+
+    out.push_str("  </body>\n");
+    out.push_str("</opml>\n");
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    const NESTED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head><title>reader</title></head>
+  <body>
+    <outline type="rss" text="公告" xmlUrl="http://bbs.example/notice.xml"/>
+    <outline text="Boards">
+      <outline title="Tech">
+        <outline type="rss" text="内核" xmlUrl="http://bbs.example/kernel.xml"/>
+        <outline type="rss" title="Rust" xmlUrl="http://bbs.example/rust.xml"/>
+      </outline>
+      <outline type="rss" xmlUrl="http://bbs.example/market.xml"/>
+    </outline>
+  </body>
+</opml>
+"#;
+
+    #[test]
+    fn nested_folders_become_groups_unless_flattened() {
+        let feeds = parse_opml(NESTED, false).unwrap();
+        let found: Vec<(&str, &str, Option<&str>)> = feeds.iter()
+            .map(|f| (f.name.as_str(), f.url.as_str(), f.group.as_deref()))
+            .collect();
+        assert_eq!(found, vec![
+            ("公告", "http://bbs.example/notice.xml", None),
+            ("内核", "http://bbs.example/kernel.xml", Some("Boards / Tech")),
+            ("Rust", "http://bbs.example/rust.xml", Some("Boards / Tech")),
+            ("http://bbs.example/market.xml", "http://bbs.example/market.xml", Some("Boards")),
+        ]);
+
+        let flat = parse_opml(NESTED, true).unwrap();
+        assert_eq!(flat.len(), 4);
+        assert!(flat.iter().all(|f| f.group.is_none()));
+    }
+
+    #[test]
+    fn malformed_opml_names_the_line() {
+        let broken = "<opml version=\"2.0\">\n  <body>\n    <outline text=\"a\" xmlUrl=\"http://bbs/a.xml\">\n  \
+                      </body>\n</opml>\n";
+        let e = parse_opml(broken, false).unwrap_err();
+        assert!(e.to_string().starts_with("malformed OPML at line 4"), "{}", e);
+
+        let e = parse_opml("<rss version=\"2.0\"><channel/></rss>", false).unwrap_err();
+        assert!(e.to_string().contains("no <opml> element"), "{}", e);
+    }
+
+    #[test]
+    fn imports_skip_known_urls_and_keep_other_settings() {
+        let dir = env::temp_dir().join(format!("bbsmon-opml-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (config_file, opml_file) = (dir.join("bbsmon.json"), dir.join("feeds.opml"));
+        let config_file = config_file.to_str().unwrap();
+        let opml_file = opml_file.to_str().unwrap();
+
+        let config = r#"{"subject": "s", "interval_secs": 600,
+                         "feeds": [{"name": "内核", "remote_rss": "http://bbs.example/kernel.xml"},
+                                   {"name": "Rust", "remote_rss": "http://bbs.example/old-rust.xml"}]}"#;
+        fs::write(config_file, config).unwrap();
+        fs::write(opml_file, NESTED).unwrap();
+
+        import_opml(config_file, opml_file, true, false, true).unwrap();
+        assert_eq!(fs::read_to_string(config_file).unwrap(), config);
+
+        import_opml(config_file, opml_file, true, false, false).unwrap();
+        let root: Value = serde_json::from_str(&fs::read_to_string(config_file).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(root["subject"], "s");
+        assert_eq!(root["interval_secs"], 600);
+        let feeds: Vec<(&str, &str)> = root["feeds"].as_array().unwrap().iter()
+            .map(|f| (f["name"].as_str().unwrap(), f["remote_rss"].as_str().unwrap()))
+            .collect();
+        assert_eq!(feeds, vec![
+            ("内核", "http://bbs.example/kernel.xml"),
+            ("Rust", "http://bbs.example/old-rust.xml"),
+            ("公告", "http://bbs.example/notice.xml"),
+            ("Rust (2)", "http://bbs.example/rust.xml"),
+            ("http://bbs.example/market.xml", "http://bbs.example/market.xml"),
+        ]);
+        assert_eq!(root["feeds"][3]["group"], "Boards / Tech");
+    }
+
+    #[test]
+    fn exports_import_back_unchanged() {
+        let config: Config = serde_json::from_str(r#"{"feeds": [
+                                                          {"name": "Q&A <new>", "remote_rss": "http://a/rss?fid=1&page=2"},
+                                                          {"name": "本地", "remote_rss": "/var/lib/bbs/local.xml"},
+                                                          {"name": "\"Market\"", "remote_rss": "http://b/rss?fid=2",
+                                                           "group": "Boards & more"},
+                                                          {"name": "Scraped", "type": "command",
+                                                           "remote_rss": "fetch-board --fid 3 > /dev/stdout",
+                                                           "group": "Boards & more"},
+                                                          {"name": "Lounge", "remote_rss": "file:///srv/lounge.xml",
+                                                           "group": "Off topic"}],
+                                                      "subject": "s", "from": "f@bbs", "to": "t@bbs",
+                                                      "password": "p", "server": "bbs"}"#)
+            .unwrap();
+
+        let feeds = parse_opml(&export_opml(&config), false).unwrap();
+        let found: Vec<(&str, &str, Option<&str>)> = feeds.iter()
+            .map(|f| (f.name.as_str(), f.url.as_str(), f.group.as_deref()))
+            .collect();
+        let exported: Vec<(&str, &str, Option<&str>)> = config.feeds.iter()
+            .map(|f| (f.name.as_str(), f.remote_rss.as_str(), f.group.as_deref()))
+            .collect();
+        assert_eq!(found, exported);
+    }
+}
@@ -0,0 +1,1558 @@
+//! One pass over every feed: fetch, diff, notify and record.
+
+use std::path::Path;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::DateTime;
+use chrono::Local;
+use chrono::TimeZone;
+use chrono::Utc;
+
+use rss::Item;
+
+#[cfg(feature = "sink-webhook")]
+use serde_json;
+use tera;
+
+use tokio::runtime::Runtime;
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
+use tokio::task::JoinHandle;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::config::DigestSchedule;
+use crate::config::FeedConfig;
+use crate::config::Subscription;
+use crate::config::in_quiet_hours;
+use crate::config::on_active_day;
+use crate::archive::ARCHIVE_BUDGET_SECS;
+use crate::archive::add_archive_links;
+use crate::diff::Changes;
+use crate::diff::MetaChange;
+use crate::feed::RssContext;
+use crate::feed::DiffOptions;
+use crate::feed::fetch_diff_items;
+use crate::feed::item_key;
+use crate::feed::reply_count;
+use crate::hints::PublisherHints;
+use crate::history::HistoryEntry;
+use crate::notification_log::LoggedItem;
+use crate::log;
+use crate::log::Record;
+use crate::merged::update_merged;
+use crate::messages::fill;
+use crate::notify::Failure;
+use crate::notify::Notifier;
+#[cfg(feature = "sink-webhook")]
+use crate::notify::RocketChat;
+use crate::notify::deliver;
+use crate::notify::deliver_all;
+use crate::notify::report_failures;
+use crate::notify::send_alerts;
+#[cfg(feature = "sink-webhook")]
+use crate::notify::rocketchat_messages;
+use crate::queue::PendingItem;
+use crate::queue::PendingQueue;
+use crate::queue::ThrottleState;
+use crate::queue::pending_items;
+use crate::report::send_heartbeat;
+use crate::report::send_report;
+use crate::retry::random_fraction;
+use crate::runtime::block_on;
+use crate::runtime::runtime;
+use crate::render::DigestGroup;
+use crate::render::HotThread;
+use crate::render::item_link;
+use crate::render::SerItem;
+use crate::render::Section;
+use crate::render::convert_to_ser_items;
+use crate::render::count_items;
+use crate::render::group_threads;
+use crate::render::render_changes;
+use crate::render::render_context;
+use crate::render::render_digest;
+use crate::render::render_hot_changes;
+use crate::render::render_sections;
+use crate::render::order_sections;
+use crate::render::sections_subject;
+use crate::render::counts_subject;
+use crate::render::restrict_fields;
+use crate::seen::SeenStore;
+use crate::server;
+use crate::server::Pending;
+use crate::server::Triggers;
+use crate::source::feed_source;
+use crate::state::SendJournal;
+use crate::state::StatePaths;
+use crate::state::save_snapshot;
+use crate::status::FeedStatus;
+use crate::status::RunStatus;
+use crate::timings;
+use crate::timings::Phases;
+use crate::timings::millis;
+use crate::timings::timed;
+use crate::transform::transform_items;
+
+/// The configured feeds and where they are told about. 'run_once' is what
+/// a plain 'bbsmon' does.
+pub struct Pipeline {
+    config: Config,
+    paths: StatePaths,
+    notifiers: Arc<Vec<Box<dyn Notifier>>>,
+}
+
+// the notifiers besides mail that 'config' asks for.
+#[cfg(feature = "sink-webhook")]
+fn webhook_notifiers(config: &Config) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(ref webhook) = config.rocketchat_webhook {
+        notifiers.push(Box::new(RocketChat::new(webhook, config.webhook_summary_only, config.push_description_chars,
+                                                &config.retry)));
+    }
+    notifiers
+}
+
+#[cfg(not(feature = "sink-webhook"))]
+fn webhook_notifiers(_config: &Config) -> Vec<Box<dyn Notifier>> {
+    Vec::new()
+}
+
+impl Pipeline {
+    /// A pipeline over the feeds of 'config', posting to 'rocketchat_webhook'
+    /// when one is configured and logging as 'log_format' says.
+    pub fn new(config: Config) -> Pipeline {
+        let paths = StatePaths::resolve(&config);
+        log::set_format(&config.log_format);
+
+        let notifiers = webhook_notifiers(&config);
+
+        Pipeline {
+            config,
+            paths,
+            notifiers: Arc::new(notifiers),
+        }
+    }
+
+    /// Also tell 'notifier' about what every run notified.
+    pub fn add_notifier(&mut self, notifier: Box<dyn Notifier>) {
+        // runs hand the list to their delivery tasks and wait for them
+        // before returning, so it is not shared outside of one.
+        Arc::get_mut(&mut self.notifiers).expect("notifiers shared outside of a run").push(notifier);
+    }
+
+    /// The config the pipeline runs with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Where the pipeline keeps its state.
+    pub fn paths(&self) -> &StatePaths {
+        &self.paths
+    }
+
+    /// Check every feed once and notify what changed. Fails when a feed
+    /// failed, after the others were processed, and right away when
+    /// another process holds the lock of the state. Blocks until the run
+    /// is done; feeds are fetched and notifiers told on a runtime the run
+    /// starts, see 'runtime'.
+    pub fn run_once(&self) -> Result<()> {
+        self.paths.create_dirs(&self.config.feeds)?;
+        let _lock = self.paths.lock()?;
+        run_once(&self.config, &self.paths, &self.notifiers)
+    }
+
+    /// Run until 'stop_daemons', one pass every 'interval_secs'. A failed
+    /// pass is logged and does not stop the daemon. With 'listen' set,
+    /// triggers received there run their feeds in between, see 'server'; a
+    /// trigger of every feed starts the interval anew. The lock of the state
+    /// is held for as long as the daemon runs.
+    pub fn daemon(&self) -> Result<()> {
+        self.paths.create_dirs(&self.config.feeds)?;
+        let _lock = self.paths.lock()?;
+
+        let triggers = Arc::new(Triggers::new());
+        if let Some(ref address) = self.config.listen {
+            let token = self.config.trigger_token.clone().expect("'listen' is validated to have a token");
+            let feeds = self.config.feeds.iter().map(|f| f.name.clone()).collect();
+            server::listen(address, token, feeds, self.paths.status.clone(), triggers.clone())?;
+            Record::info("listening for triggers").field("address", address)
+                .log(format!("listening for triggers on {}.", address));
+        }
+
+        let mut pending = Pending::default();
+        pending.all = true;
+        let mut next_run = Local::now().timestamp();
+        let mut next_instant = Instant::now();
+
+        loop {
+            let (result, feeds) = if pending.all {
+                let skipped = hinted_skips(&self.config, &self.paths);
+                let mut config = self.config.clone();
+                config.feeds.retain(|f| !skipped.contains(&f.name));
+                (run_once(&config, &self.paths, &self.notifiers), config.feeds.iter().map(|f| f.name.clone()).collect())
+            } else {
+                let feeds: Vec<String> = pending.feeds.iter().cloned().collect();
+                let mut config = self.config.clone();
+                let result = config.select_feeds(feeds.iter().map(|f| f.as_str()).collect())
+                    .and_then(|_| run_once(&config, &self.paths, &self.notifiers));
+                (result, feeds)
+            };
+            if let Err(ref e) = result {
+                Record::error("run failed").field("error", error_chain_string(e))
+                    .log(format!("run failed: {}", error_chain_string(e)));
+            }
+
+            if pending.all {
+                next_run = Local::now().timestamp() + self.config.interval_secs as i64;
+                next_instant = Instant::now() + Duration::from_secs(self.config.interval_secs);
+            }
+            let mut run_status = RunStatus::load(&self.paths.status)?.unwrap_or_default();
+            run_status.next_run = Some(Local.timestamp_opt(next_run, 0).unwrap().to_rfc3339());
+            for feed in &self.config.feeds {
+                let next_fetch = next_run + jitter(&self.config, &feed.name).as_secs() as i64;
+                run_status.feeds.entry(feed.name.clone()).or_insert(FeedStatus::default()).next_fetch =
+                    Some(Local.timestamp_opt(next_fetch, 0).unwrap().to_rfc3339());
+            }
+            run_status.save(&self.paths.status)?;
+
+            let (code, body) = server::summary(&run_status, &feeds, result.err().map(|e| error_chain_string(&e)));
+            pending.answer(code, &body);
+
+            // woken now and then to notice 'stop_daemons'.
+            pending = Pending::default();
+            while pending.is_empty() && Instant::now() < next_instant && !stopping() {
+                let poll = Instant::now() + Duration::from_millis(STOP_POLL_MILLIS);
+                pending = triggers.wait(if poll < next_instant { poll } else { next_instant });
+            }
+            if stopping() {
+                Record::info("stopping").log("stopping.".to_string());
+                return Ok(());
+            }
+            if Instant::now() >= next_instant {
+                pending.all = true;
+            }
+        }
+    }
+
+    /// Fetch and diff every feed like a run, then print what each enabled
+    /// backend would be sent. Nothing is sent and nothing is written.
+    pub fn dry_run(&self) -> Result<()> {
+        dry_run(&self.config, &self.paths)
+    }
+}
+
+// the feeds of 'config' whose last snapshot asks not to be fetched now,
+// see 'PublisherHints'; each is logged.
+fn hinted_skips(config: &Config, paths: &StatePaths) -> Vec<String> {
+    if !config.respect_ttl {
+        return Vec::new();
+    }
+
+    let run_status = RunStatus::load(&paths.status).ok().and_then(|status| status).unwrap_or_default();
+    let mut skipped = Vec::new();
+    for feed in &config.feeds {
+        let snapshot = paths.snapshot(feed);
+        let hints = match RssContext::from_file(&snapshot) {
+            Ok(ctx) => PublisherHints::of(&ctx.channel),
+            Err(_) => continue,
+        };
+        let last_check = run_status.feeds.get(&feed.name)
+            .and_then(|status| status.last_check.as_ref())
+            .and_then(|last_check| DateTime::parse_from_rfc3339(last_check).ok());
+
+        if let Some(reason) = hints.skip_reason(config.interval_secs, last_check, Utc::now()) {
+            Record::info("fetch skipped on the hints of the feed").field("feed", &feed.name).field("reason", &reason)
+                .log(format!("{}: fetch skipped, {}.", feed.name, reason));
+            skipped.push(feed.name.clone());
+        }
+    }
+    skipped
+}
+
+// returns the history entries of what was sent out.
+fn notify_feed(config: &Config, paths: &StatePaths, feed: &FeedConfig,
+               changes: Option<Changes>, new_ctx: RssContext) -> Result<Vec<HistoryEntry>> {
+    if config.skip_if_empty && new_ctx.channel.items.is_empty() {
+        Record::warn("fetched feed has no items, keeping the stored snapshot").field("feed", &feed.name)
+            .log(format!("{}: warning: fetched feed has no items, keeping the stored snapshot.", feed.name));
+        return Ok(Vec::new());
+    }
+
+    let changes = match changes {
+        Some(changes) => changes,
+        None => {
+            Record::info("no usable snapshot, saving current rss as baseline").field("feed", &feed.name)
+                .log(format!("{}: no usable snapshot, saving current rss as baseline.", feed.name));
+            return notify_first_run(config, paths, feed, new_ctx);
+        }
+    };
+
+    let changes = drop_self_authored(config, feed, changes);
+
+    // a run that stopped between a send and the snapshot left its journal.
+    let journal = SendJournal::load(&paths.journal(feed))?;
+    let changes = if journal.sends.is_empty() {
+        changes
+    } else {
+        let rest = resume_journal(feed, &journal, changes);
+        if rest.notified().is_empty() && rest.channel_meta.is_empty() {
+            advance_snapshot(config, paths, feed, &new_ctx)?;
+            return Ok(Vec::new());
+        }
+        rest
+    };
+
+    // transformed and archived before the changes are routed, so held and queued items are too.
+    let mut changes = changes;
+    if let Some(ref command) = feed.transform_command {
+        transform_changes(config, feed, command, &mut changes);
+    }
+    if feed.archive_links {
+        add_archive_links(&feed.name, &mut changes.new_items, Duration::from_secs(ARCHIVE_BUDGET_SECS));
+    }
+
+    // the note is safe in the spool when sending fails, the items go on.
+    if config.watch_channel_meta && !changes.channel_meta.is_empty() {
+        if let Some(e) = send_channel_meta(config, paths, feed, &changes.channel_meta)? {
+            Record::error("channel change not sent").field("feed", &feed.name).field("error", error_chain_string(&e))
+                .log(format!("{}: {}", feed.name, error_chain_string(&e)));
+        }
+    }
+
+    if feed.digest.is_some() || feed.drip_interval_secs.is_some() {
+        return queue_for_digest(config, paths, feed, changes, new_ctx);
+    }
+
+    // off days hold everything, the quiet hours all but priority items.
+    let off_day = !on_active_day(&config.active_days, Local::now());
+    let quiet = off_day || in_quiet_hours(&config.quiet_hours, Local::now());
+    let throttle = ThrottleState::load(&paths.throttle)?;
+    let throttled = !is_batched(config, feed)
+        && !throttle.allows(&feed.name, config.throttle_limit(feed), Local::now());
+    let cooling = !is_batched(config, feed)
+        && !throttle.cooled_down(&feed.name, feed.send_cooldown_secs, Local::now());
+
+    // priority items may skip the quiet hours and the throttle and go out right away.
+    let mut urgent_sent = Vec::new();
+    let mut urgent_failed = None;
+    let exempt = !off_day && ((quiet && config.quiet_hours_override_priority)
+                              || (throttled && config.throttle_override_priority));
+    let changes = if exempt {
+        let (urgent, rest) = changes.partition(|item| is_priority(config, item));
+        let notified = pending_items(urgent.notified(), &config.missing_link);
+        if !notified.is_empty() {
+            urgent_failed = send_journaled(config, paths, feed, &urgent, &Vec::new())?;
+            urgent_sent = HistoryEntry::from_pending(&feed.name, &notified);
+        }
+        rest
+    } else {
+        changes
+    };
+
+    let mut entries = if is_batched(config, feed) {
+        notify_batched(config, paths, feed, changes, new_ctx, quiet)?
+    } else {
+        let holds = Holds { quiet, throttled, cooling };
+        notify_immediate(config, paths, feed, changes, new_ctx, &holds)?
+    };
+
+    if let Some(e) = urgent_failed {
+        return Err(e);
+    }
+
+    entries.extend(urgent_sent);
+    Ok(entries)
+}
+
+// what keeps the changes of a feed from going out right away.
+struct Holds {
+    quiet: bool,
+    throttled: bool,
+    cooling: bool,
+}
+
+fn notify_immediate(config: &Config, paths: &StatePaths, feed: &FeedConfig, changes: Changes,
+                    new_ctx: RssContext, holds: &Holds) -> Result<Vec<HistoryEntry>> {
+    let Holds { quiet, throttled, cooling } = *holds;
+    // during the quiet hours, while throttled or cooling down, and until
+    // 'send_held' sent what was held back, changes wait in the feed's
+    // pending queue.
+    let queue_path = paths.pending(feed);
+    let mut queue = PendingQueue::load(&queue_path)?;
+    if quiet || throttled || cooling || !queue.is_empty() {
+        let new_items = pending_items(&changes.new_items, &config.missing_link);
+        let updated_items = pending_items(&changes.updated_items, &config.missing_link);
+        let count = new_items.len() + updated_items.len();
+
+        queue.add(new_items, updated_items);
+        queue.save(&queue_path)?;
+        advance_snapshot(config, paths, feed, &new_ctx)?;
+
+        if throttled && count > 0 {
+            let mut throttle = ThrottleState::load(&paths.throttle)?;
+            throttle.hold(&feed.name, count);
+            throttle.save(&paths.throttle)?;
+        }
+
+        if !queue.is_empty() {
+            let reason = if throttled {
+                ", max_notifications_per_hour reached"
+            } else if cooling {
+                ", send_cooldown_secs not over"
+            } else {
+                ""
+            };
+            Record::info("holding items").field("feed", &feed.name).field("items", queue.len())
+                .field("throttled", throttled).field("cooling", cooling)
+                .log(format!("{}: holding {} items{}.", feed.name, queue.len(), reason));
+        }
+        return Ok(Vec::new());
+    }
+
+    // items dropped by 'missing_link' don't count.
+    let notified = pending_items(changes.notified(), &config.missing_link);
+    let (hot, seen) = hot_threads(config, paths, feed, &new_ctx, &changes)?;
+    if notified.is_empty() && hot.is_empty() {
+        // threads seen for the first time keep their mark all the same.
+        if let Some(seen) = seen {
+            seen.save(&paths.seen_db)?;
+        }
+        Record::info("no changes").field("feed", &feed.name)
+            .log(format!("{}: new and old rss are same.", feed.name));
+        return Ok(Vec::new());
+    }
+
+    // once queued in the spool the notification is safe, so the snapshot may advance.
+    let failed = send_journaled(config, paths, feed, &changes, &hot)?;
+    advance_snapshot(config, paths, feed, &new_ctx)?;
+    if let Some(seen) = seen {
+        seen.save(&paths.seen_db)?;
+    }
+
+    let mut throttle = ThrottleState::load(&paths.throttle)?;
+    throttle.record(&feed.name, Local::now());
+    throttle.save(&paths.throttle)?;
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Ok(HistoryEntry::from_pending(&feed.name, &notified))
+}
+
+// the threads of 'feed' whose reply count grew by 'hot_threshold', with
+// the seen store holding their new marks, to be saved once they are sent.
+// a thread is told about by its item when that is among 'changes'. none
+// without a 'hot_threshold'.
+fn hot_threads(config: &Config, paths: &StatePaths, feed: &FeedConfig, new_ctx: &RssContext, changes: &Changes)
+               -> Result<(Vec<HotThread<'static>>, Option<SeenStore>)> {
+    let threshold = match config.hot_threshold {
+        Some(threshold) => threshold,
+        None => return Ok((Vec::new(), None)),
+    };
+
+    let thread_of = |item: &Item| -> String {
+        if let Some(ref key) = feed.thread_key {
+            let text = if key.field == "title" { &item.title } else { &item.link };
+            if let Some(thread) = text.as_ref().and_then(|text| key.thread(text)) {
+                return thread;
+            }
+        }
+        item_key(item)
+    };
+
+    // a thread spread over several items counts as its most replied one.
+    let mut counts: Vec<(String, u64)> = Vec::new();
+    let mut items: Vec<&Item> = Vec::new();
+    for item in &new_ctx.channel.items {
+        let count = match reply_count(item, &feed.reply_count_compiled) {
+            Some(count) => count,
+            None => continue,
+        };
+        let thread = thread_of(item);
+        let known = counts.iter().position(|(t, _)| *t == thread);
+        match known {
+            Some(i) if counts[i].1 >= count => {}
+            Some(i) => {
+                counts[i].1 = count;
+                items[i] = item;
+            }
+            None => {
+                counts.push((thread, count));
+                items.push(item);
+            }
+        }
+    }
+
+    let mut seen = SeenStore::load(&paths.seen_db)?;
+    let now = Local::now();
+    let heating = seen.heating_up(&feed.name, &counts, threshold, &now.to_rfc3339());
+    let changed: Vec<String> = changes.notified().into_iter().map(thread_of).collect();
+
+    let mut hot = Vec::new();
+    for (thread, delta, since) in heating {
+        if changed.contains(&thread) {
+            continue;
+        }
+        let i = counts.iter().position(|(t, _)| *t == thread).expect("a counted thread");
+        let item = convert_to_ser_items(vec![items[i]], &config.include_fields, &config.missing_link).pop();
+        if let Some(item) = item {
+            hot.push(HotThread::new(item.into_owned(), counts[i].1, delta, &since, now));
+        }
+    }
+
+    Ok((hot, Some(seen)))
+}
+
+// drop the items a journaled send got out before the last run stopped; the
+// ones whose send wasn't confirmed are sent again.
+fn resume_journal(feed: &FeedConfig, journal: &SendJournal, changes: Changes) -> Changes {
+    let confirmed = journal.confirmed_keys();
+    let pending = journal.pending_keys();
+    let (sent, rest) = changes.partition(|item| confirmed.contains(&item_key(item)));
+
+    let retried = rest.notified().iter().filter(|item| pending.contains(&item_key(item))).count();
+    Record::warn("resuming an interrupted send").field("feed", &feed.name)
+        .field("already_sent", sent.notified().len()).field("retried", retried)
+        .log(format!("{}: the last run stopped while sending, {} items already went out, {} are sent again.",
+                     feed.name, sent.notified().len(), retried));
+
+    rest
+}
+
+// 'send_changes', journaled so that a run stopping before the snapshot
+// advances neither loses nor repeats the items, see 'SendJournal'.
+fn send_journaled(config: &Config, paths: &StatePaths, feed: &FeedConfig, changes: &Changes,
+                  hot: &[HotThread]) -> Result<Option<Error>> {
+    let path = paths.journal(feed);
+    let mut journal = SendJournal::load(&path)?;
+    let send = journal.begin(changes.notified().iter().map(|item| item_key(item)).collect());
+    journal.save(&path)?;
+
+    let failed = send_changes(config, paths, feed, changes, hot)?;
+    journal.confirm(send);
+    journal.save(&path)?;
+
+    Ok(failed)
+}
+
+// save the snapshot of 'feed'; its journaled sends are done with then.
+fn advance_snapshot(config: &Config, paths: &StatePaths, feed: &FeedConfig, new_ctx: &RssContext) -> Result<()> {
+    save_snapshot(config, new_ctx, &paths.snapshot(feed))?;
+    SendJournal::clear(&paths.journal(feed))
+}
+
+// 'hot' threads are told about along with the changes.
+fn send_changes(config: &Config, paths: &StatePaths, feed: &FeedConfig, changes: &Changes,
+                hot: &[HotThread]) -> Result<Option<Error>> {
+    let fields = &config.include_fields;
+    let new_items = threaded(feed, convert_to_ser_items(&changes.new_items, fields, &config.missing_link));
+    let updated_items = threaded(feed, convert_to_ser_items(&changes.updated_items, fields, &config.missing_link));
+    let removed_items = convert_to_ser_items(&changes.removed_items, fields, &config.missing_link);
+
+    let notified = new_items.iter().chain(updated_items.iter()).chain(hot.iter().map(|h| &h.item)).collect();
+    deliver_routed(config, paths, &config.subject, notified, |keep| {
+        let hot: Vec<HotThread> = hot.iter().filter(|h| keep(&h.item)).cloned().collect();
+        render_hot_changes(config, &config.templates(), "mail.html", &kept(&new_items, keep),
+                           &kept(&updated_items, keep), &kept(&removed_items, keep), &hot)
+    })
+}
+
+// 'items' of 'feed' grouped by thread, when it has a 'thread_key'.
+fn threaded<'a>(feed: &FeedConfig, items: Vec<SerItem<'a>>) -> Vec<SerItem<'a>> {
+    match feed.thread_key {
+        Some(ref key) => group_threads(&items, key),
+        None => items,
+    }
+}
+
+// whether 'item' goes to the recipient of 'subscription'.
+fn subscribed(subscription: &Subscription, item: &SerItem) -> bool {
+    subscription.matches(item.title.as_deref().unwrap_or(""))
+}
+
+// the 'items' that 'keep' lets through, borrowed.
+fn kept<'a>(items: &'a [SerItem], keep: &dyn Fn(&SerItem) -> bool) -> Vec<SerItem<'a>> {
+    items.iter().filter(|item| keep(item)).map(SerItem::borrowed).collect()
+}
+
+// mail what 'render' makes of the 'items' a recipient gets: all of them to
+// 'to' without 'subscriptions', else to every address of a subscription its
+// matches over one SMTP session. a subscription matching nothing gets no
+// mail; items matching nobody are handled all the same, they just go into
+// no mail.
+fn deliver_routed<F>(config: &Config, paths: &StatePaths, subject: &str, items: Vec<&SerItem>, render: F)
+                     -> Result<Option<Error>>
+    where F: Fn(&dyn Fn(&SerItem) -> bool) -> Result<String>
+{
+    if config.subscriptions.is_empty() {
+        let content = timed("render", || render(&|_| true))?;
+        return timed("send", || deliver(config, paths, subject, content));
+    }
+
+    let mut mails = Vec::new();
+    for subscription in &config.subscriptions {
+        if items.iter().any(|item| subscribed(subscription, item)) {
+            let content = timed("render", || render(&|item| subscribed(subscription, item)))?;
+            for to in subscription.recipients() {
+                mails.push((to.to_string(), content.clone()));
+            }
+        } else if subscription.group.is_some() {
+            Record::info("nothing for the group").field("group", subscription.label())
+                .log(format!("no item for the group {}, not mailed.", subscription.label()));
+        }
+    }
+
+    let unrouted = items.iter().filter(|item| !config.subscriptions.iter().any(|s| subscribed(s, item))).count();
+    if unrouted > 0 {
+        Record::info("items matching no subscription").field("items", unrouted)
+            .log(format!("{} items match no subscription, not mailed.", unrouted));
+    }
+
+    if mails.is_empty() {
+        return Ok(None);
+    }
+    timed("send", || deliver_all(config, paths, subject, mails))
+}
+
+// a dedicated mail telling what changed about the channel of 'feed'.
+fn send_channel_meta(config: &Config, paths: &StatePaths, feed: &FeedConfig, meta: &Vec<MetaChange>)
+                     -> Result<Option<Error>> {
+    let mut tctx = tera::Context::new();
+    tctx.insert("feed", &feed.name);
+    tctx.insert("changes", meta);
+    let content = render_context(&config.templates(), "channel.html", tctx)?;
+
+    let fields: Vec<&str> = meta.iter().map(|m| m.field.as_str()).collect();
+    let subject = fill(&config.locale, "meta_changed", &[("subject", &config.subject), ("feed", &feed.name),
+                                                           ("fields", &fields.join(", "))]);
+
+    Record::info("channel changed").field("feed", &feed.name).field("fields", &fields)
+        .log(format!("{}: channel {} changed.", feed.name, fields.join(", ")));
+    deliver(config, paths, &subject, content)
+}
+
+fn is_batched(config: &Config, feed: &FeedConfig) -> bool {
+    feed.batch_threshold.unwrap_or(config.batch_threshold) > 1
+        || feed.max_hold_secs.or(config.max_hold_secs).is_some()
+}
+
+// a title containing one of 'priority_keywords', ignoring case.
+fn is_priority(config: &Config, item: &Item) -> bool {
+    let title = item.title.as_ref().map_or(String::new(), |t| t.to_lowercase());
+    config.priority_keywords.iter().any(|k| title.contains(&k.to_lowercase()))
+}
+
+// whether one of 'self_authors' wrote 'item'.
+fn is_self_authored(config: &Config, item: &Item) -> bool {
+    let mut authors: Vec<&String> = item.author.iter().collect();
+    if let Some(ref dc) = item.dublin_core_ext {
+        authors.extend(dc.creators.iter());
+    }
+
+    authors.iter().any(|author| {
+        config.self_authors.iter().any(|me| author.trim().to_lowercase() == me.trim().to_lowercase())
+    })
+}
+
+// 'changes' of 'feed' without what the user wrote; the snapshot keeps
+// those items, so they don't come up later either.
+fn drop_self_authored(config: &Config, feed: &FeedConfig, changes: Changes) -> Changes {
+    if config.self_authors.is_empty() {
+        return changes;
+    }
+
+    let (own, rest) = changes.partition(|item| is_self_authored(config, item));
+    if !own.notified().is_empty() {
+        Record::info("skipping own items").field("feed", &feed.name).field("items", own.notified().len())
+            .log(format!("{}: skipping {} items by self_authors.", feed.name, own.notified().len()));
+    }
+    rest
+}
+
+// how many of 'changes' never make it into a notification: the items of
+// 'self_authors' and, with missing_link "drop", those without a link.
+fn filtered_count(config: &Config, changes: &Changes) -> usize {
+    changes.notified().into_iter()
+        .filter(|item| is_self_authored(config, item) || item_link(item, &config.missing_link).is_none())
+        .count()
+}
+
+// outside the quiet hours, send in one mail what they, the throttle and
+// the cooldown held back, for every feed whose throttle and cooldown allow
+// it. batched, digest and drip feeds keep following their own rules. the
+// feeds come in 'digest_order', and once feeds have a 'site' the mail is
+// sectioned by site and board.
+fn send_held(config: &Config, paths: &StatePaths) -> Result<Vec<HistoryEntry>> {
+    let mut sections = Vec::new();
+    let mut sent = Vec::new();
+    let mut queues = Vec::new();
+    let mut suppressed = 0;
+
+    let now = Local::now();
+    let mut throttle = ThrottleState::load(&paths.throttle)?;
+
+    let fields = &config.include_fields;
+    let restrict = |items: &[PendingItem]| -> Vec<SerItem<'static>> {
+        items.iter().map(|p| restrict_fields(p.item.clone(), fields)).collect()
+    };
+
+    let held_back = |f: &&FeedConfig| !is_batched(config, f) && f.digest.is_none() && f.drip_interval_secs.is_none();
+    for feed in config.feeds.iter().filter(held_back) {
+        let queue_path = paths.pending(feed);
+        let queue = PendingQueue::load(&queue_path)?;
+        if queue.is_empty() || !throttle.allows(&feed.name, config.throttle_limit(feed), now)
+            || !throttle.cooled_down(&feed.name, feed.send_cooldown_secs, now) {
+            continue;
+        }
+
+        sections.push(Section::new(feed, restrict(&queue.new_items), restrict(&queue.updated_items)));
+        sent.extend(HistoryEntry::from_pending(&feed.name, &queue.new_items));
+        sent.extend(HistoryEntry::from_pending(&feed.name, &queue.updated_items));
+        suppressed += throttle.held(&feed.name);
+        queues.push((feed, queue_path));
+    }
+
+    if queues.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let sectioned = config.feeds.iter().any(|f| f.site.is_some());
+    order_sections(config, &mut sections);
+    let counts: Vec<(String, usize)> = sections.iter().map(|s| (s.board.clone(), count_items(&s.new_items))).collect();
+
+    let subject = match (config.subject_counts, sectioned) {
+        (true, true) => sections_subject(&config.subject, &sections, &config.locale),
+        (true, false) => counts_subject(&config.subject, &counts, &config.locale),
+        (false, _) => config.subject.clone(),
+    };
+    let subject = if suppressed > 0 {
+        fill(&config.locale, "held_back", &[("subject", &subject), ("count", &suppressed.to_string())])
+    } else {
+        subject
+    };
+
+    let notified = sections.iter().flat_map(|s| s.new_items.iter().chain(s.updated_items.iter())).collect();
+    let failed = deliver_routed(config, paths, &subject, notified, |keep| {
+        let mut routed = Vec::new();
+        for section in &sections {
+            let (new_items, updated_items) = (kept(&section.new_items, keep), kept(&section.updated_items, keep));
+            let count = count_items(&new_items) + count_items(&updated_items);
+            if count > 0 {
+                routed.push(Section {
+                    site: section.site.clone(),
+                    site_url: section.site_url.clone(),
+                    board: section.board.clone(),
+                    count,
+                    new_items,
+                    updated_items,
+                    separator: section.separator.clone(),
+                });
+            }
+        }
+        order_sections(config, &mut routed);
+        render_sections(config, &config.templates(), "mail.html", &routed, sectioned)
+    })?;
+
+    for (feed, queue_path) in queues {
+        PendingQueue::default().save(&queue_path)?;
+        throttle.record(&feed.name, now);
+    }
+    throttle.save(&paths.throttle)?;
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Record::info("sent held back items").field("items", sent.len())
+        .log(format!("sent {} held back items.", sent.len()));
+    Ok(sent)
+}
+
+// seed the snapshot and, with 'notify_first_run', tell that monitoring started.
+// the items already in the feed are listed but not recorded as notified.
+fn notify_first_run(config: &Config, paths: &StatePaths, feed: &FeedConfig, new_ctx: RssContext)
+                    -> Result<Vec<HistoryEntry>> {
+    if !config.notify_first_run {
+        advance_snapshot(config, paths, feed, &new_ctx)?;
+        return Ok(Vec::new());
+    }
+
+    let items = threaded(feed, convert_to_ser_items(&new_ctx.channel.items, &config.include_fields,
+                                                    &config.missing_link));
+    let failed = deliver_routed(config, paths, &config.subject, items.iter().collect(), |keep| {
+        render_changes(config, &config.templates(), "mail.html",
+                       &kept(&items, keep), &Vec::new(), &Vec::new(), true)
+    })?;
+    advance_snapshot(config, paths, feed, &new_ctx)?;
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Ok(Vec::new())
+}
+
+// collect changes in the feed's pending queue and only send once it holds
+// 'batch_threshold' items or its oldest item waited 'max_hold_secs', and not
+// during the quiet hours.
+fn notify_batched(config: &Config, paths: &StatePaths, feed: &FeedConfig, changes: Changes,
+                  new_ctx: RssContext, quiet: bool) -> Result<Vec<HistoryEntry>> {
+    let threshold = feed.batch_threshold.unwrap_or(config.batch_threshold);
+    let max_hold = feed.max_hold_secs.or(config.max_hold_secs);
+    let queue_path = paths.pending(feed);
+    let mut queue = PendingQueue::load(&queue_path)?;
+
+    queue.add(pending_items(&changes.new_items, &config.missing_link),
+              pending_items(&changes.updated_items, &config.missing_link));
+
+    // the items are safe in the queue before the snapshot forgets them.
+    queue.save(&queue_path)?;
+    advance_snapshot(config, paths, feed, &new_ctx)?;
+
+    if queue.is_empty() {
+        Record::info("no changes").field("feed", &feed.name)
+            .log(format!("{}: new and old rss are same.", feed.name));
+        return Ok(Vec::new());
+    }
+
+    let held_for = queue.held_for(Local::now());
+    let due = queue.len() >= threshold || max_hold.is_some_and(|max| held_for >= max);
+    if quiet {
+        Record::info("holding items for the quiet hours").field("feed", &feed.name).field("items", queue.len())
+            .log(format!("{}: holding {} items for the quiet hours.", feed.name, queue.len()));
+        return Ok(Vec::new());
+    }
+    if !due {
+        Record::info("holding items for the batch").field("feed", &feed.name).field("items", queue.len())
+            .field("threshold", threshold).field("held_for_secs", held_for)
+            .log(format!("{}: holding {} items, waiting for {} or {}s.",
+                         feed.name, queue.len(), threshold,
+                         max_hold.map_or("-".to_string(), |max| (max - held_for).to_string())));
+        return Ok(Vec::new());
+    }
+
+    let fields = &config.include_fields;
+    let restrict = |items: &[PendingItem]| -> Vec<SerItem<'static>> {
+        items.iter().map(|p| restrict_fields(p.item.clone(), fields)).collect()
+    };
+    let (new_items, updated_items) = (threaded(feed, restrict(&queue.new_items)),
+                                      threaded(feed, restrict(&queue.updated_items)));
+    let notified = new_items.iter().chain(updated_items.iter()).collect();
+    let failed = deliver_routed(config, paths, &config.subject, notified, |keep| {
+        render_changes(config, &config.templates(), "mail.html",
+                       &kept(&new_items, keep), &kept(&updated_items, keep), &Vec::new(), false)
+    })?;
+
+    let mut sent = queue.new_items.clone();
+    sent.extend(queue.updated_items.iter().cloned());
+    PendingQueue::default().save(&queue_path)?;
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Ok(HistoryEntry::from_pending(&feed.name, &sent))
+}
+
+// digest and drip feeds only collect their changes, 'send_digest' and
+// 'send_drip' send them.
+fn queue_for_digest(config: &Config, paths: &StatePaths, feed: &FeedConfig, changes: Changes,
+                    new_ctx: RssContext) -> Result<Vec<HistoryEntry>> {
+    let queue_path = paths.pending(feed);
+    let mut queue = PendingQueue::load(&queue_path)?;
+
+    queue.add(pending_items(&changes.new_items, &config.missing_link),
+              pending_items(&changes.updated_items, &config.missing_link));
+    queue.save(&queue_path)?;
+    advance_snapshot(config, paths, feed, &new_ctx)?;
+
+    if !queue.is_empty() {
+        let kind = if feed.digest.is_some() { "digest" } else { "drip" };
+        Record::info("holding items").field("feed", &feed.name).field("items", queue.len()).field("for", kind)
+            .log(format!("{}: holding {} items for the {}.", feed.name, queue.len(), kind));
+    }
+
+    Ok(Vec::new())
+}
+
+// send the feed's digest once its scheduled time passed since the last
+// one. a digest missed while bbsmon was not running goes out on the next run.
+fn send_digest(config: &Config, paths: &StatePaths, feed: &FeedConfig, schedule: &DigestSchedule,
+               now: DateTime<Local>) -> Result<Vec<HistoryEntry>> {
+    let queue_path = paths.pending(feed);
+    let queue = PendingQueue::load(&queue_path)?;
+    if queue.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let reference = match queue.last_sent.as_ref().or(queue.since.as_ref()) {
+        Some(reference) => DateTime::parse_from_rfc3339(reference)?.timestamp(),
+        None => 0,
+    };
+    if schedule.last_due(now).timestamp() <= reference {
+        return Ok(Vec::new());
+    }
+
+    let mut sent = queue.new_items.clone();
+    sent.extend(queue.updated_items.iter().cloned());
+
+    let items: Vec<(String, SerItem)> = sent.iter().map(|pending| {
+        let day = pending.item.pub_date.as_ref().map_or("-".to_string(), |d| d.chars().take(10).collect());
+        (day, restrict_fields(pending.item.borrowed(), &config.include_fields))
+    }).collect();
+
+    let subject = fill(&config.locale, "digest", &[("subject", &config.subject), ("feed", &feed.name)]);
+    let failed = deliver_routed(config, paths, &subject, items.iter().map(|i| &i.1).collect(), |keep| {
+        // grouped by the day an item was posted on, oldest day first.
+        let mut days: BTreeMap<String, Vec<SerItem>> = BTreeMap::new();
+        for (day, item) in items.iter().filter(|i| keep(&i.1)) {
+            days.entry(day.clone()).or_default().push(item.borrowed());
+        }
+        let groups: Vec<DigestGroup> = days.into_iter().map(|(day, items)| DigestGroup {
+            day,
+            items: threaded(feed, items),
+        }).collect();
+
+        render_digest(config, &config.templates(), "digest.html", &feed.name, schedule.period == "weekly", &groups)
+    })?;
+
+    let emptied = PendingQueue { last_sent: Some(now.to_rfc3339()), ..Default::default() };
+    emptied.save(&queue_path)?;
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Record::info("sent digest").field("feed", &feed.name).field("items", sent.len())
+        .log(format!("{}: sent digest with {} items.", feed.name, sent.len()));
+    Ok(HistoryEntry::from_pending(&feed.name, &sent))
+}
+
+// release the oldest held item of a drip feed, once 'interval' seconds
+// passed since the last one went out.
+fn send_drip(config: &Config, paths: &StatePaths, feed: &FeedConfig, interval: i64,
+             now: DateTime<Local>) -> Result<Vec<HistoryEntry>> {
+    let queue_path = paths.pending(feed);
+    let mut queue = PendingQueue::load(&queue_path)?;
+
+    if let Some(ref last) = queue.last_sent {
+        if now.timestamp() - DateTime::parse_from_rfc3339(last)?.timestamp() < interval {
+            return Ok(Vec::new());
+        }
+    }
+
+    let (pending, updated) = match queue.take_oldest() {
+        Some(oldest) => oldest,
+        None => return Ok(Vec::new()),
+    };
+
+    let item = vec![restrict_fields(pending.item.borrowed(), &config.include_fields)];
+    let (new_items, updated_items) = if updated { (Vec::new(), item) } else { (item, Vec::new()) };
+    let notified = new_items.iter().chain(updated_items.iter()).collect();
+    let failed = deliver_routed(config, paths, &config.subject, notified, |keep| {
+        render_changes(config, &config.templates(), "mail.html",
+                       &kept(&new_items, keep), &kept(&updated_items, keep), &Vec::new(), false)
+    })?;
+
+    queue.last_sent = Some(now.to_rfc3339());
+    queue.save(&queue_path)?;
+
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    Record::info("released item").field("feed", &feed.name).field("items", 1).field("held", queue.len())
+        .log(format!("{}: released 1 item, {} still held.", feed.name, queue.len()));
+    Ok(HistoryEntry::from_pending(&feed.name, &[pending]))
+}
+
+// what notifiers are told about a run: the notified items by feed.
+fn notifier_batches(config: &Config, notified: &Vec<HistoryEntry>) -> Vec<(String, Vec<SerItem<'static>>)> {
+    let mut batches: Vec<(String, Vec<SerItem<'static>>)> = Vec::new();
+    for entry in notified {
+        let item = restrict_fields(entry.item.clone(), &config.include_fields);
+        match batches.iter().position(|b| b.0 == entry.feed) {
+            Some(i) => batches[i].1.push(item),
+            None => batches.push((entry.feed.clone(), vec![item])),
+        }
+    }
+
+    batches
+}
+
+// tell 'notifier' about each batch of 'notifier_batches', one after another.
+async fn tell(notifier: &dyn Notifier, batches: &[(String, Vec<SerItem<'static>>)]) -> Result<()> {
+    for (feed, items) in batches {
+        notifier.notify(feed, items).await?;
+    }
+    Ok(())
+}
+
+// tell every notifier about 'notified' on 'runtime', at most
+// 'max_concurrent_deliveries' at a time. results come back in the order of
+// 'notifiers'.
+fn post_to_notifiers(runtime: &Runtime, config: &Config, notifiers: &Arc<Vec<Box<dyn Notifier>>>,
+                     notified: &Vec<HistoryEntry>) -> Vec<(String, Result<()>)> {
+    let batches = Arc::new(notifier_batches(config, notified));
+    let sem = Arc::new(Semaphore::new(config.max_concurrent_deliveries));
+
+    let mut workers = Vec::new();
+    for i in 0..notifiers.len() {
+        let notifiers = notifiers.clone();
+        let batches = batches.clone();
+        let sem = sem.clone();
+
+        workers.push(runtime.spawn(async move {
+            let _permit = sem.acquire().await.expect("the delivery semaphore stays open");
+            tell(notifiers[i].as_ref(), &batches).await
+        }));
+    }
+
+    notifiers.iter().zip(workers).map(|(notifier, worker)| {
+        let result = match runtime.block_on(worker) {
+            Ok(result) => result,
+            Err(_) => Err(format!("delivery task for {} panicked", notifier.name()).into()),
+        };
+        (notifier.name().to_string(), result)
+    }).collect()
+}
+
+// fetch and diff every feed like a run, then print what each enabled backend
+// would be sent. nothing is sent and nothing is written to the state.
+fn dry_run(config: &Config, paths: &StatePaths) -> Result<()> {
+    let sem = Semaphore::new(config.max_concurrent_fetches);
+    let client = config.timeouts().client()?;
+
+    for feed in &config.feeds {
+        let source = feed_source(feed, &config.retry, config.max_feed_bytes, config.timeouts(), None);
+        let fetched = source.and_then(|source| {
+            block_on(fetch_diff_items(&client, &paths.snapshot(feed), &paths.validators(feed), source.as_ref(),
+                                      &DiffOptions::of(config, feed), &sem))
+        });
+        let (changes, new_ctx, _) = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                println!("{}: {}", feed.name, error_chain_string(&e));
+                continue;
+            }
+        };
+
+        let (changes, is_first_run) = match changes {
+            Some(changes) => (drop_self_authored(config, feed, changes), false),
+            None if config.notify_first_run => {
+                (Changes { new_items: new_ctx.channel.items.clone(), ..Changes::default() }, true)
+            }
+            None => {
+                println!("{}: no usable snapshot, would only save the current rss as baseline.", feed.name);
+                continue;
+            }
+        };
+
+        let mut changes = changes;
+        if let Some(ref command) = feed.transform_command {
+            let before = changes.notified().into_iter().cloned().collect::<Vec<Item>>();
+            transform_changes(config, feed, command, &mut changes);
+            print_transforms(feed, &before, changes.notified());
+        }
+
+        print_preview(config, feed, &changes, is_first_run)?;
+    }
+
+    Ok(())
+}
+
+// the new and updated items of 'changes' piped through 'command' of 'feed'.
+fn transform_changes(config: &Config, feed: &FeedConfig, command: &str, changes: &mut Changes) {
+    let timeout = Duration::from_secs(config.transform_timeout_secs);
+    let max = config.max_concurrent_transforms;
+    transform_items(&feed.name, command, &mut changes.new_items, max, timeout);
+    transform_items(&feed.name, command, &mut changes.updated_items, max, timeout);
+}
+
+// what the transform of 'feed' changed, field by field.
+fn print_transforms(feed: &FeedConfig, before: &[Item], after: Vec<&Item>) {
+    for (old, new) in before.iter().zip(after) {
+        let fields = [("title", &old.title, &new.title), ("link", &old.link, &new.link),
+                      ("description", &old.description, &new.description), ("author", &old.author, &new.author)];
+        for &(field, old_value, new_value) in &fields {
+            if old_value != new_value {
+                println!("{}: note: transform changed the {} from '{}' to '{}'.", feed.name, field,
+                         old_value.as_ref().map_or("", |v| v.as_str()), new_value.as_ref().map_or("", |v| v.as_str()));
+            }
+        }
+    }
+}
+
+// what the dry run shows for 'changes' of 'feed': notes on how they would
+// go out, then every mail and webhook message, rendered.
+fn print_preview(config: &Config, feed: &FeedConfig, changes: &Changes, is_first_run: bool) -> Result<()> {
+    let fields = &config.include_fields;
+    let missing_link = &config.missing_link;
+
+    if config.watch_channel_meta {
+        for meta in &changes.channel_meta {
+            println!("{}: note: channel {} changed from '{}' to '{}', would be mailed.",
+                     feed.name, meta.field, meta.old, meta.new);
+        }
+    }
+
+    let new_items = threaded(feed, convert_to_ser_items(&changes.new_items, fields, missing_link));
+    let updated_items = threaded(feed, convert_to_ser_items(&changes.updated_items, fields, missing_link));
+    let removed_items = convert_to_ser_items(&changes.removed_items, fields, missing_link);
+    if new_items.is_empty() && updated_items.is_empty() {
+        println!("{}: new and old rss are same.", feed.name);
+        return Ok(());
+    }
+
+    if feed.digest.is_some() {
+        println!("{}: note: would be held for the digest, shown as if sent now.", feed.name);
+    } else if feed.drip_interval_secs.is_some() {
+        println!("{}: note: would be released one item at a time, shown as if sent now.", feed.name);
+    } else if is_batched(config, feed) {
+        println!("{}: note: would be queued for batching, shown as if sent now.", feed.name);
+    } else if in_quiet_hours(&config.quiet_hours, Local::now()) {
+        println!("{}: note: would be held during the quiet hours, shown as if sent now.", feed.name);
+    } else if !on_active_day(&config.active_days, Local::now()) {
+        println!("{}: note: would be held until the next active day, shown as if sent now.", feed.name);
+    }
+
+    let recipients: Vec<(&str, Option<&Subscription>)> = if config.subscriptions.is_empty() {
+        vec![(config.to.as_str(), None)]
+    } else {
+        print_routing(config, &feed.name, new_items.iter().chain(updated_items.iter()).collect());
+        config.subscriptions.iter().flat_map(|s| s.recipients().into_iter().map(move |to| (to, Some(s)))).collect()
+    };
+
+    for (to, subscription) in recipients {
+        let keep = |item: &SerItem| subscription.is_none_or(|s| subscribed(s, item));
+        let (new_kept, updated_kept) = (kept(&new_items, &keep), kept(&updated_items, &keep));
+        if new_kept.is_empty() && updated_kept.is_empty() {
+            continue;
+        }
+
+        let content = render_changes(config, &config.templates(), "mail.html",
+                                     &new_kept, &updated_kept, &kept(&removed_items, &keep), is_first_run)?;
+        println!("===== {}: email =====", feed.name);
+        println!("From: {}", config.from);
+        println!("To: {}", to);
+        println!("Subject: {}", config.subject);
+        println!();
+        println!("{}", content);
+    }
+
+    let mut items = new_items.clone();
+    items.extend(updated_items.iter().cloned());
+    print_webhook_messages(config, &feed.name, &items)?;
+
+    Ok(())
+}
+
+/// Show what the items of the feed file 'fixture' would be mailed as if
+/// they were all new to 'feed', the first configured one when absent,
+/// like the dry run does. Nothing is fetched, sent or written.
+pub fn preview_fixture(config: &Config, feed: Option<&str>, fixture: &str) -> Result<()> {
+    let feed = match feed {
+        Some(name) => config.feeds.iter().find(|f| f.name == name),
+        None => config.feeds.first(),
+    };
+    let feed = match feed {
+        Some(feed) => feed,
+        None => bail!("no such feed configured"),
+    };
+
+    let ctx = RssContext::from_file(fixture).chain_err(|| format!("can't read {}", fixture))?;
+    let changes = drop_self_authored(config, feed, Changes { new_items: ctx.channel.items, ..Changes::default() });
+    print_preview(config, feed, &changes, false)
+}
+
+// which subscriber gets which of 'items' of 'feed', as a table.
+fn print_routing(config: &Config, feed: &str, items: Vec<&SerItem>) {
+    let width = config.subscriptions.iter().map(|s| s.label().chars().count()).max().unwrap_or(0).max("(nobody)".len());
+
+    println!("===== {}: routing =====", feed);
+    for item in items {
+        let title = item.title.as_deref().unwrap_or("(no title)");
+        let to: Vec<&str> = config.subscriptions.iter()
+            .filter(|s| subscribed(s, item))
+            .map(|s| s.label())
+            .collect();
+        let to = if to.is_empty() { "(nobody)".to_string() } else { to.join(", ") };
+        println!("{:width$}  {}", to, title, width = width);
+    }
+}
+
+// what the webhooks would be posted for 'items' of 'feed'.
+#[cfg(feature = "sink-webhook")]
+fn print_webhook_messages(config: &Config, feed: &str, items: &[SerItem]) -> Result<()> {
+    if let Some(ref webhook) = config.rocketchat_webhook {
+        for message in rocketchat_messages(feed, items, config.webhook_summary_only, config.push_description_chars) {
+            println!("===== {}: rocketchat, POST {} =====", feed, webhook);
+            println!("{}", serde_json::to_string_pretty(&message)?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sink-webhook"))]
+fn print_webhook_messages(_config: &Config, _feed: &str, _items: &[SerItem]) -> Result<()> {
+    Ok(())
+}
+
+// note the items of each fetched '(feed name, items)' in the seen store,
+// then drop what is past the retention of its feed.
+fn remember_seen(config: &Config, paths: &StatePaths, fetched: &Vec<(String, Vec<Item>)>) -> Result<()> {
+    let mut store = SeenStore::load(&paths.seen_db)?;
+    let now = Local::now();
+
+    for (feed, items) in fetched {
+        store.observe(feed, items, &now.to_rfc3339());
+    }
+
+    let present: Vec<(String, Vec<String>)> = fetched.iter()
+        .map(|(feed, items)| (feed.clone(), items.iter().map(item_key).collect()))
+        .collect();
+    for (feed, dropped) in store.prune(config, &present, now) {
+        if dropped > 0 {
+            Record::info("forgot expired items").field("feed", &feed).field("items", dropped)
+                .log(format!("{}: forgot {} items past seen_retention_days.", feed, dropped));
+        }
+    }
+
+    store.save(&paths.seen_db)
+}
+
+// seed of the 'jitter' of this process, 0 until it is rolled.
+static JITTER_SEED: AtomicUsize = AtomicUsize::new(0);
+
+// set once 'stop_daemons' was called.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+// how often a daemon waiting for its next pass checks for 'stop_daemons'.
+const STOP_POLL_MILLIS: u64 = 500;
+
+/// Have every 'Pipeline::daemon' of the process return, giving back the
+/// lock of its state. The pass it is in aborts the fetches still in
+/// flight, closing their connections and writing nothing for them, like
+/// those still running at 'max_run_seconds'; notifications already being
+/// sent are finished. 'bbsmon daemon' calls it on Ctrl-C, or the console
+/// event of it on windows, and exits once the daemons returned.
+pub fn stop_daemons() {
+    STOPPING.store(true, Ordering::SeqCst);
+}
+
+fn stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+// what 'worker' returns, waiting until 'deadline' at most. once
+// 'stop_daemons' was called it is only taken if it is done already. a
+// worker given up on is aborted, dropping its requests, and None.
+async fn receive<T>(worker: &mut JoinHandle<T>, deadline: Option<Instant>)
+                    -> Option<::std::result::Result<T, JoinError>> {
+    loop {
+        let now = Instant::now();
+        if stopping() || deadline.is_some_and(|deadline| deadline <= now) {
+            if worker.is_finished() {
+                return Some(worker.await);
+            }
+            worker.abort();
+            return None;
+        }
+
+        let poll = now + Duration::from_millis(STOP_POLL_MILLIS);
+        let until = deadline.map_or(poll, |deadline| deadline.min(poll));
+        if let Ok(joined) = tokio::time::timeout(until - now, &mut *worker).await {
+            return Some(joined);
+        }
+    }
+}
+
+// how long after the start of a run 'feed' is fetched, below
+// 'jitter_seconds'. the same for the life of the process, and different
+// after a restart.
+fn jitter(config: &Config, feed: &str) -> Duration {
+    if config.jitter_seconds == 0 {
+        return Duration::from_secs(0);
+    }
+
+    if JITTER_SEED.load(Ordering::SeqCst) == 0 {
+        let _ = JITTER_SEED.compare_exchange(0, (random_fraction() * 1e9) as usize + 1, Ordering::SeqCst,
+                                             Ordering::SeqCst);
+    }
+    let mut hash = JITTER_SEED.load(Ordering::SeqCst) as u64 ^ 0xcbf29ce484222325;
+    for byte in feed.bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+
+    Duration::from_millis(hash % (config.jitter_seconds * 1000))
+}
+
+// a pass over the feeds of 'config' on a runtime of its own. blocking work
+// still running at its end, a command past its timeout, is left behind.
+fn run_once(config: &Config, paths: &StatePaths, notifiers: &Arc<Vec<Box<dyn Notifier>>>) -> Result<()> {
+    let runtime = runtime()?;
+    let result = run_on(&runtime, config, paths, notifiers);
+    runtime.shutdown_background();
+    result
+}
+
+// fetches run side by side on 'runtime'; what they fetched is handled here,
+// a feed at a time and in their order.
+fn run_on(runtime: &Runtime, config: &Config, paths: &StatePaths, notifiers: &Arc<Vec<Box<dyn Notifier>>>)
+          -> Result<()> {
+    let started = Instant::now();
+    let deadline = config.max_run_seconds.map(|secs| Instant::now() + Duration::from_secs(secs));
+    let sem = Arc::new(Semaphore::new(config.max_concurrent_fetches));
+    let client = config.timeouts().client()?;
+
+    let mut workers = Vec::new();
+    for feed in &config.feeds {
+        let sem = sem.clone();
+        let client = client.clone();
+        let local = paths.snapshot(feed);
+        let validators = paths.validators(feed);
+        let source = feed_source(feed, &config.retry, config.max_feed_bytes, config.timeouts(),
+                                 Some(paths.session(feed)));
+        let options = DiffOptions::of(config, feed);
+        let delay = jitter(config, &feed.name);
+
+        // a fetch still running at the deadline is aborted; it writes nothing.
+        workers.push(runtime.spawn(async move {
+            tokio::time::sleep(delay).await;
+            let fetching = Instant::now();
+            let (fetched, phases) = timings::scope(async {
+                let source = source?;
+                fetch_diff_items(&client, &local, &validators, source.as_ref(), &options, &sem).await
+            }).await;
+            (fetched, fetching.elapsed(), phases)
+        }));
+    }
+
+    let mut skipped = Vec::new();
+    let mut stopped = Vec::new();
+    let mut failures = Vec::new();
+    let mut checked = Vec::new();
+    let mut notified = Vec::new();
+    let mut fetched = Vec::new();
+    let mut timed_feeds: Vec<(String, Phases)> = Vec::new();
+    let mut run_status = RunStatus::load(&paths.status)?.unwrap_or_default();
+    let now = Local::now().to_rfc3339();
+
+    for (feed, mut worker) in config.feeds.iter().zip(workers) {
+        let (result, fetch_time, mut phases) = match runtime.block_on(receive(&mut worker, deadline)) {
+            Some(Ok(received)) => received,
+            None if stopping() => {
+                Record::warn("skipped, stopping").field("feed", &feed.name)
+                    .log(format!("{}: skipped, stopping.", feed.name));
+                let feed_status = run_status.feeds.entry(feed.name.clone()).or_insert(FeedStatus::default());
+                feed_status.last_result = Some("skipped: stopping".to_string());
+                stopped.push(feed.name.clone());
+                continue;
+            }
+            None => {
+                Record::warn("skipped, max_run_seconds passed").field("feed", &feed.name)
+                    .log(format!("{}: skipped, max_run_seconds passed.", feed.name));
+                let feed_status = run_status.feeds.entry(feed.name.clone()).or_insert(FeedStatus::default());
+                feed_status.last_result = Some("skipped: max_run_seconds passed".to_string());
+                skipped.push(feed.name.clone());
+                continue;
+            }
+            Some(Err(_)) => {
+                (Err(format!("fetch task for {} panicked", feed.remote_rss).into()), Duration::from_secs(0),
+                 Phases::new())
+            }
+        };
+        let result = result.chain_err(|| "nothing fetched, snapshot kept");
+
+        // validators are only saved once the changes they stand for are handled.
+        let result = result.and_then(|(items, new_ctx, validators)| {
+            let fetched_items = new_ctx.channel.items.clone();
+            let filtered = items.as_ref().map_or(0, |changes| filtered_count(config, changes));
+            let entries = notify_feed(config, paths, feed, items, new_ctx)?;
+            if let Some(validators) = validators {
+                validators.save(&paths.validators(feed))?;
+            }
+            Ok((entries, fetched_items, filtered))
+        });
+        timings::merge(&mut phases, timings::take());
+        timed_feeds.push((feed.name.clone(), phases));
+        checked.push(feed.name.clone());
+
+        let feed_status = run_status.feeds.entry(feed.name.clone()).or_insert(FeedStatus::default());
+        feed_status.last_check = Some(now.clone());
+
+        match result {
+            Ok((entries, fetched_items, filtered)) => {
+                Record::info("feed checked").field("feed", &feed.name).field("url", &feed.remote_rss)
+                    .field("items", entries.len()).field("duration_ms", millis(fetch_time))
+                    .log_json();
+                feed_status.last_result = Some("ok".to_string());
+                feed_status.last_ok = Some(now.clone());
+                if !entries.is_empty() {
+                    feed_status.last_new = Some(now.clone());
+                    feed_status.items_seen += entries.len() as u64;
+                }
+                notified.extend(entries);
+                fetched.push((feed.name.clone(), fetched_items));
+                run_status.add_filtered(Local::now().date_naive(), filtered);
+            }
+            Err(e) => {
+                feed_status.last_result = Some(format!("error: {}", error_chain_string(&e)));
+                Record::error("feed failed").field("feed", &feed.name).field("url", &feed.remote_rss)
+                    .field("error", error_chain_string(&e)).field("duration_ms", millis(fetch_time))
+                    .log(format!("{}: {}", feed.name, error_chain_string(&e)));
+                failures.push(Failure {
+                    feed: feed.name.clone(),
+                    error: error_chain_string(&e),
+                    time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                });
+            }
+        }
+    }
+
+    if !skipped.is_empty() {
+        Record::warn("not sending held back items and digests, max_run_seconds passed")
+            .log("not sending held back items and digests, max_run_seconds passed.".to_string());
+    } else if !stopped.is_empty() {
+        Record::warn("not sending held back items and digests, stopping")
+            .log("not sending held back items and digests, stopping.".to_string());
+    } else if !in_quiet_hours(&config.quiet_hours, Local::now()) && on_active_day(&config.active_days, Local::now()) {
+        let mut digests = vec![("held back items".to_string(), send_held(config, paths))];
+        for feed in &config.feeds {
+            if let Some(ref schedule) = feed.digest {
+                digests.push((feed.name.clone(), send_digest(config, paths, feed, schedule, Local::now())));
+            }
+            if let Some(interval) = feed.drip_interval_secs {
+                digests.push((feed.name.clone(), send_drip(config, paths, feed, interval, Local::now())));
+            }
+        }
+
+        if let Some(ref schedule) = config.report_schedule {
+            let sent = send_report(config, paths, schedule, &mut run_status, Local::now());
+            digests.push(("activity report".to_string(), sent.map(|_| Vec::new())));
+        }
+        if let Some(ref schedule) = config.heartbeat {
+            let sent = send_heartbeat(config, paths, schedule, &mut run_status, Local::now());
+            digests.push(("heartbeat".to_string(), sent.map(|_| Vec::new())));
+        }
+
+        for (name, result) in digests {
+            checked.push(name.clone());
+            match result {
+                Ok(entries) => notified.extend(entries),
+                Err(e) => {
+                    Record::error("digest failed").field("feed", &name).field("error", error_chain_string(&e))
+                        .log(format!("{}: {}", name, error_chain_string(&e)));
+                    failures.push(Failure {
+                        feed: name,
+                        error: error_chain_string(&e),
+                        time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    // what the held items and digests took, whichever feed they are of.
+    let phases = timings::take();
+    if !phases.is_empty() {
+        timed_feeds.push(("(held, digests)".to_string(), phases));
+    }
+
+    for (name, result) in post_to_notifiers(runtime, config, notifiers, &notified) {
+        checked.push(name.clone());
+        if let Err(e) = result {
+            Record::error("notifier failed").field("sink", &name).field("error", error_chain_string(&e))
+                .log(format!("{}: {}", name, error_chain_string(&e)));
+            failures.push(Failure {
+                feed: name,
+                error: error_chain_string(&e),
+                time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            });
+        }
+    }
+
+    // failed and skipped feeds keep their items in the merged feed all the same.
+    if let Some(ref merged) = config.merged_feed {
+        checked.push("merged feed".to_string());
+        if let Err(e) = update_merged(merged, &fetched) {
+            Record::error("merged feed not written").field("path", &merged.path).field("error", error_chain_string(&e))
+                .log(format!("{}: {}", merged.path, error_chain_string(&e)));
+            failures.push(Failure {
+                feed: "merged feed".to_string(),
+                error: error_chain_string(&e),
+                time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            });
+        }
+    }
+
+    checked.push("seen store".to_string());
+    if let Err(e) = remember_seen(config, paths, &fetched) {
+        Record::error("seen store not updated").field("error", error_chain_string(&e))
+            .log(format!("{}: {}", paths.seen_db.display(), error_chain_string(&e)));
+        failures.push(Failure {
+            feed: "seen store".to_string(),
+            error: error_chain_string(&e),
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        });
+    }
+
+    let notified_count = notified.len();
+    LoggedItem::append(&paths.notification_log, &notified)?;
+    // kept for 'bbsmon replay --last' until a later run notifies something.
+    if notified_count > 0 {
+        HistoryEntry::save(&paths.last_run, &notified, paths.compress)?;
+    }
+    if let Some(ref history) = config.history {
+        HistoryEntry::append(Path::new(history), notified, config.compress_snapshots)?;
+    }
+
+    run_status.last_run = Some(now);
+    run_status.next_run = None;
+    for feed_status in run_status.feeds.values_mut() {
+        feed_status.next_fetch = None;
+    }
+    run_status.save(&paths.status)?;
+
+    if timings::enabled() {
+        print!("{}", timings::report(&timed_feeds, started.elapsed()));
+    }
+
+    Record::info("run finished").field("feeds", config.feeds.len()).field("items", notified_count)
+        .field("failed", failures.len()).field("skipped", skipped.len() + stopped.len())
+        .field("duration_ms", millis(started.elapsed()))
+        .log(format!("run finished: {} items from {} feeds, {} failed, {} skipped, {} ms.",
+                     notified_count, config.feeds.len(), failures.len(), skipped.len() + stopped.len(),
+                     millis(started.elapsed())));
+
+    if !failures.is_empty() {
+        if let Err(e) = report_failures(config, paths, &failures) {
+            Record::warn("error report not sent").field("error", error_chain_string(&e))
+                .log(format!("error report not sent: {}", error_chain_string(&e)));
+        }
+    }
+
+    if let Err(e) = send_alerts(config, paths, &checked, &failures) {
+        Record::warn("alert not sent").field("error", error_chain_string(&e))
+            .log(format!("alert not sent: {}", error_chain_string(&e)));
+    }
+
+    if !skipped.is_empty() {
+        bail!(ErrorKind::DeadlineExceeded(skipped));
+    }
+
+    if !failures.is_empty() {
+        bail!(ErrorKind::RunIncomplete(failures.iter().map(|f| f.feed.clone()).collect()));
+    }
+
+    Ok(())
+}
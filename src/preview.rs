@@ -0,0 +1,201 @@
+//! 'bbsmon preview': serve the rendered 'mail.html' on localhost while
+//! the templates are worked on. The page reloads itself whenever the
+//! config, the templates or the fixture change.
+
+use std::borrow::Cow;
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::diff::DiffSegment;
+use crate::feed::RssContext;
+use crate::history::HistoryEntry;
+use crate::log::Record;
+use crate::render::SerItem;
+use crate::render::convert_to_ser_items;
+use crate::render::render_changes;
+use crate::server::read_request;
+use crate::server::respond_with;
+use crate::state::StatePaths;
+use crate::watch::POLL_MILLIS;
+use crate::watch::TEMPLATE_DIR;
+use crate::watch::fingerprint;
+
+/// Port 'bbsmon preview' listens on unless told otherwise.
+pub const DEFAULT_PREVIEW_PORT: u16 = 8788;
+
+// polls '/generation' and reloads the page once it changed.
+const RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+  var generation = null;
+  setInterval(function () {
+    var request = new XMLHttpRequest();
+    request.onload = function () {
+      if (generation !== null && request.responseText !== generation) {
+        location.reload();
+      }
+      generation = request.responseText;
+    };
+    request.open("GET", "/generation");
+    request.send();
+  }, 1000);
+})();
+</script>
+"#;
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn sample_item(tid: u32, title: &'static str, author: &'static str, description: &'static str) -> SerItem<'static> {
+    SerItem {
+        title: Some(title.into()),
+        link: Some(format!("http://bbs.example/read.php?tid={}", tid).into()),
+        description: Some(description.into()),
+        author: Some(author.into()),
+        pub_date: Some("2016-11-05 09:00:00".into()),
+        thumbnail: None,
+        categories: Cow::default(),
+        replies: Cow::default(),
+        archive_link: None,
+        diff: Cow::default(),
+    }
+}
+
+/// Made up new and updated items, for a preview without a fixture before
+/// any run notified something. The updated one has a 'diff'.
+pub fn sample_items() -> (Vec<SerItem<'static>>, Vec<SerItem<'static>>) {
+    let new_items = vec![sample_item(1, "周末聚餐", "yami", "周六晚上六点，老地方。"),
+                         sample_item(2, "二手自行车", "bob", "九成新，200 元。")];
+
+    let mut updated = sample_item(3, "出租单间", "carol", "朝南单间，1500 元一个月，已租出。");
+    let segment = |kind: &str, text: &str| DiffSegment { kind: kind.to_string(), text: text.to_string() };
+    updated.diff = Cow::Owned(vec![segment("equal", "朝南单间，"), segment("deleted", "1800"),
+                                   segment("inserted", "1500"), segment("equal", " 元一个月，"),
+                                   segment("inserted", "已租出。")]);
+    (new_items, vec![updated])
+}
+
+/// What the preview shows: every item of 'fixture' as new, else what the
+/// last run notified as new, else 'sample_items'.
+pub fn preview_items(config: &Config, fixture: Option<&str>)
+                     -> Result<(Vec<SerItem<'static>>, Vec<SerItem<'static>>)> {
+    if let Some(fixture) = fixture {
+        let ctx = RssContext::from_file(fixture).chain_err(|| format!("can't read {}", fixture))?;
+        let items = convert_to_ser_items(&ctx.channel.items, &config.include_fields, &config.missing_link);
+        return Ok((items.into_iter().map(SerItem::into_owned).collect(), Vec::new()));
+    }
+
+    let last_run = StatePaths::resolve(config).last_run;
+    if last_run.exists() {
+        let entries = HistoryEntry::load(&last_run)?;
+        if !entries.is_empty() {
+            return Ok((entries.into_iter().map(|e| e.item).collect(), Vec::new()));
+        }
+    }
+
+    Ok(sample_items())
+}
+
+/// The page for the current 'config_file': 'mail.html' as a run renders
+/// it, or what went wrong, with 'RELOAD_SCRIPT' added.
+pub fn preview_page(config_file: &str, fixture: Option<&str>) -> String {
+    let rendered = Config::load(config_file).and_then(|config| {
+        let (new_items, updated_items) = preview_items(&config, fixture)?;
+        render_changes(&config, &config.templates(), "mail.html", &new_items, &updated_items, &Vec::new(),
+                       false)
+    });
+
+    let page = match rendered {
+        Ok(page) => page,
+        Err(e) => format!("<html><body><pre>{}</pre></body></html>", escape_html(&error_chain_string(&e))),
+    };
+    match page.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &page[..end], RELOAD_SCRIPT, &page[end..]),
+        None => format!("{}{}", page, RELOAD_SCRIPT),
+    }
+}
+
+// one connection: the page, its generation or nothing.
+fn handle(mut stream: TcpStream, config_file: &str, fixture: Option<&str>, generation: &AtomicUsize) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let request = read_request(BufReader::new(stream.try_clone()?))?;
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => {
+            respond_with(&mut stream, 200, "text/html; charset=utf-8", &preview_page(config_file, fixture))?
+        }
+        ("GET", "/generation") => {
+            respond_with(&mut stream, 200, "text/plain", &generation.load(Ordering::SeqCst).to_string())?
+        }
+        _ => respond_with(&mut stream, 404, "text/plain", "not found")?,
+    }
+    Ok(())
+}
+
+/// Serve the preview of 'config_file' on 127.0.0.1:'port' until killed,
+/// see 'preview_page'; a page loaded in a browser reloads when the config,
+/// its 'templates_dir' or 'fixture' change. State is only read for the
+/// last run, and nothing is fetched, sent or written, so Ctrl-C stops it
+/// without leaving anything behind.
+pub fn preview(config_file: &str, fixture: Option<&str>, port: u16) -> Result<()> {
+    let templates_dir = Config::load(config_file).map(|c| c.templates_dir).unwrap_or(TEMPLATE_DIR.to_string());
+    let listener = TcpListener::bind(("127.0.0.1", port)).chain_err(|| format!("can't listen on port {}", port))?;
+
+    let generation = Arc::new(AtomicUsize::new(0));
+    let changes = generation.clone();
+    let watched: Vec<String> = vec![Some(config_file.to_string()), Some(templates_dir), fixture.map(|f| f.to_string())]
+        .into_iter().flatten().collect();
+    thread::spawn(move || {
+        let paths: Vec<&Path> = watched.iter().map(Path::new).collect();
+        let mut seen = fingerprint(&paths);
+        loop {
+            thread::sleep(Duration::from_millis(POLL_MILLIS));
+            let current = fingerprint(&paths);
+            if current != seen {
+                changes.fetch_add(1, Ordering::SeqCst);
+                seen = current;
+            }
+        }
+    });
+
+    Record::info("serving the preview").field("port", port)
+        .log(format!("previewing mail.html on http://127.0.0.1:{}/, Ctrl-C to stop.", port));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if let Err(e) = handle(stream, config_file, fixture, &generation) {
+            Record::warn("preview request failed").field("error", error_chain_string(&e))
+                .log(format!("preview request failed: {}", error_chain_string(&e)));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_page_reloads_itself_and_shows_what_went_wrong() {
+        let page = preview_page("no-such-bbsmon.json", None);
+        assert!(page.contains("no-such-bbsmon.json"));
+        assert!(page.contains("/generation"));
+        assert!(page.find("<script>") < page.find("</body>"));
+
+        let (new_items, updated_items) = sample_items();
+        assert!(new_items.iter().all(|i| i.diff.is_empty()));
+        assert!(!updated_items[0].diff.is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+//! Changes held back across runs, and how many mails a feed may send.
+
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::path::Path;
+use std::collections::BTreeMap;
+
+use chrono::DateTime;
+use chrono::Local;
+
+use rss::Item;
+
+use serde_json;
+
+use crate::error::*;
+use crate::feed::item_key;
+use crate::render::SerItem;
+use crate::render::item_link;
+use crate::render::convert_to_ser_items;
+use crate::state::write_atomic;
+
+/// An item waiting in a pending queue, with everything needed to render it
+/// and record it later on.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingItem {
+    /// Identity of the item, see 'item_key'.
+    pub key: String,
+    /// The item as it will be rendered.
+    pub item: SerItem<'static>,
+}
+
+/// 'items' ready to be queued, minus those the 'missing_link' policy drops.
+pub fn pending_items<'a, I>(items: I, missing_link: &str) -> Vec<PendingItem>
+    where I: IntoIterator<Item = &'a Item>
+{
+    let items: Vec<&Item> = items.into_iter().filter(|i| item_link(i, missing_link).is_some()).collect();
+    let ser_items = convert_to_ser_items(items.iter().cloned(), &None, missing_link);
+    items.iter().zip(ser_items).map(|(item, ser_item)| PendingItem {
+        key: item_key(item),
+        item: ser_item.into_owned(),
+    }).collect()
+}
+
+/// Changes of a feed held back across runs, persisted under 'pending/'.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PendingQueue {
+    /// When the oldest held item was queued.
+    pub since: Option<String>,
+    /// Held new items.
+    pub new_items: Vec<PendingItem>,
+    /// Held updated items.
+    pub updated_items: Vec<PendingItem>,
+
+    /// When the last digest of the feed was sent.
+    pub last_sent: Option<String>,
+}
+
+impl PendingQueue {
+    /// The queue stored at 'path', empty when there is none.
+    pub fn load(path: &Path) -> Result<PendingQueue> {
+        if !path.exists() {
+            return Ok(PendingQueue::default());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Replace what is stored at 'path' with this queue.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+
+    /// A later version of an already queued item replaces it.
+    pub fn add(&mut self, new_items: Vec<PendingItem>, updated_items: Vec<PendingItem>) {
+        if self.since.is_none() && (!new_items.is_empty() || !updated_items.is_empty()) {
+            self.since = Some(Local::now().to_rfc3339());
+        }
+
+        for item in new_items {
+            PendingQueue::upsert(&mut self.new_items, item);
+        }
+
+        for item in updated_items {
+            if let Some(queued) = self.new_items.iter_mut().find(|q| q.key == item.key) {
+                *queued = item;
+                continue;
+            }
+            PendingQueue::upsert(&mut self.updated_items, item);
+        }
+    }
+
+    /// Remove the item published first and tell whether it was an updated
+    /// one. Items without a date come after all dated ones, in the order
+    /// they were queued.
+    pub fn take_oldest(&mut self) -> Option<(PendingItem, bool)> {
+        let key = |p: &PendingItem| (p.item.pub_date.is_none(), p.item.pub_date.clone());
+        let oldest_new = self.new_items.iter().enumerate().min_by_key(|&(_, p)| key(p)).map(|(i, p)| (i, key(p)));
+        let oldest_updated = self.updated_items.iter().enumerate()
+            .min_by_key(|&(_, p)| key(p))
+            .map(|(i, p)| (i, key(p)));
+
+        let oldest = match (oldest_new, oldest_updated) {
+            (Some((i, a)), Some((j, b))) => if a <= b {
+                (self.new_items.remove(i), false)
+            } else {
+                (self.updated_items.remove(j), true)
+            },
+            (Some((i, _)), None) => (self.new_items.remove(i), false),
+            (None, Some((j, _))) => (self.updated_items.remove(j), true),
+            (None, None) => return None,
+        };
+
+        if self.is_empty() {
+            self.since = None;
+        }
+
+        Some(oldest)
+    }
+
+    fn upsert(items: &mut Vec<PendingItem>, item: PendingItem) {
+        match items.iter().position(|q| q.key == item.key) {
+            Some(i) => items[i] = item,
+            None => items.push(item),
+        }
+    }
+
+    /// Number of held items.
+    pub fn len(&self) -> usize {
+        self.new_items.len() + self.updated_items.len()
+    }
+
+    /// Whether nothing is held.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Seconds since the oldest item was queued.
+    pub fn held_for(&self, now: DateTime<Local>) -> i64 {
+        self.since.as_ref()
+            .and_then(|since| DateTime::parse_from_rfc3339(since).ok())
+            .map_or(0, |since| now.timestamp() - since.timestamp())
+    }
+}
+
+/// Length of a throttle window.
+pub const THROTTLE_WINDOW_SECS: i64 = 3600;
+
+/// Mails sent per feed in its current hour long window, and when each feed
+/// sent its last one. Persisted so that restarting bbsmon doesn't reset
+/// the count.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ThrottleState {
+    /// Window of each feed, by name.
+    pub feeds: BTreeMap<String, ThrottleWindow>,
+    /// When each feed last sent a mail, for 'send_cooldown_secs'.
+    #[serde(default)]
+    pub last_sent: BTreeMap<String, String>,
+}
+
+/// The current window of a feed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ThrottleWindow {
+    /// When the window opened.
+    pub start: String,
+    /// Mails sent within it.
+    pub sent: u32,
+    /// Items held back since the limit was reached.
+    pub held: usize,
+}
+
+impl ThrottleWindow {
+    fn expired(&self, now: DateTime<Local>) -> bool {
+        match DateTime::parse_from_rfc3339(&self.start) {
+            Ok(start) => now.timestamp() - start.timestamp() >= THROTTLE_WINDOW_SECS,
+            Err(_) => true,
+        }
+    }
+}
+
+impl ThrottleState {
+    /// The state stored at 'path', empty when there is none.
+    pub fn load(path: &Path) -> Result<ThrottleState> {
+        if !path.exists() {
+            return Ok(ThrottleState::default());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Replace what is stored at 'path' with this state.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+
+    /// Whether another mail of 'feed' may go out at 'now'.
+    pub fn allows(&self, feed: &str, limit: Option<u32>, now: DateTime<Local>) -> bool {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+
+        match self.feeds.get(feed) {
+            Some(window) if !window.expired(now) => window.sent < limit,
+            _ => true,
+        }
+    }
+
+    /// Whether 'cooldown' seconds passed since the last mail of 'feed'.
+    pub fn cooled_down(&self, feed: &str, cooldown: Option<i64>, now: DateTime<Local>) -> bool {
+        let cooldown = match cooldown {
+            Some(cooldown) => cooldown,
+            None => return true,
+        };
+
+        match self.last_sent.get(feed).map(|sent| DateTime::parse_from_rfc3339(sent)) {
+            Some(Ok(sent)) => now.timestamp() - sent.timestamp() >= cooldown,
+            _ => true,
+        }
+    }
+
+    /// Count a mail of 'feed', opening a new window when the last one is over.
+    pub fn record(&mut self, feed: &str, now: DateTime<Local>) {
+        self.last_sent.insert(feed.to_string(), now.to_rfc3339());
+
+        let expired = self.feeds.get(feed).is_none_or(|w| w.expired(now));
+        if expired {
+            self.feeds.insert(feed.to_string(), ThrottleWindow {
+                start: now.to_rfc3339(),
+                sent: 1,
+                held: 0,
+            });
+            return;
+        }
+
+        if let Some(window) = self.feeds.get_mut(feed) {
+            window.sent += 1;
+        }
+    }
+
+    /// Count 'count' items of 'feed' held back by the limit.
+    pub fn hold(&mut self, feed: &str, count: usize) {
+        if let Some(window) = self.feeds.get_mut(feed) {
+            window.held += count;
+        }
+    }
+
+    /// Items of 'feed' held back in its current window.
+    pub fn held(&self, feed: &str) -> usize {
+        self.feeds.get(feed).map_or(0, |w| w.held)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn pending(key: &str, title: &str) -> PendingItem {
+        PendingItem {
+            key: key.to_string(),
+            item: SerItem {
+                title: Some(title.to_string().into()),
+                link: None,
+                description: None,
+                author: None,
+                pub_date: None,
+                thumbnail: None,
+                categories: Vec::new().into(),
+                replies: Vec::new().into(),
+                archive_link: None,
+                diff: Vec::new().into(),
+            },
+        }
+    }
+
+    #[test]
+    fn later_versions_replace_queued_items() {
+        let mut queue = PendingQueue::default();
+        queue.add(vec![pending("1", "first"), pending("2", "second")], Vec::new());
+        queue.add(vec![pending("1", "first, edited")], vec![pending("2", "second, edited"),
+                                                               pending("3", "third, edited")]);
+
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.new_items[0].item.title, Some("first, edited".into()));
+        assert_eq!(queue.new_items[1].item.title, Some("second, edited".into()));
+        assert_eq!(queue.updated_items[0].key, "3");
+        assert!(queue.since.is_some());
+    }
+
+    #[test]
+    fn oldest_item_is_taken_first() {
+        let mut dated = pending("2", "older");
+        dated.item.pub_date = Some("2016-11-04 08:00:00".into());
+        let mut newer = pending("1", "newer");
+        newer.item.pub_date = Some("2016-11-04 09:00:00".into());
+
+        let mut queue = PendingQueue::default();
+        queue.add(vec![pending("0", "undated"), newer, dated], Vec::new());
+
+        let titles: Vec<String> = (0..3)
+            .map(|_| queue.take_oldest().unwrap().0.item.title.unwrap().into_owned())
+            .collect();
+        assert_eq!(titles, vec!["older", "newer", "undated"]);
+        assert!(queue.take_oldest().is_none());
+        assert!(queue.since.is_none());
+    }
+
+    #[test]
+    fn throttle_window_reopens_after_an_hour() {
+        let mut throttle = ThrottleState::default();
+        let start = Local.with_ymd_and_hms(2016, 11, 4, 12, 0, 0).unwrap();
+
+        assert!(throttle.allows("bbs", Some(2), start));
+        throttle.record("bbs", start);
+        throttle.record("bbs", start);
+        assert!(!throttle.allows("bbs", Some(2), start));
+        assert!(throttle.allows("bbs", None, start));
+        assert!(throttle.allows("other", Some(2), start));
+
+        throttle.hold("bbs", 5);
+        assert_eq!(throttle.held("bbs"), 5);
+
+        let later = Local.with_ymd_and_hms(2016, 11, 4, 13, 0, 0).unwrap();
+        assert!(throttle.allows("bbs", Some(2), later));
+        throttle.record("bbs", later);
+        assert_eq!(throttle.held("bbs"), 0);
+    }
+
+    #[test]
+    fn cooldown_runs_from_the_last_mail() {
+        let mut throttle = ThrottleState::default();
+        let start = Local.with_ymd_and_hms(2016, 11, 4, 12, 0, 0).unwrap();
+
+        assert!(throttle.cooled_down("bbs", Some(3600), start));
+        throttle.record("bbs", start);
+        assert!(!throttle.cooled_down("bbs", Some(3600), Local.with_ymd_and_hms(2016, 11, 4, 12, 59, 59).unwrap()));
+        assert!(throttle.cooled_down("bbs", Some(3600), Local.with_ymd_and_hms(2016, 11, 4, 13, 0, 0).unwrap()));
+        assert!(throttle.cooled_down("bbs", None, start));
+        assert!(throttle.cooled_down("other", Some(3600), start));
+    }
+}
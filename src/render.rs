@@ -0,0 +1,963 @@
+//! Turning feed items into what notifications show.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Local;
+use chrono::TimeZone;
+
+use rss::Item;
+
+use reqwest::Url;
+
+use scraper::Html;
+use scraper::Selector;
+
+use tera;
+use tera::Tera;
+
+use chrono::NaiveDateTime;
+
+use crate::error::*;
+use crate::log::Record;
+use crate::config::Config;
+use crate::config::FeedConfig;
+use crate::config::ThreadKey;
+use crate::config::OTHER_SITE;
+use crate::archive::archive_link;
+use crate::diff::DiffSegment;
+use crate::diff::diff_text;
+use crate::diff::item_diff;
+use crate::messages::fill;
+use crate::messages::message;
+
+/// An item as templates, history and webhooks see it. Its fields borrow
+/// from the feed item it was converted from, or from another 'SerItem',
+/// until 'into_owned'; what is read back from disk owns them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SerItem<'a> {
+    /// Title of the item.
+    pub title: Option<Cow<'a, str>>,
+    /// Link of the item, see 'item_link'.
+    pub link: Option<Cow<'a, str>>,
+    /// Description of the item.
+    pub description: Option<Cow<'a, str>>,
+    /// Author of the item.
+    pub author: Option<Cow<'a, str>>,
+    /// Publication date in local time, "%Y-%m-%d %H:%M:%S".
+    pub pub_date: Option<Cow<'a, str>>,
+    /// URL of the item's picture, see 'item_thumbnail'.
+    #[serde(default)]
+    pub thumbnail: Option<Cow<'a, str>>,
+    /// Names of the categories of the item.
+    #[serde(default)]
+    pub categories: Cow<'a, [String]>,
+    /// Titles of the items of a thread folded into this one, oldest
+    /// first, see 'group_threads'; empty for a single item.
+    #[serde(default)]
+    pub replies: Cow<'a, [String]>,
+    /// Wayback Machine snapshot of 'link', for feeds with 'archive_links'
+    /// whose snapshot was made in time; see 'archive::add_archive_links'.
+    #[serde(default)]
+    pub archive_link: Option<Cow<'a, str>>,
+    /// Word diff of the description of an updated item against the one it
+    /// had, see 'diff::item_diff'; empty for other items, unchanged text
+    /// and when 'include_fields' leaves the description out.
+    #[serde(default)]
+    pub diff: Cow<'a, [DiffSegment]>,
+}
+
+// 'text' borrowed, whether 'text' itself borrows or owns it.
+fn borrow_text<'b>(text: &'b Option<Cow<str>>) -> Option<Cow<'b, str>> {
+    text.as_deref().map(Cow::Borrowed)
+}
+
+// 'text' owning what it borrowed.
+fn own_text(text: Option<Cow<str>>) -> Option<Cow<'static, str>> {
+    text.map(|text| Cow::Owned(text.into_owned()))
+}
+
+impl<'a> SerItem<'a> {
+    /// The item borrowing every field of this one, copying nothing.
+    pub fn borrowed(&self) -> SerItem<'_> {
+        SerItem {
+            title: borrow_text(&self.title),
+            link: borrow_text(&self.link),
+            description: borrow_text(&self.description),
+            author: borrow_text(&self.author),
+            pub_date: borrow_text(&self.pub_date),
+            thumbnail: borrow_text(&self.thumbnail),
+            categories: Cow::Borrowed(&self.categories),
+            replies: Cow::Borrowed(&self.replies),
+            archive_link: borrow_text(&self.archive_link),
+            diff: Cow::Borrowed(&self.diff),
+        }
+    }
+
+    /// The item owning every field, for keeping it past what it borrows
+    /// from.
+    pub fn into_owned(self) -> SerItem<'static> {
+        SerItem {
+            title: own_text(self.title),
+            link: own_text(self.link),
+            description: own_text(self.description),
+            author: own_text(self.author),
+            pub_date: own_text(self.pub_date),
+            thumbnail: own_text(self.thumbnail),
+            categories: Cow::Owned(self.categories.into_owned()),
+            replies: Cow::Owned(self.replies.into_owned()),
+            archive_link: own_text(self.archive_link),
+            diff: Cow::Owned(self.diff.into_owned()),
+        }
+    }
+}
+
+/// The items of one board in a mail spanning several, headed "site ▸
+/// board" by the default template.
+#[derive(Serialize, Debug, Clone)]
+pub struct Section<'a> {
+    /// 'FeedConfig::site' of the board.
+    pub site: String,
+    /// 'FeedConfig::site_url', empty when there is none.
+    pub site_url: String,
+    /// Name of the feed.
+    pub board: String,
+    /// Number of new and updated items.
+    pub count: usize,
+    /// New items of the board.
+    pub new_items: Vec<SerItem<'a>>,
+    /// Updated items of the board.
+    pub updated_items: Vec<SerItem<'a>>,
+    /// 'digest_separator' before every section but the first, else empty.
+    pub separator: String,
+}
+
+/// A thread grown by 'hot_threshold' replies, shown under "Heating up"
+/// though none of its items is new.
+#[derive(Serialize, Debug, Clone)]
+pub struct HotThread<'a> {
+    /// The item of the thread with the most replies.
+    pub item: SerItem<'a>,
+    /// Replies of the thread.
+    pub replies: u64,
+    /// Replies gained since 'since'.
+    pub delta: u64,
+    /// When the thread was last shown, or first seen: "%H:%M" today, else
+    /// "%m-%d %H:%M".
+    pub since: String,
+}
+
+impl<'a> HotThread<'a> {
+    /// 'item' with 'replies', 'delta' of them gained since the RFC 3339 time
+    /// 'at', shown in the time zone of 'now'.
+    pub fn new<Tz: TimeZone>(item: SerItem<'a>, replies: u64, delta: u64, at: &str, now: DateTime<Tz>)
+                             -> HotThread<'a>
+        where Tz::Offset: fmt::Display
+    {
+        let since = match DateTime::parse_from_rfc3339(at) {
+            Ok(at) => {
+                let at = at.with_timezone(&now.timezone());
+                if at.date_naive() == now.date_naive() {
+                    at.format("%H:%M").to_string()
+                } else {
+                    at.format("%m-%d %H:%M").to_string()
+                }
+            }
+            Err(_) => at.to_string(),
+        };
+
+        HotThread {
+            item,
+            replies,
+            delta,
+            since,
+        }
+    }
+}
+
+impl<'a> Section<'a> {
+    /// A section of 'feed', counting its items; they are grouped by
+    /// thread when 'feed' has a 'thread_key'.
+    pub fn new(feed: &FeedConfig, new_items: Vec<SerItem<'a>>, updated_items: Vec<SerItem<'a>>) -> Section<'a> {
+        let (new_items, updated_items) = match feed.thread_key {
+            Some(ref key) => (group_threads(&new_items, key), group_threads(&updated_items, key)),
+            None => (new_items, updated_items),
+        };
+
+        Section {
+            site: feed.site().to_string(),
+            site_url: feed.site_url(),
+            board: feed.name.clone(),
+            count: count_items(&new_items) + count_items(&updated_items),
+            new_items,
+            updated_items,
+            separator: String::new(),
+        }
+    }
+}
+
+/// Fold the 'items' of one thread, as 'key' tells, into a single entry:
+/// the newest of them, with the titles of all of them as its 'replies'.
+/// Entries come in the order of their first item; items 'key' finds no
+/// thread for stay as they are.
+pub fn group_threads<'a>(items: &[SerItem<'a>], key: &ThreadKey) -> Vec<SerItem<'a>> {
+    let mut threads: Vec<(Option<String>, Vec<&SerItem>)> = Vec::new();
+
+    for item in items {
+        let text = if key.field == "title" { &item.title } else { &item.link };
+        let thread = text.as_deref().and_then(|text| key.thread(text));
+
+        let known = thread.as_ref().and_then(|t| threads.iter().position(|(other, _)| other.as_ref() == Some(t)));
+        match known {
+            Some(i) => threads[i].1.push(item),
+            None => threads.push((thread, vec![item])),
+        }
+    }
+
+    threads.into_iter().map(|(_, thread)| {
+        if thread.len() == 1 {
+            return thread[0].clone();
+        }
+
+        // dates sort as they are stored; the last of equal ones wins.
+        let mut ordered = thread.clone();
+        ordered.sort_by(|a, b| a.pub_date.cmp(&b.pub_date));
+        let mut newest = ordered[ordered.len() - 1].clone();
+        newest.replies = Cow::Owned(ordered.iter().map(|item| item.title.as_deref().unwrap_or_default().to_string())
+                                    .collect());
+        newest
+    }).collect()
+}
+
+/// How many items 'items' stand for, counting the replies of threads.
+pub fn count_items(items: &[SerItem]) -> usize {
+    items.iter().map(|item| if item.replies.is_empty() { 1 } else { item.replies.len() }).sum()
+}
+
+/// How 'SerItem::pub_date' is stored; it sorts like the dates it stands for.
+pub const PUB_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// rfc2822 dates become local time, anything else is kept as it is.
+fn convert_pub_date(old: &Option<String>) -> Option<Cow<'_, str>> {
+    if let Some(date_str) = old {
+        if let Ok(date) =  DateTime::parse_from_rfc2822(date_str) {
+            return Some(Cow::Owned(date
+                                   .with_timezone(&Local)
+                                   .format(PUB_DATE_FORMAT)
+                                   .to_string()));
+        }
+    }
+
+    old.as_deref().map(Cow::Borrowed)
+}
+
+/// Languages 'locale' may name.
+pub const LOCALES: &[&str] = &["en", "de", "fr", "es", "zh", "ja"];
+
+/// "de" of "de", "de_DE" and "de-DE".
+pub fn locale_language(locale: &str) -> &str {
+    locale.split(['_', '-']).next().unwrap_or(locale)
+}
+
+// month names, then weekday names starting on monday, full and abbreviated.
+fn date_names(language: &str) -> ([&'static str; 12], [&'static str; 12], [&'static str; 7], [&'static str; 7]) {
+    match language {
+        "de" => (["Januar", "Februar", "März", "April", "Mai", "Juni", "Juli",
+                  "August", "September", "Oktober", "November", "Dezember"],
+                 ["Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez"],
+                 ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"],
+                 ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"]),
+        "fr" => (["janvier", "février", "mars", "avril", "mai", "juin", "juillet",
+                  "août", "septembre", "octobre", "novembre", "décembre"],
+                 ["janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.", "nov.", "déc."],
+                 ["lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche"],
+                 ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."]),
+        "es" => (["enero", "febrero", "marzo", "abril", "mayo", "junio", "julio",
+                  "agosto", "septiembre", "octubre", "noviembre", "diciembre"],
+                 ["ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic"],
+                 ["lunes", "martes", "miércoles", "jueves", "viernes", "sábado", "domingo"],
+                 ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"]),
+        "zh" => (["一月", "二月", "三月", "四月", "五月", "六月", "七月",
+                  "八月", "九月", "十月", "十一月", "十二月"],
+                 ["1月", "2月", "3月", "4月", "5月", "6月", "7月", "8月", "9月", "10月", "11月", "12月"],
+                 ["星期一", "星期二", "星期三", "星期四", "星期五", "星期六", "星期日"],
+                 ["周一", "周二", "周三", "周四", "周五", "周六", "周日"]),
+        "ja" => (["1月", "2月", "3月", "4月", "5月", "6月", "7月", "8月", "9月", "10月", "11月", "12月"],
+                 ["1月", "2月", "3月", "4月", "5月", "6月", "7月", "8月", "9月", "10月", "11月", "12月"],
+                 ["月曜日", "火曜日", "水曜日", "木曜日", "金曜日", "土曜日", "日曜日"],
+                 ["月", "火", "水", "木", "金", "土", "日"]),
+        _ => (["January", "February", "March", "April", "May", "June", "July",
+               "August", "September", "October", "November", "December"],
+              ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"],
+              ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"],
+              ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]),
+    }
+}
+
+/// A 'pub_date' as stored in 'format', with the month and weekday names of
+/// 'locale'. Dates that aren't in 'PUB_DATE_FORMAT' are kept as they are.
+pub fn format_pub_date(date: &str, format: &str, locale: &str) -> String {
+    let parsed = match NaiveDateTime::parse_from_str(date, PUB_DATE_FORMAT) {
+        Ok(parsed) => parsed,
+        Err(_) => return date.to_string(),
+    };
+
+    let (months, months_abbr, weekdays, weekdays_abbr) = date_names(locale_language(locale));
+    let month = parsed.month0() as usize;
+    let weekday = parsed.weekday().num_days_from_monday() as usize;
+
+    // names are put in before chrono sees the pattern, which then only
+    // formats numbers.
+    let mut localized = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            localized.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('B') => localized.push_str(months[month]),
+            Some('b') | Some('h') => localized.push_str(months_abbr[month]),
+            Some('A') => localized.push_str(weekdays[weekday]),
+            Some('a') => localized.push_str(weekdays_abbr[weekday]),
+            Some(other) => {
+                localized.push('%');
+                localized.push(other);
+            }
+            None => localized.push('%'),
+        }
+    }
+
+    parsed.format(&localized).to_string()
+}
+
+/// 'items' with their dates as 'date_format' and 'locale' ask for,
+/// borrowing the rest.
+pub fn localize_dates<'b>(config: &Config, items: &'b [SerItem]) -> Vec<SerItem<'b>> {
+    items.iter().map(|item| SerItem {
+        pub_date: item.pub_date.as_deref().map(|d| Cow::Owned(format_pub_date(d, &config.date_format, &config.locale))),
+        ..item.borrowed()
+    }).collect()
+}
+
+/// Names 'include_fields' may list.
+pub const SER_ITEM_FIELDS: &[&str] = &["title", "link", "description", "author", "pub_date",
+                                       "thumbnail", "categories"];
+
+// 'value' only if 'field' is allowed by the 'include_fields' list; it is
+// not made at all otherwise.
+fn include_field<T, F: FnOnce() -> Option<T>>(fields: &Option<Vec<String>>, field: &str, value: F) -> Option<T> {
+    if let Some(fields) = fields {
+        if !fields.iter().any(|f| f == field) {
+            return None;
+        }
+    }
+
+    value()
+}
+
+/// Apply 'include_fields' to an already converted item.
+pub fn restrict_fields<'a>(item: SerItem<'a>, fields: &Option<Vec<String>>) -> SerItem<'a> {
+    let SerItem { title, link, description, author, pub_date, thumbnail, categories, replies, archive_link, diff } = item;
+    let described = include_field(fields, "description", || Some(()));
+
+    SerItem {
+        title: include_field(fields, "title", || title),
+        link: include_field(fields, "link", || link),
+        description: include_field(fields, "description", || description),
+        author: include_field(fields, "author", || author),
+        pub_date: include_field(fields, "pub_date", || pub_date),
+        thumbnail: include_field(fields, "thumbnail", || thumbnail),
+        categories: include_field(fields, "categories", || Some(categories)).unwrap_or_default(),
+        replies,
+        archive_link,
+        diff: if described.is_some() { diff } else { Cow::default() },
+    }
+}
+
+/// The link an item is shown with, or None when 'missing_link' drops it.
+pub fn item_link<'a>(item: &'a Item, missing_link: &str) -> Option<Option<&'a str>> {
+    if item.link.as_ref().is_some_and(|l| !l.trim().is_empty()) {
+        return Some(item.link.as_deref());
+    }
+
+    match missing_link {
+        "drop" => None,
+        "use_guid_as_link" => Some(item.guid.as_ref().map(|g| g.value.as_str())),
+        _ => Some(item.link.as_deref()),
+    }
+}
+
+/// The picture of 'item': its enclosure when that is an image, else the
+/// first image in its description, resolved against its link.
+pub fn item_thumbnail(item: &Item) -> Option<String> {
+    if let Some(ref enclosure) = item.enclosure {
+        if enclosure.mime_type.starts_with("image/") && !enclosure.url.is_empty() {
+            return Some(enclosure.url.clone());
+        }
+    }
+
+    let description = match item.description {
+        Some(ref description) if description.contains("<img") => description,
+        _ => return None,
+    };
+    let img = Selector::parse("img[src]").expect("a valid selector");
+    let fragment = Html::parse_fragment(description);
+    let src = match fragment.select(&img).filter_map(|img| img.value().attr("src")).find(|s| !s.trim().is_empty()) {
+        Some(src) => src.trim(),
+        None => return None,
+    };
+
+    // relative to the item's page; left as it is when there is nothing to resolve it against.
+    match item.link.as_ref().and_then(|link| Url::parse(link).ok()) {
+        Some(base) => base.join(src).ok().map(|url| url.to_string()),
+        None => Some(src.to_string()),
+    }
+}
+
+/// Convert 'items' for rendering, applying 'include_fields' and the
+/// 'missing_link' policy. The text of the items is borrowed, and fields
+/// left out are never made.
+pub fn convert_to_ser_items<'a, I>(items: I, fields: &Option<Vec<String>>, missing_link: &str) -> Vec<SerItem<'a>>
+    where I: IntoIterator<Item = &'a Item>
+{
+    let mut ser_items = Vec::new();
+    
+    for item in items {
+        let link = match item_link(item, missing_link) {
+            Some(link) => link,
+            None => continue,
+        };
+
+        ser_items.push(SerItem {
+            title: include_field(fields, "title", || item.title.as_deref().map(Cow::Borrowed)),
+            link: include_field(fields, "link", || link.map(Cow::Borrowed)),
+            description: include_field(fields, "description", || item.description.as_deref().map(Cow::Borrowed)),
+            author: include_field(fields, "author", || item.author.as_deref().map(Cow::Borrowed)),
+            pub_date: include_field(fields, "pub_date", || convert_pub_date(&item.pub_date)),
+            thumbnail: include_field(fields, "thumbnail", || item_thumbnail(item).map(Cow::Owned)),
+            categories: include_field(fields, "categories", || {
+                Some(Cow::Owned(item.categories.iter().map(|c| c.name.clone()).collect()))
+            }).unwrap_or_default(),
+            replies: Cow::default(),
+            archive_link: archive_link(item).map(Cow::Owned),
+            diff: include_field(fields, "description", || Some(Cow::Owned(item_diff(item)))).unwrap_or_default(),
+        })
+    }
+
+    ser_items
+}
+
+/// Render 'tmpl_file' with 'items' as the new items.
+pub fn render(config: &Config, templates: &str, tmpl_file: &str, items: &[SerItem]) -> Result<String> {
+    render_changes(config, templates, tmpl_file, items, &Vec::new(), &Vec::new(), false)
+}
+
+/// 'is_first_run' marks the mail sent when a feed is seeded, whose
+/// 'new_items' are simply everything the feed holds at that point. Dates
+/// are shown as 'date_format' and 'locale' of 'config' ask for.
+///
+/// With 'skip_unrenderable_items', a mail that fails to render is rendered
+/// again without the items that fail on their own, which are logged.
+///
+/// With 'simple_format' no template is used: the new and updated items
+/// become a line each, joined by 'simple_format_joiner'.
+pub fn render_changes(config: &Config, templates: &str, tmpl_file: &str, new_items: &[SerItem],
+                      updated_items: &[SerItem], removed_items: &[SerItem],
+                      is_first_run: bool) -> Result<String> {
+    let lists = [borrow_all(new_items), borrow_all(updated_items), borrow_all(removed_items)];
+    render_all(config, templates, tmpl_file, lists, &Extras { is_first_run, ..Extras::default() })
+}
+
+/// 'render_changes', with the 'hot' threads handed to the template as
+/// 'hot_threads', which the default template shows under "Heating up".
+pub fn render_hot_changes(config: &Config, templates: &str, tmpl_file: &str, new_items: &[SerItem],
+                          updated_items: &[SerItem], removed_items: &[SerItem],
+                          hot: &[HotThread]) -> Result<String> {
+    let lists = [borrow_all(new_items), borrow_all(updated_items), borrow_all(removed_items)];
+    render_all(config, templates, tmpl_file, lists, &Extras { hot, ..Extras::default() })
+}
+
+/// Render 'tmpl_file' with the items of 'sections', in their order, as new
+/// and updated items. Templates also get the sections as 'feeds', and as
+/// 'sections' when 'by_site', which the default template shows apart.
+/// Otherwise like 'render_changes'.
+pub fn render_sections(config: &Config, templates: &str, tmpl_file: &str, sections: &[Section],
+                       by_site: bool) -> Result<String> {
+    let mut lists = [Vec::new(), Vec::new(), Vec::new()];
+    for section in sections {
+        lists[0].extend(section.new_items.iter().map(SerItem::borrowed));
+        lists[1].extend(section.updated_items.iter().map(SerItem::borrowed));
+    }
+
+    render_all(config, templates, tmpl_file, lists, &Extras { sections, by_site, ..Extras::default() })
+}
+
+/// The items of a digest posted on one day.
+#[derive(Serialize, Debug)]
+pub struct DigestGroup<'a> {
+    /// The day, "%Y-%m-%d", or "-" for items without a date.
+    pub day: String,
+    /// Items posted that day, grouped by thread when the feed has a 'thread_key'.
+    pub items: Vec<SerItem<'a>>,
+}
+
+/// Render 'tmpl_file' as the digest of the board 'feed' with 'groups', one
+/// per day, oldest first. Dates are shown as in 'render_changes'.
+pub fn render_digest(config: &Config, templates: &str, tmpl_file: &str, feed: &str, weekly: bool,
+                     groups: &[DigestGroup]) -> Result<String> {
+    let groups: Vec<DigestGroup> = groups.iter().map(|g| DigestGroup {
+        day: g.day.clone(),
+        items: localize_dates(config, &g.items),
+    }).collect();
+    let count = groups.iter().map(|g| count_items(&g.items)).sum::<usize>();
+
+    let mut tctx = tera::Context::new();
+    tctx.insert("feed", feed);
+    tctx.insert("weekly", &weekly);
+    tctx.insert("count", &count);
+    tctx.insert("groups", &groups);
+    render_context(templates, tmpl_file, tctx)
+}
+
+// 'items' borrowed, see 'SerItem::borrowed'.
+fn borrow_all<'b>(items: &'b [SerItem]) -> Vec<SerItem<'b>> {
+    items.iter().map(SerItem::borrowed).collect()
+}
+
+// what 'render_all' hands the template next to the lists of items.
+#[derive(Default)]
+struct Extras<'a> {
+    hot: &'a [HotThread<'a>],
+    sections: &'a [Section<'a>],
+    by_site: bool,
+    is_first_run: bool,
+}
+
+// 'render_changes', also handing 'hot' threads and 'sections' to the template.
+fn render_all(config: &Config, templates: &str, tmpl_file: &str, lists: [Vec<SerItem>; 3], extras: &Extras)
+              -> Result<String> {
+    let Extras { hot, sections, by_site, is_first_run } = *extras;
+    let lists = [localize_dates(config, &lists[0]),
+                 localize_dates(config, &lists[1]),
+                 localize_dates(config, &lists[2])];
+    let hot: Vec<HotThread> = hot.iter().map(|h| HotThread {
+        item: localize_dates(config, std::slice::from_ref(&h.item)).remove(0),
+        replies: h.replies,
+        delta: h.delta,
+        since: h.since.clone(),
+    }).collect();
+    let sections: Vec<Section> = sections.iter().map(|s| Section {
+        site: s.site.clone(),
+        site_url: s.site_url.clone(),
+        board: s.board.clone(),
+        count: s.count,
+        new_items: localize_dates(config, &s.new_items),
+        updated_items: localize_dates(config, &s.updated_items),
+        separator: s.separator.clone(),
+    }).collect();
+
+    if let Some(ref format) = config.simple_format {
+        let mut lines: Vec<String> = lists[0].iter().chain(lists[1].iter())
+            .map(|item| format_item(format, item))
+            .collect();
+        lines.extend(hot.iter().map(|h| format!("{} (+{})", format_item(format, &h.item), h.delta)));
+        return Ok(lines.join(&config.simple_format_joiner));
+    }
+
+    let e = match render_lists(templates, tmpl_file, &lists, &hot, &sections, by_site, is_first_run) {
+        Ok(content) => return Ok(content),
+        Err(e) => e,
+    };
+    if !config.skip_unrenderable_items {
+        return Err(e);
+    }
+
+    let mut kept = [Vec::new(), Vec::new(), Vec::new()];
+    for (i, list) in lists.iter().enumerate() {
+        for item in list {
+            let mut alone = [Vec::new(), Vec::new(), Vec::new()];
+            alone[i].push(item.borrowed());
+
+            match render_lists(templates, tmpl_file, &alone, &[], &[], false, is_first_run) {
+                Ok(_) => kept[i].push(item.borrowed()),
+                Err(item_e) => {
+                    let title = item.title.as_deref().unwrap_or("");
+                    Record::warn("skipping item that can't be rendered").field("title", title)
+                        .field("error", error_chain_string(&item_e))
+                        .log(format!("skipping item '{}', it can't be rendered: {}",
+                                     title, error_chain_string(&item_e)));
+                }
+            }
+        }
+    }
+
+    // when no item was to blame, the template itself is.
+    if kept.iter().map(|l| l.len()).sum::<usize>() == lists.iter().map(|l| l.len()).sum::<usize>() {
+        return Err(e);
+    }
+
+    let sections: Vec<Section> = sections.into_iter().map(|s| {
+        let new_items: Vec<SerItem> = s.new_items.into_iter().filter(|i| kept[0].contains(i)).collect();
+        let updated_items: Vec<SerItem> = s.updated_items.into_iter().filter(|i| kept[1].contains(i)).collect();
+        Section {
+            count: new_items.len() + updated_items.len(),
+            new_items,
+            updated_items,
+            ..s
+        }
+    }).filter(|s| s.count > 0).collect();
+
+    render_lists(templates, tmpl_file, &kept, &hot, &sections, by_site, is_first_run)
+}
+
+/// Put 'sections', made in the order of 'config.feeds', in the order of
+/// their sites there, with the 'OTHER_SITE' section last, and the boards of
+/// a site as 'digest_order' says. Sets their 'separator'.
+pub fn order_sections(config: &Config, sections: &mut [Section]) {
+    let rank = |section: &Section| -> usize {
+        config.feeds.iter()
+            .position(|f| f.site.as_ref().is_some_and(|s| *s == section.site))
+            .unwrap_or(config.feeds.len())
+    };
+
+    sections.sort_by(|a, b| {
+        let by_board = match config.digest_order.as_str() {
+            "name" => a.board.cmp(&b.board),
+            "count" => b.new_items.len().cmp(&a.new_items.len()),
+            _ => Ordering::Equal,
+        };
+        match rank(a).cmp(&rank(b)) {
+            Ordering::Equal => by_board,
+            by_site => by_site,
+        }
+    });
+
+    for (i, section) in sections.iter_mut().enumerate() {
+        section.separator = match config.digest_separator {
+            Some(ref separator) if i > 0 => separator.clone(),
+            _ => String::new(),
+        };
+    }
+}
+
+/// 'format' with "{title}", "{link}", "{description}", "{author}" and
+/// "{pub_date}" replaced by those fields of 'item', or nothing when the
+/// item has none. "{diff}" is its 'diff' as 'diff_text' writes it.
+pub fn format_item(format: &str, item: &SerItem) -> String {
+    let field = |value: &Option<Cow<str>>| value.as_deref().unwrap_or("").to_string();
+
+    format.replace("{title}", &field(&item.title))
+        .replace("{link}", &field(&item.link))
+        .replace("{description}", &field(&item.description))
+        .replace("{author}", &field(&item.author))
+        .replace("{pub_date}", &field(&item.pub_date))
+        .replace("{diff}", &diff_text(&item.diff))
+}
+
+/// 'subject' followed by the number of new items and how they split over
+/// the feeds of 'counts', e.g. "BBS: 3 new (General 2, Market 1)" in the
+/// language of 'locale'. Feeds without new items are left out; 'subject'
+/// alone when none has any.
+pub fn counts_subject(subject: &str, counts: &[(String, usize)], locale: &str) -> String {
+    let feeds: Vec<String> = counts.iter()
+        .filter(|&&(_, count)| count > 0)
+        .map(|&(ref feed, count)| format!("{} {}", feed, count))
+        .collect();
+    if feeds.is_empty() {
+        return subject.to_string();
+    }
+
+    let total = counts.iter().map(|&(_, count)| count).sum::<usize>().to_string();
+    fill(locale, "new", &[("subject", subject), ("total", &total),
+                                 ("feeds", &feeds.join(message(locale, "separator")))])
+}
+
+/// 'subject' followed by the number of new items and how they split over
+/// the sites of 'sections', e.g. "BBS: 3 new on 2 sites, 3 boards (Rust 2,
+/// Other 1)". Like 'counts_subject' otherwise.
+pub fn sections_subject(subject: &str, sections: &[Section], locale: &str) -> String {
+    let mut sites: Vec<(String, usize)> = Vec::new();
+    let mut boards = 0;
+    for section in sections.iter().filter(|s| !s.new_items.is_empty()) {
+        match sites.iter().position(|(site, _)| *site == section.site) {
+            Some(i) => sites[i].1 += section.new_items.len(),
+            None => sites.push((section.site.clone(), section.new_items.len())),
+        }
+        boards += 1;
+    }
+    if sites.is_empty() {
+        return subject.to_string();
+    }
+
+    let total = sites.iter().map(|&(_, count)| count).sum::<usize>().to_string();
+    let listed: Vec<String> = sites.iter().map(|&(ref site, count)| {
+        let site = if site == OTHER_SITE { message(locale, "other_site") } else { site.as_str() };
+        format!("{} {}", site, count)
+    }).collect();
+    fill(locale, "new_on_sites", &[
+        ("subject", subject),
+        ("total", &total),
+        ("sites", &sites.len().to_string()),
+        ("sites_word", message(locale, if sites.len() == 1 { "site" } else { "sites" })),
+        ("boards", &boards.to_string()),
+        ("boards_word", message(locale, if boards == 1 { "board" } else { "boards" })),
+        ("feeds", &listed.join(message(locale, "separator"))),
+    ])
+}
+
+// render new, updated and removed items as they are.
+fn render_lists(templates: &str, tmpl_file: &str, lists: &[Vec<SerItem>; 3], hot: &[HotThread],
+                sections: &[Section], by_site: bool, is_first_run: bool) -> Result<String> {
+    let none: &[Section] = &[];
+    let mut tctx = tera::Context::new();
+    tctx.insert("is_first_run", &is_first_run);
+    tctx.insert("feeds", sections);
+    tctx.insert("sections", if by_site { sections } else { none });
+    // 'items' is what templates used before updates and removals were told apart.
+    tctx.insert("items", &lists[0]);
+    tctx.insert("new_items", &lists[0]);
+    tctx.insert("updated_items", &lists[1]);
+    tctx.insert("removed_items", &lists[2]);
+    tctx.insert("hot_threads", hot);
+
+    render_context(templates, tmpl_file, tctx)
+}
+
+/// Render 'tmpl_file' out of the 'templates' glob with 'tctx'. A template
+/// that doesn't compile is an error of the caller, not the end of the
+/// process like with 'compile_templates!'.
+pub fn render_context(templates: &str, tmpl_file: &str, tctx: tera::Context) -> Result<String> {
+    let tera = Tera::new(templates).chain_err(|| format!("can't compile templates {}", templates))?;
+
+    let content = tera.render(tmpl_file, &tctx).chain_err(|| format!("can't render template {}", tmpl_file))?;
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rss::Enclosure;
+    use rss::Guid;
+    use serde_json;
+
+    fn unlinked() -> Item {
+        Item {
+            title: Some("no link".into()),
+            guid: Some(Guid { value: "tag:bbs,1".to_string(), ..Guid::default() }),
+            ..Item::default()
+        }
+    }
+
+    fn reply(tid: u32, title: &'static str, pub_date: &'static str) -> SerItem<'static> {
+        SerItem {
+            title: Some(title.into()),
+            link: Some(format!("http://bbs.example/read.php?tid={}&page=3", tid).into()),
+            description: None,
+            author: None,
+            pub_date: Some(pub_date.into()),
+            thumbnail: None,
+            categories: Vec::new().into(),
+            replies: Vec::new().into(),
+            archive_link: None,
+            diff: Vec::new().into(),
+        }
+    }
+
+    #[test]
+    fn replies_are_grouped_by_thread() {
+        let mut key: ThreadKey = serde_json::from_str(r#"{"regex": "tid=(\\d+)"}"#).unwrap();
+        key.validate().unwrap();
+        let items = vec![reply(7, "Re: 周末聚餐 (page 3)", "2016-11-05 09:00:00"),
+                         reply(8, "二手自行车", "2016-11-05 08:30:00"),
+                         reply(7, "Re: 周末聚餐 (page 2)", "2016-11-05 08:00:00"),
+                         reply(7, "Re: 周末聚餐 (page 4)", "2016-11-05 10:00:00")];
+
+        let grouped = group_threads(&items, &key);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].title, Some("Re: 周末聚餐 (page 4)".into()));
+        assert_eq!(grouped[0].pub_date, Some("2016-11-05 10:00:00".into()));
+        assert_eq!(grouped[0].replies, vec!["Re: 周末聚餐 (page 2)", "Re: 周末聚餐 (page 3)",
+                                            "Re: 周末聚餐 (page 4)"]);
+        assert_eq!(grouped[1], items[1]);
+        assert_eq!(count_items(&grouped), 4);
+    }
+
+    #[test]
+    fn thumbnail_is_the_first_image() {
+        let mut item = Item {
+            link: Some("http://bbs.example/bbs/read.php?tid=1".into()),
+            description: Some("<p>出 <img alt=\"\" src=\" \"> <img src=\"attachment/1.jpg\"> \
+                               <img src=\"http://img.example/2.jpg\"></p>".to_string()),
+            ..Item::default()
+        };
+        assert_eq!(item_thumbnail(&item), Some("http://bbs.example/bbs/attachment/1.jpg".into()));
+
+        item.enclosure = Some(Enclosure {
+            url: "http://img.example/cover.png".to_string(),
+            length: "0".to_string(),
+            mime_type: "image/png".to_string(),
+        });
+        assert_eq!(item_thumbnail(&item), Some("http://img.example/cover.png".into()));
+
+        item.enclosure = None;
+        item.description = Some("no picture".into());
+        assert_eq!(item_thumbnail(&item), None);
+    }
+
+    #[test]
+    fn missing_link_policies() {
+        let items = vec![unlinked()];
+
+        assert!(convert_to_ser_items(&items, &None, "drop").is_empty());
+        assert_eq!(convert_to_ser_items(&items, &None, "keep")[0].link, None);
+        assert_eq!(convert_to_ser_items(&items, &None, "use_guid_as_link")[0].link,
+                   Some("tag:bbs,1".into()));
+    }
+
+    #[test]
+    fn dates_use_the_names_of_the_locale() {
+        let date = "2016-11-04 23:30:00";
+
+        assert_eq!(format_pub_date(date, PUB_DATE_FORMAT, "en"), date);
+        assert_eq!(format_pub_date(date, "%A, %d. %B %Y", "de_DE"), "Freitag, 04. November 2016");
+        assert_eq!(format_pub_date(date, "%a %b %d 100%%", "en"), "Fri Nov 04 100%");
+        assert_eq!(format_pub_date(date, "%m月%d日 %A", "zh"), "11月04日 星期五");
+        assert_eq!(format_pub_date("yesterday", "%B", "fr"), "yesterday");
+    }
+
+    #[test]
+    fn include_fields_restricts_items() {
+        let fields = Some(vec!["title".to_string()]);
+        let rss_items = vec![unlinked()];
+        let items = convert_to_ser_items(&rss_items, &fields, "use_guid_as_link");
+
+        assert_eq!(items[0].title, Some("no link".into()));
+        assert_eq!(items[0].link, None);
+    }
+
+    #[test]
+    fn simple_format_skips_the_templates() {
+        let mut config: Config = serde_json::from_str(r#"{"feeds": [], "subject": "s", "from": "f@bbs",
+                                                          "to": "t@bbs", "password": "p", "server": "bbs",
+                                                          "simple_format": "{title} - {link}{author}"}"#)
+            .unwrap();
+        let rss_items = vec![unlinked()];
+        let items = convert_to_ser_items(&rss_items, &None, "use_guid_as_link");
+
+        assert_eq!(render_changes(&config, "nowhere/*", "mail.html", &items, &items, &[], false).unwrap(),
+                   "no link - tag:bbs,1\nno link - tag:bbs,1");
+
+        config.simple_format_joiner = " | ".to_string();
+        assert_eq!(render(&config, "nowhere/*", "mail.html", &items).unwrap(), "no link - tag:bbs,1");
+    }
+
+    #[test]
+    fn subject_counts_new_items_per_feed() {
+        let counts = vec![("General".to_string(), 2), ("Off-topic".to_string(), 0), ("Market".to_string(), 1)];
+
+        assert_eq!(counts_subject("BBS", &counts, "en"), "BBS: 3 new (General 2, Market 1)");
+        assert_eq!(counts_subject("BBS", &[("General".to_string(), 0)], "en"), "BBS");
+        assert_eq!(counts_subject("论坛", &counts, "zh_CN"), "论坛：3 条新帖（General 2，Market 1）");
+    }
+
+    #[test]
+    fn mails_are_sectioned_by_site_and_board() {
+        let config: Config = serde_json::from_str(r#"{"feeds": [
+                                                          {"name": "Lounge", "remote_rss": "-"},
+                                                          {"name": "Market", "remote_rss": "http://b/rss?fid=2",
+                                                           "site": "B"},
+                                                          {"name": "General", "remote_rss": "http://a/rss",
+                                                           "site": "A", "site_url": "http://a/bbs"},
+                                                          {"name": "Jobs", "remote_rss": "http://b/rss?fid=3",
+                                                           "site": "B"}],
+                                                      "subject": "s", "from": "f@bbs", "to": "t@bbs",
+                                                      "password": "p", "server": "bbs"}"#).unwrap();
+        let rss_items = vec![unlinked()];
+        let items = convert_to_ser_items(&rss_items, &None, "use_guid_as_link");
+        let twice: Vec<SerItem> = items.iter().chain(items.iter()).cloned().collect();
+        let mut sections = vec![Section::new(&config.feeds[0], items.clone(), Vec::new()),
+                                Section::new(&config.feeds[1], items.clone(), Vec::new()),
+                                Section::new(&config.feeds[2], twice, Vec::new()),
+                                Section::new(&config.feeds[3], Vec::new(), items.clone())];
+
+        order_sections(&config, &mut sections);
+        let order: Vec<(&str, &str, &str)> = sections.iter()
+            .map(|s| (s.site.as_str(), s.board.as_str(), s.site_url.as_str()))
+            .collect();
+        assert_eq!(order, vec![("B", "Market", "http://b"), ("B", "Jobs", "http://b"),
+                               ("A", "General", "http://a/bbs"), ("Other", "Lounge", "")]);
+
+        assert_eq!(sections_subject("BBS", &sections, "en"), "BBS: 4 new on 3 sites, 3 boards (B 1, A 2, Other 1)");
+        assert_eq!(sections_subject("BBS", &sections, "zh"),
+                   "BBS：4 条新帖，3 个站点、3 个版面（B 1，A 2，其他 1）");
+        assert_eq!(sections_subject("BBS", &[sections[1].clone()], "en"), "BBS");
+
+        let content = render_sections(&config, "templates/**/*", "mail.html", &sections, true).unwrap();
+        assert!(content.contains("<a href=\"http:&#x2F;&#x2F;b\">B</a> ▸ Jobs（1 条）"), "{}", content);
+        assert!(content.contains("Other ▸ Lounge（1 条）"), "{}", content);
+        assert!(content.contains("\">A</a> ▸ General（2 条）"), "{}", content);
+        assert_eq!(content.matches("tag:bbs,1").count(), 5);
+    }
+
+    #[test]
+    fn digest_order_orders_the_boards_of_a_site() {
+        let mut config: Config = serde_json::from_str(r#"{"feeds": [
+                                                              {"name": "Market", "remote_rss": "-"},
+                                                              {"name": "General", "remote_rss": "-"},
+                                                              {"name": "Jobs", "remote_rss": "-"}],
+                                                          "subject": "s", "from": "f@bbs", "to": "t@bbs",
+                                                          "password": "p", "server": "bbs",
+                                                          "digest_separator": "<hr>"}"#).unwrap();
+        let rss_items = vec![unlinked()];
+        let items = convert_to_ser_items(&rss_items, &None, "use_guid_as_link");
+        let sections = vec![Section::new(&config.feeds[0], items.clone(), Vec::new()),
+                            Section::new(&config.feeds[1], Vec::new(), items.clone()),
+                            Section::new(&config.feeds[2], vec![items[0].clone(), items[0].clone()], Vec::new())];
+        let ordered = |config: &Config| -> Vec<String> {
+            let mut sections = sections.clone();
+            order_sections(config, &mut sections);
+            sections.iter().map(|s| format!("{}{}", s.separator, s.board)).collect()
+        };
+
+        assert_eq!(ordered(&config), vec!["Market", "<hr>General", "<hr>Jobs"]);
+        config.digest_order = "name".to_string();
+        assert_eq!(ordered(&config), vec!["General", "<hr>Jobs", "<hr>Market"]);
+        config.digest_order = "count".to_string();
+        config.digest_separator = None;
+        assert_eq!(ordered(&config), vec!["Jobs", "Market", "General"]);
+    }
+
+    #[test]
+    fn unrenderable_items_are_skipped() {
+        use std::env;
+        use std::fs;
+        use std::io::Write;
+
+        let dir = env::temp_dir().join(format!("bbsmon-render-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::File::create(dir.join("upper.html")).unwrap()
+            .write_all(b"{% for item in new_items %}{{ item.title | upper }};{% endfor %}").unwrap();
+        let templates = format!("{}/*", dir.display());
+
+        let mut config: Config = serde_json::from_str(r#"{"feeds": [], "subject": "s", "from": "f@bbs",
+                                                          "to": "t@bbs", "password": "p", "server": "bbs"}"#)
+            .unwrap();
+        let rss_items = vec![unlinked(), Item::default()];
+        let items = convert_to_ser_items(&rss_items, &None, "keep");
+
+        assert!(render_changes(&config, &templates, "upper.html", &items, &[], &[], false).is_err());
+
+        config.skip_unrenderable_items = true;
+        assert_eq!(render_changes(&config, &templates, "upper.html", &items, &[], &[], false).unwrap(),
+                   "NO LINK;");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,386 @@
+//! The activity report: what the history archive recorded over a day or
+//! a week, board by board.
+
+use std::cmp::Reverse;
+use std::path::Path;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Local;
+use chrono::NaiveTime;
+
+use tera;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::config::DigestSchedule;
+use crate::history::HistoryEntry;
+use crate::history::parse_since;
+use crate::log::Record;
+use crate::messages::fill;
+use crate::notify::deliver;
+use crate::render::render_context;
+use crate::state::StatePaths;
+use crate::state::load_snapshot;
+use crate::status::RunStatus;
+
+/// How many of the busiest authors a report lists.
+pub const TOP_AUTHORS: usize = 10;
+
+/// How many of the most used categories a report lists.
+pub const TOP_CATEGORIES: usize = 10;
+
+/// What 'report.html' is rendered with.
+#[derive(Serialize, Debug)]
+pub struct ActivityReport {
+    /// Start of the period, "%Y-%m-%d %H:%M".
+    pub since: String,
+    /// End of the period, excluded, "%Y-%m-%d %H:%M".
+    pub until: String,
+    /// The days of the period, "%m-%d".
+    pub days: Vec<String>,
+    /// The boards items were notified of, busiest first.
+    pub boards: Vec<BoardActivity>,
+    /// The 'TOP_AUTHORS' authors of the most items, most first.
+    pub authors: Vec<AuthorActivity>,
+    /// The 'TOP_CATEGORIES' categories of the most items, most first.
+    pub categories: Vec<CategoryActivity>,
+    /// Number of notified items.
+    pub items: usize,
+    /// Number of notifications they went out in.
+    pub notifications: usize,
+    /// Number of items left out of notifications on 'days'.
+    pub filtered: usize,
+}
+
+/// The items of one board in an 'ActivityReport'.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BoardActivity {
+    /// Name of the feed.
+    pub feed: String,
+    /// Items notified on each of 'ActivityReport::days'.
+    pub per_day: Vec<usize>,
+    /// Items notified over the period.
+    pub items: usize,
+}
+
+/// The items of one author in an 'ActivityReport'.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AuthorActivity {
+    /// Author as the items name them.
+    pub author: String,
+    /// Items they wrote.
+    pub items: usize,
+}
+
+/// The items of one category in an 'ActivityReport'.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CategoryActivity {
+    /// Name of the category.
+    pub category: String,
+    /// Items in it.
+    pub items: usize,
+}
+
+/// Sum up the 'entries' notified from 'since' until 'until'. Entries of a
+/// feed sharing their 'notified_at' went out as one notification.
+/// 'filtered' is 'RunStatus::filtered'.
+pub fn activity_report(entries: &Vec<HistoryEntry>, filtered: &BTreeMap<String, usize>,
+                       since: DateTime<Local>, until: DateTime<Local>) -> ActivityReport {
+    let mut days = Vec::new();
+    let mut date = since.date_naive();
+    while date.and_time(NaiveTime::MIN) < until.naive_local() {
+        days.push(date);
+        date = date.succ_opt().unwrap();
+    }
+
+    let mut boards: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut authors: BTreeMap<String, usize> = BTreeMap::new();
+    let mut categories: BTreeMap<String, usize> = BTreeMap::new();
+    let mut notifications = HashSet::new();
+    let mut items = 0;
+
+    for entry in entries {
+        let notified_at = match DateTime::parse_from_rfc3339(&entry.notified_at) {
+            Ok(at) => at.with_timezone(&Local),
+            Err(_) => continue,
+        };
+        if notified_at < since || notified_at >= until {
+            continue;
+        }
+
+        let day = days.iter().position(|d| *d == notified_at.date_naive()).unwrap_or(0);
+        boards.entry(entry.feed.clone()).or_insert(vec![0; days.len()])[day] += 1;
+        if let Some(ref author) = entry.item.author {
+            *authors.entry(author.to_string()).or_insert(0) += 1;
+        }
+        for category in entry.item.categories.iter() {
+            *categories.entry(category.clone()).or_insert(0) += 1;
+        }
+        notifications.insert((entry.feed.clone(), entry.notified_at.clone()));
+        items += 1;
+    }
+
+    let mut boards: Vec<BoardActivity> = boards.into_iter().map(|(feed, per_day)| BoardActivity {
+        feed,
+        items: per_day.iter().sum(),
+        per_day,
+    }).collect();
+    boards.sort_by_key(|board| Reverse(board.items));
+
+    let mut authors: Vec<AuthorActivity> = authors.into_iter().map(|(author, items)| AuthorActivity {
+        author,
+        items,
+    }).collect();
+    authors.sort_by_key(|author| Reverse(author.items));
+    authors.truncate(TOP_AUTHORS);
+
+    let mut categories: Vec<CategoryActivity> = categories.into_iter().map(|(category, items)| CategoryActivity {
+        category,
+        items,
+    }).collect();
+    categories.sort_by_key(|category| Reverse(category.items));
+    categories.truncate(TOP_CATEGORIES);
+
+    let filtered = days.iter().filter_map(|d| filtered.get(&d.format("%Y-%m-%d").to_string())).sum();
+
+    ActivityReport {
+        since: since.format("%Y-%m-%d %H:%M").to_string(),
+        until: until.format("%Y-%m-%d %H:%M").to_string(),
+        days: days.iter().map(|d| d.format("%m-%d").to_string()).collect(),
+        boards,
+        authors,
+        categories,
+        items,
+        notifications: notifications.len(),
+        filtered,
+    }
+}
+
+// the report of what 'history' recorded from 'since' until 'until', rendered;
+// 'filtered' is 'RunStatus::filtered'.
+fn render_report(config: &Config, filtered: &BTreeMap<String, usize>, since: DateTime<Local>,
+                 until: DateTime<Local>) -> Result<(String, usize)> {
+    let path = match config.history {
+        Some(ref path) => Path::new(path),
+        None => bail!("no 'history' file configured"),
+    };
+
+    let report = activity_report(&HistoryEntry::load(path)?, filtered, since, until);
+    let content = render_activity(&config.templates(), "report.html", &report)?;
+
+    Ok((content, report.items))
+}
+
+/// Render 'tmpl_file' out of the 'templates' glob with 'report'.
+pub fn render_activity(templates: &str, tmpl_file: &str, report: &ActivityReport) -> Result<String> {
+    let mut tctx = tera::Context::new();
+    tctx.insert("report", report);
+    render_context(templates, tmpl_file, tctx)
+}
+
+/// Mail the report of the day or week before the time 'schedule' was
+/// last due, once that is later than the 'last_report' of 'status'. With
+/// 'skip_empty_report' a period without items goes unreported. The very
+/// first run only starts the schedule.
+pub fn send_report(config: &Config, paths: &StatePaths, schedule: &DigestSchedule, status: &mut RunStatus,
+                   now: DateTime<Local>) -> Result<()> {
+    let due = schedule.last_due(now);
+    let last = status.last_report.clone();
+    match last {
+        Some(ref last) if DateTime::parse_from_rfc3339(last)?.timestamp() >= due.timestamp() => return Ok(()),
+        Some(_) => {}
+        None => {
+            status.last_report = Some(now.to_rfc3339());
+            return Ok(());
+        }
+    }
+
+    let weekly = schedule.period == "weekly";
+    let since = due - Duration::days(if weekly { 7 } else { 1 });
+    let (content, items) = render_report(config, &status.filtered, since, due)?;
+    status.last_report = Some(now.to_rfc3339());
+
+    if items == 0 && config.skip_empty_report {
+        Record::info("no activity, report skipped").log("no activity, report skipped.".to_string());
+        return Ok(());
+    }
+
+    let subject = fill(&config.locale, if weekly { "weekly_report" } else { "daily_report" },
+                       &[("subject", &config.subject)]);
+    if let Some(e) = deliver(config, paths, &subject, content)? {
+        return Err(e);
+    }
+
+    Record::info("sent activity report").field("items", items)
+        .log(format!("sent activity report of {} items.", items));
+    Ok(())
+}
+
+/// A feed as the heartbeat shows it.
+#[derive(Serialize, Debug)]
+pub struct HeartbeatFeed {
+    /// Name of the feed.
+    pub name: String,
+    /// When it was last checked without an error, "%Y-%m-%d %H:%M"; None
+    /// if it never was.
+    pub last_ok: Option<String>,
+    /// Items in its snapshot.
+    pub items: usize,
+}
+
+/// What 'heartbeat.html' is rendered with.
+#[derive(Serialize, Debug)]
+pub struct Heartbeat {
+    /// When it was sent, "%Y-%m-%d %H:%M".
+    pub now: String,
+    /// Every configured feed.
+    pub feeds: Vec<HeartbeatFeed>,
+}
+
+/// Mail the heartbeat once 'schedule' says one is due since the last was
+/// handled. It is skipped when items were notified since then, unless
+/// 'heartbeat_even_if_notified'. The first run only starts the schedule.
+pub fn send_heartbeat(config: &Config, paths: &StatePaths, schedule: &DigestSchedule, status: &mut RunStatus,
+                      now: DateTime<Local>) -> Result<()> {
+    let due = schedule.last_due(now);
+    let last = match status.last_heartbeat.clone() {
+        Some(last) => DateTime::parse_from_rfc3339(&last)?,
+        None => {
+            status.last_heartbeat = Some(now.to_rfc3339());
+            return Ok(());
+        }
+    };
+    if last.timestamp() >= due.timestamp() {
+        return Ok(());
+    }
+    status.last_heartbeat = Some(now.to_rfc3339());
+
+    let notified = status.feeds.values()
+        .filter_map(|feed| feed.last_new.as_ref().and_then(|t| DateTime::parse_from_rfc3339(t).ok()))
+        .any(|t| t.timestamp() >= last.timestamp());
+    if notified && !config.heartbeat_even_if_notified {
+        Record::info("items were notified, heartbeat skipped")
+            .log("items were notified, heartbeat skipped.".to_string());
+        return Ok(());
+    }
+
+    let feeds = config.feeds.iter().map(|feed| HeartbeatFeed {
+        name: feed.name.clone(),
+        last_ok: status.feeds.get(&feed.name).and_then(|s| s.last_ok.as_ref())
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()),
+        items: load_snapshot(&paths.snapshot(feed), &config.state_key).ok()
+            .and_then(|ctx| ctx).map_or(0, |ctx| ctx.channel.items.len()),
+    }).collect();
+
+    let heartbeat = Heartbeat {
+        now: now.format("%Y-%m-%d %H:%M").to_string(),
+        feeds,
+    };
+    let content = render_heartbeat(&config.templates(), "heartbeat.html", &heartbeat)?;
+
+    let subject = fill(&config.locale, "heartbeat", &[("subject", &config.subject)]);
+    if let Some(e) = deliver(config, paths, &subject, content)? {
+        return Err(e);
+    }
+
+    Record::info("sent heartbeat").log("sent heartbeat.".to_string());
+    Ok(())
+}
+
+/// Render 'tmpl_file' out of the 'templates' glob with 'heartbeat'.
+pub fn render_heartbeat(templates: &str, tmpl_file: &str, heartbeat: &Heartbeat) -> Result<String> {
+    let mut tctx = tera::Context::new();
+    tctx.insert("heartbeat", heartbeat);
+    render_context(templates, tmpl_file, tctx)
+}
+
+/// Print the report of what the history recorded from 'since' until
+/// 'until', now when absent; both as 'parse_since' takes them.
+pub fn report(config: &Config, since: &str, until: Option<&str>) -> Result<()> {
+    let since = parse_since(since).chain_err(|| "bad --since")?.with_timezone(&Local);
+    let until = match until {
+        Some(until) => parse_since(until).chain_err(|| "bad --until")?.with_timezone(&Local),
+        None => Local::now(),
+    };
+    if until <= since {
+        bail!("--until must be later than --since");
+    }
+
+    let status = RunStatus::load(&StatePaths::resolve(config).status)?.unwrap_or_default();
+    print!("{}", render_report(config, &status.filtered, since, until)?.0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Duration;
+    use chrono::TimeZone;
+
+    use crate::render::SerItem;
+
+    fn entry(feed: &str, author: Option<&str>, notified_at: &str) -> HistoryEntry {
+        entry_in(feed, author, &[], notified_at)
+    }
+
+    fn entry_in(feed: &str, author: Option<&str>, categories: &[&str], notified_at: &str) -> HistoryEntry {
+        HistoryEntry {
+            feed: feed.to_string(),
+            key: format!("{}-{}", feed, notified_at),
+            notified_at: notified_at.to_string(),
+            item: SerItem {
+                title: Some("t".into()),
+                link: None,
+                description: None,
+                author: author.map(|a| a.to_string().into()),
+                pub_date: None,
+                thumbnail: None,
+                categories: categories.iter().map(|c| c.to_string()).collect(),
+                replies: Vec::new().into(),
+                archive_link: None,
+                diff: Vec::new().into(),
+            },
+        }
+    }
+
+    #[test]
+    fn report_counts_by_board_and_day() {
+        let since = Local.with_ymd_and_hms(2016, 11, 7, 0, 0, 0).unwrap();
+        let at = |day: u32, hour: u32| Local.with_ymd_and_hms(2016, 11, day, hour, 0, 0).unwrap().to_rfc3339();
+        let entries = vec![entry("二手", Some("mingzai"), &at(6, 23)),
+                           entry_in("二手", Some("mingzai"), &["出售"], &at(7, 9)),
+                           entry_in("二手", Some("tom"), &["出售", "求购"], &at(7, 9)),
+                           entry_in("聚餐", Some("mingzai"), &["求购", "出售"], &at(9, 18)),
+                           entry("二手", None, &at(13, 22)),
+                           entry("聚餐", None, &at(14, 0))];
+
+        let mut filtered = BTreeMap::new();
+        filtered.insert("2016-11-06".to_string(), 5);
+        filtered.insert("2016-11-08".to_string(), 2);
+        filtered.insert("2016-11-13".to_string(), 1);
+
+        let report = activity_report(&entries, &filtered, since, since + Duration::days(7));
+
+        assert_eq!(report.days.len(), 7);
+        assert_eq!(report.items, 4);
+        assert_eq!(report.notifications, 3);
+        assert_eq!(report.filtered, 3);
+        assert_eq!(report.boards, vec![
+            BoardActivity { feed: "二手".to_string(), per_day: vec![2, 0, 0, 0, 0, 0, 1], items: 3 },
+            BoardActivity { feed: "聚餐".to_string(), per_day: vec![0, 0, 1, 0, 0, 0, 0], items: 1 },
+        ]);
+        assert_eq!(report.authors, vec![
+            AuthorActivity { author: "mingzai".to_string(), items: 2 },
+            AuthorActivity { author: "tom".to_string(), items: 1 },
+        ]);
+        assert_eq!(report.categories, vec![
+            CategoryActivity { category: "出售".to_string(), items: 3 },
+            CategoryActivity { category: "求购".to_string(), items: 2 },
+        ]);
+    }
+}
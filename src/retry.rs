@@ -0,0 +1,231 @@
+//! Retrying steps that failed for reasons that may go away.
+
+use std::future::Future;
+use std::io;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::error::*;
+
+/// How fetches and sends are retried, the 'retry' section of the config.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Tries in all, 1 never retries.
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each further one.
+    #[serde(default = "default_base_ms")]
+    pub base_ms: u64,
+    /// Longest wait between two tries.
+    #[serde(default = "default_cap_ms")]
+    pub cap_ms: u64,
+    /// Up to this fraction of each wait is taken off at random, 0 to 1, so
+    /// that feeds failing together don't retry together.
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+}
+
+fn default_attempts() -> u32 {
+    1
+}
+
+fn default_base_ms() -> u64 {
+    1000
+}
+
+fn default_cap_ms() -> u64 {
+    60000
+}
+
+fn default_jitter() -> f64 {
+    0.5
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            attempts: default_attempts(),
+            base_ms: default_base_ms(),
+            cap_ms: default_cap_ms(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Fails on settings 'retry' can't work with.
+    pub fn validate(&self) -> Result<()> {
+        if self.attempts == 0 {
+            bail!("retry.attempts must be at least 1");
+        }
+
+        if self.jitter < 0.0 || self.jitter > 1.0 {
+            bail!("retry.jitter must be between 0 and 1");
+        }
+
+        Ok(())
+    }
+
+    /// 'retry' with these settings.
+    pub fn run<F, T, P>(&self, transient: P, f: F) -> Result<T>
+        where F: FnMut() -> Result<T>, P: Fn(&Error) -> bool
+    {
+        retry(self.attempts, Duration::from_millis(self.base_ms), Duration::from_millis(self.cap_ms),
+                     self.jitter, transient, f)
+    }
+
+    /// 'retry_async' with these settings.
+    pub async fn run_async<F, Fut, T, P>(&self, transient: P, f: F) -> Result<T>
+        where F: FnMut() -> Fut, Fut: Future<Output = Result<T>>, P: Fn(&Error) -> bool
+    {
+        retry_async(self.attempts, Duration::from_millis(self.base_ms), Duration::from_millis(self.cap_ms),
+                    self.jitter, transient, f).await
+    }
+}
+
+/// The wait before retry number 'retry' (0 for the first): 'base' doubled
+/// 'retry' times but at most 'cap', less 'jitter' times 'random' (0 to 1) of it.
+pub fn backoff(retry: u32, base: Duration, cap: Duration, jitter: f64, random: f64) -> Duration {
+    let base_ms = base.as_secs() * 1000 + base.subsec_nanos() as u64 / 1000000;
+    let cap_ms = cap.as_secs() * 1000 + cap.subsec_nanos() as u64 / 1000000;
+
+    let factor = 1u64.checked_shl(retry).unwrap_or(u64::MAX);
+    let wait_ms = base_ms.saturating_mul(factor).min(cap_ms);
+    let jittered = wait_ms as f64 * (1.0 - jitter * random);
+
+    Duration::from_millis(jittered as u64)
+}
+
+/// A number between 0 and 1 that differs from call to call; good enough
+/// to spread retries and fetches out, not for anything secret.
+pub fn random_fraction() -> f64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let mut x = nanos as u64 ^ 0x9e3779b97f4a7c15;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+
+    (x % 1000000) as f64 / 1000000.0
+}
+
+/// Call 'f' up to 'attempts' times until it succeeds, waiting as 'backoff'
+/// says between tries. Only errors 'transient' accepts are retried, others
+/// and the last one are handed back as they are.
+pub fn retry<F, T, P>(attempts: u32, base: Duration, cap: Duration, jitter: f64, transient: P, mut f: F)
+                      -> Result<T>
+    where F: FnMut() -> Result<T>, P: Fn(&Error) -> bool
+{
+    let mut tried = 0;
+
+    loop {
+        let e = match f() {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+
+        tried += 1;
+        if tried >= attempts || !transient(&e) {
+            return Err(e);
+        }
+
+        thread::sleep(backoff(tried - 1, base, cap, jitter, random_fraction()));
+    }
+}
+
+/// 'retry' for steps run on the runtime, the waits not holding up its
+/// thread.
+pub async fn retry_async<F, Fut, T, P>(attempts: u32, base: Duration, cap: Duration, jitter: f64, transient: P,
+                                       mut f: F) -> Result<T>
+    where F: FnMut() -> Fut, Fut: Future<Output = Result<T>>, P: Fn(&Error) -> bool
+{
+    let mut tried = 0;
+
+    loop {
+        let e = match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+
+        tried += 1;
+        if tried >= attempts || !transient(&e) {
+            return Err(e);
+        }
+
+        tokio::time::sleep(backoff(tried - 1, base, cap, jitter, random_fraction())).await;
+    }
+}
+
+/// Whether 'e' may go away on its own: a network error, an HTTP 5xx or
+/// 429, or an SMTP 4xx. Only 'e' itself is looked at, so retries wrap a
+/// step before context is added to its errors.
+pub fn is_transient(e: &Error) -> bool {
+    match *e.kind() {
+        ErrorKind::HttpStatus(_, code) => code >= 500 || code == 429,
+        ErrorKind::Http(_) => true,
+        ErrorKind::Io(ref e) => matches!(e.kind(),
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset |
+            io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe |
+            io::ErrorKind::TimedOut | io::ErrorKind::Interrupted |
+            io::ErrorKind::UnexpectedEof),
+        // without a reply code and not a protocol or TLS error, the
+        // connection broke.
+        ErrorKind::Tranport(ref e) => {
+            e.is_transient() || (e.status().is_none() && !e.is_response() && !e.is_client() && !e.is_tls())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let base = Duration::from_millis(100);
+        let cap = Duration::from_millis(1000);
+
+        let waits: Vec<Duration> = (0..5).map(|n| backoff(n, base, cap, 0.5, 0.0)).collect();
+        assert_eq!(waits, vec![Duration::from_millis(100), Duration::from_millis(200), Duration::from_millis(400),
+                               Duration::from_millis(800), Duration::from_secs(1)]);
+        assert_eq!(backoff(70, base, cap, 0.5, 0.0), Duration::from_secs(1));
+
+        assert_eq!(backoff(2, base, cap, 0.5, 1.0), Duration::from_millis(200));
+        assert_eq!(backoff(2, base, cap, 0.0, 1.0), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let calls = Cell::new(0);
+        let zero = Duration::from_millis(0);
+
+        let result: Result<()> = retry(3, zero, zero, 0.0, is_transient, || {
+            calls.set(calls.get() + 1);
+            bail!(ErrorKind::HttpStatus("http://bbs".to_string(), 503));
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 3);
+
+        calls.set(0);
+        let result: Result<()> = retry(3, zero, zero, 0.0, is_transient, || {
+            calls.set(calls.get() + 1);
+            bail!(ErrorKind::HttpStatus("http://bbs".to_string(), 404));
+        });
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        let result = retry(3, zero, zero, 0.0, is_transient, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 2 {
+                return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into());
+            }
+            Ok(calls.get())
+        });
+        assert_eq!(result.unwrap(), 2);
+    }
+}
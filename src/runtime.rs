@@ -0,0 +1,53 @@
+//! The tokio runtime feeds are fetched and notifiers told on. Parsing,
+//! diffing and rendering stay plain functions; only the waiting is async.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use tokio::runtime::Builder;
+use tokio::runtime::Runtime;
+use tokio::task;
+
+use crate::error::*;
+use crate::log;
+
+/// What 'FeedSource::fetch' and 'Notifier::notify' hand back: work for
+/// the runtime of the run, failing as 'Result' does.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+// 'builder' with every thread tagged with the job of the current one, so
+// that what the tasks log is told apart just like with threads.
+fn tagged(mut builder: Builder) -> Result<Runtime> {
+    let job = log::job();
+    builder.enable_all().on_thread_start(move || log::set_job(job.clone()));
+    builder.build().chain_err(|| "can't start the runtime")
+}
+
+/// A runtime for the fetches and sends of one run. Drop it with
+/// 'Runtime::shutdown_background', a command feed past its timeout would
+/// otherwise hold up the end of the run.
+pub fn runtime() -> Result<Runtime> {
+    tagged(Builder::new_multi_thread())
+}
+
+/// 'f' run to its end on a runtime of its own, for the one-shot commands
+/// that fetch a single feed.
+pub fn block_on<T, F: Future<Output = Result<T>>>(f: F) -> Result<T> {
+    let runtime = tagged(Builder::new_current_thread())?;
+    let result = runtime.block_on(f);
+    runtime.shutdown_background();
+    result
+}
+
+/// 'f' run on a thread of the runtime that may block: reading files,
+/// running commands, talking SMTP.
+pub fn blocking<T, F>(f: F) -> BoxFuture<'static, T>
+    where T: Send + 'static, F: FnOnce() -> Result<T> + Send + 'static
+{
+    Box::pin(async move {
+        match task::spawn_blocking(f).await {
+            Ok(result) => result,
+            Err(e) => Err(format!("blocking work failed: {}", e).into()),
+        }
+    })
+}
@@ -0,0 +1,298 @@
+//! Items of boards without a feed, picked out of their HTML pages.
+
+use chrono::Local;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono::NaiveTime;
+use chrono::TimeZone;
+
+use reqwest::Client;
+use reqwest::Url;
+
+use scraper::ElementRef;
+use scraper::Html;
+use scraper::Selector;
+
+use crate::error::*;
+use crate::config::FeedConfig;
+use crate::config::ScrapeConfig;
+use crate::feed::Validators;
+use crate::source::FeedSource;
+use crate::retry::RetryPolicy;
+use crate::runtime::BoxFuture;
+use crate::runtime::block_on;
+use crate::source::HttpSource;
+use crate::source::RawFeed;
+use crate::source::Timeouts;
+use crate::source::http_source;
+
+/// An item as found on the page.
+#[derive(Debug)]
+pub struct ScrapedItem {
+    /// Text of the title element.
+    pub title: Option<String>,
+    /// The link, resolved against the page URL.
+    pub link: String,
+    /// Text of the guid element, else the link.
+    pub guid: String,
+    /// The date, RFC 2822 when it parsed with 'date_format'.
+    pub date: Option<String>,
+    /// Text of the author element.
+    pub author: Option<String>,
+}
+
+/// 'css' as a selector, or an error telling which one doesn't parse.
+pub fn selector(css: &str) -> Result<Selector> {
+    Selector::parse(css).map_err(|_| format!("bad CSS selector '{}'", css).into())
+}
+
+// the text of the first element 'css' matches within 'element', with its
+// whitespace collapsed; None when nothing matches or it is empty.
+fn text_of(element: &ElementRef, css: &str) -> Result<Option<String>> {
+    let found = match element.select(&selector(css)?).next() {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let text = found.text().collect::<Vec<_>>().join(" ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok(if text.is_empty() { None } else { Some(text) })
+}
+
+// 'text' as RFC 2822 when it is a local date or time in 'format'.
+fn parse_date(text: &str, format: &str) -> Option<String> {
+    let naive = NaiveDateTime::parse_from_str(text, format)
+        .or_else(|_| NaiveDate::parse_from_str(text, format).map(|d| d.and_time(NaiveTime::MIN)));
+
+    match naive {
+        Ok(naive) => Local.from_local_datetime(&naive).single().map(|d| d.to_rfc2822()),
+        Err(_) => None,
+    }
+}
+
+/// The title of the page and the items 'scrape' picks out of 'html', which
+/// was fetched from 'page'. Items without a link are left out.
+pub fn scrape_items(html: &str, page: &str, scrape: &ScrapeConfig) -> Result<(String, Vec<ScrapedItem>)> {
+    let base = Url::parse(page).chain_err(|| format!("bad page url '{}'", page))?;
+    let document = Html::parse_document(html);
+    let link = selector(&scrape.link)?;
+
+    let title = match document.select(&selector("title")?).next() {
+        Some(title) => title.text().collect::<Vec<_>>().join(" ").trim().to_string(),
+        None => page.to_string(),
+    };
+
+    let mut items = Vec::new();
+    for element in document.select(&selector(&scrape.item)?) {
+        let href = match element.select(&link).next() {
+            Some(a) => match a.value().attr("href") {
+                Some(href) => href.trim().to_string(),
+                None => a.text().collect::<String>().trim().to_string(),
+            },
+            None => continue,
+        };
+        let href = match base.join(&href) {
+            Ok(url) if !href.is_empty() => url.to_string(),
+            _ => continue,
+        };
+
+        let date = match scrape.date {
+            Some(ref css) => text_of(&element, css)?.map(|text| {
+                scrape.date_format.as_ref().and_then(|format| parse_date(&text, format)).unwrap_or(text)
+            }),
+            None => None,
+        };
+        let guid = match scrape.guid {
+            Some(ref css) => text_of(&element, css)?,
+            None => None,
+        };
+
+        items.push(ScrapedItem {
+            title: text_of(&element, &scrape.title)?,
+            guid: guid.unwrap_or(href.clone()),
+            link: href,
+            date,
+            author: match scrape.author {
+                Some(ref css) => text_of(&element, css)?,
+                None => None,
+            },
+        });
+    }
+
+    Ok((title, items))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// An RSS 2.0 document of 'items', for the rest of bbsmon to handle like
+/// any fetched feed.
+pub fn to_rss(title: &str, page: &str, items: &Vec<ScrapedItem>) -> String {
+    let mut rss = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\"><channel>\
+                           <title>{}</title><link>{}</link><description>scraped from {}</description>\n",
+                          escape(title), escape(page), escape(page));
+
+    for item in items {
+        rss.push_str("<item>");
+        if let Some(ref title) = item.title {
+            rss.push_str(&format!("<title>{}</title>", escape(title)));
+        }
+        rss.push_str(&format!("<link>{}</link><guid isPermaLink=\"false\">{}</guid>",
+                              escape(&item.link), escape(&item.guid)));
+        if let Some(ref author) = item.author {
+            rss.push_str(&format!("<author>{}</author>", escape(author)));
+        }
+        if let Some(ref date) = item.date {
+            rss.push_str(&format!("<pubDate>{}</pubDate>", escape(date)));
+        }
+        rss.push_str("</item>\n");
+    }
+
+    rss.push_str("</channel></rss>\n");
+    rss
+}
+
+/// A board page fetched like an http feed, its items turned into RSS. A
+/// page on which the item selector matches nothing fails, so that wrong
+/// selectors don't look like every item was removed.
+pub struct ScrapeSource {
+    /// Fetches the page.
+    pub page: HttpSource,
+    /// What to pick out of it.
+    pub scrape: ScrapeConfig,
+}
+
+impl ScrapeSource {
+    /// The source of a "scrape" feed, 'page' fetching its 'remote_rss'.
+    pub fn new(feed: &FeedConfig, page: HttpSource) -> Result<ScrapeSource> {
+        let scrape = match feed.scrape {
+            Some(ref scrape) => scrape.clone(),
+            None => bail!("feed '{}' is scraped but has no 'scrape' selectors", feed.name),
+        };
+
+        Ok(ScrapeSource {
+            page,
+            scrape,
+        })
+    }
+
+    /// The items on the page and its title, None when it didn't change.
+    pub async fn items(&self, client: &Client, validators: Option<&Validators>)
+                       -> Result<Option<(String, Vec<ScrapedItem>, Validators)>> {
+        let raw = match self.page.fetch(client, validators).await? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+
+        let html = String::from_utf8_lossy(&raw.body);
+        let (title, items) = scrape_items(&html, &self.page.url, &self.scrape)
+            .chain_err(|| format!("can't scrape {}", self.page.url))?;
+        if items.is_empty() {
+            bail!("'{}' matched no item with a link on {}", self.scrape.item, self.page.url);
+        }
+
+        Ok(Some((title, items, raw.validators)))
+    }
+
+    // the items as an RSS document, see 'FeedSource::fetch'.
+    async fn document(&self, client: &Client, validators: Option<&Validators>) -> Result<Option<RawFeed>> {
+        Ok(self.items(client, validators).await?.map(|(title, items, validators)| RawFeed {
+            body: to_rss(&title, &self.page.url, &items).into_bytes(),
+            validators,
+            content_type: None,
+        }))
+    }
+}
+
+impl FeedSource for ScrapeSource {
+    fn describe(&self) -> String {
+        format!("{} (scraped)", self.page.url)
+    }
+
+    fn fetch<'a>(&'a self, client: &'a Client, validators: Option<&'a Validators>)
+                 -> BoxFuture<'a, Option<RawFeed>> {
+        Box::pin(self.document(client, validators))
+    }
+}
+
+/// Fetch the page of 'feed' and print what its selectors pick out of it,
+/// the first few items in full.
+pub fn validate_scrape(feed: &FeedConfig, retry: &RetryPolicy, max_bytes: u64, timeouts: Timeouts) -> Result<()> {
+    let source = ScrapeSource::new(feed, http_source(feed, retry, max_bytes, None))?;
+    let client = timeouts.client()?;
+
+    let (title, items, _) = block_on(source.items(&client, None))?.expect("an unconditional fetch answers");
+    println!("source:     {}", source.describe());
+    println!("title:      {}", title);
+    println!("items:      {}", items.len());
+
+    for item in items.iter().take(VALIDATE_SHOWN) {
+        println!();
+        println!("title:      {}", item.title.as_ref().map_or("(none)", |t| t.as_str()));
+        println!("link:       {}", item.link);
+        if item.guid != item.link {
+            println!("guid:       {}", item.guid);
+        }
+        println!("date:       {}", item.date.as_ref().map_or("(none)", |d| d.as_str()));
+        println!("author:     {}", item.author.as_ref().map_or("(none)", |a| a.as_str()));
+    }
+
+    Ok(())
+}
+
+/// How many scraped items 'validate_scrape' prints.
+pub const VALIDATE_SHOWN: usize = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    use crate::feed::RssContext;
+
+    const PAGE: &str = r#"<html><head><title>小论坛 - 主题</title></head><body><table>
+        <tr class="thread"><td><a href="read.php?tid=2">二手 <b>自行车</b></a></td>
+            <td class="by">mingzai</td><td class="at">2016-11-05 08:00</td></tr>
+        <tr class="thread"><td><a href="/bbs/read.php?tid=1">周末 &amp; 聚餐</a></td>
+            <td class="by">yami</td><td class="at">昨天</td></tr>
+        <tr class="thread"><td>置顶公告，没有链接</td></tr>
+    </table></body></html>"#;
+
+    fn scrape() -> ScrapeConfig {
+        serde_json::from_str(r#"{"item": "tr.thread", "title": "a", "date": "td.at",
+                                        "date_format": "%Y-%m-%d %H:%M", "author": "td.by"}"#).unwrap()
+    }
+
+    #[test]
+    fn items_are_picked_out_of_the_page() {
+        let (title, items) = scrape_items(PAGE, "http://bbs.example/bbs/thread.php?fid=2", &scrape()).unwrap();
+
+        assert_eq!(title, "小论坛 - 主题");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, Some("二手 自行车".to_string()));
+        assert_eq!(items[0].link, "http://bbs.example/bbs/read.php?tid=2");
+        assert_eq!(items[0].guid, items[0].link);
+        assert_eq!(items[0].author, Some("mingzai".to_string()));
+        assert!(items[0].date.as_ref().unwrap().contains("5 Nov 2016 08:00:00"), "{:?}", items[0].date);
+        assert_eq!(items[1].link, "http://bbs.example/bbs/read.php?tid=1");
+        assert_eq!(items[1].date, Some("昨天".to_string()));
+    }
+
+    #[test]
+    fn scraped_items_read_like_a_feed() {
+        let (title, items) = scrape_items(PAGE, "http://bbs.example/bbs/thread.php?fid=2", &scrape()).unwrap();
+        let ctx = RssContext::from_reader(to_rss(&title, "http://bbs.example/", &items).as_bytes()).unwrap();
+
+        assert_eq!(ctx.channel.items.len(), 2);
+        assert_eq!(ctx.channel.items[1].title, Some("周末 & 聚餐".to_string()));
+        assert_eq!(ctx.channel.items[1].guid.as_ref().unwrap().value, "http://bbs.example/bbs/read.php?tid=1");
+    }
+
+    #[test]
+    fn bad_selectors_are_refused() {
+        assert!(selector("tr.thread > a").is_ok());
+        assert!(selector("tr..thread").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! The seen store: which items of which feed are known, and since when.
+
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::path::Path;
+use std::collections::BTreeMap;
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Local;
+
+use rss::Item;
+
+use serde_json;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::feed::item_key;
+use crate::state::StatePaths;
+use crate::state::load_snapshot;
+use crate::state::write_atomic;
+
+/// What 'seen_db' holds.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SeenStore {
+    /// By feed name, the RFC 3339 time each item key was first seen.
+    pub feeds: BTreeMap<String, BTreeMap<String, String>>,
+    /// By feed name, the RFC 3339 time each item key was last seen in its
+    /// feed; its time in 'feeds' counts for keys missing here.
+    #[serde(default)]
+    pub last_seen: BTreeMap<String, BTreeMap<String, String>>,
+    /// By feed name, the reply count each thread was last shown with, or
+    /// first seen with; see 'heating_up'.
+    #[serde(default)]
+    pub reply_counts: BTreeMap<String, BTreeMap<String, ReplyMark>>,
+}
+
+/// A reply count of a thread and when it was taken.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplyMark {
+    /// Number of replies.
+    pub count: u64,
+    /// RFC 3339 time of the count.
+    pub at: String,
+}
+
+impl SeenStore {
+    /// The store at 'path', empty if there is none yet.
+    pub fn load(path: &Path) -> Result<SeenStore> {
+        if !path.exists() {
+            return Ok(SeenStore::default());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        serde_json::from_str(&content).chain_err(|| format!("bad seen store {}", path.display()))
+    }
+
+    /// Replace what is stored at 'path' with this store.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+
+    /// Record 'items' of 'feed' that aren't known yet, as seen at their
+    /// publication date or else at 'now'. Returns how many were added.
+    pub fn record(&mut self, feed: &str, items: &Vec<Item>, now: &str) -> usize {
+        let seen = self.feeds.entry(feed.to_string()).or_default();
+        let mut added = 0;
+
+        for item in items {
+            let key = item_key(item);
+            if key.is_empty() || seen.contains_key(&key) {
+                continue;
+            }
+
+            let at = item.pub_date.as_ref()
+                .and_then(|d| DateTime::parse_from_rfc2822(d).ok())
+                .map_or(now.to_string(), |d| d.to_rfc3339());
+            seen.insert(key, at);
+            added += 1;
+        }
+
+        added
+    }
+
+    /// 'record' the 'items' of 'feed' and note they were seen at 'now',
+    /// the RFC 3339 time of the run. Returns how many were added.
+    pub fn observe(&mut self, feed: &str, items: &Vec<Item>, now: &str) -> usize {
+        let added = self.record(feed, items, now);
+
+        let last_seen = self.last_seen.entry(feed.to_string()).or_default();
+        for item in items {
+            let key = item_key(item);
+            if !key.is_empty() {
+                last_seen.insert(key, now.to_string());
+            }
+        }
+
+        added
+    }
+
+    /// The keys of 'feed' last seen more than 'days' before 'now', as
+    /// '(key, last seen)', except those 'present' in the feed. Keys whose
+    /// time doesn't parse are kept.
+    pub fn expired(&self, feed: &str, days: i64, present: &[String], now: DateTime<Local>)
+                   -> Vec<(String, String)> {
+        let seen = match self.feeds.get(feed) {
+            Some(seen) => seen,
+            None => return Vec::new(),
+        };
+        let cutoff = (now - Duration::days(days)).timestamp();
+        let mut expired = Vec::new();
+
+        for (key, first_seen) in seen {
+            if present.contains(key) {
+                continue;
+            }
+
+            let at = self.last_seen.get(feed).and_then(|last_seen| last_seen.get(key)).unwrap_or(first_seen);
+            if let Ok(time) = DateTime::parse_from_rfc3339(at) {
+                if time.timestamp() < cutoff {
+                    expired.push((key.clone(), at.clone()));
+                }
+            }
+        }
+
+        expired
+    }
+
+    /// Forget 'keys' of 'feed'.
+    pub fn forget(&mut self, feed: &str, keys: &Vec<String>) {
+        for map in [self.feeds.get_mut(feed), self.last_seen.get_mut(feed)].iter_mut().flatten() {
+            for key in keys {
+                map.remove(key);
+            }
+        }
+    }
+
+    /// Drop what 'expired' finds of each feed with a retention, see
+    /// 'Config::seen_retention_days', 'present' giving the keys of '(feed
+    /// name, items)' still in it.
+    /// Returns how many were dropped by feed.
+    pub fn prune(&mut self, config: &Config, present: &[(String, Vec<String>)], now: DateTime<Local>)
+                 -> Vec<(String, usize)> {
+        let mut pruned = Vec::new();
+
+        for feed in &config.feeds {
+            let days = match config.seen_retention_days(feed) {
+                Some(days) => days,
+                None => continue,
+            };
+            let keys = match present.iter().find(|&(name, _)| *name == feed.name) {
+                Some((_, keys)) => keys,
+                None => continue,
+            };
+
+            let expired: Vec<String> = self.expired(&feed.name, days, keys, now).into_iter().map(|(k, _)| k).collect();
+            self.forget(&feed.name, &expired);
+            pruned.push((feed.name.clone(), expired.len()));
+        }
+
+        pruned
+    }
+
+    /// The threads of 'feed' among '(thread, reply count)' 'counts' whose
+    /// count grew by at least 'threshold' since their mark, as '(thread,
+    /// replies gained, mark time)'. Their mark moves to 'now', so they come
+    /// up again only after another 'threshold' replies. Threads not seen
+    /// before, or whose count went down, are only marked.
+    pub fn heating_up(&mut self, feed: &str, counts: &Vec<(String, u64)>, threshold: u64, now: &str)
+                      -> Vec<(String, u64, String)> {
+        let marks = self.reply_counts.entry(feed.to_string()).or_default();
+        let mut hot = Vec::new();
+
+        for &(ref thread, count) in counts {
+            let mark = ReplyMark {
+                count,
+                at: now.to_string(),
+            };
+            let known = marks.get(thread).cloned();
+            let last = match known {
+                Some(last) => last,
+                None => {
+                    marks.insert(thread.clone(), mark);
+                    continue;
+                }
+            };
+
+            if count < last.count {
+                marks.insert(thread.clone(), mark);
+            } else if count - last.count >= threshold {
+                hot.push((thread.clone(), count - last.count, last.at));
+                marks.insert(thread.clone(), mark);
+            }
+        }
+
+        hot
+    }
+}
+
+/// Fill the seen store with the items of every feed's snapshot, so nothing
+/// already in a snapshot counts as new once the store is used. Running it
+/// again only adds what the snapshots gained meanwhile.
+pub fn migrate_state(config: &Config, paths: &StatePaths) -> Result<()> {
+    let mut store = SeenStore::load(&paths.seen_db)?;
+    let now = Local::now().to_rfc3339();
+
+    for feed in &config.feeds {
+        let snapshot = paths.snapshot(feed);
+        let ctx = match load_snapshot(&snapshot, &config.state_key)
+            .chain_err(|| format!("can't read the snapshot of {}", feed.name))? {
+            Some(ctx) => ctx,
+            None => {
+                println!("{}: no snapshot at {}, nothing to migrate.", feed.name, snapshot.display());
+                continue;
+            }
+        };
+
+        let added = store.record(&feed.name, &ctx.channel.items, &now);
+        println!("{}: {} of {} items added to the seen store.", feed.name, added, ctx.channel.items.len());
+    }
+
+    store.save(&paths.seen_db).chain_err(|| format!("can't write {}", paths.seen_db.display()))?;
+
+    Ok(())
+}
+
+/// Drop the items of the seen store last seen longer ago than the
+/// retention of their feed, or 'older_than' days for every feed when
+/// given, except those still in its snapshot. With 'dry_run' they are
+/// only listed.
+pub fn prune_seen(config: &Config, paths: &StatePaths, older_than: Option<i64>, dry_run: bool) -> Result<()> {
+    let mut store = SeenStore::load(&paths.seen_db)?;
+    let now = Local::now();
+
+    for feed in &config.feeds {
+        let days = match older_than.or(config.seen_retention_days(feed)) {
+            Some(days) => days,
+            None => continue,
+        };
+
+        // a feed without a snapshot yet has no items that must stay.
+        let present: Vec<String> = load_snapshot(&paths.snapshot(feed), &config.state_key)
+            .chain_err(|| format!("can't read the snapshot of {}", feed.name))?
+            .map_or(Vec::new(), |ctx| ctx.channel.items.iter().map(item_key).collect());
+        let expired = store.expired(&feed.name, days, &present, now);
+
+        if dry_run {
+            for (key, at) in &expired {
+                println!("{}: {} (last seen {})", feed.name, key, at);
+            }
+        }
+        println!("{}: {} items last seen over {} days ago {}.", feed.name, expired.len(), days,
+                 if dry_run { "would be dropped" } else { "dropped" });
+
+        let keys: Vec<String> = expired.into_iter().map(|(key, _)| key).collect();
+        store.forget(&feed.name, &keys);
+    }
+
+    if !dry_run {
+        store.save(&paths.seen_db).chain_err(|| format!("can't write {}", paths.seen_db.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+    use rss::Guid;
+
+    fn item(guid: Option<&str>, link: &str, pub_date: Option<&str>) -> Item {
+        Item {
+            link: Some(link.to_string()),
+            guid: guid.map(|g| Guid { value: g.to_string(), ..Guid::default() }),
+            pub_date: pub_date.map(|d| d.to_string()),
+            ..Item::default()
+        }
+    }
+
+    #[test]
+    fn recording_twice_adds_nothing() {
+        let items = vec![item(Some("tag:bbs,1"), "http://bbs/1", Some("Fri, 04 Nov 2016 23:30:00 +0800")),
+                         item(None, "http://bbs/2", None)];
+        let mut store = SeenStore::default();
+
+        assert_eq!(store.record("bbs", &items, "2016-11-05T00:00:00+08:00"), 2);
+        assert_eq!(store.record("bbs", &items, "2016-11-06T00:00:00+08:00"), 0);
+
+        let seen = &store.feeds["bbs"];
+        assert_eq!(seen["tag:bbs,1"], "2016-11-04T23:30:00+08:00");
+        assert_eq!(seen["http://bbs/2"], "2016-11-05T00:00:00+08:00");
+    }
+
+    #[test]
+    fn old_items_are_forgotten_unless_still_in_the_feed() {
+        let now = Local.with_ymd_and_hms(2016, 11, 5, 12, 0, 0).unwrap();
+        let items = vec![item(None, "http://bbs/1", None), item(None, "http://bbs/2", None),
+                         item(None, "http://bbs/3", None)];
+        let mut store = SeenStore::default();
+
+        store.observe("bbs", &items, "2016-06-01T00:00:00+08:00");
+        // the second one is seen again later, the third is still in the feed.
+        store.observe("bbs", &vec![items[1].clone()], "2016-10-01T00:00:00+08:00");
+        let present = vec!["http://bbs/3".to_string()];
+
+        let expired = store.expired("bbs", 90, &present, now);
+        assert_eq!(expired, vec![("http://bbs/1".to_string(), "2016-06-01T00:00:00+08:00".to_string())]);
+
+        store.forget("bbs", &vec!["http://bbs/1".to_string()]);
+        assert_eq!(store.feeds["bbs"].keys().collect::<Vec<_>>(), vec!["http://bbs/2", "http://bbs/3"]);
+        assert!(!store.last_seen["bbs"].contains_key("http://bbs/1"));
+    }
+
+    #[test]
+    fn threads_heat_up_once_per_threshold() {
+        let mut store = SeenStore::default();
+        let at = |hour: u32| format!("2016-11-05T{:02}:00:00+08:00", hour);
+        let counts = |n: u64| vec![("tid=7".to_string(), n)];
+
+        assert!(store.heating_up("bbs", &counts(10), 20, &at(9)).is_empty());
+        assert!(store.heating_up("bbs", &counts(25), 20, &at(10)).is_empty());
+        assert_eq!(store.heating_up("bbs", &counts(33), 20, &at(11)), vec![("tid=7".to_string(), 23, at(9))]);
+        // the next 20 replies count from 33.
+        assert!(store.heating_up("bbs", &counts(50), 20, &at(12)).is_empty());
+        assert_eq!(store.heating_up("bbs", &counts(53), 20, &at(13)), vec![("tid=7".to_string(), 20, at(11))]);
+        // a thread that lost replies starts over.
+        assert!(store.heating_up("bbs", &counts(5), 20, &at(14)).is_empty());
+        assert_eq!(store.reply_counts["bbs"]["tid=7"], ReplyMark { count: 5, at: at(14) });
+    }
+}
@@ -0,0 +1,469 @@
+//! The HTTP listener of 'bbsmon daemon'. "POST /trigger" runs every feed
+//! right away instead of at the next 'interval_secs', "POST
+//! /trigger/<feed>" only that one; both answer with what the run did.
+//! "GET /health", on the same port and without a token, answers while the
+//! daemon runs, with when it last ran and which feeds fail.
+
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use serde_json;
+
+use crate::error::*;
+use crate::log::Record;
+use crate::status::FeedStatus;
+use crate::status::RunStatus;
+
+/// Header carrying the 'trigger_token'.
+pub const TOKEN_HEADER: &str = "x-bbsmon-token";
+
+/// Most requests waiting for a run at once; further ones are turned away
+/// with a 503 until the run answered them.
+pub const MAX_WAITING: usize = 64;
+
+/// Longest request line and headers read.
+pub const MAX_HEAD_BYTES: u64 = 8192;
+
+/// Longest request body read, and thrown away.
+pub const MAX_BODY_BYTES: u64 = 65536;
+
+/// How long a client may take to send its request.
+pub const CLIENT_TIMEOUT_SECS: u64 = 10;
+
+/// What a trigger asks to run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// Every feed.
+    All,
+    /// The feed of that name.
+    Feed(String),
+}
+
+/// Triggers not run yet. However many arrive while a cycle runs, the next
+/// cycle runs each feed they name once and answers all of them.
+#[derive(Default)]
+pub struct Pending {
+    /// Run every feed.
+    pub all: bool,
+    /// Run these feeds, when not 'all'.
+    pub feeds: BTreeSet<String>,
+    waiting: Vec<Sender<(u16, String)>>,
+}
+
+impl Pending {
+    /// Whether nothing was asked for.
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.feeds.is_empty()
+    }
+
+    /// Hand response 'body' with status 'code' to every request waiting.
+    pub fn answer(&mut self, code: u16, body: &str) {
+        for waiting in self.waiting.drain(..) {
+            let _ = waiting.send((code, body.to_string()));
+        }
+    }
+}
+
+/// Where the listener leaves triggers for the daemon loop.
+#[derive(Default)]
+pub struct Triggers {
+    pending: Mutex<Pending>,
+    wake: Condvar,
+}
+
+impl Triggers {
+    /// No triggers yet.
+    pub fn new() -> Triggers {
+        Triggers::default()
+    }
+
+    /// Ask for 'target' to run. The response arrives on the receiver once
+    /// it did; None when 'MAX_WAITING' requests wait already.
+    pub fn request(&self, target: Target) -> Option<Receiver<(u16, String)>> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.waiting.len() >= MAX_WAITING {
+            return None;
+        }
+
+        match target {
+            Target::All => pending.all = true,
+            Target::Feed(feed) => {
+                pending.feeds.insert(feed);
+            }
+        }
+        let (tx, rx) = mpsc::channel();
+        pending.waiting.push(tx);
+        self.wake.notify_one();
+
+        Some(rx)
+    }
+
+    /// The triggers that arrived, waiting for one until 'until' when there
+    /// are none; empty when none came.
+    pub fn wait(&self, until: Instant) -> Pending {
+        let mut pending = self.pending.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            if !pending.is_empty() || now >= until {
+                break;
+            }
+            pending = self.wake.wait_timeout(pending, until - now).unwrap().0;
+        }
+
+        std::mem::take(&mut *pending)
+    }
+}
+
+/// What a trigger is answered with.
+#[derive(Serialize, Debug)]
+pub struct TriggerSummary {
+    /// When the run finished.
+    pub finished: Option<String>,
+    /// Status of each feed that ran, by name.
+    pub feeds: BTreeMap<String, FeedStatus>,
+    /// Why the run failed, when it did.
+    pub error: Option<String>,
+}
+
+/// Status code and JSON body answering the triggers of a run of 'feeds',
+/// which ended as 'status' says and failed with 'error' if any.
+pub fn summary(status: &RunStatus, feeds: &[String], error: Option<String>) -> (u16, String) {
+    let summary = TriggerSummary {
+        finished: status.last_run.clone(),
+        feeds: feeds.iter().filter_map(|f| status.feeds.get(f).map(|s| (f.clone(), s.clone()))).collect(),
+        error,
+    };
+    let code = if summary.error.is_some() { 500 } else { 200 };
+    let body = serde_json::to_string_pretty(&summary).unwrap_or_else(|_| "{}".to_string());
+
+    (code, body)
+}
+
+/// What "GET /health" is answered with.
+#[derive(Serialize, Debug)]
+pub struct Health {
+    /// When the last run finished.
+    pub last_run: Option<String>,
+    /// When the next scheduled run starts.
+    pub next_run: Option<String>,
+    /// Feeds whose last check failed.
+    pub failing: Vec<String>,
+}
+
+/// Status code and JSON body answering a health check, from the run
+/// status stored at 'status'.
+pub fn health(status: &Path) -> (u16, String) {
+    let status = match RunStatus::load(status) {
+        Ok(status) => status.unwrap_or_default(),
+        Err(e) => return (500, refusal(&format!("can't read the run status: {}", error_chain_string(&e)))),
+    };
+    let health = Health {
+        last_run: status.last_run,
+        next_run: status.next_run,
+        failing: status.feeds.iter()
+            .filter(|&(_, s)| s.last_result.as_ref().is_some_and(|r| r.starts_with("error")))
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+
+    (200, serde_json::to_string_pretty(&health).unwrap_or_else(|_| "{}".to_string()))
+}
+
+/// The head of a request.
+#[derive(Debug)]
+pub struct Request {
+    /// "POST", "GET", ...
+    pub method: String,
+    /// The path, query included.
+    pub path: String,
+    /// Header values by name, lowercased.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// The head of the request 'reader' starts with, no more than
+/// 'MAX_HEAD_BYTES' of it.
+pub fn read_request<R: BufRead>(reader: R) -> Result<Request> {
+    let mut lines = reader.take(MAX_HEAD_BYTES).lines();
+
+    let first = match lines.next() {
+        Some(line) => line?,
+        None => bail!("empty request"),
+    };
+    let mut parts = first.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => bail!("bad request line '{}'", first),
+    };
+
+    let mut headers = BTreeMap::new();
+    loop {
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => bail!("request head longer than {} bytes", MAX_HEAD_BYTES),
+        };
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            headers.insert(line[..colon].trim().to_lowercase(), line[colon + 1..].trim().to_string());
+        }
+    }
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+    })
+}
+
+/// 'text' with its %XX escapes decoded.
+pub fn percent_decode(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let digit = |at: usize| bytes.get(at).and_then(|b| (*b as char).to_digit(16));
+        match (digit(i + 1), digit(i + 2)) {
+            (Some(high), Some(low)) => decoded.push((high * 16 + low) as u8),
+            _ => bail!("bad escape in '{}'", text),
+        }
+        i += 3;
+    }
+
+    String::from_utf8(decoded).chain_err(|| format!("'{}' isn't UTF-8", text))
+}
+
+// compares in the same time wherever the tokens differ.
+fn same_token(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// What becomes of a request.
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    /// Run this.
+    Run(Target),
+    /// Answer with 'health'.
+    Health,
+    /// Answer with this status code and message.
+    Refuse(u16, &'static str),
+}
+
+/// Where 'request' goes, given the 'token' it must carry and the names of
+/// the configured 'feeds'. Health checks need no token.
+pub fn route(request: &Request, token: &str, feeds: &[String]) -> Route {
+    let path = request.path.split('?').next().unwrap_or("");
+    if path == "/health" {
+        if request.method != "GET" {
+            return Route::Refuse(405, "only GET checks health");
+        }
+        return Route::Health;
+    }
+
+    if !request.headers.get(TOKEN_HEADER).is_some_and(|t| same_token(t, token)) {
+        return Route::Refuse(401, "missing or wrong token");
+    }
+
+    if path != "/trigger" && !path.starts_with("/trigger/") {
+        return Route::Refuse(404, "no such endpoint");
+    }
+    if request.method != "POST" {
+        return Route::Refuse(405, "only POST triggers a run");
+    }
+
+    if path == "/trigger" {
+        return Route::Run(Target::All);
+    }
+    match percent_decode(&path["/trigger/".len()..]) {
+        Ok(ref feed) if feeds.contains(feed) => Route::Run(Target::Feed(feed.clone())),
+        Ok(_) => Route::Refuse(404, "no such feed"),
+        Err(_) => Route::Refuse(400, "bad feed name"),
+    }
+}
+
+fn reason(code: u16) -> &'static str {
+    match code {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+fn respond(stream: &mut TcpStream, code: u16, body: &str) -> io::Result<()> {
+    respond_with(stream, code, "application/json", body)
+}
+
+/// Answer with 'code' and 'body' of 'content_type', and close.
+pub fn respond_with(stream: &mut TcpStream, code: u16, content_type: &str, body: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}", code, reason(code), content_type, body.len(), body)?;
+    stream.flush()
+}
+
+fn refusal(message: &str) -> String {
+    let mut body = BTreeMap::new();
+    body.insert("error", message);
+    serde_json::to_string(&body).unwrap_or_else(|_| "{}".to_string())
+}
+
+// one connection: read the request, wait for its run and answer.
+fn handle(mut stream: TcpStream, token: &str, feeds: &[String], status: &Path, triggers: &Triggers) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(CLIENT_TIMEOUT_SECS)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(e) => {
+            respond(&mut stream, 400, &refusal("bad request"))?;
+            return Err(e);
+        }
+    };
+    let length = request.headers.get("content-length").and_then(|l| l.parse::<u64>().ok()).unwrap_or(0);
+    io::copy(&mut reader.take(length.min(MAX_BODY_BYTES)), &mut io::sink())?;
+
+    let target = match route(&request, token, feeds) {
+        Route::Run(target) => target,
+        Route::Health => {
+            let (code, body) = health(status);
+            respond(&mut stream, code, &body)?;
+            return Ok(());
+        }
+        Route::Refuse(code, message) => {
+            Record::warn("trigger refused").field("path", &request.path).field("status", code)
+                .log(format!("trigger {} refused: {}.", request.path, message));
+            respond(&mut stream, code, &refusal(message))?;
+            return Ok(());
+        }
+    };
+
+    Record::info("triggered").field("path", &request.path).log(format!("triggered by {}.", request.path));
+    let (code, body) = match triggers.request(target) {
+        Some(answer) => answer.recv().unwrap_or((500, refusal("the daemon stopped"))),
+        None => (503, refusal("too many triggers waiting")),
+    };
+    respond(&mut stream, code, &body)?;
+
+    Ok(())
+}
+
+/// Listen on 'address' and leave every request to trigger a run of
+/// 'feeds' that carries 'token' in 'triggers'; health checks are answered
+/// from the run status stored at 'status'. Fails when the address can't be
+/// bound; connections are served on threads of their own.
+pub fn listen(address: &str, token: String, feeds: Vec<String>, status: PathBuf, triggers: Arc<Triggers>)
+              -> Result<()> {
+    let listener = TcpListener::bind(address).chain_err(|| format!("can't listen on {}", address))?;
+
+    thread::spawn(move || {
+        let token = Arc::new(token);
+        let feeds = Arc::new(feeds);
+        let status = Arc::new(status);
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let (token, feeds, status, triggers) = (token.clone(), feeds.clone(), status.clone(), triggers.clone());
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &token, &feeds, &status, &triggers) {
+                    Record::warn("trigger request failed").field("error", error_chain_string(&e))
+                        .log(format!("trigger request failed: {}", error_chain_string(&e)));
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(head: &str) -> Request {
+        read_request(head.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn triggers_are_routed_by_path_and_token() {
+        let feeds = vec!["二手".to_string(), "kernel".to_string()];
+        let post = |path: &str, token: &str| {
+            route(&request(&format!("POST {} HTTP/1.1\r\nHost: localhost\r\nX-Bbsmon-Token: {}\r\n\r\n",
+                                    path, token)), "s3cret", &feeds)
+        };
+
+        assert_eq!(post("/trigger", "s3cret"), Route::Run(Target::All));
+        assert_eq!(post("/trigger/kernel", "s3cret"), Route::Run(Target::Feed("kernel".to_string())));
+        assert_eq!(post("/trigger/%E4%BA%8C%E6%89%8B", "s3cret"), Route::Run(Target::Feed("二手".to_string())));
+        assert_eq!(post("/trigger/gone", "s3cret"), Route::Refuse(404, "no such feed"));
+        assert_eq!(post("/trigger", "guess"), Route::Refuse(401, "missing or wrong token"));
+        assert_eq!(post("/metrics", "s3cret"), Route::Refuse(404, "no such endpoint"));
+
+        let get = request("GET /trigger HTTP/1.1\r\nx-bbsmon-token: s3cret\r\n\r\n");
+        assert_eq!(route(&get, "s3cret", &feeds), Route::Refuse(405, "only POST triggers a run"));
+        assert_eq!(route(&request("GET /health HTTP/1.1\r\n\r\n"), "s3cret", &feeds), Route::Health);
+        assert_eq!(post("/health", ""), Route::Refuse(405, "only GET checks health"));
+        assert!(read_request("POST /trigger HTTP/1.1\r\nHost: localhost\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn health_is_answered_before_the_first_run() {
+        let (code, body) = health(Path::new("no/such/status.json"));
+        assert_eq!(code, 200);
+        let health: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(health["last_run"], serde_json::Value::Null);
+        assert!(health["failing"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn triggers_during_a_cycle_coalesce() {
+        let triggers = Triggers::new();
+        let first = triggers.request(Target::Feed("kernel".to_string())).unwrap();
+        let second = triggers.request(Target::Feed("kernel".to_string())).unwrap();
+        let _third = triggers.request(Target::Feed("二手".to_string())).unwrap();
+
+        let mut pending = triggers.wait(Instant::now());
+        assert!(!pending.all);
+        assert_eq!(pending.feeds.len(), 2);
+        pending.answer(200, "{}");
+        assert_eq!(first.recv().unwrap(), (200, "{}".to_string()));
+        assert_eq!(second.recv().unwrap(), (200, "{}".to_string()));
+
+        assert!(triggers.wait(Instant::now()).is_empty());
+
+        for _ in 0..MAX_WAITING {
+            triggers.request(Target::All).unwrap();
+        }
+        assert!(triggers.request(Target::All).is_none());
+    }
+}
@@ -0,0 +1,532 @@
+//! Where feeds come from: web servers, files and commands.
+
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::Client;
+use reqwest::Response;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::COOKIE;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderValue;
+use reqwest::header::IF_MODIFIED_SINCE;
+use reqwest::header::IF_NONE_MATCH;
+
+use crate::error::*;
+use crate::config::FeedConfig;
+use crate::feed::Validators;
+use crate::log::Record;
+use crate::login::Login;
+use crate::scrape::ScrapeSource;
+use crate::retry::RetryPolicy;
+use crate::retry::is_transient;
+use crate::runtime::BoxFuture;
+use crate::runtime::blocking;
+
+/// What a feed's 'type' may be.
+pub const SOURCE_TYPES: &[&str] = &["http", "file", "command", "scrape"];
+
+/// A fetched document, before it is parsed.
+pub struct RawFeed {
+    /// The document.
+    pub body: Vec<u8>,
+    /// Validators to send along with the next fetch, empty when the source
+    /// has none.
+    pub validators: Validators,
+    /// Content-Type the document was served with, None when the source
+    /// doesn't say.
+    pub content_type: Option<String>,
+}
+
+/// Where the document of a feed comes from. 'feed_source' builds the one a
+/// feed is configured with; library users may bring their own to
+/// 'fetch_diff_items'. Fetches run on the runtime of the run, see
+/// 'runtime'; a source that has to block does so in 'runtime::blocking'.
+pub trait FeedSource: Send + Sync {
+    /// Where the document comes from, for messages.
+    fn describe(&self) -> String;
+
+    /// The current document. Given the 'validators' of the last fetch, a
+    /// source may answer None for a document that didn't change.
+    fn fetch<'a>(&'a self, client: &'a Client, validators: Option<&'a Validators>)
+                 -> BoxFuture<'a, Option<RawFeed>>;
+
+    /// Most pages of the feed read by following their "next" links, the
+    /// document 'fetch' answers included; see 'FeedConfig::follow_next'.
+    fn max_pages(&self) -> u32 {
+        1
+    }
+
+    /// The page at 'url', a "next" link of an earlier page. Only called
+    /// on sources with more than one of 'max_pages'.
+    fn fetch_page<'a>(&'a self, _client: &'a Client, url: &'a str) -> BoxFuture<'a, RawFeed> {
+        let e = format!("{} can't fetch the page {}", self.describe(), url);
+        Box::pin(async move { Err(e.into()) })
+    }
+}
+
+/// How long a fetch may take, see 'Config::timeouts'. HTTP feeds are
+/// fetched with the client 'client' builds; None connects as long as it
+/// takes and reads for 'DEFAULT_READ_TIMEOUT'.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timeouts {
+    /// To connect to the server.
+    pub connect: Option<Duration>,
+    /// For the server to answer the request, and then for each read of
+    /// the response body; for a command feed, to print the whole feed.
+    pub read: Option<Duration>,
+}
+
+/// How long an HTTP response may stall without a 'read' timeout, as long
+/// as the blocking client bbsmon fetched with used to wait.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Timeouts {
+    /// A client connecting within 'connect' and failing a response that
+    /// stalls for 'read'.
+    pub fn client(&self) -> Result<Client> {
+        let mut builder = Client::builder().read_timeout(self.read.unwrap_or(DEFAULT_READ_TIMEOUT));
+        if let Some(connect) = self.connect {
+            builder = builder.connect_timeout(connect);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// A feed served over HTTP, fetched conditionally.
+pub struct HttpSource {
+    /// Where the feed is fetched from.
+    pub url: String,
+    /// Ask with a HEAD request first, see 'FeedConfig::precheck_head'.
+    pub precheck_head: bool,
+    /// How a failed GET is retried.
+    pub retry: RetryPolicy,
+    /// Longest response read, see 'Config::max_feed_bytes'.
+    pub max_bytes: u64,
+    /// Most pages read, 1 when next links aren't followed.
+    pub max_pages: u32,
+    /// Login giving the session cookie the feed needs.
+    pub login: Option<Login>,
+}
+
+// all of 'reader', but no more than 'max_bytes'; 'what' is read, for errors.
+fn read_limited<R: Read>(reader: R, max_bytes: u64, what: &str) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    reader.take(max_bytes + 1).read_to_end(&mut body).chain_err(|| format!("can't read {}", what))?;
+
+    if body.len() as u64 > max_bytes {
+        bail!("{} is larger than {} bytes, see max_feed_bytes", what, max_bytes);
+    }
+
+    Ok(body)
+}
+
+// the body of 'resp', but no more than 'max_bytes'; 'what' is read, for
+// errors. the rest isn't downloaded.
+async fn read_body(mut resp: Response, max_bytes: u64, what: &str) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await.chain_err(|| format!("can't read {}", what))? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > max_bytes {
+            bail!("{} is larger than {} bytes, see max_feed_bytes", what, max_bytes);
+        }
+    }
+
+    Ok(body)
+}
+
+/// 'f' run on a thread of its own, failing with "no 'what' within" once
+/// 'timeout' is up. The thread is left behind then, whatever it still
+/// holds is dropped once it returns. Without a timeout 'f' runs right here.
+fn within<T, F>(timeout: Option<Duration>, what: &str, f: F) -> Result<T>
+    where T: Send + 'static, F: FnOnce() -> Result<T> + Send + 'static
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return f(),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut,
+                                     format!("no {} within {} s", what, timeout.as_secs())).into()),
+    }
+}
+
+impl HttpSource {
+    // the document at 'url', None when unchanged, and whether the server
+    // sent its login page instead.
+    async fn get(&self, url: &str, client: &Client, validators: Option<&Validators>, cookie: Option<&str>)
+                 -> Result<(Option<RawFeed>, bool)> {
+        let mut headers = HeaderMap::new();
+        if let Some(cookie) = cookie {
+            headers.insert(COOKIE, HeaderValue::from_str(cookie).chain_err(|| "bad session cookie")?);
+        }
+        if let Some(validators) = validators {
+            // validators a server sent but that can't be sent back are left out.
+            if let Some(etag) = validators.etag.as_ref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = validators.last_modified.as_ref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        // with a login, being turned away means the session expired.
+        let refused = |code: u16| self.login.is_some() && (code == 401 || code == 403);
+
+        let resp = self.retry.run_async(is_transient, || async {
+            let resp = client.get(url).headers(headers.clone()).send().await?;
+            let code = resp.status().as_u16();
+            if code != 304 && !resp.status().is_success() && !refused(code) {
+                bail!(ErrorKind::HttpStatus(url.to_string(), code));
+            }
+            Ok(resp)
+        }).await.chain_err(|| format!("can't fetch {}", url))?;
+
+        match resp.status().as_u16() {
+            304 => return Ok((None, false)),
+            code if refused(code) => return Ok((None, true)),
+            _ => {}
+        }
+
+        let validators = Validators::from_headers(resp.headers());
+        let content_type = resp.headers().get(CONTENT_TYPE).and_then(|ct| ct.to_str().ok()).map(|ct| ct.to_string());
+        let body = read_body(resp, self.max_bytes, url).await.chain_err(|| format!("can't fetch {}", url))?;
+        let login_page = self.login.as_ref().is_some_and(|login| login.is_login_page(&body));
+
+        Ok((Some(RawFeed {
+            body,
+            validators,
+            content_type,
+        }), login_page))
+    }
+
+    // the feed, see 'FeedSource::fetch'.
+    async fn document(&self, client: &Client, validators: Option<&Validators>) -> Result<Option<RawFeed>> {
+        let login = match self.login {
+            Some(ref login) => login,
+            None => {
+                if let (true, Some(stored)) = (self.precheck_head, validators) {
+                    // a failed HEAD falls back to the GET.
+                    let head = Validators::head(client, &self.url).await;
+                    if head.map(|v| v.same_document(stored)).unwrap_or(false) {
+                        return Ok(None);
+                    }
+                }
+                return Ok(self.get(&self.url, client, validators, None).await?.0);
+            }
+        };
+
+        let cookie = login.cookie(false).await?;
+        let (raw, login_page) = self.get(&self.url, client, validators, Some(&cookie)).await?;
+        if !login_page {
+            return Ok(raw);
+        }
+
+        Record::info("session expired, logging in again").field("url", &self.url)
+            .log(format!("{}: session expired, logging in again.", self.url));
+        let cookie = login.cookie(true).await?;
+        let (raw, login_page) = self.get(&self.url, client, validators, Some(&cookie)).await?;
+        if login_page {
+            bail!(ErrorKind::LoginFailed(login.config.url.clone(),
+                                         format!("{} still answers with the login page", self.url)));
+        }
+
+        Ok(raw)
+    }
+
+    // the page at 'url', see 'FeedSource::fetch_page'.
+    async fn page(&self, client: &Client, url: &str) -> Result<RawFeed> {
+        let cookie = match self.login {
+            Some(ref login) => Some(login.cookie(false).await?),
+            None => None,
+        };
+
+        let (raw, login_page) = self.get(url, client, None, cookie.as_deref()).await?;
+        if login_page {
+            bail!("{} answers with the login page", url);
+        }
+        Ok(raw.expect("an unconditional fetch answers"))
+    }
+}
+
+impl FeedSource for HttpSource {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    fn fetch<'a>(&'a self, client: &'a Client, validators: Option<&'a Validators>)
+                 -> BoxFuture<'a, Option<RawFeed>> {
+        Box::pin(self.document(client, validators))
+    }
+
+    fn max_pages(&self) -> u32 {
+        self.max_pages
+    }
+
+    fn fetch_page<'a>(&'a self, client: &'a Client, url: &'a str) -> BoxFuture<'a, RawFeed> {
+        Box::pin(self.page(client, url))
+    }
+}
+
+/// A feed kept in a file, or read from stdin when 'path' is "-".
+#[derive(Clone)]
+pub struct FileSource {
+    /// The file.
+    pub path: String,
+    /// Longest file read.
+    pub max_bytes: u64,
+}
+
+impl FileSource {
+    // the feed, read on the thread at hand.
+    fn read(&self) -> Result<Option<RawFeed>> {
+        let body = if self.path == "-" {
+            read_limited(io::stdin(), self.max_bytes, "stdin")?
+        } else {
+            let file = File::open(&self.path).chain_err(|| format!("can't read {}", self.path))?;
+            read_limited(file, self.max_bytes, &self.path)?
+        };
+
+        Ok(Some(RawFeed {
+            body,
+            validators: Validators::default(),
+            content_type: None,
+        }))
+    }
+}
+
+impl FeedSource for FileSource {
+    fn describe(&self) -> String {
+        if self.path == "-" { "stdin".to_string() } else { self.path.clone() }
+    }
+
+    fn fetch<'a>(&'a self, _client: &'a Client, _validators: Option<&'a Validators>)
+                 -> BoxFuture<'a, Option<RawFeed>> {
+        let source = self.clone();
+        blocking(move || source.read())
+    }
+}
+
+/// A feed printed by a shell command.
+#[derive(Clone)]
+pub struct CommandSource {
+    /// The command, run with "sh -c", or "cmd /C" on windows.
+    pub command: String,
+    /// Longest output read; the command is killed past it.
+    pub max_bytes: u64,
+    /// How long the command may take to print the feed; it is killed
+    /// once this is up. None waits for it as long as it takes.
+    pub timeout: Option<Duration>,
+}
+
+/// Most of what a command prints to stderr kept for the error message.
+pub const MAX_STDERR_BYTES: u64 = 64 * 1024;
+
+// the start of everything read from 'reader', the rest read and dropped.
+fn read_start<R: Read>(mut reader: R, max_bytes: u64) -> Vec<u8> {
+    let mut start = Vec::new();
+    let _ = (&mut reader).take(max_bytes).read_to_end(&mut start);
+    let _ = io::copy(&mut reader, &mut io::sink());
+    start
+}
+
+/// 'command' run with "sh -c", or "cmd /C" on windows.
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    return shell;
+}
+
+/// 'command' run with "sh -c", or "cmd /C" on windows.
+#[cfg(not(windows))]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+impl CommandSource {
+    // the feed, the command run and waited for on the thread at hand.
+    fn run(&self) -> Result<Option<RawFeed>> {
+        let mut child = shell(&self.command).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+            .chain_err(|| format!("can't run '{}'", self.command))?;
+
+        // stderr is read on a thread of its own, a command filling its pipe
+        // would otherwise stall while stdout is read. stdout is read under
+        // 'within', so a command running past 'timeout' can be killed.
+        let stderr = child.stderr.take().expect("stderr is piped");
+        let stderr = thread::spawn(move || read_start(stderr, MAX_STDERR_BYTES));
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (max_bytes, what) = (self.max_bytes, self.describe());
+        let read = within(self.timeout, &format!("feed from {}", what),
+                          move || read_limited(stdout, max_bytes, &what));
+        let body = match read {
+            Ok(body) => body,
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e);
+            }
+        };
+
+        let status = child.wait().chain_err(|| format!("can't run '{}'", self.command))?;
+        if !status.success() {
+            let stderr = stderr.join().unwrap_or(Vec::new());
+            bail!("'{}' failed with {}: {}", self.command, status, String::from_utf8_lossy(&stderr).trim());
+        }
+
+        Ok(Some(RawFeed {
+            body,
+            validators: Validators::default(),
+            content_type: None,
+        }))
+    }
+}
+
+impl FeedSource for CommandSource {
+    fn describe(&self) -> String {
+        format!("'{}'", self.command)
+    }
+
+    fn fetch<'a>(&'a self, _client: &'a Client, _validators: Option<&'a Validators>)
+                 -> BoxFuture<'a, Option<RawFeed>> {
+        let source = self.clone();
+        blocking(move || source.run())
+    }
+}
+
+/// The 'type' of 'feed': as configured, else "http" for http and https
+/// URLs and "file" for anything else.
+pub fn source_type(feed: &FeedConfig) -> &str {
+    if let Some(ref source_type) = feed.source_type {
+        return source_type;
+    }
+
+    let remote = &feed.remote_rss;
+    if remote.starts_with("http://") || remote.starts_with("https://") {
+        return "http";
+    }
+
+    "file"
+}
+
+/// 'remote_rss' of 'feed' fetched over http, whatever its 'source_type'.
+pub fn http_source(feed: &FeedConfig, retry: &RetryPolicy, max_bytes: u64, session: Option<PathBuf>) -> HttpSource {
+    HttpSource {
+        url: feed.remote_rss.clone(),
+        precheck_head: feed.precheck_head,
+        retry: retry.clone(),
+        max_bytes,
+        max_pages: if feed.follow_next { feed.max_pages } else { 1 },
+        login: feed.login.clone().map(|login| Login::new(login, session)),
+    }
+}
+
+/// The source 'feed' is fetched from, its 'remote_rss' read as 'source_type'
+/// says. HTTP fetches are retried as 'retry' says and limited by the
+/// client they are given, see 'Timeouts::client'; a command by the 'read'
+/// of 'timeouts'. No source reads more than 'max_bytes'. The session of a
+/// feed with a 'login' is kept at 'session' for its 'ttl_secs', see
+/// 'StatePaths::session'.
+pub fn feed_source(feed: &FeedConfig, retry: &RetryPolicy, max_bytes: u64, timeouts: Timeouts,
+                   session: Option<PathBuf>) -> Result<Box<dyn FeedSource>> {
+    let remote = feed.remote_rss.clone();
+
+    let source: Box<dyn FeedSource> = match source_type(feed) {
+        "http" => Box::new(http_source(feed, retry, max_bytes, session)),
+        "scrape" => Box::new(ScrapeSource::new(feed, http_source(feed, retry, max_bytes, session))?),
+        "file" => Box::new(FileSource {
+            path: remote.trim_start_matches("file://").to_string(),
+            max_bytes,
+        }),
+        "command" => Box::new(CommandSource {
+            command: remote,
+            max_bytes,
+            timeout: timeouts.read,
+        }),
+        other => bail!("unknown type '{}' of feed '{}', expected one of {:?}", other, feed.name, SOURCE_TYPES),
+    };
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    fn feed(json: &str) -> FeedConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn type_is_inferred_from_the_url() {
+        assert_eq!(source_type(&feed(r#"{"name": "a", "remote_rss": "https://bbs/rss.xml"}"#)), "http");
+        assert_eq!(source_type(&feed(r#"{"name": "a", "remote_rss": "file:///srv/rss.xml"}"#)), "file");
+        assert_eq!(source_type(&feed(r#"{"name": "a", "remote_rss": "-"}"#)), "file");
+        assert_eq!(source_type(&feed(r#"{"name": "a", "remote_rss": "dump-rss", "type": "command"}"#)),
+                   "command");
+
+        let (retry, timeouts) = (RetryPolicy::default(), Timeouts::default());
+        assert_eq!(feed_source(&feed(r#"{"name": "a", "remote_rss": "file:///srv/rss.xml"}"#), &retry, 100,
+                               timeouts, None).unwrap().describe(), "/srv/rss.xml");
+        assert!(feed_source(&feed(r#"{"name": "a", "remote_rss": "x", "type": "ftp"}"#), &retry, 100, timeouts, None)
+                .is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn commands_print_the_feed() {
+        let command = |command: &str, timeout: Option<u64>| {
+            CommandSource { command: command.to_string(), max_bytes: 100, timeout: timeout.map(Duration::from_secs) }
+        };
+
+        let source = command("printf '<rss/>'", None);
+        assert_eq!(source.run().unwrap().unwrap().body, b"<rss/>".to_vec());
+
+        let failing = command("echo gone >&2; exit 3", None);
+        let e = failing.run().err().unwrap();
+        assert!(e.to_string().ends_with(": gone"), "{}", e);
+
+        let endless = command("yes '<rss/>'", None);
+        let e = endless.run().err().unwrap();
+        assert!(e.to_string().contains("larger than 100 bytes"), "{}", e);
+
+        // more than a pipe holds on stderr before the feed.
+        let chatty = command("yes warning | head -c 200000 >&2; printf '<rss/>'", Some(10));
+        assert_eq!(chatty.run().unwrap().unwrap().body, b"<rss/>".to_vec());
+
+        let stuck = command("exec sleep 30", Some(1));
+        let e = stuck.run().err().unwrap();
+        assert!(e.to_string().contains("within 1 s"), "{}", e);
+    }
+
+    #[test]
+    fn work_past_its_timeout_fails() {
+        assert_eq!(within(Some(Duration::from_secs(60)), "answer", || Ok(7)).unwrap(), 7);
+        assert_eq!(within(None, "answer", || Ok(7)).unwrap(), 7);
+
+        let late = within(Some(Duration::from_millis(10)), "answer", || {
+            thread::sleep(Duration::from_secs(5));
+            Ok(())
+        });
+        assert!(late.err().unwrap().to_string().contains("no answer within"));
+    }
+}
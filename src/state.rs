@@ -0,0 +1,809 @@
+//! Where state is kept on disk, and how it is written and read back.
+
+use std::io::Read;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::DirBuilder;
+use std::path::Path;
+use std::path::PathBuf;
+use std::collections::HashSet;
+
+use chrono::Local;
+
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+
+use fs2::FileExt;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use serde_json;
+
+use crate::error::*;
+use crate::log::Record;
+use crate::config::Config;
+use crate::config::FeedConfig;
+use crate::feed::RssContext;
+
+/// Feed names are free text, keep only what is safe in a file name.
+pub fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// 'over' when it is set, else 'default'.
+pub fn path_or(over: &Option<String>, default: PathBuf) -> PathBuf {
+    match *over {
+        Some(ref path) => PathBuf::from(path),
+        None => default,
+    }
+}
+
+/// Resolved location of every on-disk artifact.
+#[derive(Debug)]
+pub struct StatePaths {
+    /// The state directory itself.
+    pub dir: PathBuf,
+    /// The seen store.
+    pub seen_db: PathBuf,
+    /// Directory of notifications that could not be delivered.
+    pub spool: PathBuf,
+    /// What 'bbsmon status' reports.
+    pub status: PathBuf,
+    /// What 'lock' holds while a process works on the state.
+    pub lock: PathBuf,
+    /// When the last error report went out.
+    pub alerts: PathBuf,
+    /// Per feed throttle windows.
+    pub throttle: PathBuf,
+    /// What the last run that notified anything notified, for 'bbsmon
+    /// replay --last'.
+    pub last_run: PathBuf,
+    /// Every item sent so far, see 'notification_log'.
+    pub notification_log: PathBuf,
+    /// Whether snapshots are gzipped, see 'compress_state'.
+    pub compress: bool,
+}
+
+impl StatePaths {
+    /// Apply the overrides of 'c' to the default layout under 'state_dir'.
+    pub fn resolve(c: &Config) -> StatePaths {
+        let dir = PathBuf::from(&c.state_dir);
+
+        StatePaths {
+            seen_db: path_or(&c.seen_db, dir.join("seen.db")),
+            spool: path_or(&c.spool_dir, dir.join("spool")),
+            status: path_or(&c.status_file, dir.join("status.json")),
+            lock: path_or(&c.lock_file, dir.join("lock")),
+            alerts: dir.join("alerts.json"),
+            throttle: dir.join("throttle.json"),
+            last_run: dir.join("last_run.jsonl"),
+            notification_log: dir.join("notified.jsonl"),
+            compress: c.compress_state,
+            dir,
+        }
+    }
+
+    /// The last fetched copy of 'feed', with ".gz" appended when snapshots
+    /// are compressed.
+    pub fn snapshot(&self, feed: &FeedConfig) -> PathBuf {
+        let default = self.dir.join("snapshots").join(format!("{}.xml", safe_file_name(&feed.name)));
+        let snapshot = path_or(&feed.local_rss, default);
+
+        if self.compress && !is_gz(&snapshot) {
+            return gz_sibling(&snapshot);
+        }
+        snapshot
+    }
+
+    /// Changes of 'feed' held back across runs.
+    pub fn pending(&self, feed: &FeedConfig) -> PathBuf {
+        self.dir.join("pending").join(format!("{}.json", safe_file_name(&feed.name)))
+    }
+
+    /// Sends of 'feed' not yet followed by a snapshot, see 'SendJournal'.
+    pub fn journal(&self, feed: &FeedConfig) -> PathBuf {
+        self.dir.join("journal").join(format!("{}.json", safe_file_name(&feed.name)))
+    }
+
+    /// The session cookies the login of 'feed' got, kept for 'ttl_secs'.
+    pub fn session(&self, feed: &FeedConfig) -> PathBuf {
+        self.dir.join("sessions").join(format!("{}.json", safe_file_name(&feed.name)))
+    }
+
+    /// HTTP validators of 'feed'.
+    pub fn validators(&self, feed: &FeedConfig) -> PathBuf {
+        let default = self.dir.join("snapshots").join(format!("{}.http.json", safe_file_name(&feed.name)));
+        path_or(&feed.validators, default)
+    }
+
+    /// Create the state directory and the directories artifacts live in.
+    pub fn create_dirs(&self, feeds: &Vec<FeedConfig>) -> Result<()> {
+        create_private_dir(&self.dir)?;
+        create_private_dir(&self.spool)?;
+
+        let mut files = vec![self.seen_db.clone(), self.status.clone(), self.lock.clone()];
+        for feed in feeds {
+            files.push(self.snapshot(feed));
+            files.push(self.validators(feed));
+            files.push(self.pending(feed));
+            files.push(self.journal(feed));
+            if feed.login.is_some() {
+                files.push(self.session(feed));
+            }
+        }
+
+        for file in &files {
+            if let Some(parent) = file.parent() {
+                if parent != Path::new("") {
+                    create_private_dir(parent)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// (label, path) of every artifact, per-feed ones labeled 'what[feed]'.
+    pub fn artifacts(&self, feeds: &Vec<FeedConfig>) -> Vec<(String, PathBuf)> {
+        let mut artifacts = vec![
+            ("state dir".to_string(), self.dir.clone()),
+            ("seen store".to_string(), self.seen_db.clone()),
+            ("spool".to_string(), self.spool.clone()),
+            ("status".to_string(), self.status.clone()),
+            ("lock".to_string(), self.lock.clone()),
+            ("alerts".to_string(), self.alerts.clone()),
+            ("throttle".to_string(), self.throttle.clone()),
+            ("last run".to_string(), self.last_run.clone()),
+            ("notification log".to_string(), self.notification_log.clone()),
+        ];
+
+        for feed in feeds {
+            artifacts.push((format!("snapshot[{}]", feed.name), self.snapshot(feed)));
+            artifacts.push((format!("validators[{}]", feed.name), self.validators(feed)));
+            artifacts.push((format!("pending[{}]", feed.name), self.pending(feed)));
+            artifacts.push((format!("journal[{}]", feed.name), self.journal(feed)));
+            if feed.login.is_some() {
+                artifacts.push((format!("session[{}]", feed.name), self.session(feed)));
+            }
+        }
+
+        artifacts
+    }
+
+    /// Print 'artifacts' as an aligned list.
+    pub fn print(&self, feeds: &Vec<FeedConfig>) {
+        for (label, path) in self.artifacts(feeds) {
+            println!("{:<24} {}", format!("{}:", label), path.display());
+        }
+    }
+
+    /// Take the lock file for as long as the returned 'StateLock' lives,
+    /// see 'lock'. 'create_dirs' first.
+    pub fn lock(&self) -> Result<StateLock> {
+        lock(&self.lock)
+    }
+}
+
+/// An exclusive lock on a lock file, so that two bbsmon processes, like a
+/// daemon and a run from cron, don't write the same state at once. Given
+/// back when dropped, and by the OS when the process ends however it ends.
+pub struct StateLock {
+    file: File,
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Lock 'path', created when missing: flock on unix, LockFileEx on
+/// windows. Fails right away when another process holds it.
+pub fn lock(path: &Path) -> Result<StateLock> {
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+        .chain_err(|| format!("can't open the lock file {}", path.display()))?;
+    if file.try_lock_exclusive().is_err() {
+        bail!("another bbsmon is working on this state, it holds {}", path.display());
+    }
+
+    Ok(StateLock { file })
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    DirBuilder::new().recursive(true).mode(0o700).create(path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> Result<()> {
+    DirBuilder::new().recursive(true).create(path)?;
+    return Ok(());
+}
+
+/// The temporary file 'write_atomic' fills before renaming it to 'path'.
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+/// Write 'path' through a temporary file in the same directory which is
+/// fsynced and then renamed over the target, so a crash or a failing 'fill'
+/// leaves the previous content intact. std's rename replaces an existing
+/// target on windows as well (MoveFileEx with MOVEFILE_REPLACE_EXISTING).
+pub fn write_atomic<P, F>(path: P, fill: F) -> Result<()>
+    where P: AsRef<Path>, F: FnOnce(&mut File) -> Result<()>
+{
+    let path = path.as_ref();
+    let tmp = tmp_path(path);
+
+    let written = (|| -> Result<()> {
+        let mut file = File::create(&tmp)?;
+        fill(&mut file)?;
+        file.sync_all()?;
+        Ok(())
+    })();
+
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    fs::rename(&tmp, path)?;
+    sync_parent_dir(path);
+
+    Ok(())
+}
+
+// make the rename itself durable; best effort, not every filesystem allows it.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    if let Some(parent) = path.parent() {
+        let parent = if parent == Path::new("") { Path::new(".") } else { parent };
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) {
+}
+
+/// Whether 'path' names a gzipped file.
+pub fn is_gz(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+// 'path' with ".gz" appended, or removed when it is already there.
+fn gz_sibling(path: &Path) -> PathBuf {
+    if is_gz(path) {
+        return path.with_extension("");
+    }
+
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}.gz", name))
+}
+
+// "old-rss" for both "old-rss.xml" and "old-rss.xml.gz".
+fn snapshot_stem(snapshot: &Path) -> String {
+    let plain = if is_gz(snapshot) { snapshot.with_extension("") } else { snapshot.to_path_buf() };
+    plain.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// The content of a state file, gunzipped when it starts with the gzip
+/// magic bytes.
+pub fn read_state_file(path: &Path) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut raw = Vec::new();
+        GzDecoder::new(&bytes[..]).read_to_end(&mut raw)?;
+        return Ok(raw);
+    }
+
+    Ok(bytes)
+}
+
+/// Replace 'path' atomically with 'bytes', gzipped with 'compress'.
+/// 'read_state_file' reads either form back.
+pub fn write_state_file(path: &Path, bytes: &[u8], compress: bool) -> Result<()> {
+    write_atomic(path, |writer| {
+        if compress {
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()?;
+        } else {
+            writer.write_all(bytes)?;
+        }
+        Ok(())
+    })
+}
+
+/// Rotated copies of 'snapshot' sit next to it as '<stem>.<timestamp>.xml[.gz]'.
+pub fn rotated_snapshots(snapshot: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match snapshot.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let stem = snapshot_stem(snapshot);
+    let prefix = format!("{}.", stem);
+
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+
+        let rest = &name[prefix.len()..];
+        let stamp = rest.trim_end_matches(".gz").trim_end_matches(".xml");
+        // seconds as older releases named them, or nanoseconds.
+        if (stamp.len() == 14 || stamp.len() == 23) && stamp.chars().all(|c| c.is_ascii_digit()) {
+            rotated.push(path);
+        }
+    }
+
+    // newest first
+    rotated.sort_by(|a, b| b.cmp(a));
+    Ok(rotated)
+}
+
+// '<stem>.<timestamp>.<ext>' next to 'snapshot', named by a time no rotated
+// copy has yet, so that two rotations within the same instant of the clock
+// don't overwrite one another.
+fn rotation_target(snapshot: &Path, ext: &str) -> PathBuf {
+    let stem = snapshot_stem(snapshot);
+    loop {
+        let target = snapshot.with_file_name(format!("{}.{}.{}", stem, Local::now().format("%Y%m%d%H%M%S%f"), ext));
+        if !target.exists() {
+            return target;
+        }
+    }
+}
+
+// move the current snapshot aside under a timestamped name and drop the
+// oldest copies beyond 'snapshot_keep'.
+fn rotate_snapshot(config: &Config, snapshot: &Path) -> Result<()> {
+    if is_gz(snapshot) {
+        let target = rotation_target(snapshot, "xml.gz");
+        fs::rename(snapshot, &target)?;
+    } else if config.snapshot_compress {
+        let target = rotation_target(snapshot, "xml.gz");
+
+        let mut raw = Vec::new();
+        File::open(snapshot)?.read_to_end(&mut raw)?;
+
+        write_state_file(&target, &raw, true)?;
+        fs::remove_file(snapshot)?;
+    } else {
+        let target = rotation_target(snapshot, "xml");
+        fs::rename(snapshot, &target)?;
+    }
+
+    for old in rotated_snapshots(snapshot)?.iter().skip(config.snapshot_keep) {
+        fs::remove_file(old)?;
+    }
+
+    Ok(())
+}
+
+/// Whether 'a' and 'b' are the same document, whatever line endings each
+/// uses; a snapshot copied through windows tools may have gained "\r\n".
+pub fn same_document(a: &[u8], b: &[u8]) -> bool {
+    let lines = |doc: &[u8]| -> Vec<u8> {
+        let mut out = Vec::with_capacity(doc.len());
+        for (i, byte) in doc.iter().enumerate() {
+            if *byte != b'\r' || doc.get(i + 1) != Some(&b'\n') {
+                out.push(*byte);
+            }
+        }
+        out
+    };
+
+    a == b || lines(a) == lines(b)
+}
+
+/// Replace the stored snapshot with 'ctx', rotating the previous one first
+/// when 'snapshot_keep' asks for it. An unchanged feed is not rotated.
+pub fn save_snapshot(config: &Config, ctx: &RssContext, snapshot: &Path) -> Result<()> {
+    // after 'compress_state' was switched, the snapshot still sits under its
+    // other name until this first save.
+    let previous = gz_sibling(snapshot);
+    let current = if !snapshot.exists() && previous.exists() { previous.as_path() } else { snapshot };
+
+    if config.snapshot_keep > 0 && current.exists() {
+        let unchanged = same_document(&read_state_file(current)?, ctx.raw.as_bytes());
+        if unchanged && current == snapshot {
+            return Ok(());
+        }
+
+        if !unchanged {
+            rotate_snapshot(config, current)?;
+        }
+    }
+
+    // the sig is written first under its temporary name and renamed only
+    // after the snapshot; 'load_snapshot' takes such a pending sig when it
+    // matches, so a crash in between doesn't leave a pair that mismatches.
+    let sig = digest_path(snapshot);
+    let pending = tmp_path(&sig);
+    {
+        let mut file = File::create(&pending)?;
+        file.write_all(state_digest(ctx.raw.as_bytes(), &config.state_key).as_bytes())?;
+        file.sync_all()?;
+    }
+    ctx.to_file(snapshot)?;
+    fs::rename(&pending, &sig)?;
+    mark_signed(snapshot)?;
+
+    if previous.exists() {
+        fs::remove_file(&previous)?;
+    }
+    if digest_path(&previous).exists() {
+        fs::remove_file(digest_path(&previous))?;
+    }
+
+    Ok(())
+}
+
+/// The '.sig' sidecar of 'snapshot'.
+pub fn digest_path(snapshot: &Path) -> PathBuf {
+    let name = snapshot.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    snapshot.with_file_name(format!("{}.sig", name))
+}
+
+/// Hex HMAC-SHA256 of 'raw' under 'key', or its plain SHA-256 without one.
+pub fn state_digest(raw: &[u8], key: &Option<String>) -> String {
+    let code = match *key {
+        Some(ref key) => {
+            let mut mac = Hmac::new(Sha256::new(), key.as_bytes());
+            mac.input(raw);
+            mac.result().code().to_vec()
+        }
+        None => {
+            let mut sha = Sha256::new();
+            sha.input(raw);
+            let mut code = vec![0; sha.output_bytes()];
+            sha.result(&mut code);
+            code
+        }
+    };
+
+    code.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// marks the directory of 'snapshot' as one whose snapshots are signed, so
+// that a snapshot in it without a '.sig' sidecar is no longer trusted.
+fn signed_marker(snapshot: &Path) -> PathBuf {
+    match snapshot.parent() {
+        Some(parent) if parent != Path::new("") => parent.join(".signed"),
+        _ => PathBuf::from(".signed"),
+    }
+}
+
+fn mark_signed(snapshot: &Path) -> Result<()> {
+    let marker = signed_marker(snapshot);
+    if !marker.exists() {
+        write_atomic(&marker, |_| Ok(()))?;
+    }
+    Ok(())
+}
+
+// whether the digest stored at 'path' is 'actual'.
+fn digest_matches(path: &Path, actual: &str) -> bool {
+    let mut expected = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut expected)) {
+        Ok(_) => fixed_time_eq(expected.trim().as_bytes(), actual.as_bytes()),
+        Err(_) => false,
+    }
+}
+
+/// The stored snapshot, if there is one that matches its '.sig' sidecar
+/// and parses. Otherwise a warning is logged and the feed starts over as
+/// on its first run. A snapshot without a sidecar is only trusted in a
+/// directory where no snapshot was signed yet, as left by a bbsmon from
+/// before sidecars, and is signed right away. One saved before
+/// 'compress_state' was switched is found under its other name.
+pub fn load_snapshot(snapshot: &Path, key: &Option<String>) -> Result<Option<RssContext>> {
+    let previous = gz_sibling(snapshot);
+    let snapshot = if !snapshot.exists() && previous.exists() { previous.as_path() } else { snapshot };
+
+    if !snapshot.exists() {
+        return Ok(None);
+    }
+
+    let raw = read_state_file(snapshot).chain_err(|| format!("can't read {}", snapshot.display()))?;
+    let actual = state_digest(&raw, key);
+    let sig = digest_path(snapshot);
+    let ignored = |why: String| -> Result<Option<RssContext>> {
+        Record::warn("ignoring the stored snapshot").field("path", snapshot.display().to_string())
+            .field("reason", &why)
+            .log(format!("warning: {} {}, ignoring it.", snapshot.display(), why));
+        Ok(None)
+    };
+
+    if !digest_matches(&sig, &actual) {
+        if digest_matches(&tmp_path(&sig), &actual) {
+            // a save stopped between the snapshot and the rename of its sig.
+            fs::rename(tmp_path(&sig), &sig)?;
+        } else if !sig.exists() && !signed_marker(snapshot).exists() {
+            write_atomic(&sig, |writer| {
+                writer.write_all(actual.as_bytes())?;
+                Ok(())
+            })?;
+            mark_signed(snapshot)?;
+        } else if sig.exists() {
+            return ignored(format!("does not match {}", sig.display()));
+        } else {
+            return ignored(format!("has no {} although its snapshots are signed", sig.display()));
+        }
+    }
+
+    match RssContext::from_reader(&raw[..]) {
+        Ok(ctx) => Ok(Some(ctx)),
+        Err(e) => ignored(format!("can't be parsed ({})", error_chain_string(&e))),
+    }
+}
+
+/// One send recorded in a 'SendJournal'.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournaledSend {
+    /// Keys of the items sent, see 'item_key'.
+    pub keys: Vec<String>,
+    /// Whether the mail went out or was queued in the spool.
+    pub confirmed: bool,
+    /// When the send began.
+    pub began_at: String,
+}
+
+/// Sends of a feed between its fetch and its next snapshot. A send is
+/// recorded as pending before the mail goes out and confirmed after, and
+/// the journal is cleared once the snapshot advanced. A run that stopped
+/// in between leaves it behind for the next one: items of confirmed sends
+/// went out and aren't notified again, items of pending ones are retried.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SendJournal {
+    /// The sends, oldest first.
+    pub sends: Vec<JournaledSend>,
+}
+
+impl SendJournal {
+    /// The journal stored at 'path', empty when there is none.
+    pub fn load(path: &Path) -> Result<SendJournal> {
+        if !path.exists() {
+            return Ok(SendJournal::default());
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Replace what is stored at 'path' with this journal.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+
+    /// Forget every send, once the snapshot holds their items.
+    pub fn clear(path: &Path) -> Result<()> {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Record a pending send of the items with 'keys', returning its index.
+    pub fn begin(&mut self, keys: Vec<String>) -> usize {
+        self.sends.push(JournaledSend {
+            keys,
+            confirmed: false,
+            began_at: Local::now().to_rfc3339(),
+        });
+        self.sends.len() - 1
+    }
+
+    /// Mark send 'i' as gone out.
+    pub fn confirm(&mut self, i: usize) {
+        self.sends[i].confirmed = true;
+    }
+
+    /// Keys of the items that went out.
+    pub fn confirmed_keys(&self) -> HashSet<String> {
+        self.sends.iter().filter(|s| s.confirmed).flat_map(|s| s.keys.iter().cloned()).collect()
+    }
+
+    /// Keys of the items whose send may or may not have gone out.
+    pub fn pending_keys(&self) -> HashSet<String> {
+        let confirmed = self.confirmed_keys();
+        self.sends.iter().filter(|s| !s.confirmed).flat_map(|s| s.keys.iter().cloned())
+            .filter(|k| !confirmed.contains(k)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::io::Write;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir()
+            .join(format!("bbsmon-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn line_endings_dont_make_a_new_document() {
+        assert!(same_document(b"<rss>\r\n<channel/>\r\n</rss>", b"<rss>\n<channel/>\n</rss>"));
+        assert!(same_document(b"<rss>\r</rss>", b"<rss>\r</rss>"));
+        assert!(!same_document(b"<rss>\r</rss>", b"<rss></rss>"));
+    }
+
+    #[test]
+    fn interrupted_write_keeps_old_snapshot() {
+        let dir = scratch_dir("atomic");
+        let snapshot = dir.join("old-rss.xml");
+
+        write_atomic(&snapshot, |f| { f.write_all(b"<rss>old</rss>")?; Ok(()) }).unwrap();
+
+        let result = write_atomic(&snapshot, |f| {
+            f.write_all(b"<rss>tru")?;
+            bail!("disk full");
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&snapshot).unwrap(), "<rss>old</rss>");
+        assert!(!tmp_path(&snapshot).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gz_snapshots_round_trip() {
+        let dir = scratch_dir("gz");
+        let xml = "<rss version=\"2.0\"><channel><title>t</title><link>l</link>\
+                   <description>d</description></channel></rss>";
+        let ctx = RssContext::from_reader(xml.as_bytes()).unwrap();
+
+        let snapshot = dir.join("old-rss.xml.gz");
+        ctx.to_file(&snapshot).unwrap();
+
+        let mut head = [0; 2];
+        File::open(&snapshot).unwrap().read_exact(&mut head).unwrap();
+        assert_eq!(head, [0x1f, 0x8b]);
+        assert_eq!(RssContext::from_file(&snapshot).unwrap().raw, xml);
+
+        // a plain snapshot is still found once 'compress_state' is switched on.
+        fs::remove_file(&snapshot).unwrap();
+        ctx.to_file(dir.join("old-rss.xml")).unwrap();
+        assert_eq!(load_snapshot(&snapshot, &None).unwrap().unwrap().raw, xml);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn back_to_back_rotations_keep_both_copies() {
+        let dir = scratch_dir("rotate");
+        let config: Config = serde_json::from_str(r#"{"feeds": [], "subject": "s", "from": "f@bbs", "to": "t@bbs",
+                                                      "password": "p", "server": "bbs", "snapshot_keep": 5}"#)
+            .unwrap();
+        let snapshot = dir.join("old-rss.xml");
+        // a copy named by an older release, rotated in the same second.
+        fs::write(dir.join(format!("old-rss.{}.xml", Local::now().format("%Y%m%d%H%M%S"))), "<rss>0</rss>").unwrap();
+
+        fs::write(&snapshot, "<rss>1</rss>").unwrap();
+        rotate_snapshot(&config, &snapshot).unwrap();
+        fs::write(&snapshot, "<rss>2</rss>").unwrap();
+        rotate_snapshot(&config, &snapshot).unwrap();
+
+        let rotated: Vec<String> = rotated_snapshots(&snapshot).unwrap().iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect();
+        assert_eq!(rotated, vec!["<rss>2</rss>", "<rss>1</rss>", "<rss>0</rss>"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_that_lost_their_sig_or_dont_parse_start_over() {
+        let dir = scratch_dir("sig");
+        let xml = "<rss version=\"2.0\"><channel><title>t</title><link>l</link>\
+                   <description>d</description></channel></rss>";
+        let snapshot = dir.join("old-rss.xml");
+        let sig = digest_path(&snapshot);
+        let sign = |path: &Path, raw: &str| fs::write(path, state_digest(raw.as_bytes(), &None)).unwrap();
+
+        // unsigned as left by an older bbsmon: trusted once, and signed.
+        fs::write(&snapshot, xml).unwrap();
+        assert_eq!(load_snapshot(&snapshot, &None).unwrap().unwrap().raw, xml);
+        assert!(sig.exists());
+
+        fs::remove_file(&sig).unwrap();
+        assert!(load_snapshot(&snapshot, &None).unwrap().is_none());
+
+        // a save stopped before renaming its sig.
+        sign(&tmp_path(&sig), xml);
+        assert!(load_snapshot(&snapshot, &None).unwrap().is_some());
+        assert!(sig.exists() && !tmp_path(&sig).exists());
+
+        fs::write(&snapshot, "<rss><chan").unwrap();
+        assert!(load_snapshot(&snapshot, &None).unwrap().is_none());
+        sign(&sig, "<rss><chan");
+        assert!(load_snapshot(&snapshot, &None).unwrap().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_one_holds_the_lock() {
+        let dir = scratch_dir("lock");
+        let path = dir.join("lock");
+
+        let held = lock(&path).unwrap();
+        assert!(lock(&path).is_err());
+        drop(held);
+        assert!(lock(&path).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keyed_digest_differs_from_plain_one() {
+        let plain = state_digest(b"<rss/>", &None);
+        let keyed = state_digest(b"<rss/>", &Some("secret".to_string()));
+
+        assert_eq!(plain.len(), 64);
+        assert_eq!(plain, state_digest(b"<rss/>", &None));
+        assert!(plain != keyed);
+    }
+
+    #[test]
+    fn journal_tells_sent_from_unconfirmed_items() {
+        let dir = scratch_dir("journal");
+        let path = dir.join("board.json");
+
+        let mut journal = SendJournal::load(&path).unwrap();
+        let urgent = journal.begin(vec!["t1".to_string()]);
+        journal.confirm(urgent);
+        journal.begin(vec!["t1".to_string(), "t2".to_string()]);
+        journal.save(&path).unwrap();
+
+        // the second send was cut short, only t2 is left to retry.
+        let journal = SendJournal::load(&path).unwrap();
+        assert_eq!(journal.confirmed_keys(), vec!["t1".to_string()].into_iter().collect());
+        assert_eq!(journal.pending_keys(), vec!["t2".to_string()].into_iter().collect());
+
+        SendJournal::clear(&path).unwrap();
+        assert!(SendJournal::load(&path).unwrap().sends.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
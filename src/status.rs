@@ -0,0 +1,167 @@
+//! What 'bbsmon status' reports.
+
+use std::io::Read;
+use std::io::Write;
+use std::fs::File;
+use std::path::Path;
+use std::collections::BTreeMap;
+
+use chrono::Duration;
+use chrono::NaiveDate;
+
+use serde_json;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::notify::DeadLetter;
+use crate::queue::PendingQueue;
+use crate::state::StatePaths;
+use crate::state::write_atomic;
+
+/// How many days of filtered items 'RunStatus' keeps count of.
+pub const FILTERED_DAYS: i64 = 35;
+
+/// What 'bbsmon status' reports, kept up to date by every run.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RunStatus {
+    /// When the last run finished.
+    pub last_run: Option<String>,
+    /// Only set while 'bbsmon daemon' is running.
+    pub next_run: Option<String>,
+    /// When the last scheduled activity report was handled.
+    pub last_report: Option<String>,
+    /// When the last heartbeat was handled, see 'report::send_heartbeat'.
+    #[serde(default)]
+    pub last_heartbeat: Option<String>,
+    /// Status of each feed, by name.
+    pub feeds: BTreeMap<String, FeedStatus>,
+    /// Items left out of notifications on each day, "%Y-%m-%d", of the
+    /// last 'FILTERED_DAYS'.
+    #[serde(default)]
+    pub filtered: BTreeMap<String, usize>,
+}
+
+/// How a feed did in the runs so far.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct FeedStatus {
+    /// When the feed was last checked.
+    pub last_check: Option<String>,
+    /// "ok" or "error: ..." of the last check.
+    pub last_result: Option<String>,
+    /// When items of the feed were last notified.
+    pub last_new: Option<String>,
+    /// When the feed was last checked without an error.
+    #[serde(default)]
+    pub last_ok: Option<String>,
+    /// Number of items notified so far.
+    pub items_seen: u64,
+    /// When 'bbsmon daemon' fetches the feed next, its 'jitter_seconds'
+    /// offset included; only set while it is running.
+    #[serde(default)]
+    pub next_fetch: Option<String>,
+}
+
+impl RunStatus {
+    /// The status stored at 'path', None before the first run.
+    pub fn load(path: &Path) -> Result<Option<RunStatus>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut content = String::new();
+        File::open(path)?.read_to_string(&mut content)?;
+
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    /// Count 'items' left out of notifications on 'day', forgetting the
+    /// days older than 'FILTERED_DAYS'.
+    pub fn add_filtered(&mut self, day: NaiveDate, items: usize) {
+        if items > 0 {
+            *self.filtered.entry(day.format("%Y-%m-%d").to_string()).or_insert(0) += items;
+        }
+        let oldest = (day - Duration::days(FILTERED_DAYS)).format("%Y-%m-%d").to_string();
+        self.filtered = self.filtered.split_off(&oldest);
+    }
+
+    /// Replace what is stored at 'path' with this status.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, |writer| {
+            writer.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+            Ok(())
+        })
+    }
+}
+
+fn spool_depth(paths: &StatePaths) -> Result<usize> {
+    Ok(DeadLetter::load_all(&paths.spool)?.len())
+}
+
+/// What 'status' prints as JSON: the 'artifacts', the 'spool_depth' and
+/// the stored 'status'.
+pub fn status_json(config: &Config, paths: &StatePaths) -> Result<Value> {
+    let mut artifacts = BTreeMap::new();
+    for (label, path) in paths.artifacts(&config.feeds) {
+        artifacts.insert(label, path.display().to_string());
+    }
+
+    let mut out = BTreeMap::new();
+    out.insert("artifacts", serde_json::to_value(&artifacts)?);
+    out.insert("spool_depth", serde_json::to_value(spool_depth(paths)?)?);
+    out.insert("status", serde_json::to_value(&RunStatus::load(&paths.status)?)?);
+    Ok(serde_json::to_value(&out)?)
+}
+
+/// Print where state is kept and how the last runs went, as text or JSON.
+pub fn status(config: &Config, paths: &StatePaths, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status_json(config, paths)?)?);
+        return Ok(());
+    }
+
+    let run_status = RunStatus::load(&paths.status)?;
+    let depth = spool_depth(paths)?;
+
+    paths.print(&config.feeds);
+    println!();
+    println!("queued notifications: {}", depth);
+
+    let run_status = match run_status {
+        Some(run_status) => run_status,
+        None => {
+            println!("bbsmon has not run yet, {} does not exist.", paths.status.display());
+            return Ok(());
+        }
+    };
+
+    println!("last run:             {}", run_status.last_run.unwrap_or("never".to_string()));
+    if let Some(next_run) = run_status.next_run {
+        println!("next run:             {}", next_run);
+    }
+
+    for feed in &config.feeds {
+        println!("[{}]", feed.name);
+
+        let feed_status = match run_status.feeds.get(&feed.name) {
+            Some(feed_status) => feed_status,
+            None => {
+                println!("  never checked");
+                continue;
+            }
+        };
+
+        let or_never = |v: &Option<String>| v.clone().unwrap_or("never".to_string());
+        println!("  last check:  {} ({})", or_never(&feed_status.last_check),
+                 feed_status.last_result.clone().unwrap_or_default());
+        println!("  last ok:     {}", or_never(&feed_status.last_ok));
+        println!("  last new:    {}", or_never(&feed_status.last_new));
+        if let Some(ref next_fetch) = feed_status.next_fetch {
+            println!("  next fetch:  {}", next_fetch);
+        }
+        println!("  items seen:  {}", feed_status.items_seen);
+        println!("  pending:     {}", PendingQueue::load(&paths.pending(feed))?.len());
+    }
+
+    Ok(())
+}
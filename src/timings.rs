@@ -0,0 +1,161 @@
+//! Where the time of a run goes, as '--timings' prints it.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The timed phases, in the order 'report' shows them: waiting for a
+/// fetch slot, fetching, parsing, diffing, rendering and sending.
+pub const PHASES: &[&str] = &["wait", "fetch", "parse", "diff", "render", "send"];
+
+/// Time spent by phase.
+pub type Phases = BTreeMap<&'static str, Duration>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local!(static SPENT: RefCell<Phases> = const { RefCell::new(Phases::new()) });
+
+tokio::task_local! {
+    // what the task run by 'scope' spent, it moves between threads.
+    static TASK_SPENT: RefCell<Phases>;
+}
+
+/// Time phases from now on. Until then 'timed' only runs what it is given.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether 'enable' was called.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// add 'elapsed' to 'phase' of the current 'scope', else of the current thread.
+fn add(phase: &'static str, elapsed: Duration) {
+    let add_to = |spent: &RefCell<Phases>| {
+        *spent.borrow_mut().entry(phase).or_insert(Duration::from_secs(0)) += elapsed;
+    };
+    if TASK_SPENT.try_with(add_to).is_err() {
+        SPENT.with(add_to);
+    }
+}
+
+/// Run 'f', adding the time it took to 'phase' of the current 'scope', or
+/// of the current thread outside of one.
+pub fn timed<T, F: FnOnce() -> T>(phase: &'static str, f: F) -> T {
+    if !enabled() {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    add(phase, start.elapsed());
+    result
+}
+
+/// 'timed' for work on the runtime: the time until 'f' is done.
+pub async fn timed_async<F: Future>(phase: &'static str, f: F) -> F::Output {
+    if !enabled() {
+        return f.await;
+    }
+
+    let start = Instant::now();
+    let result = f.await;
+    add(phase, start.elapsed());
+    result
+}
+
+/// Run 'f', and what the phases timed in it spent, whichever threads of
+/// the runtime it ran on.
+pub async fn scope<F: Future>(f: F) -> (F::Output, Phases) {
+    TASK_SPENT.scope(RefCell::new(Phases::new()), async move {
+        let result = f.await;
+        (result, TASK_SPENT.with(|spent| std::mem::take(&mut *spent.borrow_mut())))
+    }).await
+}
+
+/// What the current thread spent since the last 'take', by phase.
+pub fn take() -> Phases {
+    SPENT.with(|spent| std::mem::take(&mut *spent.borrow_mut()))
+}
+
+/// Add the time of 'from' to 'into'.
+pub fn merge(into: &mut Phases, from: Phases) {
+    for (phase, spent) in from {
+        *into.entry(phase).or_insert(Duration::from_secs(0)) += spent;
+    }
+}
+
+/// Whole milliseconds of 'duration'.
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+/// A table of the milliseconds each of 'rows' spent by phase, their
+/// totals, and the 'wall' time of the run. Feeds are fetched side by
+/// side, so the totals may add up to more than the run took.
+pub fn report(rows: &Vec<(String, Phases)>, wall: Duration) -> String {
+    let width = rows.iter().map(|(name, _)| name.chars().count()).chain(Some("total".len()))
+        .max().unwrap_or(0);
+    let pad = |name: &str| -> String {
+        let mut padded = name.to_string();
+        for _ in name.chars().count()..width {
+            padded.push(' ');
+        }
+        padded
+    };
+    let line = |name: &str, phases: &Phases| -> String {
+        let mut line = pad(name);
+        for phase in PHASES {
+            line.push_str(&format!(" {:>8}", phases.get(phase).map_or(0, |d| millis(*d))));
+        }
+        line
+    };
+
+    let mut out = pad("ms");
+    for phase in PHASES {
+        out.push_str(&format!(" {:>8}", phase));
+    }
+    out.push('\n');
+
+    let mut total = Phases::new();
+    for (name, phases) in rows {
+        out.push_str(&line(name, phases));
+        out.push('\n');
+        merge(&mut total, phases.clone());
+    }
+    out.push_str(&line("total", &total));
+    out.push('\n');
+    out.push_str(&format!("run took {} ms\n", millis(wall)));
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn report_adds_up_the_phases() {
+        let mut a = Phases::new();
+        a.insert("fetch", Duration::from_millis(120));
+        a.insert("send", Duration::from_millis(30));
+        let mut b = Phases::new();
+        b.insert("fetch", Duration::from_millis(80));
+        b.insert("diff", Duration::from_millis(2));
+
+        let report = report(&vec![("board".to_string(), a), ("b".to_string(), b)], Duration::from_millis(150));
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert_eq!(lines[0], "ms        wait    fetch    parse     diff   render     send");
+        assert_eq!(lines[1], "board        0      120        0        0        0       30");
+        assert_eq!(lines[3], "total        0      200        0        2        0       30");
+        assert_eq!(lines[4], "run took 150 ms");
+    }
+}
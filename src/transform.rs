@@ -0,0 +1,210 @@
+//! Items piped through a feed's 'transform_command' before they are
+//! notified, for summarizers and translation scripts.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rss::Item;
+
+use serde_json;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::log::Record;
+use crate::source::shell;
+
+/// Fields of an item a transform may change; those its answer leaves out
+/// are kept, a null clears one.
+pub const TRANSFORMED_FIELDS: &[&str] = &["title", "link", "description", "author"];
+
+// what the command is given on stdin.
+fn item_json(item: &Item) -> BTreeMap<&'static str, Option<String>> {
+    let mut json = BTreeMap::new();
+    json.insert("title", item.title.clone());
+    json.insert("link", item.link.clone());
+    json.insert("description", item.description.clone());
+    json.insert("author", item.author.clone());
+    json.insert("pub_date", item.pub_date.clone());
+    json.insert("guid", item.guid.as_ref().map(|g| g.value.clone()));
+    json
+}
+
+/// 'item' with the 'TRANSFORMED_FIELDS' of 'answer', a JSON object.
+pub fn apply_answer(item: &Item, answer: &[u8]) -> Result<Item> {
+    let value: Value = serde_json::from_slice(answer).chain_err(|| "the answer isn't JSON")?;
+    let object = match value.as_object() {
+        Some(object) => object,
+        None => bail!("the answer isn't a JSON object"),
+    };
+
+    let mut item = item.clone();
+    for field in TRANSFORMED_FIELDS {
+        let text = match object.get(*field) {
+            None => continue,
+            Some(&Value::Null) => None,
+            Some(Value::String(text)) => Some(text.clone()),
+            Some(_) => bail!("'{}' of the answer isn't a string", field),
+        };
+        match *field {
+            "title" => item.title = text,
+            "link" => item.link = text,
+            "description" => item.description = text,
+            _ => item.author = text,
+        }
+    }
+    Ok(item)
+}
+
+/// Run 'command' on 'item', killing it when it hasn't answered within
+/// 'timeout'.
+pub fn transform(command: &str, item: &Item, timeout: Duration) -> Result<Item> {
+    let input = serde_json::to_vec(&item_json(item))?;
+    let mut child = shell(command).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+        .chain_err(|| format!("can't run '{}'", command))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut answer = Vec::new();
+        let _ = tx.send(stdout.read_to_end(&mut answer).map(|_| answer));
+    });
+
+    let answer = match rx.recv_timeout(timeout) {
+        Ok(answer) => answer.chain_err(|| format!("can't read the answer of '{}'", command))?,
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("'{}' didn't answer within {} s", command, timeout.as_secs());
+        }
+    };
+
+    let output = child.wait_with_output().chain_err(|| format!("can't run '{}'", command))?;
+    if !output.status.success() {
+        bail!("'{}' failed with {}: {}", command, output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    apply_answer(item, &answer).chain_err(|| format!("bad answer of '{}'", command))
+}
+
+/// Counting semaphore capping how many transform commands run at once.
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A taken permit, given back when dropped.
+pub struct Permit<'a> {
+    sem: &'a Semaphore,
+}
+
+impl Semaphore {
+    /// A semaphore handing out 'permits' permits at a time.
+    pub fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait for a free permit.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.released.wait(permits).unwrap();
+        }
+        *permits -= 1;
+
+        Permit { sem: self }
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        *self.sem.permits.lock().unwrap() += 1;
+        self.sem.released.notify_one();
+    }
+}
+
+/// Pipe each of 'items' through 'command', at most 'max_concurrent' at
+/// once. An item whose transform fails keeps what it had, with a warning
+/// naming 'feed'.
+pub fn transform_items(feed: &str, command: &str, items: &mut [Item], max_concurrent: usize,
+                       timeout: Duration) {
+    if items.is_empty() {
+        return;
+    }
+
+    let sem = Arc::new(Semaphore::new(max_concurrent));
+    let mut workers = Vec::new();
+    for item in items.iter() {
+        let (sem, command, item) = (sem.clone(), command.to_string(), item.clone());
+        workers.push(thread::spawn(move || {
+            let _permit = sem.acquire();
+            transform(&command, &item, timeout)
+        }));
+    }
+
+    for (item, worker) in items.iter_mut().zip(workers) {
+        let transformed = match worker.join() {
+            Ok(transformed) => transformed,
+            Err(_) => Err(format!("transforming '{}' panicked", command).into()),
+        };
+        match transformed {
+            Ok(transformed) => *item = transformed,
+            Err(e) => {
+                Record::warn("item kept as it was").field("feed", feed).field("error", error_chain_string(&e))
+                    .log(format!("{}: warning: item kept as it was: {}", feed, error_chain_string(&e)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_replace_only_the_fields_they_have() {
+        let item = Item {
+            title: Some("原帖".to_string()),
+            description: Some("a long posting".to_string()),
+            author: Some("yami".to_string()),
+            ..Item::default()
+        };
+
+        let item = apply_answer(&item, br#"{"description": "short", "author": null, "pub_date": "x"}"#).unwrap();
+        assert_eq!(item.title, Some("原帖".to_string()));
+        assert_eq!(item.description, Some("short".to_string()));
+        assert_eq!(item.author, None);
+        assert_eq!(item.pub_date, None);
+
+        assert!(apply_answer(&item, b"[]").is_err());
+        assert!(apply_answer(&item, br#"{"title": 1}"#).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn slow_commands_are_given_up() {
+        let item = Item::default();
+        let started = ::std::time::Instant::now();
+        assert!(transform("sleep 5", &item, Duration::from_secs(1)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(4));
+
+        let item = transform("sed 's/old/new/'", &Item { title: Some("old".to_string()), ..Item::default() },
+                             Duration::from_secs(5)).unwrap();
+        assert_eq!(item.title, Some("new".to_string()));
+    }
+}
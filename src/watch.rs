@@ -0,0 +1,140 @@
+//! 'bbsmon watch': preview a fixture feed again whenever the config, the
+//! templates or the fixture change.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use chrono::Local;
+
+use crate::error::*;
+use crate::config::Config;
+use crate::pipeline::preview_fixture;
+
+/// Where 'watch' looks for template changes.
+pub const TEMPLATE_DIR: &str = "templates";
+
+/// How often 'watch' looks at the watched files.
+pub const POLL_MILLIS: u64 = 200;
+
+/// How long the watched files must stay unchanged before a preview, so
+/// that an editor saving several files makes one preview.
+pub const DEBOUNCE_MILLIS: u64 = 500;
+
+/// Modification time and size of each watched file, None for one that
+/// doesn't exist.
+pub type Fingerprint = BTreeMap<PathBuf, Option<(SystemTime, u64)>>;
+
+// 'path' and, for a directory, every file below it.
+fn add_files(path: &Path, fingerprint: &mut Fingerprint) {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => {
+            fingerprint.insert(path.to_path_buf(), None);
+            return;
+        }
+    };
+
+    if meta.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                add_files(&entry.path(), fingerprint);
+            }
+        }
+        return;
+    }
+
+    fingerprint.insert(path.to_path_buf(), meta.modified().ok().map(|m| (m, meta.len())));
+}
+
+/// The current state of 'paths', directories walked.
+pub fn fingerprint(paths: &Vec<&Path>) -> Fingerprint {
+    let mut fingerprint = Fingerprint::new();
+    for path in paths {
+        add_files(path, &mut fingerprint);
+    }
+    fingerprint
+}
+
+/// Print the preview of 'fixture' as 'preview_fixture' makes it, for
+/// 'feed' of 'config_file', and again after every change to either of
+/// them or to the templates, until killed. A config that doesn't load is
+/// reported and the last one that did is used. Nothing is fetched, sent
+/// or written.
+pub fn watch(config_file: &str, fixture: &str, feed: Option<&str>) -> Result<()> {
+    let watched = vec![Path::new(config_file), Path::new(TEMPLATE_DIR), Path::new(fixture)];
+    let mut last_good: Option<Config> = None;
+    let mut seen = Fingerprint::new();
+
+    loop {
+        // wait for a change, then for the files to settle.
+        let mut current = fingerprint(&watched);
+        if current == seen {
+            thread::sleep(Duration::from_millis(POLL_MILLIS));
+            continue;
+        }
+        loop {
+            thread::sleep(Duration::from_millis(DEBOUNCE_MILLIS));
+            let settled = fingerprint(&watched);
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+        seen = current;
+
+        println!("===== {} =====", Local::now().format("%H:%M:%S"));
+        match Config::load(config_file) {
+            Ok(config) => last_good = Some(config),
+            Err(e) => match last_good {
+                Some(_) => println!("{}, previewing with the last config that loaded.", error_chain_string(&e)),
+                None => {
+                    println!("{}", error_chain_string(&e));
+                    continue;
+                }
+            },
+        }
+
+        if let Some(ref config) = last_good {
+            if let Err(e) = preview_fixture(config, feed, fixture) {
+                println!("{}", error_chain_string(&e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::Write;
+
+    #[test]
+    fn fingerprint_notices_new_and_grown_files() {
+        let dir = env::temp_dir().join(format!("bbsmon-watch-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("templates")).unwrap();
+        let fixture = dir.join("fixture.xml");
+        let watched = [dir.join("templates"), fixture.clone()];
+        let watched: Vec<&Path> = watched.iter().map(|p| p.as_path()).collect();
+
+        let empty = fingerprint(&watched);
+        assert_eq!(empty.get(&fixture), Some(&None));
+
+        fs::File::create(dir.join("templates").join("mail.html")).unwrap().write_all(b"<p>").unwrap();
+        let with_template = fingerprint(&watched);
+        assert!(with_template != empty);
+        assert_eq!(fingerprint(&watched), with_template);
+
+        fs::OpenOptions::new().append(true).open(dir.join("templates").join("mail.html")).unwrap()
+            .write_all(b"</p>").unwrap();
+        assert!(fingerprint(&watched) != with_template);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,169 @@
+// runs the bbsmon binary into each kind of failure and checks how it ends.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+// the binary cargo built next to this test.
+fn bbsmon() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().parent().unwrap().join(format!("bbsmon{}", env::consts::EXE_SUFFIX))
+}
+
+fn scratch(name: &str, config: Option<&str>) -> PathBuf {
+    let dir = env::temp_dir().join(format!("bbsmon-cli-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    if let Some(config) = config {
+        fs::File::create(dir.join("bbsmon.json")).unwrap().write_all(config.as_bytes()).unwrap();
+    }
+
+    dir
+}
+
+// a port nothing listens on.
+fn closed_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn config(extra: &str) -> String {
+    let port = closed_port();
+    format!("{{\"feeds\": [{{\"name\": \"bbs\", \"remote_rss\": \"http://127.0.0.1:{}/rss.xml\"}}],
+                      \"subject\": \"s\", \"from\": \"f@bbs\", \"to\": \"t@bbs\", \"password\": \"p\",
+                      \"server\": \"127.0.0.1\", \"smtp_port\": {}{}}}", port, port, extra)
+}
+
+fn bbsmon_in(dir: &PathBuf, args: &[&str]) -> (i32, String) {
+    let output = Command::new(bbsmon()).args(args).current_dir(dir).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    (output.status.code().unwrap(), stderr)
+}
+
+#[test]
+fn missing_config() {
+    let dir = scratch("missing", None);
+
+    let (code, stderr) = bbsmon_in(&dir, &[]);
+    assert_eq!(code, 3);
+    assert!(stderr.starts_with("error: bad config bbsmon.json\n  caused by: can't open it\n"), "{}", stderr);
+    assert!(!stderr.contains("stack backtrace"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_config() {
+    let dir = scratch("invalid", Some(&config(", \"max_concurrent_fetches\": 0")));
+
+    let (code, stderr) = bbsmon_in(&dir, &["status"]);
+    assert_eq!(code, 3);
+    assert_eq!(stderr, "error: bad config bbsmon.json\n  caused by: max_concurrent_fetches must be at least 1\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unreachable_feed() {
+    let dir = scratch("unreachable", Some(&config("")));
+
+    let (code, stderr) = bbsmon_in(&dir, &[]);
+    assert_eq!(code, 4);
+    assert_eq!(stderr, "error: run incomplete, failed: bbs\n");
+
+    let status = fs::read_to_string(dir.join("state").join("status.json")).unwrap();
+    assert!(status.contains("nothing fetched, snapshot kept"), "{}", status);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn run_deadline() {
+    // takes the connection but never answers.
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = silent.local_addr().unwrap().port();
+    let config = format!("{{\"feeds\": [{{\"name\": \"bbs\", \"remote_rss\": \"http://127.0.0.1:{}/rss.xml\"}}],
+                          \"subject\": \"s\", \"from\": \"f@bbs\", \"to\": \"t@bbs\", \"password\": \"p\",
+                          \"server\": \"127.0.0.1\", \"smtp_port\": {}, \"max_run_seconds\": 1}}",
+                         port, closed_port());
+    let dir = scratch("deadline", Some(&config));
+
+    let (code, stderr) = bbsmon_in(&dir, &[]);
+    assert_eq!(code, 5);
+    assert_eq!(stderr, "error: max_run_seconds passed, skipped: bbs\n");
+
+    let status = fs::read_to_string(dir.join("state").join("status.json")).unwrap();
+    assert!(status.contains("skipped: max_run_seconds passed"), "{}", status);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stdin_feed() {
+    let dir = scratch("stdin", Some(&config("")));
+    let feed = "<rss version=\"2.0\"><channel><title>bbs</title><link>http://bbs</link>\
+                <description>d</description><item><title>piped</title><link>http://bbs/1</link></item>\
+                </channel></rss>";
+
+    let mut child = Command::new(bbsmon()).args(["--stdin-feed", "--state", "piped.xml"]).current_dir(&dir)
+        .stdin(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(feed.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let snapshot = fs::read_to_string(dir.join("piped.xml")).unwrap();
+    assert!(snapshot.contains("piped"), "{}", snapshot);
+
+    let (code, stderr) = bbsmon_in(&dir, &["--stdin-feed", "daemon"]);
+    assert_eq!(code, 1);
+    assert_eq!(stderr, "error: --stdin-feed only works on a single run, not with 'daemon'\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn self_authored_items_are_not_notified() {
+    let dir = scratch("self-authors", Some(&config(", \"self_authors\": [\"Mingzai\"]")));
+    let channel = |items: &str| -> String {
+        format!("<rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><channel>\
+                        <title>bbs</title><link>http://bbs</link><description>d</description>{}\
+                        </channel></rss>", items)
+    };
+    let pipe = |feed: String, args: &[&str]| -> String {
+        let mut child = Command::new(bbsmon()).args(["--stdin-feed", "--state", "piped.xml"]).args(args)
+            .current_dir(&dir).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn().unwrap();
+        child.stdin.take().unwrap().write_all(feed.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let theirs = "<item><title>theirs</title><link>http://bbs/0</link></item>";
+    pipe(channel(theirs), &[]);
+    let stdout = pipe(channel(&format!("{}<item><title>mine</title><link>http://bbs/1</link>\
+                                        <dc:creator>mingzai</dc:creator></item>", theirs)), &["--dry-run"]);
+
+    assert!(stdout.contains("bbs: skipping 1 items by self_authors."), "{}", stdout);
+    assert!(stdout.contains("bbs: new and old rss are same."), "{}", stdout);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn debug_prints_a_backtrace() {
+    let dir = scratch("debug", None);
+
+    let (code, stderr) = bbsmon_in(&dir, &["--debug"]);
+    assert_eq!(code, 3);
+    assert!(stderr.starts_with("error: bad config bbsmon.json\n"));
+    assert!(stderr.contains("stack backtrace"), "{}", stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,398 @@
+// drives the fetch/diff half of a run against a scripted local HTTP server,
+// run after run over the same state directory.
+
+extern crate bbsmon;
+extern crate reqwest;
+extern crate tokio;
+
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use bbsmon::Config;
+use bbsmon::Pipeline;
+use bbsmon::error::*;
+use bbsmon::diff::Changes;
+use bbsmon::feed::RssContext;
+use bbsmon::feed::DiffOptions;
+use bbsmon::feed::fetch_diff_items;
+use bbsmon::runtime::block_on;
+use bbsmon::source::feed_source;
+use bbsmon::state::StatePaths;
+use bbsmon::state::save_snapshot;
+
+use tokio::sync::Semaphore;
+
+// one scripted answer: status line, extra headers, body.
+struct Reply {
+    status: &'static str,
+    headers: Vec<String>,
+    body: String,
+}
+
+fn reply(status: &'static str, headers: Vec<&str>, body: &str) -> Reply {
+    Reply {
+        status,
+        headers: headers.iter().map(|h| h.to_string()).collect(),
+        body: body.to_string(),
+    }
+}
+
+// serve 'replies' one connection each, in order, and keep the request heads.
+fn serve(replies: Vec<Reply>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/rss.xml", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let seen = requests.clone();
+    thread::spawn(move || {
+        for reply in replies {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut head = Vec::new();
+            let mut byte = [0; 1];
+            while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                head.push(byte[0]);
+            }
+            seen.lock().unwrap().push(String::from_utf8_lossy(&head).into_owned());
+
+            let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                                       reply.status, reply.body.len());
+            for header in &reply.headers {
+                response.push_str(&format!("{}\r\n", header));
+            }
+            response.push_str("\r\n");
+            response.push_str(&reply.body);
+
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    (url, requests)
+}
+
+fn feed(items: &[(&str, &str)]) -> String {
+    let items: String = items.iter()
+        .map(|&(guid, title)| format!("<item><guid>{}</guid><title>{}</title></item>", guid, title))
+        .collect();
+    format!("<rss version=\"2.0\"><channel><title>bbs</title><link>http://bbs</link>\
+                    <description>board</description>{}</channel></rss>", items)
+}
+
+// 'feed_options' are added to the feed, like ", \"precheck_head\": true".
+fn state(name: &str, url: &str, feed_options: &str) -> (Config, StatePaths, PathBuf) {
+    let dir = ::std::env::temp_dir().join(format!("bbsmon-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let config_file = dir.join("bbsmon.json");
+    fs::File::create(&config_file).unwrap().write_all(format!(
+        "{{\"state_dir\": \"{}\", \"feeds\": [{{\"name\": \"bbs\", \"remote_rss\": \"{}\"{}}}],
+           \"subject\": \"s\", \"from\": \"f@bbs\", \"to\": \"t@bbs\", \"password\": \"p\",
+           \"server\": \"localhost\"}}",
+        dir.join("state").display(), url, feed_options).as_bytes()).unwrap();
+
+    let config = Config::load(config_file.to_str().unwrap()).unwrap();
+    let paths = StatePaths::resolve(&config);
+    paths.create_dirs(&config.feeds).unwrap();
+
+    (config, paths, dir)
+}
+
+// what a run does up to notifying: fetch, diff, then save the snapshot and
+// the validators.
+fn run(config: &Config, paths: &StatePaths) -> Result<Option<Changes>> {
+    let feed = &config.feeds[0];
+    let source = feed_source(feed, &config.retry, config.max_feed_bytes, config.timeouts(),
+                             Some(paths.session(feed)))?;
+    let client = config.timeouts().client()?;
+    let options = DiffOptions { key: None, ..DiffOptions::of(config, feed) };
+    let (changes, ctx, validators) = block_on(fetch_diff_items(&client, &paths.snapshot(feed), &paths.validators(feed),
+                                                               source.as_ref(), &options, &Semaphore::new(1)))?;
+
+    save_snapshot(config, &ctx, &paths.snapshot(feed))?;
+    if let Some(validators) = validators {
+        validators.save(&paths.validators(feed))?;
+    }
+
+    Ok(changes)
+}
+
+fn titles(changes: &Changes) -> Vec<String> {
+    changes.new_items.iter().map(|i| i.title.clone().unwrap_or_default()).collect()
+}
+
+fn snapshot(config: &Config, paths: &StatePaths) -> String {
+    RssContext::from_file(paths.snapshot(&config.feeds[0])).unwrap().raw
+}
+
+#[test]
+fn runs_against_a_scripted_feed() {
+    let first = feed(&[("1", "hello")]);
+    let second = feed(&[("3", "third"), ("2", "second"), ("1", "hello")]);
+
+    let (url, requests) = serve(vec![
+        reply("200 OK", vec!["ETag: \"v1\""], &first),
+        reply("200 OK", vec!["ETag: \"v2\""], &second),
+        reply("500 Internal Server Error", vec![], "oops"),
+        reply("304 Not Modified", vec![], ""),
+        reply("200 OK", vec![], "<rss version=\"2.0\"><channel><title>bbs"),
+    ]);
+    let (config, paths, dir) = state("fetch", &url, "");
+
+    // bootstrap: nothing to compare against yet.
+    assert!(run(&config, &paths).unwrap().is_none());
+    assert_eq!(snapshot(&config, &paths), first);
+
+    let changes = run(&config, &paths).unwrap().unwrap();
+    assert_eq!(titles(&changes), vec!["third", "second"]);
+    assert_eq!((changes.updated_items.len(), changes.removed_items.len()), (0, 0));
+    assert_eq!(snapshot(&config, &paths), second);
+
+    // a failing server leaves the state alone, and the next run recovers.
+    assert!(run(&config, &paths).is_err());
+    assert_eq!(snapshot(&config, &paths), second);
+
+    let changes = run(&config, &paths).unwrap().unwrap();
+    assert!(changes.notified().is_empty());
+    assert_eq!(snapshot(&config, &paths), second);
+
+    let malformed = run(&config, &paths);
+    assert!(malformed.is_err());
+    assert_eq!(snapshot(&config, &paths), second);
+
+    let requests = requests.lock().unwrap();
+    assert!(!requests[0].to_lowercase().contains("if-none-match"));
+    assert!(requests[1].contains("\"v1\""));
+    assert!(requests[3].contains("\"v2\""));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn server_errors_are_retried_within_a_run() {
+    let first = feed(&[("1", "hello")]);
+    let second = feed(&[("2", "second"), ("1", "hello")]);
+
+    let (url, requests) = serve(vec![
+        reply("200 OK", vec![], &first),
+        reply("500 Internal Server Error", vec![], "oops"),
+        reply("503 Service Unavailable", vec![], "busy"),
+        reply("200 OK", vec![], &second),
+        reply("404 Not Found", vec![], "gone"),
+    ]);
+    let (mut config, paths, dir) = state("retry", &url, "");
+    config.retry.attempts = 3;
+    config.retry.base_ms = 10;
+
+    assert!(run(&config, &paths).unwrap().is_none());
+
+    let changes = run(&config, &paths).unwrap().unwrap();
+    assert_eq!(titles(&changes), vec!["second"]);
+    assert_eq!(requests.lock().unwrap().len(), 4);
+
+    // a client error isn't worth another try.
+    assert!(run(&config, &paths).is_err());
+    assert_eq!(requests.lock().unwrap().len(), 5);
+    assert_eq!(snapshot(&config, &paths), second);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn feeds_are_read_straight_from_a_url() {
+    let served = feed(&[("2", "second"), ("1", "hello")]);
+    let (url, requests) = serve(vec![reply("200 OK", vec![], &served)]);
+
+    let ctx = RssContext::from_url(&url).unwrap();
+    assert_eq!(ctx.raw, served);
+    assert_eq!(ctx.channel.items.len(), 2);
+    assert!(requests.lock().unwrap()[0].starts_with("GET /rss.xml "));
+}
+
+#[test]
+fn head_precheck_skips_unchanged_feeds() {
+    let first = feed(&[("1", "hello")]);
+    let second = feed(&[("2", "second"), ("1", "hello")]);
+
+    let (url, requests) = serve(vec![
+        reply("200 OK", vec!["ETag: \"v1\""], &first),
+        reply("200 OK", vec!["ETag: \"v1\""], ""),
+        reply("200 OK", vec!["ETag: \"v2\""], ""),
+        reply("200 OK", vec!["ETag: \"v2\""], &second),
+        reply("405 Method Not Allowed", vec![], ""),
+        reply("304 Not Modified", vec![], ""),
+    ]);
+    let (config, paths, dir) = state("precheck", &url, ", \"precheck_head\": true");
+
+    // nothing to compare a HEAD against yet.
+    assert!(run(&config, &paths).unwrap().is_none());
+
+    // same ETag, no GET.
+    assert!(run(&config, &paths).unwrap().unwrap().notified().is_empty());
+
+    let changes = run(&config, &paths).unwrap().unwrap();
+    assert_eq!(titles(&changes), vec!["second"]);
+    assert_eq!(snapshot(&config, &paths), second);
+
+    // a server without HEAD still gets the conditional GET.
+    assert!(run(&config, &paths).unwrap().unwrap().notified().is_empty());
+
+    let requests = requests.lock().unwrap();
+    let methods: Vec<&str> = requests.iter().map(|r| r.split(' ').next().unwrap()).collect();
+    assert_eq!(methods, vec!["GET", "HEAD", "HEAD", "GET", "HEAD", "GET"]);
+    assert!(requests[5].contains("\"v2\""));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn next_links_are_followed_until_a_seen_page() {
+    let page = |items: &[(&str, &str)], next: &str| {
+        feed(items).replace("<rss version=\"2.0\">", "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">")
+            .replace("<description>board</description>",
+                     &format!("<description>board</description><atom:link rel=\"next\" href=\"{}\"/>", next))
+    };
+
+    let (url, requests) = serve(vec![
+        reply("200 OK", vec![], &page(&[("2", "second")], "/rss.xml?page=2")),
+        reply("200 OK", vec![], &feed(&[("1", "hello")])),
+        reply("200 OK", vec![], &page(&[("5", "fifth"), ("4", "fourth")], "/rss.xml?page=2")),
+        reply("200 OK", vec![], &page(&[("3", "third"), ("2", "second")], "/rss.xml?page=3")),
+        reply("200 OK", vec![], &page(&[("1", "hello")], "/rss.xml?page=4")),
+    ]);
+    let (config, paths, dir) = state("pages", &url, ", \"follow_next\": true, \"max_pages\": 4");
+
+    assert!(run(&config, &paths).unwrap().is_none());
+    assert!(snapshot(&config, &paths).contains("hello"));
+
+    // the third page only has items seen before, so the fourth isn't read.
+    let changes = run(&config, &paths).unwrap().unwrap();
+    assert_eq!(titles(&changes), vec!["fifth", "fourth", "third"]);
+    assert_eq!(changes.removed_items.len(), 0);
+
+    let requests = requests.lock().unwrap();
+    let asked: Vec<&str> = requests.iter().map(|r| r.split(' ').nth(1).unwrap()).collect();
+    assert_eq!(asked, vec!["/rss.xml", "/rss.xml?page=2", "/rss.xml", "/rss.xml?page=2", "/rss.xml?page=3"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stalled_servers_time_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/rss.xml", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let _stalled = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(30));
+    });
+
+    let (mut config, paths, dir) = state("stalled", &url, "");
+    config.read_timeout_secs = Some(1);
+    config.retry.attempts = 1;
+
+    let started = Instant::now();
+    assert!(run(&config, &paths).is_err());
+    assert!(started.elapsed() < Duration::from_secs(10));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fetches_past_max_run_seconds_are_aborted() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/rss.xml", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // never answers, and tells when the client hangs up.
+        let (mut stalled, _) = listener.accept().unwrap();
+        stalled.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        let mut buf = [0; 1024];
+        while let Ok(read) = stalled.read(&mut buf) {
+            if read == 0 {
+                let _ = tx.send(());
+                return;
+            }
+        }
+    });
+
+    let (mut config, _, dir) = state("aborted", &url, "");
+    config.max_run_seconds = Some(1);
+
+    let started = Instant::now();
+    let e = Pipeline::new(config).run_once().err().unwrap();
+    assert!(matches!(*e.kind(), ErrorKind::DeadlineExceeded(_)), "{}", error_chain_string(&e));
+    assert!(started.elapsed() < Duration::from_secs(10));
+    rx.recv_timeout(Duration::from_secs(10)).expect("the aborted fetch closes its connection");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn oversized_feeds_are_refused() {
+    let big = feed(&[("1", "hello"), ("2", "second")]);
+
+    let (url, _) = serve(vec![reply("200 OK", vec![], &big)]);
+    let (mut config, paths, dir) = state("oversized", &url, "");
+    config.max_feed_bytes = 100;
+
+    let e = run(&config, &paths).err().unwrap();
+    assert!(error_chain_string(&e).contains("larger than 100 bytes"), "{}", error_chain_string(&e));
+    assert!(!paths.snapshot(&config.feeds[0]).exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn login_gets_the_session_and_renews_it() {
+    let first = feed(&[("1", "hello")]);
+    let second = feed(&[("2", "second"), ("1", "hello")]);
+    let login_page = "<html><body><form action=\"login.php\">请登录</form></body></html>";
+
+    let (url, requests) = serve(vec![
+        reply("302 Found", vec!["Set-Cookie: winduser=s1; path=/", "Set-Cookie: lastvisit=1; path=/",
+                                "Location: /index.php"], ""),
+        reply("200 OK", vec![], &first),
+        reply("200 OK", vec![], login_page),
+        reply("302 Found", vec!["Set-Cookie: winduser=s2; path=/", "Location: /index.php"], ""),
+        reply("200 OK", vec![], &second),
+        reply("403 Forbidden", vec![], ""),
+        reply("200 OK", vec![], "密码错误"),
+    ]);
+    let login_url = url.replace("rss.xml", "login.php");
+    let (config, paths, dir) = state("login", &url, &format!(
+        ", \"login\": {{\"url\": \"{}\", \"method\": \"GET\", \"form\": {{\"pwuser\": \"yami\", \"pwpwd\": \"p\"}},
+                      \"cookies\": [\"winduser\"], \"failure_marker\": \"密码错误\", \"ttl_secs\": 3600}}",
+        login_url));
+
+    assert!(run(&config, &paths).unwrap().is_none());
+
+    // the kept session is tried first and renewed once the login page comes back.
+    let changes = run(&config, &paths).unwrap().unwrap();
+    assert_eq!(titles(&changes), vec!["second"]);
+    assert!(fs::read_to_string(paths.session(&config.feeds[0])).unwrap().contains("winduser=s2"));
+
+    let e = run(&config, &paths).err().unwrap();
+    match *e.kind() {
+        ErrorKind::LoginFailed(ref at, _) => assert_eq!(*at, login_url),
+        _ => panic!("{}", error_chain_string(&e)),
+    }
+    assert_eq!(snapshot(&config, &paths), second);
+
+    let requests = requests.lock().unwrap();
+    assert!(requests[0].starts_with("GET /login.php?pwpwd=p&pwuser=yami "), "{}", requests[0]);
+    assert!(requests[1].to_lowercase().contains("cookie: winduser=s1\r\n"), "{}", requests[1]);
+    assert!(requests[2].to_lowercase().contains("cookie: winduser=s1\r\n"), "{}", requests[2]);
+    assert!(requests[4].to_lowercase().contains("cookie: winduser=s2\r\n"), "{}", requests[4]);
+    assert!(requests[5].to_lowercase().contains("cookie: winduser=s2\r\n"), "{}", requests[5]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,157 @@
+// renders fixed changes through the shipped templates and compares the
+// result byte for byte with tests/golden. run with BBSMON_BLESS=1 to write
+// the current output as the new expectation after a deliberate change.
+//
+// rfc2822 dates of fetched feeds become machine local time in
+// 'convert_to_ser_items', so the fixtures start from items already in
+// 'PUB_DATE_FORMAT'. what depends on the time of the run, hot threads, the
+// period of a report and the time of a heartbeat, is given a fixed now.
+
+extern crate bbsmon;
+extern crate chrono;
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+
+use chrono::DateTime;
+use chrono::Local;
+use chrono::TimeZone;
+
+use bbsmon::Config;
+use bbsmon::history::HistoryEntry;
+use bbsmon::render::DigestGroup;
+use bbsmon::render::HotThread;
+use bbsmon::render::SerItem;
+use bbsmon::render::render_changes;
+use bbsmon::render::render_digest;
+use bbsmon::render::render_hot_changes;
+use bbsmon::report::Heartbeat;
+use bbsmon::report::HeartbeatFeed;
+use bbsmon::report::activity_report;
+use bbsmon::report::render_activity;
+use bbsmon::report::render_heartbeat;
+
+fn config() -> Config {
+    serde_json::from_str(r#"{"feeds": [], "subject": "s", "from": "f@bbs", "to": "t@bbs",
+                                    "password": "p", "server": "bbs",
+                                    "date_format": "%A, %d. %B %Y", "locale": "de_DE"}"#).unwrap()
+}
+
+fn item(tid: u32, title: &str, author: &str, pub_date: &str, description: &str) -> SerItem<'static> {
+    SerItem {
+        title: Some(title.to_string().into()),
+        link: Some(format!("read.php?tid={}", tid).into()),
+        description: Some(description.to_string().into()),
+        author: Some(author.to_string().into()),
+        pub_date: Some(pub_date.to_string().into()),
+        thumbnail: None,
+        categories: Vec::new().into(),
+        replies: Vec::new().into(),
+        archive_link: None,
+        diff: Vec::new().into(),
+    }
+}
+
+fn check(golden: &str, rendered: String) {
+    let path = format!("tests/golden/{}", golden);
+
+    if env::var("BBSMON_BLESS").is_ok() {
+        File::create(&path).unwrap().write_all(rendered.as_bytes()).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap();
+    assert!(rendered == expected, "{} changed, run with BBSMON_BLESS=1 if that is intended:\n{}", path, rendered);
+}
+
+#[test]
+fn mail_with_every_kind_of_change() {
+    let new = vec![item(3, "周末聚餐", "yami", "2016-11-04 23:30:00", "地点待定"),
+                   item(4, "Rust 入门", "mingzai", "2016-11-05 08:00:00", "第一讲")];
+    let updated = vec![item(2, "招聘 (已满)", "yami", "2016-11-03 12:00:00", "谢谢大家")];
+    let removed = vec![item(1, "测试帖", "admin", "2016-11-01 09:15:00", "请忽略")];
+
+    check("mail.html", render_changes(&config(), "templates/**/*", "mail.html",
+                                      &new, &updated, &removed, false).unwrap());
+}
+
+#[test]
+fn mail_of_the_first_run() {
+    let new = vec![item(1, "测试帖", "admin", "2016-11-01 09:15:00", "请忽略")];
+
+    check("mail_first_run.html", render_changes(&config(), "templates/**/*", "mail.html",
+                                                &new, &Vec::new(), &Vec::new(), true).unwrap());
+}
+
+#[test]
+fn mail_with_hot_threads() {
+    let new = vec![item(4, "Rust 入门", "mingzai", "2016-11-05 08:00:00", "第一讲")];
+    let now = DateTime::parse_from_rfc3339("2016-11-05T21:00:00+08:00").unwrap();
+    let hot = vec![HotThread::new(item(3, "周末聚餐", "yami", "2016-11-04 23:30:00", "地点待定"), 42, 17,
+                                  "2016-11-05T09:30:00+08:00", now),
+                   HotThread::new(item(2, "招聘", "yami", "2016-11-03 12:00:00", "谢谢大家"), 8, 5,
+                                  "2016-11-04T18:00:00+08:00", now)];
+
+    check("mail_hot.html", render_hot_changes(&config(), "templates/**/*", "mail.html",
+                                              &new, &Vec::new(), &Vec::new(), &hot).unwrap());
+}
+
+#[test]
+fn weekly_digest() {
+    let mut thread = item(3, "Re: 周末聚餐", "mingzai", "2016-11-05 10:00:00", "我也去");
+    thread.replies = vec!["周末聚餐".to_string(), "Re: 周末聚餐".to_string()].into();
+    let groups = vec![
+        DigestGroup {
+            day: "2016-11-04".to_string(),
+            items: vec![item(2, "招聘", "yami", "2016-11-04 12:00:00", "谢谢大家")],
+        },
+        DigestGroup {
+            day: "2016-11-05".to_string(),
+            items: vec![thread, item(4, "Rust 入门", "mingzai", "2016-11-05 08:00:00", "第一讲")],
+        },
+    ];
+
+    check("digest.html", render_digest(&config(), "templates/**/*", "digest.html", "综合", true, &groups).unwrap());
+}
+
+// dates of the report are machine local time, so the entries are too.
+fn local(day: u32, hour: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2016, 11, day, hour, 0, 0).unwrap()
+}
+
+fn entry(feed: &str, notified_at: DateTime<Local>, item: SerItem<'static>) -> HistoryEntry {
+    HistoryEntry {
+        feed: feed.to_string(),
+        key: item.link.as_deref().unwrap().to_string(),
+        notified_at: notified_at.to_rfc3339(),
+        item,
+    }
+}
+
+#[test]
+fn daily_report() {
+    let mut market = item(5, "二手自行车", "yami", "2016-11-04 09:00:00", "九成新");
+    market.categories = vec!["出售".to_string()].into();
+    let entries = vec![entry("综合", local(4, 10), item(2, "招聘", "yami", "2016-11-04 09:00:00", "谢谢大家")),
+                       entry("综合", local(4, 10), item(3, "周末聚餐", "mingzai", "2016-11-04 09:30:00", "地点待定")),
+                       entry("市场", local(4, 22), market),
+                       entry("综合", local(5, 10), item(4, "Rust 入门", "mingzai", "2016-11-05 08:00:00", "第一讲"))];
+    let filtered = vec![("2016-11-04".to_string(), 2)].into_iter().collect();
+
+    let report = activity_report(&entries, &filtered, local(4, 0), local(5, 0));
+    check("report.html", render_activity("templates/**/*", "report.html", &report).unwrap());
+}
+
+#[test]
+fn heartbeat() {
+    let heartbeat = Heartbeat {
+        now: "2016-11-05 09:00".to_string(),
+        feeds: vec![HeartbeatFeed { name: "综合".to_string(), last_ok: Some("2016-11-05 08:50".to_string()), items: 30 },
+                    HeartbeatFeed { name: "市场".to_string(), last_ok: None, items: 0 }],
+    };
+
+    check("heartbeat.html", render_heartbeat("templates/**/*", "heartbeat.html", &heartbeat).unwrap());
+}
@@ -0,0 +1,343 @@
+// sends mails through an in-process SMTP sink and looks at what arrived.
+
+extern crate bbsmon;
+
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+use bbsmon::Config;
+use bbsmon::notify::DeadLetter;
+use bbsmon::notify::Failure;
+use bbsmon::notify::deliver;
+use bbsmon::notify::deliver_all;
+use bbsmon::notify::retry_failed;
+#[cfg(feature = "sink-webhook")]
+use bbsmon::notify::rocketchat_messages;
+use bbsmon::notify::send_alerts;
+use bbsmon::notify::send_mail;
+use bbsmon::notify::truncate_chars;
+use bbsmon::notify::verify_recipient;
+#[cfg(feature = "sink-webhook")]
+use bbsmon::render::SerItem;
+use bbsmon::state::StatePaths;
+
+// how the sink treats one connection.
+#[derive(Clone, Copy, PartialEq)]
+enum Session {
+    Accept,
+    RejectMailFrom,
+    DropInData,
+}
+
+// what a session saw: every command line, and the DATA payload.
+#[derive(Default, Debug)]
+struct Transcript {
+    commands: Vec<String>,
+    data: String,
+}
+
+fn smtp_sink(sessions: Vec<Session>) -> (u16, Arc<Mutex<Vec<Transcript>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let transcripts = Arc::new(Mutex::new(Vec::new()));
+
+    let seen = transcripts.clone();
+    thread::spawn(move || {
+        for session in sessions {
+            let (stream, _) = listener.accept().unwrap();
+            let mut out = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream);
+            let mut transcript = Transcript::default();
+
+            out.write_all(b"220 sink ESMTP\r\n").unwrap();
+
+            loop {
+                let mut line = String::new();
+                if lines.read_line(&mut line).unwrap_or(0) == 0 {
+                    break;
+                }
+                let command = line.trim_end().to_string();
+                transcript.commands.push(command.clone());
+                let verb = command.split(' ').next().unwrap_or("").to_uppercase();
+
+                let answer = match verb.as_str() {
+                    "EHLO" => "250-sink\r\n250-AUTH PLAIN\r\n250-8BITMIME\r\n250 SMTPUTF8\r\n",
+                    "AUTH" => "235 2.7.0 accepted\r\n",
+                    "MAIL" if session == Session::RejectMailFrom => "451 4.3.0 try again later\r\n",
+                    "DATA" => {
+                        out.write_all(b"354 go ahead\r\n").unwrap();
+                        if session == Session::DropInData {
+                            let mut first = String::new();
+                            let _ = lines.read_line(&mut first);
+                            break;
+                        }
+
+                        loop {
+                            let mut data = String::new();
+                            if lines.read_line(&mut data).unwrap_or(0) == 0 || data == ".\r\n" {
+                                break;
+                            }
+                            transcript.data.push_str(&data);
+                        }
+                        "250 2.0.0 queued\r\n"
+                    }
+                    "QUIT" => {
+                        let _ = out.write_all(b"221 bye\r\n");
+                        break;
+                    }
+                    _ => "250 ok\r\n",
+                };
+                out.write_all(answer.as_bytes()).unwrap();
+            }
+
+            seen.lock().unwrap().push(transcript);
+        }
+    });
+
+    (port, transcripts)
+}
+
+fn state(name: &str, port: u16) -> (Config, StatePaths, PathBuf) {
+    let dir = ::std::env::temp_dir().join(format!("bbsmon-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let config_file = dir.join("bbsmon.json");
+    fs::File::create(&config_file).unwrap().write_all(format!(
+        "{{\"state_dir\": \"{}\", \"remote_rss\": \"http://bbs/rss.xml\",
+           \"subject\": \"新帖\", \"from\": \"bbsmon@bbs\", \"to\": \"reader@bbs\",
+           \"password\": \"secret\", \"server\": \"127.0.0.1\", \"smtp_port\": {}}}",
+        dir.join("state").display(), port).as_bytes()).unwrap();
+
+    let config = Config::load(config_file.to_str().unwrap()).unwrap();
+    let paths = StatePaths::resolve(&config);
+    paths.create_dirs(&config.feeds).unwrap();
+
+    (config, paths, dir)
+}
+
+fn base64_decode(text: &str) -> Vec<u8> {
+    let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut bits = 0u32;
+    let mut count = 0;
+    let mut out = Vec::new();
+
+    for c in text.bytes().filter(|c| *c != b'=') {
+        let value = alphabet.iter().position(|a| *a == c).unwrap() as u32;
+        bits = (bits << 6) | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+
+    out
+}
+
+// the Subject header, with RFC 2047 base64 words decoded.
+fn subject(data: &str) -> String {
+    let raw = data.lines()
+        .find(|l| l.to_lowercase().starts_with("subject:"))
+        .unwrap()["subject:".len()..]
+        .trim()
+        .to_string();
+
+    let lower = raw.to_lowercase();
+    if lower.starts_with("=?utf-8?b?") && raw.ends_with("?=") {
+        return String::from_utf8(base64_decode(&raw["=?utf-8?b?".len()..raw.len() - 2])).unwrap();
+    }
+
+    raw
+}
+
+#[test]
+fn mail_reaches_the_sink_intact() {
+    let (port, transcripts) = smtp_sink(vec![Session::Accept]);
+    let (config, _, dir) = state("mail", port);
+
+    send_mail(&config, &config.subject, "<p>你好</p>").unwrap();
+
+    let transcripts = transcripts.lock().unwrap();
+    let session = &transcripts[0];
+
+    let auth = session.commands.iter().find(|c| c.starts_with("AUTH PLAIN")).unwrap();
+    assert_eq!(base64_decode(auth["AUTH PLAIN ".len()..].trim()), b"\0bbsmon@bbs\0secret".to_vec());
+
+    assert!(session.commands.iter().any(|c| c.starts_with("MAIL FROM:<bbsmon@bbs>")));
+    let recipients: Vec<&String> = session.commands.iter().filter(|c| c.starts_with("RCPT TO:")).collect();
+    assert_eq!(recipients.len(), 1);
+    assert!(recipients[0].contains("<reader@bbs>"));
+
+    let headers = session.data.split("\r\n\r\n").next().unwrap();
+    assert_eq!(subject(headers), "新帖");
+    assert!(headers.contains("Content-Type: text/html; charset=UTF-8"));
+    assert!(headers.lines().any(|l| l.starts_with("To:") && l.contains("reader@bbs")));
+    assert!(!headers.to_lowercase().contains("bcc:"));
+    assert!(session.data.contains("<p>你好</p>"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_mails_are_spooled_until_retried() {
+    let (port, transcripts) = smtp_sink(vec![Session::RejectMailFrom, Session::DropInData, Session::Accept,
+                                             Session::Accept]);
+    let (config, paths, dir) = state("spool", port);
+
+    let rejected = deliver(&config, &paths, "first", "<p>1</p>".to_string()).unwrap();
+    assert!(rejected.is_some());
+    let dropped = deliver(&config, &paths, "second", "<p>2</p>".to_string()).unwrap();
+    assert!(dropped.is_some());
+
+    let spooled: Vec<String> = DeadLetter::load_all(&paths.spool).unwrap()
+        .into_iter()
+        .map(|(_, letter)| letter.subject)
+        .collect();
+    assert_eq!(spooled, vec!["first", "second"]);
+
+    retry_failed(&config, &paths).unwrap();
+    assert!(DeadLetter::load_all(&paths.spool).unwrap().is_empty());
+
+    let transcripts = transcripts.lock().unwrap();
+    assert_eq!(transcripts.len(), 4);
+    assert!(transcripts[0].data.is_empty());
+    assert!(transcripts[2].data.contains("<p>1</p>"));
+    assert!(transcripts[3].data.contains("<p>2</p>"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn subscribers_get_their_own_mail_over_one_session() {
+    let (port, transcripts) = smtp_sink(vec![Session::Accept]);
+    let (config, paths, dir) = state("subscribers", port);
+
+    let mails = vec![("kernel@bbs".to_string(), "<p>kernel</p>".to_string()),
+                     ("web@bbs".to_string(), "<p>frontend</p>".to_string())];
+    assert!(deliver_all(&config, &paths, "新帖", mails).unwrap().is_none());
+
+    let transcripts = transcripts.lock().unwrap();
+    assert_eq!(transcripts.len(), 1);
+    let recipients: Vec<&String> = transcripts[0].commands.iter().filter(|c| c.starts_with("RCPT TO:")).collect();
+    assert_eq!(recipients.len(), 2);
+    assert!(recipients[0].contains("<kernel@bbs>"));
+    assert!(recipients[1].contains("<web@bbs>"));
+    assert!(transcripts[0].data.contains("<p>kernel</p>"));
+    assert!(transcripts[0].data.contains("<p>frontend</p>"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// the words of a header value that RFC 2047 encoded, after checking each is
+// a well-formed UTF-8 "B" encoded-word; decoded and joined.
+fn decode_words(value: &str) -> String {
+    let mut decoded = Vec::new();
+    for word in value.split_whitespace().filter(|w| w.starts_with("=?")) {
+        assert!(word.len() <= 75, "{} is too long", word);
+        assert!(word.starts_with("=?UTF-8?B?") && word.ends_with("?="), "{} is malformed", word);
+        let encoded = &word["=?UTF-8?B?".len()..word.len() - 2];
+        assert_eq!(encoded.len() % 4, 0, "{} is malformed", word);
+        let bytes = base64_decode(encoded);
+        assert!(String::from_utf8(bytes.clone()).is_ok(), "{} splits a character", word);
+        decoded.extend(bytes);
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+#[test]
+fn headers_of_chinese_mails_are_encoded_words() {
+    let (port, transcripts) = smtp_sink(vec![Session::Accept]);
+    let (mut config, _, dir) = state("encoded", port);
+    config.to_name = Some("值班同学".to_string());
+    let subject = "论坛更新：二手 3 条，聚餐 1 条，招聘 12 条，技术分享 5 条";
+
+    send_mail(&config, subject, "<p>你好</p>").unwrap();
+
+    let transcripts = transcripts.lock().unwrap();
+    let headers = transcripts[0].data.split("\r\n\r\n").next().unwrap().replace("\r\n ", " ");
+    assert!(headers.bytes().all(|b| b < 0x80), "{}", headers);
+
+    let header = |name: &str| headers.lines().find(|l| l.starts_with(name)).unwrap()[name.len()..].to_string();
+    let to = header("To:");
+    assert!(to.contains("=?UTF-8?B?5YC854+t5ZCM5a2m?="), "{}", to);
+    assert!(to.contains("<reader@bbs>"));
+    assert_eq!(decode_words(&to), "值班同学");
+    assert_eq!(decode_words(&header("Subject:")), subject);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recipients_need_a_domain_with_mx_records() {
+    assert!(verify_recipient("yami").is_err());
+    // .invalid never resolves, see RFC 2606.
+    assert!(verify_recipient("yami@bbsmon.invalid").is_err());
+}
+
+#[test]
+fn push_descriptions_are_cut_at_characters() {
+    assert_eq!(truncate_chars("二手自行车，九成新，价格可议", 5), "二手自行车…");
+    assert_eq!(truncate_chars("ride on ", 5), "ride…");
+    assert_eq!(truncate_chars("short", 5), "short");
+}
+
+#[cfg(feature = "sink-webhook")]
+#[test]
+fn only_webhooks_get_cut_descriptions() {
+    let item = SerItem {
+        title: Some("二手".into()),
+        link: Some("http://bbs/1".into()),
+        description: Some("九成新的自行车".into()),
+        author: None,
+        pub_date: None,
+        thumbnail: None,
+        categories: Vec::new().into(),
+        replies: Vec::new().into(),
+        archive_link: None,
+        diff: Vec::new().into(),
+    };
+    let items = vec![item];
+
+    let messages = rocketchat_messages("bbs", &items, false, Some(3));
+    assert_eq!(messages[0].attachments[0].text, Some("九成新…".into()));
+    assert_eq!(items[0].description, Some("九成新的自行车".into()));
+}
+
+#[test]
+fn failing_feeds_are_alerted_once_and_on_recovery() {
+    let (port, transcripts) = smtp_sink(vec![Session::Accept, Session::Accept]);
+    let (mut config, paths, dir) = state("alerts", port);
+    config.alert_after_failures = Some(2);
+    config.alert_to = Some("ops@bbs".to_string());
+
+    let checked = vec!["default".to_string(), "seen store".to_string()];
+    let failures = vec![Failure {
+        feed: "default".to_string(),
+        error: "http://bbs/rss.xml answered 500".to_string(),
+        time: "2016-10-16 08:00:00".to_string(),
+    }];
+
+    for _ in 0..3 {
+        send_alerts(&config, &paths, &checked, &failures).unwrap();
+    }
+    send_alerts(&config, &paths, &checked, &Vec::new()).unwrap();
+    send_alerts(&config, &paths, &checked, &Vec::new()).unwrap();
+
+    let transcripts = transcripts.lock().unwrap();
+    assert_eq!(transcripts.len(), 2);
+    assert!(transcripts[0].commands.iter().any(|c| c.contains("<ops@bbs>")));
+    assert!(transcripts[0].data.contains("default failed 2 runs in a row"), "{}", transcripts[0].data);
+    assert!(transcripts[0].data.contains("answered 500"));
+    assert!(transcripts[1].data.contains("default works again after failing 3 runs"), "{}", transcripts[1].data);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
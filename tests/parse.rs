@@ -0,0 +1,148 @@
+// feeds arbitrary and mangled documents to the parser: it may refuse them
+// but must not panic or take forever, and diffs of what it parses must hold.
+
+extern crate bbsmon;
+extern crate quickcheck;
+
+use std::time::Duration;
+use std::time::Instant;
+
+use quickcheck::QuickCheck;
+
+use bbsmon::feed::MAX_FEED_BYTES;
+use bbsmon::feed::MAX_FEED_DEPTH;
+use bbsmon::feed::RssContext;
+
+const SEEDS: &[&str] = &[
+    "<rss version=\"2.0\"><channel><title>t</title><link>l</link><description>d</description>\
+     <item><guid>1</guid><title>a &amp; b</title><link>http://bbs/1</link></item>\
+     <item><title><![CDATA[<b>c</b>]]></title><pubDate>Fri, 04 Nov 2016 23:30:00 +0800</pubDate></item>\
+     </channel></rss>",
+    "<?xml version=\"1.0\"?><!DOCTYPE rss [<!ENTITY a \"aaaaaaaaaa\"><!ENTITY b \"&a;&a;&a;&a;\">]>\
+     <rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><channel><title>&b;</title>\
+     <item><dc:creator>yami</dc:creator><dc:x><dc:y a=\"1\">z</dc:y></dc:x></item></channel></rss>",
+];
+
+// the items of a feed with one item per (guid, title).
+fn feed(items: &Vec<(u8, String)>) -> String {
+    let mut xml = "<rss version=\"2.0\"><channel><title>t</title><link>l</link><description>d</description>"
+        .to_string();
+    for &(guid, ref title) in items {
+        let title = title.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;");
+        xml.push_str(&format!("<item><guid>{}</guid><title>{}</title></item>", guid, title));
+    }
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+fn diff_holds(a: &RssContext, b: &RssContext) -> bool {
+    let diff = RssContext::diff(a, b);
+    RssContext::diff(a, a).is_empty() && diff.iter().all(|item| a.channel.items.contains(item))
+}
+
+#[test]
+fn diff_of_parsed_feeds() {
+    fn prop(a: Vec<(u8, String)>, b: Vec<(u8, String)>) -> bool {
+        let a = match RssContext::from_reader(feed(&a).as_bytes()) {
+            Ok(a) => a,
+            // a title may hold characters xml doesn't allow.
+            Err(_) => return true,
+        };
+        match RssContext::from_reader(feed(&b).as_bytes()) {
+            Ok(b) => diff_holds(&a, &b),
+            Err(_) => true,
+        }
+    }
+
+    QuickCheck::new().tests(200).quickcheck(prop as fn(Vec<(u8, String)>, Vec<(u8, String)>) -> bool);
+}
+
+#[test]
+fn arbitrary_bytes() {
+    fn prop(bytes: Vec<u8>) -> bool {
+        match RssContext::from_reader(&bytes[..]) {
+            Ok(a) => diff_holds(&a, &a),
+            Err(_) => true,
+        }
+    }
+
+    QuickCheck::new().tests(500).quickcheck(prop as fn(Vec<u8>) -> bool);
+}
+
+// a bounded run of what the fuzz target does: flip, drop and repeat bytes of
+// well-formed feeds, the same way every run.
+#[test]
+fn mangled_feeds() {
+    let mut x: u64 = 0x9e3779b97f4a7c15;
+    let mut next = move || {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        x as usize
+    };
+
+    for seed in SEEDS {
+        for _ in 0..1000 {
+            let mut doc = seed.as_bytes().to_vec();
+            for _ in 0..1 + next() % 8 {
+                let at = next() % doc.len();
+                match next() % 3 {
+                    0 => doc[at] = next() as u8,
+                    1 => { doc.remove(at); }
+                    _ => {
+                        let end = ::std::cmp::min(doc.len(), at + next() % 16);
+                        let repeated: Vec<u8> = doc[at..end].to_vec();
+                        for (i, b) in repeated.into_iter().enumerate() {
+                            doc.insert(end + i, b);
+                        }
+                    }
+                }
+                if doc.is_empty() {
+                    break;
+                }
+            }
+
+            let started = Instant::now();
+            if let Ok(a) = RssContext::from_reader(&doc[..]) {
+                assert!(diff_holds(&a, &a));
+            }
+            assert!(started.elapsed() < Duration::from_secs(1), "{}", String::from_utf8_lossy(&doc));
+        }
+    }
+}
+
+#[test]
+fn deep_nesting_is_refused() {
+    let depth = MAX_FEED_DEPTH * 100;
+    let open: String = std::iter::repeat_n("<x:a>", depth).collect();
+    let close: String = std::iter::repeat_n("</x:a>", depth).collect();
+    let doc = format!("<rss version=\"2.0\" xmlns:x=\"x\"><channel><title>t</title><item>{}{}</item>\
+                       </channel></rss>", open, close);
+
+    let e = RssContext::from_reader(doc.as_bytes()).err().unwrap();
+    assert!(e.to_string().contains("nested deeper"), "{}", e);
+}
+
+#[test]
+fn huge_documents_are_refused() {
+    let doc = vec![b' '; MAX_FEED_BYTES as usize + 1];
+
+    let e = RssContext::from_reader(&doc[..]).err().unwrap();
+    assert!(e.to_string().contains("larger than"), "{}", e);
+}
+
+#[test]
+fn only_the_newest_items_are_kept() {
+    let doc = "<rss version=\"2.0\"><channel><title>t</title>\
+               <item><title>old</title><pubDate>Mon, 10 Oct 2016 08:00:00 +0800</pubDate></item>\
+               <item><title>undated</title></item>\
+               <item><title>newest</title><pubDate>Fri, 14 Oct 2016 08:00:00 +0800</pubDate></item>\
+               <item><title>newer</title><pubDate>Wed, 12 Oct 2016 08:00:00 +0800</pubDate></item>\
+               </channel></rss>";
+    let mut ctx = RssContext::from_reader(doc.as_bytes()).unwrap();
+
+    assert_eq!(ctx.keep_newest(2), 2);
+    let titles: Vec<String> = ctx.channel.items.iter().map(|i| i.title.clone().unwrap()).collect();
+    assert_eq!(titles, vec!["newest", "newer"]);
+    assert_eq!(ctx.keep_newest(5), 0);
+}